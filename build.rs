@@ -0,0 +1,12 @@
+fn main() {
+    // the C header of the ffi module, see cbindgen.toml
+    #[cfg(feature = "ffi")]
+    {
+        let crate_dir = std::env::var("CARGO_MANIFEST_DIR").unwrap();
+        println!("cargo:rerun-if-changed=src/ffi.rs");
+        println!("cargo:rerun-if-changed=cbindgen.toml");
+        cbindgen::generate(&crate_dir)
+            .expect("Unable to generate C bindings")
+            .write_to_file(format!("{}/include/strap.h", crate_dir));
+    }
+}
@@ -0,0 +1,250 @@
+//! Merging of several parquet files into one, used by `strap2parquet concat`
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use arrow::array::{new_null_array, ArrayRef, StringArray};
+use arrow::datatypes::{DataType, Field, Schema, SchemaRef};
+use arrow::record_batch::RecordBatch;
+use parquet::arrow::ArrowWriter;
+use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+use parquet::file::properties::WriterProperties;
+
+/// Name of the optional column holding the input path of every row
+pub const SOURCE_COLUMN: &str = "__source";
+
+#[derive(Debug, Clone, Default)]
+pub struct ConcatOptions {
+    /// Add the input path of every row as `__source`
+    pub source_column: bool,
+    /// Sort the merged rows by this column, done by DuckDB instead of streaming
+    pub sort_by: Option<String>,
+}
+
+/// Columns that have different types in different inputs
+#[derive(Debug)]
+pub struct SchemaConflict {
+    // column -> (input, type) of every input that has the column
+    pub columns: BTreeMap<String, Vec<(PathBuf, DataType)>>,
+}
+
+impl fmt::Display for SchemaConflict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Conflicting column types:")?;
+        for (column, types) in &self.columns {
+            writeln!(f, "  {}:", column)?;
+            for (path, data_type) in types {
+                writeln!(f, "    {} in {}", data_type, path.to_string_lossy())?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for SchemaConflict {}
+
+/// Union of the input schemas in order of first appearance, every field nullable
+pub fn union_schema(inputs: &[PathBuf]) -> Result<SchemaRef, Box<dyn std::error::Error>> {
+    let mut fields: Vec<Field> = vec![];
+    let mut types: BTreeMap<String, Vec<(PathBuf, DataType)>> = BTreeMap::new();
+    for input in inputs {
+        let builder = ParquetRecordBatchReaderBuilder::try_new(File::open(input)?)?;
+        for field in builder.schema().fields() {
+            types.entry(field.name().clone()).or_default().push((input.clone(), field.data_type().clone()));
+            if !fields.iter().any(|f| f.name() == field.name()) {
+                fields.push(Field::new(field.name(), field.data_type().clone(), true));
+            }
+        }
+    }
+    types.retain(|_, t| t.iter().any(|(_, data_type)| data_type != &t[0].1));
+    if !types.is_empty() {
+        return Err(Box::new(SchemaConflict { columns: types }));
+    }
+    Ok(Arc::new(Schema::new(fields)))
+}
+
+/// Merge the inputs into `output`, missing columns are filled with nulls
+pub fn concat_parquet(
+    inputs: &[PathBuf],
+    output: &Path,
+    options: &ConcatOptions,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let schema = union_schema(inputs)?;
+    if let Some(sort_by) = &options.sort_by {
+        return concat_sorted(inputs, output, sort_by, options.source_column);
+    }
+
+    let mut fields: Vec<Field> = schema.fields().iter().map(|f| f.as_ref().clone()).collect();
+    if options.source_column {
+        fields.push(Field::new(SOURCE_COLUMN, DataType::Utf8, false));
+    }
+    let output_schema = Arc::new(Schema::new(fields));
+    let props = WriterProperties::builder().build();
+    let mut writer = ArrowWriter::try_new(File::create(output)?, output_schema.clone(), Some(props))?;
+
+    // one record batch at a time, so memory stays bounded by the batch size
+    for input in inputs {
+        let reader = ParquetRecordBatchReaderBuilder::try_new(File::open(input)?)?.build()?;
+        for batch in reader {
+            let batch = batch?;
+            let mut arrays: Vec<ArrayRef> = schema.fields().iter()
+                .map(|field| match batch.column_by_name(field.name()) {
+                    Some(array) => array.clone(),
+                    None => new_null_array(field.data_type(), batch.num_rows()),
+                })
+                .collect();
+            if options.source_column {
+                let source = input.to_string_lossy();
+                arrays.push(Arc::new(StringArray::from(vec![source.as_ref(); batch.num_rows()])));
+            }
+            writer.write(&RecordBatch::try_new(output_schema.clone(), arrays)?)?;
+        }
+    }
+    writer.close()?;
+    Ok(())
+}
+
+/// Parquet files matching `pattern` in lexical order, a pattern without wildcards is returned as is
+pub fn expand_glob(pattern: &str) -> Result<Vec<PathBuf>, Box<dyn std::error::Error>> {
+    if !pattern.contains(['*', '?', '[']) {
+        return Ok(vec![PathBuf::from(pattern)]);
+    }
+    let connection = duckdb::Connection::open_in_memory()?;
+    let mut statement = connection.prepare("SELECT file FROM glob(?) ORDER BY file")?;
+    let files = statement.query_map([pattern], |row| row.get::<_, String>(0))?
+        .map(|file| file.map(PathBuf::from))
+        .collect::<Result<Vec<_>, _>>()?;
+    if files.is_empty() {
+        return Err(format!("No files match {}", pattern).into());
+    }
+    Ok(files)
+}
+
+fn sql_string(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "''"))
+}
+
+/// Sorting needs all rows, so DuckDB does the merge and spills to disk if needed
+fn concat_sorted(
+    inputs: &[PathBuf],
+    output: &Path,
+    sort_by: &str,
+    source_column: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let files = inputs.iter()
+        .map(|p| sql_string(&p.to_string_lossy()))
+        .collect::<Vec<_>>()
+        .join(", ");
+    let columns = if source_column {
+        format!("* EXCLUDE (filename), filename AS {}", SOURCE_COLUMN)
+    } else {
+        "*".to_string()
+    };
+    let query = format!(
+        "COPY (SELECT {} FROM read_parquet([{}], union_by_name = true, filename = {}) ORDER BY \"{}\") TO {} (FORMAT PARQUET)",
+        columns,
+        files,
+        source_column,
+        sort_by.replace('"', "\"\""),
+        sql_string(&output.to_string_lossy()),
+    );
+    duckdb::Connection::open_in_memory()?.execute_batch(&query)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::StrapTrack;
+    use arrow::array::{Array, Float64Array, Int64Array};
+
+    fn write_parquet(dir: &tempfile::TempDir, name: &str, content: &str) -> PathBuf {
+        let strap = dir.path().join(format!("{}.strap", name));
+        std::fs::write(&strap, content).unwrap();
+        let parquet = dir.path().join(format!("{}.parquet", name));
+        StrapTrack::new(&strap).unwrap().to_parquet(parquet.to_str().unwrap(), 1).unwrap();
+        parquet
+    }
+
+    fn read(path: &Path) -> Vec<RecordBatch> {
+        ParquetRecordBatchReaderBuilder::try_new(File::open(path).unwrap()).unwrap()
+            .build().unwrap()
+            .collect::<Result<Vec<_>, _>>().unwrap()
+    }
+
+    fn column(batches: &[RecordBatch], name: &str) -> Vec<Option<f64>> {
+        batches.iter().flat_map(|b| {
+            let array = b.column_by_name(name).unwrap().as_any().downcast_ref::<Float64Array>().unwrap().clone();
+            (0..array.len()).map(move |i| if array.is_null(i) { None } else { Some(array.value(i)) })
+        }).collect()
+    }
+
+    fn inputs(dir: &tempfile::TempDir) -> Vec<PathBuf> {
+        vec![
+            write_parquet(dir, "run_1", "ts 3.0 x 1.0\nts 1.0 x 2.0\n"),
+            write_parquet(dir, "run_2", "ts 2.0 y 5.0\n"),
+            write_parquet(dir, "run_3", "ts 4.0 x 3.0 y 6.0\n"),
+        ]
+    }
+
+    #[test]
+    fn test_concat_streaming() {
+        let dir = tempfile::tempdir().unwrap();
+        let inputs = inputs(&dir);
+        let output = dir.path().join("all.parquet");
+        concat_parquet(&inputs, &output, &ConcatOptions { source_column: true, sort_by: None }).unwrap();
+
+        let batches = read(&output);
+        let schema = batches[0].schema();
+        let names: Vec<&str> = schema.fields().iter().map(|f| f.name().as_str()).collect();
+        assert_eq!(names, vec!["ts", "x", "y", SOURCE_COLUMN]);
+        assert_eq!(column(&batches, "ts"), vec![Some(3.0), Some(1.0), Some(2.0), Some(4.0)]);
+        assert_eq!(column(&batches, "x"), vec![Some(1.0), Some(2.0), None, Some(3.0)]);
+        assert_eq!(column(&batches, "y"), vec![None, None, Some(5.0), Some(6.0)]);
+        let source = batches[2].column_by_name(SOURCE_COLUMN).unwrap().as_any().downcast_ref::<StringArray>().unwrap().value(0).to_string();
+        assert!(source.ends_with("run_2.parquet"));
+    }
+
+    #[test]
+    fn test_concat_sorted() {
+        let dir = tempfile::tempdir().unwrap();
+        let inputs = inputs(&dir);
+        let output = dir.path().join("all.parquet");
+        concat_parquet(&inputs, &output, &ConcatOptions { source_column: false, sort_by: Some("ts".to_string()) }).unwrap();
+
+        let batches = read(&output);
+        assert_eq!(column(&batches, "ts"), vec![Some(1.0), Some(2.0), Some(3.0), Some(4.0)]);
+        assert_eq!(column(&batches, "y"), vec![None, Some(5.0), None, Some(6.0)]);
+    }
+
+    #[test]
+    fn test_concat_type_conflict() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut inputs = inputs(&dir);
+        let path = dir.path().join("ints.parquet");
+        let schema = Arc::new(Schema::new(vec![Field::new("x", DataType::Int64, true)]));
+        let batch = RecordBatch::try_new(schema.clone(), vec![Arc::new(Int64Array::from(vec![1])) as ArrayRef]).unwrap();
+        let mut writer = ArrowWriter::try_new(File::create(&path).unwrap(), schema, None).unwrap();
+        writer.write(&batch).unwrap();
+        writer.close().unwrap();
+        inputs.push(path);
+
+        let error = concat_parquet(&inputs, &dir.path().join("all.parquet"), &ConcatOptions::default()).unwrap_err();
+        let conflict = error.downcast_ref::<SchemaConflict>().unwrap();
+        assert_eq!(conflict.columns.keys().collect::<Vec<_>>(), vec!["x"]);
+        assert_eq!(conflict.columns["x"].len(), 3);
+        assert!(error.to_string().contains("Int64"));
+    }
+
+    #[test]
+    fn test_expand_glob() {
+        let dir = tempfile::tempdir().unwrap();
+        let inputs = inputs(&dir);
+        let pattern = dir.path().join("run_*.parquet");
+        assert_eq!(expand_glob(&pattern.to_string_lossy()).unwrap(), inputs);
+        assert_eq!(expand_glob("plain.parquet").unwrap(), vec![PathBuf::from("plain.parquet")]);
+        assert!(expand_glob(&dir.path().join("none_*.parquet").to_string_lossy()).is_err());
+    }
+}
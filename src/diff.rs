@@ -0,0 +1,224 @@
+//! Comparison of two STRAP files, used by the `strap-diff` binary
+use std::collections::HashMap;
+use std::fmt;
+
+use serde::Serialize;
+
+use crate::StrapTrack;
+
+/// Values a and b match if |a - b| <= abs + rel * max(|a|, |b|)
+#[derive(Debug, Clone, Copy)]
+pub struct Tolerance {
+    pub abs: f64,
+    pub rel: f64,
+}
+
+impl Default for Tolerance {
+    fn default() -> Self {
+        Self { abs: 1e-12, rel: 1e-9 }
+    }
+}
+
+impl Tolerance {
+    pub fn matches(&self, a: f64, b: f64) -> bool {
+        a == b || (a - b).abs() <= self.abs + self.rel * a.abs().max(b.abs())
+    }
+}
+
+/// A statistic of a common column that differs beyond tolerance
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ColumnDiff {
+    pub column: String,
+    pub statistic: String,
+    pub a: f64,
+    pub b: f64,
+}
+
+/// A value that differs between rows with the same key, None if the row or column is missing
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct RowMismatch {
+    pub key: f64,
+    pub column: String,
+    pub a: Option<f64>,
+    pub b: Option<f64>,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct DiffReport {
+    pub only_in_a: Vec<String>,
+    pub only_in_b: Vec<String>,
+    pub rows_a: usize,
+    pub rows_b: usize,
+    pub columns: Vec<ColumnDiff>,
+    // only filled when aligning rows on a key column
+    pub rows: Vec<RowMismatch>,
+}
+
+impl DiffReport {
+    pub fn has_differences(&self) -> bool {
+        !self.only_in_a.is_empty()
+            || !self.only_in_b.is_empty()
+            || self.rows_a != self.rows_b
+            || !self.columns.is_empty()
+            || !self.rows.is_empty()
+    }
+}
+
+impl fmt::Display for DiffReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if !self.only_in_a.is_empty() {
+            writeln!(f, "Columns only in A: {}", self.only_in_a.join(", "))?;
+        }
+        if !self.only_in_b.is_empty() {
+            writeln!(f, "Columns only in B: {}", self.only_in_b.join(", "))?;
+        }
+        if self.rows_a != self.rows_b {
+            writeln!(f, "Rows: {} in A, {} in B", self.rows_a, self.rows_b)?;
+        }
+        for d in &self.columns {
+            writeln!(f, "{} {}: {} in A, {} in B", d.column, d.statistic, d.a, d.b)?;
+        }
+        let value = |v: Option<f64>| v.map(|v| v.to_string()).unwrap_or_else(|| "missing".to_string());
+        for m in &self.rows {
+            writeln!(f, "Row {} {}: {} in A, {} in B", m.key, m.column, value(m.a), value(m.b))?;
+        }
+        if !self.has_differences() {
+            writeln!(f, "No differences")?;
+        }
+        Ok(())
+    }
+}
+
+/// Compare columns, row counts and per-column statistics of two files
+pub fn diff(a: &StrapTrack, b: &StrapTrack, tolerance: Tolerance) -> Result<DiffReport, std::io::Error> {
+    let (stats_a, rows_a) = a.describe()?;
+    let (stats_b, rows_b) = b.describe()?;
+    let mut report = DiffReport {
+        only_in_a: stats_a.keys().filter(|k| !stats_b.contains_key(*k)).cloned().collect(),
+        only_in_b: stats_b.keys().filter(|k| !stats_a.contains_key(*k)).cloned().collect(),
+        rows_a,
+        rows_b,
+        ..Default::default()
+    };
+    for (column, sa) in &stats_a {
+        let Some(sb) = stats_b.get(column) else {
+            continue;
+        };
+        let statistics = [
+            ("count", sa.count as f64, sb.count as f64),
+            ("mean", sa.mean(), sb.mean()),
+            ("min", sa.min, sb.min),
+            ("max", sa.max, sb.max),
+        ];
+        for (statistic, va, vb) in statistics {
+            // counts are compared exactly
+            let same = if statistic == "count" { va == vb } else { tolerance.matches(va, vb) };
+            if !same {
+                report.columns.push(ColumnDiff { column: column.clone(), statistic: statistic.to_string(), a: va, b: vb });
+            }
+        }
+    }
+    Ok(report)
+}
+
+/// Align rows on the value of `key` and report up to `limit` mismatching values in key order.
+/// Rows without the key are ignored, for duplicate keys the last row wins.
+pub fn diff_rows(
+    a: &StrapTrack,
+    b: &StrapTrack,
+    key: &str,
+    tolerance: Tolerance,
+    limit: usize,
+) -> Result<Vec<RowMismatch>, std::io::Error> {
+    let keyed = |track: &StrapTrack| -> Result<HashMap<u64, HashMap<String, f64>>, std::io::Error> {
+        let mut rows = HashMap::new();
+        for row in track.iter()? {
+            let row = row?;
+            if let Some(k) = row.get(key) {
+                rows.insert(k.to_bits(), row);
+            }
+        }
+        Ok(rows)
+    };
+    let rows_a = keyed(a)?;
+    let rows_b = keyed(b)?;
+    let mut keys: Vec<f64> = rows_a.keys().chain(rows_b.keys()).map(|k| f64::from_bits(*k)).collect();
+    keys.sort_by(|x, y| x.total_cmp(y));
+    keys.dedup();
+
+    let mut mismatches = vec![];
+    let empty = HashMap::new();
+    for k in keys {
+        let ra = rows_a.get(&k.to_bits()).unwrap_or(&empty);
+        let rb = rows_b.get(&k.to_bits()).unwrap_or(&empty);
+        let mut columns: Vec<&String> = ra.keys().chain(rb.keys()).filter(|c| c.as_str() != key).collect();
+        columns.sort();
+        columns.dedup();
+        for column in columns {
+            let (va, vb) = (ra.get(column).copied(), rb.get(column).copied());
+            let same = match (va, vb) {
+                (Some(va), Some(vb)) => tolerance.matches(va, vb),
+                _ => false,
+            };
+            if !same {
+                mismatches.push(RowMismatch { key: k, column: column.clone(), a: va, b: vb });
+                if mismatches.len() >= limit {
+                    return Ok(mismatches);
+                }
+            }
+        }
+    }
+    Ok(mismatches)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use tempfile::NamedTempFile;
+
+    fn track(content: &str) -> (NamedTempFile, StrapTrack) {
+        let mut file = NamedTempFile::with_suffix(".strap").unwrap();
+        write!(file, "{}", content).unwrap();
+        let track = StrapTrack::new(file.path()).unwrap();
+        (file, track)
+    }
+
+    // B lacks column c and has three changed values of y, x only differs in formatting
+    const A: &str = "ts 1 x 1.0 y 10.0 c 0\nts 2 x 2.0 y 20.0\nts 3 x 3.0 y 30.0\nts 4 x 4.0 y 40.0\n";
+    const B: &str = "ts 1 x 1.00 y 10.0\nts 2 x 2e0 y 21.0\nts 3 x 3.0 y 31.0\nts 4 x 4.000 y 41.0\n";
+
+    #[test]
+    fn test_diff() {
+        let (_a, a) = track(A);
+        let (_b, b) = track(B);
+        let report = diff(&a, &b, Tolerance::default()).unwrap();
+        assert!(report.has_differences());
+        assert_eq!(report.only_in_a, vec!["c"]);
+        assert!(report.only_in_b.is_empty());
+        assert_eq!((report.rows_a, report.rows_b), (4, 4));
+        let differing: Vec<(&str, &str)> = report.columns.iter().map(|d| (d.column.as_str(), d.statistic.as_str())).collect();
+        assert_eq!(differing, vec![("y", "mean"), ("y", "max")]);
+
+        // a loose relative tolerance accepts the changed mean and max
+        let report = diff(&a, &b, Tolerance { abs: 0.0, rel: 0.04 }).unwrap();
+        assert_eq!(report.columns.len(), 0);
+
+        let (_same, same) = track(A);
+        assert!(!diff(&a, &same, Tolerance::default()).unwrap().has_differences());
+    }
+
+    #[test]
+    fn test_diff_rows() {
+        let (_a, a) = track(A);
+        let (_b, b) = track(B);
+        let rows = diff_rows(&a, &b, "ts", Tolerance::default(), 10).unwrap();
+        assert_eq!(rows, vec![
+            RowMismatch { key: 1.0, column: "c".to_string(), a: Some(0.0), b: None },
+            RowMismatch { key: 2.0, column: "y".to_string(), a: Some(20.0), b: Some(21.0) },
+            RowMismatch { key: 3.0, column: "y".to_string(), a: Some(30.0), b: Some(31.0) },
+            RowMismatch { key: 4.0, column: "y".to_string(), a: Some(40.0), b: Some(41.0) },
+        ]);
+        assert_eq!(diff_rows(&a, &b, "ts", Tolerance::default(), 2).unwrap().len(), 2);
+    }
+}
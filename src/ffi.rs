@@ -0,0 +1,297 @@
+//! C ABI for the parser and the parquet conversion, the header is generated into include/strap.h
+use std::cell::RefCell;
+use std::ffi::{c_char, c_int, CStr};
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::ptr;
+
+use itertools::Itertools;
+
+use crate::StrapTrack;
+
+/// Opened STRAP file with its columns, created by `strap_open`, freed by `strap_close`
+pub struct StrapHandle {
+    track: StrapTrack,
+    column_names: Vec<String>,
+    rows: usize,
+}
+
+type FfiResult<T> = Result<T, Box<dyn std::error::Error>>;
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<String>> = const { RefCell::new(None) };
+}
+
+fn set_last_error(message: String) {
+    LAST_ERROR.with(|e| *e.borrow_mut() = Some(message));
+}
+
+/// Run `f`, errors and panics are stored for `strap_last_error` and turned into `fallback`
+fn guard<T>(fallback: T, f: impl FnOnce() -> FfiResult<T>) -> T {
+    match catch_unwind(AssertUnwindSafe(f)) {
+        Ok(Ok(value)) => value,
+        Ok(Err(e)) => {
+            set_last_error(e.to_string());
+            fallback
+        }
+        Err(panic) => {
+            let message = panic.downcast_ref::<&str>().map(|s| s.to_string())
+                .or_else(|| panic.downcast_ref::<String>().cloned())
+                .unwrap_or_else(|| "unknown panic".to_string());
+            set_last_error(format!("panic: {}", message));
+            fallback
+        }
+    }
+}
+
+unsafe fn handle_ref<'a>(handle: *const StrapHandle) -> FfiResult<&'a StrapHandle> {
+    unsafe { handle.as_ref() }.ok_or_else(|| "null handle".into())
+}
+
+unsafe fn str_arg<'a>(s: *const c_char) -> FfiResult<&'a str> {
+    if s.is_null() {
+        return Err("null string".into());
+    }
+    Ok(unsafe { CStr::from_ptr(s) }.to_str()?)
+}
+
+/// Copy `s` NUL-terminated into `buf`, truncated to `len` bytes like snprintf.
+/// Returns the length of `s` without NUL, so a return value >= `len` means truncation.
+unsafe fn write_str(s: &str, buf: *mut c_char, len: usize) -> c_int {
+    if !buf.is_null() && len > 0 {
+        let n = s.len().min(len - 1);
+        unsafe {
+            ptr::copy_nonoverlapping(s.as_ptr() as *const c_char, buf, n);
+            *buf.add(n) = 0;
+        }
+    }
+    s.len() as c_int
+}
+
+/// Open a STRAP file, returns NULL on error.
+///
+/// # Safety
+/// `path` must be a valid NUL-terminated string.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn strap_open(path: *const c_char) -> *mut StrapHandle {
+    guard(ptr::null_mut(), || {
+        let track = StrapTrack::new(unsafe { str_arg(path) }?)?;
+        let column_names = track.get_column_names()?;
+        // lines without values are no rows, like in `strap_to_parquet`
+        let rows = track.iter_values()?.filter_ok(|row| !row.is_empty()).try_fold(0, |rows, row| row.map(|_| rows + 1))?;
+        Ok(Box::into_raw(Box::new(StrapHandle { track, column_names, rows })))
+    })
+}
+
+/// Free a handle returned by `strap_open`, NULL is ignored.
+///
+/// # Safety
+/// `handle` must come from `strap_open` and must not be used afterwards.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn strap_close(handle: *mut StrapHandle) {
+    if !handle.is_null() {
+        drop(unsafe { Box::from_raw(handle) });
+    }
+}
+
+/// Number of rows as written by `strap_to_parquet`, lines without values are no rows. -1 on error.
+///
+/// # Safety
+/// `handle` must be NULL or a live handle from `strap_open`.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn strap_row_count(handle: *const StrapHandle) -> i64 {
+    guard(-1, || Ok(unsafe { handle_ref(handle) }?.rows as i64))
+}
+
+/// Number of columns, -1 on error.
+///
+/// # Safety
+/// `handle` must be NULL or a live handle from `strap_open`.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn strap_column_count(handle: *const StrapHandle) -> i64 {
+    guard(-1, || Ok(unsafe { handle_ref(handle) }?.column_names.len() as i64))
+}
+
+/// Write the name of column `i` (sorted order) into `buf`.
+/// Returns the full name length, -1 on error.
+///
+/// # Safety
+/// `handle` must be NULL or a live handle, `buf` must be NULL or hold `len` bytes.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn strap_column_name(handle: *const StrapHandle, i: usize, buf: *mut c_char, len: usize) -> c_int {
+    guard(-1, || {
+        let handle = unsafe { handle_ref(handle) }?;
+        let name = handle.column_names.get(i)
+            .ok_or_else(|| format!("column index {} out of range ({} columns)", i, handle.column_names.len()))?;
+        Ok(unsafe { write_str(name, buf, len) })
+    })
+}
+
+/// Values of column `name`, one per row with NaN where the row has no number.
+/// On success `*out_ptr`/`*out_len` own a buffer that must be freed with `strap_free_column`.
+/// Returns 0 on success, -1 on error.
+///
+/// # Safety
+/// `handle` must be NULL or a live handle, `name` a valid NUL-terminated string,
+/// `out_ptr` and `out_len` valid pointers.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn strap_get_column(
+    handle: *const StrapHandle,
+    name: *const c_char,
+    out_ptr: *mut *mut f64,
+    out_len: *mut usize,
+) -> c_int {
+    guard(-1, || {
+        let handle = unsafe { handle_ref(handle) }?;
+        let name = unsafe { str_arg(name) }?;
+        if out_ptr.is_null() || out_len.is_null() {
+            return Err("null output pointer".into());
+        }
+        if !handle.column_names.iter().any(|c| c == name) {
+            return Err(format!("unknown column {}", name).into());
+        }
+        let values: Box<[f64]> = handle.track.get_column(name)?
+            .into_iter()
+            .map(|value| value.unwrap_or(f64::NAN))
+            .collect();
+        unsafe {
+            *out_len = values.len();
+            *out_ptr = Box::into_raw(values) as *mut f64;
+        }
+        Ok(0)
+    })
+}
+
+/// Free a buffer returned by `strap_get_column`, NULL is ignored.
+///
+/// # Safety
+/// `ptr` and `len` must be exactly as returned by `strap_get_column`.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn strap_free_column(ptr: *mut f64, len: usize) {
+    if !ptr.is_null() {
+        drop(unsafe { Box::from_raw(ptr::slice_from_raw_parts_mut(ptr, len)) });
+    }
+}
+
+/// Convert to a parquet file written in chunks of `chunk` rows.
+/// Returns 0 on success, -1 on error.
+///
+/// # Safety
+/// `handle` must be NULL or a live handle, `path` a valid NUL-terminated string.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn strap_to_parquet(handle: *const StrapHandle, path: *const c_char, chunk: usize) -> c_int {
+    guard(-1, || {
+        let handle = unsafe { handle_ref(handle) }?;
+        if chunk == 0 {
+            return Err("chunk size must be positive".into());
+        }
+        handle.track.to_parquet(unsafe { str_arg(path) }?, chunk)?;
+        Ok(0)
+    })
+}
+
+/// Write the message of the last error on this thread into `buf`.
+/// Returns the full message length, 0 if there was no error.
+///
+/// # Safety
+/// `buf` must be NULL or hold `len` bytes.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn strap_last_error(buf: *mut c_char, len: usize) -> c_int {
+    LAST_ERROR.with(|e| match e.borrow().as_deref() {
+        Some(message) => unsafe { write_str(message, buf, len) },
+        None => 0,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ffi::CString;
+    use std::io::Write;
+    use tempfile::NamedTempFile;
+
+    fn last_error() -> String {
+        let mut buf = [0 as c_char; 256];
+        let n = unsafe { strap_last_error(buf.as_mut_ptr(), buf.len()) };
+        assert!(n >= 0);
+        unsafe { CStr::from_ptr(buf.as_ptr()) }.to_string_lossy().to_string()
+    }
+
+    fn open(content: &str) -> (NamedTempFile, *mut StrapHandle) {
+        let mut file = NamedTempFile::with_suffix(".strap").unwrap();
+        write!(file, "{}", content).unwrap();
+        let path = CString::new(file.path().to_str().unwrap()).unwrap();
+        let handle = unsafe { strap_open(path.as_ptr()) };
+        assert!(!handle.is_null());
+        (file, handle)
+    }
+
+    #[test]
+    fn test_ffi_columns() {
+        let (_file, handle) = open("x 1.0 yy 2.0\n# comment\n\nx 3.0\n");
+        unsafe {
+            assert_eq!(strap_row_count(handle), 2);
+            assert_eq!(strap_column_count(handle), 2);
+
+            let mut buf = [0 as c_char; 8];
+            assert_eq!(strap_column_name(handle, 1, buf.as_mut_ptr(), buf.len()), 2);
+            assert_eq!(CStr::from_ptr(buf.as_ptr()).to_str().unwrap(), "yy");
+            // truncated, but still NUL-terminated
+            assert_eq!(strap_column_name(handle, 1, buf.as_mut_ptr(), 2), 2);
+            assert_eq!(CStr::from_ptr(buf.as_ptr()).to_str().unwrap(), "y");
+
+            let name = CString::new("yy").unwrap();
+            let mut values: *mut f64 = ptr::null_mut();
+            let mut len = 0;
+            assert_eq!(strap_get_column(handle, name.as_ptr(), &mut values, &mut len), 0);
+            let slice = std::slice::from_raw_parts(values, len);
+            assert_eq!(len, 2);
+            assert_eq!(slice[0], 2.0);
+            assert!(slice[1].is_nan());
+            strap_free_column(values, len);
+            strap_close(handle);
+        }
+    }
+
+    #[test]
+    fn test_ffi_errors() {
+        let missing = CString::new("/nonexistent/run.strap").unwrap();
+        unsafe {
+            assert!(strap_open(missing.as_ptr()).is_null());
+            assert!(!last_error().is_empty());
+            assert!(strap_open(ptr::null()).is_null());
+            assert_eq!(last_error(), "null string");
+            assert_eq!(strap_row_count(ptr::null()), -1);
+            assert_eq!(last_error(), "null handle");
+        }
+
+        let (_file, handle) = open("x 1.0\n");
+        let name = CString::new("z").unwrap();
+        let mut values: *mut f64 = ptr::null_mut();
+        let mut len = 0;
+        unsafe {
+            assert_eq!(strap_get_column(handle, name.as_ptr(), &mut values, &mut len), -1);
+            assert_eq!(last_error(), "unknown column z");
+            assert!(values.is_null());
+            assert_eq!(strap_column_name(handle, 5, ptr::null_mut(), 0), -1);
+            assert!(last_error().contains("out of range"));
+            strap_close(handle);
+        }
+    }
+
+    #[test]
+    fn test_ffi_to_parquet() {
+        let (_file, handle) = open("x 1.0\n# comment\nx 2.0\nx 3.0\n");
+        let dir = tempfile::tempdir().unwrap();
+        let out = dir.path().join("out.parquet");
+        let path = CString::new(out.to_str().unwrap()).unwrap();
+        unsafe {
+            assert_eq!(strap_to_parquet(handle, path.as_ptr(), 0), -1);
+            assert_eq!(strap_to_parquet(handle, path.as_ptr(), 2), 0);
+            assert_eq!(strap_row_count(handle), 3);
+            strap_close(handle);
+        }
+        let reader = parquet::file::reader::SerializedFileReader::new(std::fs::File::open(out).unwrap()).unwrap();
+        use parquet::file::reader::FileReader;
+        assert_eq!(reader.metadata().file_metadata().num_rows(), 3);
+    }
+}
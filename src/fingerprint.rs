@@ -0,0 +1,175 @@
+//! Content fingerprints of STRAP files, stored in the parquet footer to skip unchanged conversions,
+//! and the other provenance entries stored next to them
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::hash::Hasher;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+
+use parquet::file::reader::{FileReader, SerializedFileReader};
+use twox_hash::XxHash64;
+
+/// Key of the fingerprint in the parquet key-value metadata
+pub const FINGERPRINT_KEY: &str = "strap.fingerprint";
+
+/// Keys of the source file name, size in bytes and modification time in seconds since
+/// the Unix epoch, the rows and skipped lines written and the crate version
+pub const SOURCE_KEY: &str = "strap.source";
+pub const SOURCE_SIZE_KEY: &str = "strap.source_size";
+pub const SOURCE_MTIME_KEY: &str = "strap.source_mtime";
+pub const ROWS_KEY: &str = "strap.rows";
+pub const SKIPPED_KEY: &str = "strap.skipped_lines";
+pub const VERSION_KEY: &str = "strap.version";
+
+/// Key of the Arrow schema arrow-rs writes into the key-value metadata
+const ARROW_SCHEMA_KEY: &str = "ARROW:schema";
+
+/// Bytes hashed at the start and at the end of a file by `fingerprint`
+const SAMPLE_SIZE: u64 = 1 << 20;
+
+const SAMPLED: &str = "xxh64-sampled";
+const FULL: &str = "xxh64-full";
+
+fn hash(reader: &mut impl Read, hasher: &mut XxHash64) -> std::io::Result<()> {
+    let mut buf = vec![0u8; 64 * 1024];
+    loop {
+        let n = reader.read(&mut buf)?;
+        if n == 0 {
+            return Ok(());
+        }
+        hasher.write(&buf[..n]);
+    }
+}
+
+/// Size and hash of the first and last MiB, cheap even for large files
+pub fn fingerprint(path: &Path) -> std::io::Result<String> {
+    let mut file = File::open(path)?;
+    let size = file.metadata()?.len();
+    let mut hasher = XxHash64::with_seed(0);
+    if size <= 2 * SAMPLE_SIZE {
+        hash(&mut file, &mut hasher)?;
+    } else {
+        hash(&mut (&mut file).take(SAMPLE_SIZE), &mut hasher)?;
+        file.seek(SeekFrom::End(-(SAMPLE_SIZE as i64)))?;
+        hash(&mut file, &mut hasher)?;
+    }
+    Ok(format!("{}:{}:{:016x}", SAMPLED, size, hasher.finish()))
+}
+
+/// Size and hash of the whole file
+pub fn fingerprint_full(path: &Path) -> std::io::Result<String> {
+    let mut file = File::open(path)?;
+    let size = file.metadata()?.len();
+    let mut hasher = XxHash64::with_seed(0);
+    hash(&mut file, &mut hasher)?;
+    Ok(format!("{}:{}:{:016x}", FULL, size, hasher.finish()))
+}
+
+/// Fingerprint stored in a parquet file written by `to_parquet`
+pub fn stored_fingerprint(parquet: &Path) -> Option<String> {
+    let reader = SerializedFileReader::new(File::open(parquet).ok()?).ok()?;
+    reader.metadata().file_metadata().key_value_metadata()?
+        .iter()
+        .find(|kv| kv.key == FINGERPRINT_KEY)?
+        .value.clone()
+}
+
+/// Key-value metadata of a parquet file, e.g. the provenance `to_parquet` writes and
+/// `ParquetOptions::metadata`, without the Arrow schema
+pub fn read_strap_metadata(parquet: &Path) -> Result<BTreeMap<String, String>, crate::StrapError> {
+    let reader = SerializedFileReader::new(File::open(parquet)?)?;
+    Ok(reader.metadata().file_metadata().key_value_metadata()
+        .into_iter()
+        .flatten()
+        .filter(|kv| kv.key != ARROW_SCHEMA_KEY)
+        .map(|kv| (kv.key.clone(), kv.value.clone().unwrap_or_default()))
+        .collect())
+}
+
+/// Whether `parquet` was converted from the current content of `source`,
+/// the fingerprint is recomputed the same way it was stored
+pub fn is_up_to_date(source: &Path, parquet: &Path) -> bool {
+    let Some(stored) = stored_fingerprint(parquet) else {
+        return false;
+    };
+    let current = if stored.starts_with(FULL) {
+        fingerprint_full(source)
+    } else {
+        fingerprint(source)
+    };
+    current.is_ok_and(|current| current == stored)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ParquetOptions, StrapTrack};
+
+    /// Convert unless up to date, returns whether it converted
+    fn convert(source: &Path, parquet: &Path, full: bool) -> bool {
+        if is_up_to_date(source, parquet) {
+            return false;
+        }
+        let options = ParquetOptions { full_fingerprint: full, ..Default::default() };
+        StrapTrack::new(source).unwrap().to_parquet_with(parquet.to_str().unwrap(), &options).unwrap();
+        true
+    }
+
+    #[test]
+    fn test_fingerprint_reuse() {
+        for full in [false, true] {
+            let dir = tempfile::tempdir().unwrap();
+            let source = dir.path().join("run.strap");
+            let parquet = dir.path().join("run.strap.parquet");
+            std::fs::write(&source, "x 1.0\nx 2.0\n").unwrap();
+            assert!(convert(&source, &parquet, full));
+            assert!(stored_fingerprint(&parquet).unwrap().starts_with(if full { FULL } else { SAMPLED }));
+
+            // rewriting the same content changes the mtime but not the fingerprint
+            std::fs::write(&source, "x 1.0\nx 2.0\n").unwrap();
+            assert!(!convert(&source, &parquet, full));
+
+            std::fs::write(&source, "x 1.0\nx 3.0\n").unwrap();
+            assert!(convert(&source, &parquet, full));
+            assert!(!convert(&source, &parquet, full));
+        }
+    }
+
+    #[test]
+    fn test_read_strap_metadata() {
+        let dir = tempfile::tempdir().unwrap();
+        let source = dir.path().join("run.strap");
+        let parquet = dir.path().join("run.strap.parquet");
+        std::fs::write(&source, "x 1.0\n# comment\nx 2.0\n").unwrap();
+        let options = ParquetOptions { metadata: vec![("detector".to_string(), "ALPHA".to_string())], ..Default::default() };
+        StrapTrack::new(&source).unwrap().to_parquet_with(parquet.to_str().unwrap(), &options).unwrap();
+        let metadata = read_strap_metadata(&parquet).unwrap();
+        assert_eq!(metadata[SOURCE_KEY], "run.strap");
+        assert_eq!(metadata[SOURCE_SIZE_KEY], "22");
+        assert!(metadata[SOURCE_MTIME_KEY].parse::<u64>().unwrap() > 0);
+        assert_eq!((metadata[ROWS_KEY].as_str(), metadata[SKIPPED_KEY].as_str()), ("2", "1"));
+        assert_eq!(metadata[VERSION_KEY], env!("CARGO_PKG_VERSION"));
+        assert_eq!(metadata["detector"], "ALPHA");
+        assert!(metadata[FINGERPRINT_KEY].starts_with(SAMPLED));
+        assert!(!metadata.contains_key(ARROW_SCHEMA_KEY));
+    }
+
+    #[test]
+    fn test_fingerprint_samples() {
+        let dir = tempfile::tempdir().unwrap();
+        let source = dir.path().join("big.strap");
+        let mut content = vec![b'\n'; 3 * SAMPLE_SIZE as usize];
+        std::fs::write(&source, &content).unwrap();
+        let before = fingerprint(&source).unwrap();
+        // a change in the middle is only seen by the full hash
+        content[SAMPLE_SIZE as usize + 10] = b' ';
+        let full_before = fingerprint_full(&source).unwrap();
+        std::fs::write(&source, &content).unwrap();
+        assert_eq!(fingerprint(&source).unwrap(), before);
+        assert_ne!(fingerprint_full(&source).unwrap(), full_before);
+        // a change in the last MiB is seen by both
+        *content.last_mut().unwrap() = b' ';
+        std::fs::write(&source, &content).unwrap();
+        assert_ne!(fingerprint(&source).unwrap(), before);
+    }
+}
@@ -0,0 +1,382 @@
+//! Following a growing STRAP file and converting it to parquet while it is written
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use arrow::array::{ArrayRef, UInt64Array};
+use arrow::datatypes::{DataType, Field, Schema};
+use arrow::record_batch::RecordBatch;
+use parquet::arrow::ArrowWriter;
+use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+use parquet::file::properties::WriterProperties;
+use serde::{Deserialize, Serialize};
+
+use crate::{RowChunk, SpillChunk, StrapColumns, StrapTrack, StrapValue, STRAP_PREFIX};
+
+/// Reads the rows appended to an uncompressed STRAP file since the last poll
+#[derive(Debug)]
+pub struct Follower {
+    path: PathBuf,
+    all: bool,
+    offset: u64,
+    #[cfg(unix)]
+    inode: Option<u64>,
+}
+
+impl Follower {
+    pub fn new(path: impl Into<PathBuf>) -> std::io::Result<Self> {
+        let path = path.into();
+        File::open(&path)?;
+        Ok(Self {
+            all: StrapTrack::is_strap_file(&path),
+            path,
+            offset: 0,
+            #[cfg(unix)]
+            inode: None,
+        })
+    }
+
+    /// Byte offset up to which the input has been parsed
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    /// Start over if the input was truncated or replaced by a new file
+    fn detect_rotation(&mut self, metadata: &std::fs::Metadata) -> bool {
+        let mut rotated = metadata.len() < self.offset;
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::MetadataExt;
+            rotated |= self.inode.is_some_and(|inode| inode != metadata.ino());
+            self.inode = Some(metadata.ino());
+        }
+        if rotated {
+            self.offset = 0;
+        }
+        rotated
+    }
+
+    /// Rows with numbers of all complete lines written since the last poll,
+    /// a partially written last line is left for the next poll
+    pub fn poll(&mut self) -> std::io::Result<Vec<HashMap<String, f64>>> {
+        Ok(self.poll_values()?.into_iter().map(StrapTrack::numeric).filter(|row| !row.is_empty()).collect())
+    }
+
+    /// Like `poll` with text and list values, the rows `LiveParquet::append` takes
+    pub fn poll_values(&mut self) -> std::io::Result<Vec<HashMap<String, StrapValue>>> {
+        Ok(self.read_new()?.1)
+    }
+
+    /// Append the rows written since the last call to `columns` and return their
+    /// number, `columns` is emptied first if the input was truncated or rotated.
+    /// A row with only text is all None, like in `StrapTrack::columns`.
+    pub fn refresh(&mut self, columns: &mut StrapColumns) -> std::io::Result<usize> {
+        let (rotated, rows) = self.read_new()?;
+        if rotated {
+            *columns = StrapColumns::default();
+        }
+        let count = rows.len();
+        for row in rows {
+            columns.push(&StrapTrack::numeric(row));
+        }
+        Ok(count)
+    }
+
+    /// Whether the input was rotated and the new rows with any value, see `poll`
+    fn read_new(&mut self) -> std::io::Result<(bool, Vec<HashMap<String, StrapValue>>)> {
+        let mut file = File::open(&self.path)?;
+        let rotated = self.detect_rotation(&file.metadata()?);
+        file.seek(SeekFrom::Start(self.offset))?;
+        let mut reader = BufReader::new(file);
+        let mut rows = vec![];
+        loop {
+            let mut line = String::new();
+            let n = reader.read_line(&mut line)?;
+            if n == 0 || !line.ends_with('\n') {
+                break;
+            }
+            self.offset += n as u64;
+            let row = StrapTrack::parse_values(&line, self.all, &[STRAP_PREFIX]);
+            if !row.is_empty() {
+                rows.push(row);
+            }
+        }
+        Ok((rotated, rows))
+    }
+}
+
+/// Sidecar `<output>.meta.json` next to a live parquet file, cheap to poll
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LiveMeta {
+    pub rows: usize,
+    /// Byte offset in the input up to which rows are written
+    pub offset: u64,
+    /// Seconds since the unix epoch
+    pub updated: f64,
+}
+
+impl LiveMeta {
+    pub fn path(output: &Path) -> PathBuf {
+        let mut path = output.as_os_str().to_owned();
+        path.push(".meta.json");
+        PathBuf::from(path)
+    }
+
+    pub fn read(output: &Path) -> Result<Self, Box<dyn std::error::Error>> {
+        Ok(serde_json::from_str(&std::fs::read_to_string(Self::path(output))?)?)
+    }
+
+    fn write(&self, output: &Path) -> Result<(), Box<dyn std::error::Error>> {
+        let path = Self::path(output);
+        let tmp = tmp_path(&path);
+        std::fs::write(&tmp, serde_json::to_string(self)?)?;
+        std::fs::rename(tmp, path)?;
+        Ok(())
+    }
+}
+
+fn tmp_path(path: &Path) -> PathBuf {
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    PathBuf::from(tmp)
+}
+
+/// Parquet output that grows with every append.
+///
+/// Parquet can't be appended to, so every append rewrites the whole file: the rows
+/// written so far are read back and written again with the new ones into a file next
+/// to the output, which is renamed into place. Readers never see a file without
+/// footer, even if the process is killed, but an append takes time in the number of
+/// rows so far, quadratic over a long run; flush in large batches.
+///
+/// Columns are Float64, Utf8 if any value is text, otherwise a list of Float64 if
+/// any value is a list, like `StrapTrack::to_parquet` writes them. A column that
+/// turns to text or lists has its earlier values rewritten that way.
+#[derive(Debug)]
+pub struct LiveParquet {
+    output: PathBuf,
+    // sorted column names and their types
+    types: BTreeMap<String, DataType>,
+    rows: usize,
+    written: bool,
+}
+
+impl LiveParquet {
+    pub fn new(output: impl Into<PathBuf>) -> Self {
+        Self {
+            output: output.into(),
+            types: BTreeMap::new(),
+            rows: 0,
+            written: false,
+        }
+    }
+
+    pub fn rows(&self) -> usize {
+        self.rows
+    }
+
+    /// Rewrite the output with `rows` after the rows written so far, columns seen for
+    /// the first time are null in earlier rows
+    pub fn append(&mut self, rows: &[HashMap<String, StrapValue>], offset: u64) -> Result<(), Box<dyn std::error::Error>> {
+        let mut types = self.types.clone();
+        for (key, value) in rows.iter().flatten() {
+            let data_type = types.entry(key.clone()).or_insert(DataType::Float64);
+            match value {
+                StrapValue::Text(_) => *data_type = DataType::Utf8,
+                StrapValue::List(_) if *data_type != DataType::Utf8 => *data_type = DataType::new_list(DataType::Float64, true),
+                _ => {}
+            }
+        }
+        let column_names: Vec<String> = types.keys().cloned().collect();
+        let schema = StrapTrack::column_schema(&column_names, &types.clone().into_iter().collect());
+        let index = StrapTrack::column_index(&schema);
+
+        let tmp = tmp_path(&self.output);
+        let props = WriterProperties::builder().build();
+        let mut writer = ArrowWriter::try_new(File::create(&tmp)?, schema.clone(), Some(props))?;
+        if self.written {
+            let reader = ParquetRecordBatchReaderBuilder::try_new(File::open(&self.output)?)?.build()?;
+            for batch in reader {
+                let batch = batch?;
+                if batch.schema().fields() == schema.fields() {
+                    writer.write(&batch)?;
+                    continue;
+                }
+                // new columns are null, widened ones are rewritten from their values
+                let lines = UInt64Array::from_iter_values(1..=batch.num_rows() as u64);
+                let fields: Vec<Arc<Field>> = std::iter::once(Arc::new(Field::new("line", DataType::UInt64, false)))
+                    .chain(batch.schema().fields().iter().cloned())
+                    .collect();
+                let columns = std::iter::once(Arc::new(lines) as ArrayRef).chain(batch.columns().iter().cloned()).collect();
+                let batch = RecordBatch::try_new(Arc::new(Schema::new(fields)), columns)?;
+                let chunk = SpillChunk::from_record_batch(&batch, &schema, &index)?;
+                writer.write(&StrapTrack::record_batch(&schema, &chunk)?)?;
+            }
+            writer.flush()?;
+        }
+        if !rows.is_empty() {
+            let mut chunk = RowChunk::new(schema.fields());
+            for (i, row) in rows.iter().enumerate() {
+                chunk.push(self.rows + i + 1, row.clone(), &index, schema.fields())?;
+            }
+            writer.write(&StrapTrack::record_batch(&schema, &chunk)?)?;
+        }
+        writer.close()?;
+        std::fs::rename(&tmp, &self.output)?;
+
+        self.types = types;
+        self.rows += rows.len();
+        self.written = true;
+        LiveMeta {
+            rows: self.rows,
+            offset,
+            updated: SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs_f64(),
+        }.write(&self.output)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    fn readable_rows(path: &Path) -> usize {
+        ParquetRecordBatchReaderBuilder::try_new(File::open(path).unwrap()).unwrap()
+            .metadata().file_metadata().num_rows() as usize
+    }
+
+    #[test]
+    fn test_follow_partial_line() {
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("current.strap");
+        std::fs::write(&input, "x 1.0\nx 2").unwrap();
+        let mut follower = Follower::new(&input).unwrap();
+        assert_eq!(follower.poll().unwrap().len(), 1);
+        assert_eq!(follower.offset(), 6);
+
+        let mut file = std::fs::OpenOptions::new().append(true).open(&input).unwrap();
+        file.write_all(b".5\n\nx 3.0\n").unwrap();
+        let rows = follower.poll().unwrap();
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[0]["x"], 2.5);
+    }
+
+    #[test]
+    fn test_follow_rotation() {
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("current.strap");
+        std::fs::write(&input, "x 1.0\nx 2.0\n").unwrap();
+        let mut follower = Follower::new(&input).unwrap();
+        assert_eq!(follower.poll().unwrap().len(), 2);
+
+        std::fs::write(&input, "y 3.0\n").unwrap();
+        let rows = follower.poll().unwrap();
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0]["y"], 3.0);
+    }
+
+    #[test]
+    fn test_refresh_columns() {
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("current.strap");
+        std::fs::write(&input, "x 1.0\nx 2.0\n").unwrap();
+        let mut follower = Follower::new(&input).unwrap();
+        let mut columns = StrapColumns::default();
+        assert_eq!(follower.refresh(&mut columns).unwrap(), 2);
+        assert_eq!(follower.refresh(&mut columns).unwrap(), 0);
+
+        let mut file = std::fs::OpenOptions::new().append(true).open(&input).unwrap();
+        file.write_all(b"x 3.0 y 1.0\n").unwrap();
+        assert_eq!(follower.refresh(&mut columns).unwrap(), 1);
+        assert_eq!(columns.get_column("x").unwrap(), [Some(1.0), Some(2.0), Some(3.0)]);
+        assert_eq!(columns.get_column("y").unwrap(), [None, None, Some(1.0)]);
+
+        std::fs::write(&input, "z 4.0\n").unwrap();
+        assert_eq!(follower.refresh(&mut columns).unwrap(), 1);
+        assert_eq!(columns.len(), 1);
+        assert_eq!(columns.column_names(), ["z"]);
+    }
+
+    #[test]
+    fn test_live_parquet_grows() {
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("current.strap");
+        let output = dir.path().join("live.parquet");
+        std::fs::write(&input, "").unwrap();
+
+        let path = input.clone();
+        let appender = std::thread::spawn(move || {
+            let mut file = std::fs::OpenOptions::new().append(true).open(path).unwrap();
+            for i in 0..20 {
+                // a new column halfway through
+                let line = if i < 10 { format!("x {}\n", i) } else { format!("x {} y {}\n", i, -i) };
+                file.write_all(line.as_bytes()).unwrap();
+                file.flush().unwrap();
+                std::thread::sleep(std::time::Duration::from_millis(5));
+            }
+        });
+
+        let mut follower = Follower::new(&input).unwrap();
+        let mut live = LiveParquet::new(&output);
+        let mut counts = vec![];
+        loop {
+            let done = appender.is_finished();
+            let rows = follower.poll_values().unwrap();
+            if !rows.is_empty() {
+                live.append(&rows, follower.offset()).unwrap();
+                counts.push(readable_rows(&output));
+                assert_eq!(LiveMeta::read(&output).unwrap().rows, *counts.last().unwrap());
+            }
+            if done {
+                break;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(2));
+        }
+        appender.join().unwrap();
+
+        assert!(counts.windows(2).all(|w| w[0] < w[1]));
+        assert_eq!(*counts.last().unwrap(), 20);
+        let meta = LiveMeta::read(&output).unwrap();
+        assert_eq!(meta.offset, std::fs::metadata(&input).unwrap().len());
+
+        let batches = ParquetRecordBatchReaderBuilder::try_new(File::open(&output).unwrap()).unwrap()
+            .build().unwrap()
+            .collect::<Result<Vec<_>, _>>().unwrap();
+        let nulls: usize = batches.iter().map(|b| b.column_by_name("y").unwrap().null_count()).sum();
+        assert_eq!(nulls, 10);
+    }
+
+    #[test]
+    fn test_live_parquet_text_columns() {
+        use arrow::array::AsArray;
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("current.strap");
+        let output = dir.path().join("live.parquet");
+        std::fs::write(&input, "x 1 run 17\nrun A17\n").unwrap();
+        let mut follower = Follower::new(&input).unwrap();
+        let mut live = LiveParquet::new(&output);
+        let rows = follower.poll_values().unwrap();
+        assert_eq!(rows.len(), 2);
+        live.append(&rows, follower.offset()).unwrap();
+
+        // `run` was numeric in the first line only, now it has text and the new column lists
+        let mut file = std::fs::OpenOptions::new().append(true).open(&input).unwrap();
+        file.write_all(b"x 2 run B3 weights 1,2\n").unwrap();
+        live.append(&follower.poll_values().unwrap(), follower.offset()).unwrap();
+
+        let batches = ParquetRecordBatchReaderBuilder::try_new(File::open(&output).unwrap()).unwrap()
+            .build().unwrap()
+            .collect::<Result<Vec<_>, _>>().unwrap();
+        let batch = arrow::compute::concat_batches(&batches[0].schema(), &batches).unwrap();
+        assert_eq!(batch.num_rows(), 3);
+        let run: Vec<_> = batch.column_by_name("run").unwrap().as_string::<i32>().iter().collect();
+        assert_eq!(run, [Some("17"), Some("A17"), Some("B3")]);
+        assert_eq!(batch.column_by_name("x").unwrap().null_count(), 1);
+        assert_eq!(batch.column_by_name("weights").unwrap().null_count(), 2);
+        // the text row is no numeric row
+        assert_eq!(Follower::new(&input).unwrap().poll().unwrap().len(), 2);
+    }
+}
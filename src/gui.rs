@@ -1,242 +1,1509 @@
-use core::{hash, panic};
-use std::{collections::HashMap, fmt::{self}, ops::Deref};
-use std::fmt::{Display, Formatter, Result as FmtResult};
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
-use duckdb::{Connection, params};
 use eframe::egui;
-use egui::RichText;
-use egui_plot::{Bar, BarChart, Legend, Plot};
+use itertools::Itertools;
+use egui::{Align2, RichText};
+use egui_plot::{Bar, BarChart, BoxElem, BoxPlot, BoxSpread, GridMark, HLine, Legend, Line, MarkerShape, Plot, PlotPoint, PlotPoints, Points, Polygon, Text};
 use egui_file_dialog::FileDialog;
 use strum::IntoEnumIterator;
 use strum_macros::{Display, EnumIter};
 
-use straptrack::StrapTrack;
+use straptrack::{label_with_unit, ConversionStats, StrapError, StrapTrack};
+use straptrack::fingerprint::is_up_to_date;
+use straptrack::yoda::histogram_to_yoda;
+use straptrack::query::{
+    get_bin_count, get_column_names, get_file_count, get_histogram, get_units, get_null_fraction, histogram_sql_script, FilterPresets, get_stat, get_summary, get_xy_stat, table_to_csv, table_to_markdown, format_value, stats_summary, STATS_SUMMARY_HEADER, BinCountInput, BinRule, Cache, poisson_interval, SeriesInput, get_series, BoxPlotInput, get_box_plot, HistogramRangeInput, get_histogram_range, SERIES_POINT_LIMIT, MAX_AUTO_BINS, ColumnNamesInput, HistogramAggregation, DEFAULT_QUANTILE,
+    FileCountInput, HistogramInput, HistogramOutput, HistogramRange, Normalization, HistogramSubInput, ParsedString, SQLFilter, SQLFilterComparison,
+    SQLFilterComparisonOperation, SQLFilterComparisonValue, Sql, StatInput, StatOutput,
+    NullFractionInput, SummaryInput, SummaryOutput, XYStatInput, XYStatOutput,
+};
+
+/// File format of exported tables
+#[derive(Debug, Clone, Copy, PartialEq, Eq, EnumIter, Display)]
+enum TableFormat {
+    #[strum(to_string = "CSV")]
+    Csv,
+    Markdown,
+}
+
+impl TableFormat {
+    fn extension(&self) -> &'static str {
+        match self {
+            TableFormat::Csv => "csv",
+            TableFormat::Markdown => "md",
+        }
+    }
+}
 
-#[derive(Hash, Eq, PartialEq, Clone)]
-struct SQLFilter {
-    // Each Vec<SQLFilterComparison> is an OR group
-    // All groups must be satisfied (AND between groups)
-    conditions  : Vec<Vec<SQLFilterComparison >>,
+#[derive(Debug, Clone, Copy, PartialEq, Eq, EnumIter, Display)]
+enum Operation {
+    //Aggregate,
+    Histogram,
+    Series,
+    #[strum(to_string = "Box Plot")]
+    BoxPlot,
+}
+
+struct MyApp {
+    operation: Operation,
+    filedialog: FileDialog,
+    // picks a database file when the default connection failed
+    db_dialog: FileDialog,
+    // target of "Export SQL script"
+    script_dialog: FileDialog,
+    // target of "Export YODA…"
+    yoda_dialog: FileDialog,
+    // target of "Export stats summary" and its format
+    stats_dialog: FileDialog,
+    stats_format: TableFormat,
+    // file added to the curve with id add_file_curve by "Add file to curve…"
+    add_file_dialog: FileDialog,
+    add_file_curve: Option<usize>,
+    // row lines lost while converting, per converted parquet file
+    skipped_lines: HashMap<String, usize>,
+    cache : Cache,
+
+    sql : Sql,
+
+
+    histogram_view : HistogramView,
+    series_view : SeriesView,
+    box_plot_view : BoxPlotView,
+    summary_view : Option<SummaryView>,
+    presets : FilterPresets,
+    preset_window : PresetWindow,
+    global_id_counter: usize,
+    // pattern typed next to "Add Glob"
+    glob_input: String,
 }
 
-impl SQLFilter {
-    fn is_empty(&self) -> bool {
-        self.conditions.is_empty() || self.conditions.iter().all(|group| group.is_empty())
+/// State of the filter preset management window and the preset menus
+struct PresetWindow {
+    open : bool,
+    // name typed into "Save filter as preset…"
+    new_name : String,
+    // preset being renamed and its new name
+    rename : Option<(String, String)>,
+    // result of the last apply/import/export, with the curve id it belongs to if any
+    message : Option<(Option<usize>, String)>,
+    export_dialog : FileDialog,
+    import_dialog : FileDialog,
+}
+
+impl Default for PresetWindow {
+    fn default() -> Self {
+        Self {
+            open : false,
+            new_name : String::new(),
+            rename : None,
+            message : None,
+            export_dialog : FileDialog::new(),
+            import_dialog : FileDialog::new(),
+        }
     }
+}
+
+/// Column summary of the table of a curve
+struct SummaryView {
+    table : ParsedString,
+    curve_id : usize,
+    // index into SummaryOutput::HEADER
+    sort_by : usize,
+    descending : bool,
+    // the query runs one frame after opening so the spinner is drawn first
+    spinner_shown : bool,
+    export_dialog : FileDialog,
+    export_error : Option<String>,
+}
+
+struct HistogramView {
+    //bin_scale: HistogramBinScale,
+    plot_settings : HistrogramPlotSettings,
+    // hides the curve controls and lets the plot fill the window
+    maximized: bool,
+    auto_update: bool,
+    update: bool,
+    input : HistogramInput,
+    // chooses input.bins unless Manual
+    bin_rule : BinRule,
+    stat : Option<StatOutput>,
+    // correlation of x and y per curve id
+    xy_stats : HashMap<usize, XYStatOutput>,
+    histogram : Option<HistogramOutput>,
+    // display-only settings per curve id, not part of any cache key
+    displays : HashMap<usize, CurveDisplay>,
+    // name typed into the "Group" menu of a curve
+    new_group_name : String,
+    // snapshots of curves, drawn from their stored bins without any SQL
+    frozen : Vec<FrozenCurve>,
+    // curves following their growing STRAP file, by curve id
+    live : HashMap<usize, LiveCurve>,
+    // seconds between checks of live curves
+    live_interval : f64,
+    // live curves pause after their file did not grow for this many minutes
+    live_pause_minutes : f64,
+    // curves with a filter being edited this frame, not refreshed until done
+    editing : HashSet<usize>,
+    // curve id the ratio panel divides by, None hides the panel
+    ratio_denominator : Option<usize>,
+    // curve ids (a, b) of the pull panel, None hides the panel
+    pull_curves : Option<(usize, usize)>,
+}
+
+/// Lines of y against x for the histogram curves, e.g. values over time
+struct SeriesView {
+    // None draws the raw points, otherwise this many equal x intervals shared by all curves
+    buckets : Option<usize>,
+    // raw points fetched per curve
+    limit : usize,
+}
+
+/// Box-and-whisker summaries of y for the histogram curves
+struct BoxPlotView {
+    // None draws one box per curve, otherwise one per bucket of x out of this many
+    groups : Option<usize>,
+}
+
+/// Reconversion of the STRAP file behind a live curve. Whenever the file grew it is
+/// converted again like `converted_parquet` does, text columns, units and fingerprint
+/// included, on a background thread into a file next to the table, which replaces
+/// the table once done.
+struct LiveCurve {
+    source : PathBuf,
+    output : String,
+    // source size covered by the table or the running conversion
+    size : u64,
+    converting : Option<JoinHandle<Result<ConversionStats, String>>>,
+    last_poll : Instant,
+    last_growth : Instant,
+    last_updated : Option<SystemTime>,
+    paused : bool,
+}
 
-    fn to_sql(&self) -> String {
-        self.conditions.iter()
-        .filter(|group| !group.is_empty())
-        .map(|group| {
-            "(".to_string()
-            + group.iter().map(|c| c.to_sql()).collect::<Vec<_>>().join(" OR ").as_str()
-            + ")"
-        }).collect::<Vec<_>>().join(" AND ")
+impl LiveCurve {
+    /// `output` is the up-to-date conversion of `source`
+    fn new(source: PathBuf, output: &str) -> std::io::Result<Self> {
+        Ok(Self {
+            size: std::fs::metadata(&source)?.len(),
+            source,
+            output: output.to_string(),
+            converting: None,
+            last_poll: Instant::now(),
+            last_growth: Instant::now(),
+            last_updated: None,
+            paused: false,
+        })
     }
 
-    fn to_sql_and_prefix(&self) -> String {
-        let mut query = String::new();
-        if !self.is_empty() {
-            query.push_str(" AND ");
-            query.push_str(self.to_sql().as_str());
+    /// Start a conversion if the file grew, the stats of one that finished since the last refresh
+    fn refresh(&mut self) -> Result<Option<ConversionStats>, String> {
+        if let Some(converting) = self.converting.take_if(|converting| converting.is_finished()) {
+            let stats = converting.join().map_err(|_| "conversion thread panicked".to_string())??;
+            self.last_updated = Some(SystemTime::now());
+            return Ok(Some(stats));
         }
-        query
+        let size = std::fs::metadata(&self.source).map_err(|e| e.to_string())?.len();
+        if self.converting.is_some() || size == self.size {
+            return Ok(None);
+        }
+        self.size = size;
+        self.last_growth = Instant::now();
+        let (source, output) = (self.source.clone(), self.output.clone());
+        self.converting = Some(std::thread::spawn(move || {
+            let tmp = format!("{}.live", output);
+            let stats = StrapTrack::stream_to_parquet(&source, &tmp, 1000).map_err(|e| e.to_string())?;
+            std::fs::rename(&tmp, &output).map_err(|e| e.to_string())?;
+            Ok(stats)
+        }));
+        Ok(None)
     }
+}
+
+/// STRAP file a single-file table was converted from
+fn live_source(table: &ParsedString) -> Option<PathBuf> {
+    let files = table.files();
+    let [file] = files.as_slice() else {
+        return None;
+    };
+    let source = PathBuf::from(file.strip_suffix(".parquet")?);
+    source.is_file().then_some(source)
+}
+
+/// Draw-only copy of a curve as it was before a reload
+#[derive(Clone, serde::Serialize, serde::Deserialize)]
+struct FrozenCurve {
+    id: usize,
+    label: String,
+    // (bin_center, bin_width, value, error)
+    data: Vec<(f64, f64, f64, f64)>,
+    visible: bool,
+    band: bool,
+}
 
-    fn to_sql_where_prefix(&self) -> String {
-        let mut query = String::new();
-        if !self.is_empty() {
-            query.push_str(" WHERE ");
-            query.push_str(self.to_sql().as_str());
+#[derive(Clone, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+struct CurveDisplay {
+    axis: ValueAxis,
+    // purely organizational, only used by the control panel and the legend
+    group: Option<String>,
+    visible: bool,
+    style: CurveStyle,
+    // line width of the Steps style
+    stroke_width: f32,
+    // shaded error band below the Steps style
+    band: bool,
+    // scaling of the drawn values, the queried ones stay as they are
+    normalization: Normalization,
+    // asymmetric Poisson intervals instead of SQRT(COUNT) for Count curves
+    poisson_errors: bool,
+}
+
+impl Default for CurveDisplay {
+    fn default() -> Self {
+        Self {
+            axis: ValueAxis::Left,
+            group: None,
+            visible: true,
+            style: CurveStyle::Bars,
+            stroke_width: 1.5,
+            band: false,
+            normalization: Normalization::None,
+            poisson_errors: false,
         }
-        query
     }
 }
 
-#[derive(Hash, Eq, PartialEq, Clone)]
-struct SQLFilterComparison {
-    left: SQLFilterComparisonValue,
-    comparison: SQLFilterComparisonOperation,
-    right: SQLFilterComparisonValue,
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Display, EnumIter, serde::Serialize, serde::Deserialize)]
+enum CurveStyle {
+    // drawn according to the display mode of the plot
+    #[default]
+    Bars,
+    Steps,
+    Points,
 }
 
-#[derive(Hash, Eq, PartialEq, Clone)]
-enum SQLFilterComparisonValue {
-    Column(ParsedString),
-    Number(String),
+/// Structural edits of the curve list, collected while drawing the controls and applied afterwards
+enum CurveAction {
+    Clone(usize),
+    Remove(usize),
+    MoveToGroup(usize, Option<String>),
+    SetGroupVisible(String, bool),
+    RemoveGroup(String),
+    /// copy the filter of the given curve id to all members of the group
+    ApplyFilterToGroup(String, usize),
+    /// freeze the current bins of the curve, then query its file again
+    ReloadAsNew(usize),
+    RemoveFrozen(usize),
+    /// drop a file from a curve reading several files
+    RemoveFile(usize, String),
 }
 
-impl fmt::Display for SQLFilterComparisonValue {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match self {
-            Self::Column(col) => write!(f, "{}", col),
-            Self::Number(num) => write!(f, "{}", num),
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Display, EnumIter, serde::Serialize, serde::Deserialize)]
+enum ValueAxis {
+    #[default]
+    Left,
+    Right,
+}
+
+/// Affine map of right-axis values into the left-axis range: left = offset + scale * right
+#[derive(Clone, Copy)]
+struct AxisMapping {
+    offset: f64,
+    scale: f64,
+}
+
+impl AxisMapping {
+    /// Map the value range [right_min, right_max] onto [left_min, left_max]
+    fn new((left_min, left_max): (f64, f64), (right_min, right_max): (f64, f64)) -> Self {
+        let left_span = if left_max > left_min { left_max - left_min } else { 1.0 };
+        let right_span = if right_max > right_min { right_max - right_min } else { 1.0 };
+        let scale = left_span / right_span;
+        Self {
+            offset: left_min - scale * right_min,
+            scale,
         }
     }
-}
 
-impl SQLFilterComparison {
-    fn to_sql(&self) -> String {
-        format!("{} {} {}", self.left, self.comparison, self.right)
+    fn to_left(self, value: f64) -> f64 {
+        self.offset + self.scale * value
+    }
+
+    fn to_right(self, value: f64) -> f64 {
+        (value - self.offset) / self.scale
     }
 }
 
-#[derive(Hash, Eq, PartialEq, Clone, EnumIter)]
-enum SQLFilterComparisonOperation {
-    Equal,
-    NotEqual,
-    GreaterThan,
-    LessThan,
-    GreaterThanOrEqual,
-    LessThanOrEqual,
+#[derive(serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+struct HistrogramPlotSettings {
+    // scale of the bin axis, drawn along y for horizontal bars
+    x_axis_scale: HistogramAxisScale,
+    //y_axis_scale: HistogramAxisScale,
+    height: f32,
+    orientation: HistogramOrientation,
+    // prefix legend names with the group of the curve
+    group_legend: bool,
+    // warn below the plot if a selected key has more NULL rows than this fraction
+    null_warning_threshold: f64,
+    display_mode: DisplayMode,
+    stacking: BarStacking,
 }
 
-impl fmt::Display for SQLFilterComparisonOperation {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        let s = match self {
-            Self::Equal => "=",
-            Self::NotEqual => "!=",
-            Self::GreaterThan => ">",
-            Self::LessThan => "<",
-            Self::GreaterThanOrEqual => ">=",
-            Self::LessThanOrEqual => "<=",
-        };
-        write!(f, "{s}")
+impl Default for HistrogramPlotSettings {
+    fn default() -> Self {
+        Self {
+            x_axis_scale: HistogramAxisScale::Linear,
+            height: 400.0,
+            orientation: HistogramOrientation::Vertical,
+            group_legend: false,
+            null_warning_threshold: 0.5,
+            display_mode: DisplayMode::Bars,
+            stacking: BarStacking::Overlay,
+        }
     }
 }
 
+/// How the values and their uncertainties are drawn
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Display, EnumIter, serde::Serialize, serde::Deserialize)]
+enum DisplayMode {
+    Bars,
+    Band,
+    #[strum(to_string = "Line + Band")]
+    LineBand,
+}
 
-#[derive(Debug, Clone, PartialEq, Eq, Hash)]
-pub struct ParsedString(String);
+/// Display scale of an axis, the bins themselves are always linear
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Display, EnumIter, serde::Serialize, serde::Deserialize)]
+enum HistogramAxisScale {
+    Linear,
+    Log,
+}
 
-impl Deref for ParsedString {
-    type Target = str;
+impl HistogramAxisScale {
+    /// Center and width on this scale of the bin at `x` of width `w`, None for a
+    /// bin reaching zero or below on the log scale
+    fn bin(self, x: f64, w: f64) -> Option<(f64, f64)> {
+        match self {
+            Self::Linear => Some((x, w)),
+            Self::Log if x - w / 2. > 0. => {
+                let (low, high) = ((x - w / 2.).log10(), (x + w / 2.).log10());
+                Some(((low + high) / 2., high - low))
+            }
+            Self::Log => None,
+        }
+    }
 
-    fn deref(&self) -> &Self::Target {
-        &self.0
+    /// Value at the position `position` on this scale
+    fn value(self, position: f64) -> f64 {
+        match self {
+            Self::Linear => position,
+            Self::Log => 10f64.powf(position),
+        }
     }
 }
-impl Display for ParsedString {
-    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
-        write!(f, "{}", self.0)
+
+/// Whether the bars of several curves are drawn over or on top of each other
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Display, EnumIter, serde::Serialize, serde::Deserialize)]
+enum BarStacking {
+    Overlay,
+    Stacked,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Display, EnumIter, serde::Serialize, serde::Deserialize)]
+enum HistogramOrientation {
+    Vertical,
+    Horizontal,
+}
+
+const PLOT_SETTINGS_KEY: &str = "histogram_plot_settings";
+const SESSION_KEY: &str = "histogram_session";
+// stored in the same JSON format as exported preset files
+const FILTER_PRESETS_KEY: &str = "filter_presets";
+
+/// Curves and their display settings, restored on the next start
+#[derive(serde::Serialize, serde::Deserialize)]
+struct Session {
+    input: HistogramInput,
+    displays: HashMap<usize, CurveDisplay>,
+    #[serde(default)]
+    frozen: Vec<FrozenCurve>,
+    next_id: usize,
+}
+
+impl MyApp {
+    fn new(cc: &eframe::CreationContext<'_>) -> Self {
+        let mut app = Self::default();
+        if let Some(storage) = cc.storage {
+            if let Some(plot_settings) = eframe::get_value(storage, PLOT_SETTINGS_KEY) {
+                app.histogram_view.plot_settings = plot_settings;
+            }
+            if let Some(session) = eframe::get_value::<Session>(storage, SESSION_KEY) {
+                app.restore_session(session);
+            }
+            if let Some(presets) = storage.get_string(FILTER_PRESETS_KEY)
+                .and_then(|json| FilterPresets::from_json(&json).ok())
+            {
+                app.presets = presets;
+            }
+        }
+        app
+    }
+
+    fn restore_session(&mut self, mut session: Session) {
+        // stored filters are untrusted SQL fragments
+        session.input.curves.retain(|c| c.filter.validate().is_ok());
+        session.displays.retain(|id, _| session.input.curves.iter().any(|c| c.id == *id));
+        self.global_id_counter = session.input.curves.iter()
+            .map(|c| c.id)
+            .chain(session.frozen.iter().map(|f| f.id))
+            .fold(session.next_id, usize::max);
+        self.histogram_view.input = session.input;
+        self.histogram_view.displays = session.displays;
+        self.histogram_view.frozen = session.frozen;
     }
 }
-impl ParsedString {
-    fn parse(name: &str) -> duckdb::Result<ParsedString> {
-        // Allow only letters, numbers, slash, double dot and underscores
-        if name.is_empty() {
-            return Err(duckdb::Error::InvalidParameterName("Identifier cannot be empty".to_owned()));
+
+
+impl MyApp {
+    /// Full-screen panel shown while no database connection is available
+    fn show_connection_error(&mut self, ctx: &egui::Context) {
+        egui::CentralPanel::default().show(ctx, |ui| {
+            ui.heading("STRAP GUI");
+            ui.separator();
+            ui.colored_label(egui::Color32::RED, "Could not open the DuckDB database.");
+            let location = match &self.sql.db_path {
+                Some(path) => path.to_string_lossy().to_string(),
+                None => "in memory".to_string(),
+            };
+            ui.label(format!("Location: {}", location));
+            if let Some(error) = &self.sql.connection_error {
+                ui.code(error);
+            }
+            ui.horizontal(|ui| {
+                if ui.button("Retry").clicked() {
+                    self.sql.connect();
+                }
+                if ui.button("Use database file…").clicked() {
+                    self.db_dialog.save_file();
+                }
+                if self.sql.db_path.is_some() && ui.button("Use in-memory database").clicked() {
+                    self.sql.db_path = None;
+                    self.sql.connect();
+                }
+            });
+        });
+
+        self.db_dialog.update(ctx);
+        if let Some(path) = self.db_dialog.selected() {
+            self.sql.db_path = Some(path.to_path_buf());
+            self.db_dialog = FileDialog::new();
+            self.sql.connect();
         }
+    }
+}
+
+impl MyApp {
+    /// Controls of the curve at `index`, structural changes are deferred into `actions`
+    fn curve_controls(&mut self, ui: &mut egui::Ui, index: usize, groups: &[String], actions: &mut Vec<CurveAction>) {
+        let curve = &mut self.histogram_view.input.curves[index];
+        ui.vertical(|ui| {
+            ui.horizontal(|ui| {
+                if ui.button("Clone").clicked() {
+                    actions.push(CurveAction::Clone(curve.id));
+                }
+                if ui.button("Remove").clicked() {
+                    actions.push(CurveAction::Remove(curve.id));
+                }
+                if ui.button("Reload as new curve").on_hover_text("Keep the current bins for comparison and query the file again").clicked() {
+                    actions.push(CurveAction::ReloadAsNew(curve.id));
+                }
+                if ui.button("Column summary").clicked() {
+                    self.summary_view = Some(SummaryView {
+                        table: curve.table.clone(),
+                        curve_id: curve.id,
+                        sort_by: 0,
+                        descending: false,
+                        spinner_shown: false,
+                        export_dialog: FileDialog::new(),
+                        export_error: None,
+                    });
+                }
+                let display = self.histogram_view.displays.entry(curve.id).or_default();
+                ui.checkbox(&mut display.visible, "Show");
+                ui.menu_button("Group", |ui| {
+                    for group in groups {
+                        if ui.selectable_label(display.group.as_ref() == Some(group), group).clicked() {
+                            actions.push(CurveAction::MoveToGroup(curve.id, Some(group.clone())));
+                            ui.close_menu();
+                        }
+                    }
+                    ui.separator();
+                    ui.horizontal(|ui| {
+                        ui.text_edit_singleline(&mut self.histogram_view.new_group_name);
+                        if ui.button("New group").clicked() && !self.histogram_view.new_group_name.trim().is_empty() {
+                            actions.push(CurveAction::MoveToGroup(curve.id, Some(self.histogram_view.new_group_name.trim().to_string())));
+                            self.histogram_view.new_group_name.clear();
+                            ui.close_menu();
+                        }
+                    });
+                    if display.group.is_some() && ui.button("Ungroup").clicked() {
+                        actions.push(CurveAction::MoveToGroup(curve.id, None));
+                        ui.close_menu();
+                    }
+                });
+            });
+            let parquet_path = &curve.table;
+            let columns = get_column_names(&mut self.cache, &mut self.sql, ColumnNamesInput { table: parquet_path.clone() }, &format!("column names of {}", parquet_path)).clone();
+            let files = curve.table.files();
+            if files.len() > 1 {
+                // one removable chip per member file
+                ui.horizontal_wrapped(|ui| {
+                    for file in files {
+                        ui.group(|ui| {
+                            ui.label(table_label(&ParsedString::parse_table(file).unwrap_or_else(|_| curve.table.clone())))
+                                .on_hover_text(file);
+                            if ui.small_button("×").on_hover_text("Remove file from curve").clicked() {
+                                actions.push(CurveAction::RemoveFile(curve.id, file.to_string()));
+                            }
+                        });
+                    }
+                });
+            } else {
+                let mut filename = table_label(&curve.table);
+                if let Some(skipped) = curve.table.files().first().and_then(|file| self.skipped_lines.get(*file)) {
+                    filename = format!("{} — {} lines skipped", filename, skipped);
+                }
+                match get_file_count(&mut self.cache, &mut self.sql, &FileCountInput { table: curve.table.clone() }, &format!("files of {}", curve.table.name())).files {
+                    Some(files) => ui.label(format!("{} ({} files)", filename, files)).on_hover_text(curve.table.name()),
+                    None => ui.label(filename),
+                };
+            }
+            if ui.button("Add file to curve…").clicked() {
+                self.add_file_curve = Some(curve.id);
+                self.add_file_dialog.select_file();
+            }
+            if let Some(source) = live_source(&curve.table) {
+                ui.horizontal(|ui| {
+                    let mut live = self.histogram_view.live.contains_key(&curve.id);
+                    if ui.checkbox(&mut live, "Live").on_hover_text("Follow the growing STRAP file").changed() {
+                        if !live {
+                            self.histogram_view.live.remove(&curve.id);
+                        } else {
+                            match LiveCurve::new(source, curve.table.name()) {
+                                Ok(state) => {
+                                    self.histogram_view.live.insert(curve.id, state);
+                                }
+                                Err(e) => self.sql.push_error(&format!("live update of curve {}", curve.id), e.to_string()),
+                            }
+                        }
+                    }
+                    if let Some(state) = self.histogram_view.live.get_mut(&curve.id) {
+                        if let Some(time) = state.last_updated {
+                            ui.label(format!("last updated {}", format_time_of_day(time)));
+                        }
+                        if state.paused {
+                            ui.colored_label(egui::Color32::YELLOW, "paused, file stopped growing");
+                            if ui.small_button("Resume").clicked() {
+                                state.paused = false;
+                                state.last_growth = Instant::now();
+                            }
+                        }
+                    }
+                });
+            }
+
+            egui::ComboBox::new(format!("x_key_{}", curve.id) ,"X Key")
+                .selected_text(curve.x_key.as_str())
+                .show_ui(ui, |ui| {
+                    for name in &columns {
+                        column_entry(ui, &mut self.cache, &mut self.sql, parquet_path, name, &mut curve.x_key);
+                    }
+            });
+
+            egui::ComboBox::new(format!("y_key_{}", curve.id) ,"Y Key")
+                .selected_text(curve.y_key.as_str())
+                .show_ui(ui, |ui| {
+                    for name in &columns {
+                        column_entry(ui, &mut self.cache, &mut self.sql, parquet_path, name, &mut curve.y_key);
+                    }
+            });
+
+            egui::ComboBox::new(format!("type_{}", curve.id),"Type")
+                .selected_text(curve.value_type.to_string())
+                .show_ui(ui, |ui| {
+                    for name in HistogramAggregation::iter() {
+                        // keep the chosen quantile when reselecting it
+                        let name = match (name, curve.value_type) {
+                            (HistogramAggregation::Quantile(_), current @ HistogramAggregation::Quantile(_)) => current,
+                            (HistogramAggregation::Quantile(_), _) => HistogramAggregation::Quantile(DEFAULT_QUANTILE),
+                            (name, _) => name,
+                        };
+                        ui.selectable_value(&mut curve.value_type, name, name.to_string());
+                    }
+            });
+            if let HistogramAggregation::Quantile(per_mille) = &mut curve.value_type {
+                let mut percent = *per_mille as f64 / 10.0;
+                if ui.add(egui::DragValue::new(&mut percent).clamp_range(0.0..=100.0).speed(0.1).prefix("p")).changed() {
+                    *per_mille = (percent * 10.0).round() as u16;
+                }
+            }
+
+            let display = self.histogram_view.displays.entry(curve.id).or_default();
+            egui::ComboBox::new(format!("axis_{}", curve.id),"Axis")
+                .selected_text(display.axis.to_string())
+                .show_ui(ui, |ui| {
+                    for axis in ValueAxis::iter() {
+                        ui.selectable_value(&mut display.axis, axis, axis.to_string());
+                    }
+            });
+
+            egui::ComboBox::new(format!("style_{}", curve.id),"Style")
+                .selected_text(display.style.to_string())
+                .show_ui(ui, |ui| {
+                    for style in CurveStyle::iter() {
+                        ui.selectable_value(&mut display.style, style, style.to_string());
+                    }
+            });
+            egui::ComboBox::new(format!("normalization_{}", curve.id),"Normalize")
+                .selected_text(display.normalization.to_string())
+                .show_ui(ui, |ui| {
+                    for normalization in Normalization::iter() {
+                        ui.selectable_value(&mut display.normalization, normalization, normalization.to_string());
+                    }
+            });
+            if curve.value_type == HistogramAggregation::Count {
+                ui.checkbox(&mut display.poisson_errors, "Poisson errors")
+                    .on_hover_text("Asymmetric 68% intervals of the counts, also drawn for empty bins");
+            }
+            if display.style == CurveStyle::Steps {
+                ui.horizontal(|ui| {
+                    ui.label("Width: ");
+                    ui.add(egui::DragValue::new(&mut display.stroke_width).speed(0.1).clamp_range(0.5..=10.0));
+                    ui.checkbox(&mut display.band, "Band");
+                });
+            }
+
+            // Add expandable filter section
+            let mut editing = false;
+            egui::CollapsingHeader::new("Filters")
+                .id_source(format!("filters_{}", curve.id))
+                .default_open(true)
+                .show(ui, |ui| {
+                    ui.horizontal(|ui| {
+                        // Add new filter group button
+                        if ui.button("Add Filter Group").clicked() {
+                            curve.filter.conditions.push(vec![]);
+                        }
+                        ui.menu_button("Save filter as preset…", |ui| {
+                            ui.text_edit_singleline(&mut self.preset_window.new_name);
+                            let name = self.preset_window.new_name.trim().to_string();
+                            let exists = self.presets.presets.contains_key(&name);
+                            let label = if exists { "Overwrite" } else { "Save" };
+                            if ui.add_enabled(!name.is_empty(), egui::Button::new(label)).clicked() {
+                                self.presets.presets.insert(name, curve.filter.clone());
+                                self.preset_window.new_name.clear();
+                                ui.close_menu();
+                            }
+                        });
+                        ui.menu_button("Apply preset", |ui| {
+                            if self.presets.presets.is_empty() {
+                                ui.label("No presets saved");
+                            }
+                            for (name, preset) in &self.presets.presets {
+                                if ui.button(name).on_hover_text(preset.to_sql()).clicked() {
+                                    let missing = preset.missing_columns(&columns);
+                                    if missing.is_empty() {
+                                        curve.filter.merge(preset);
+                                        self.preset_window.message = None;
+                                    } else {
+                                        let missing = missing.iter().map(|c| c.name()).collect::<Vec<_>>().join(", ");
+                                        self.preset_window.message = Some((Some(curve.id), format!("Preset {} not applied, missing columns: {}", name, missing)));
+                                    }
+                                    ui.close_menu();
+                                }
+                            }
+                        });
+                    });
+                    if let Some((Some(id), message)) = &self.preset_window.message
+                        && *id == curve.id
+                    {
+                        ui.colored_label(egui::Color32::RED, message);
+                    }
+
+                    let mut groups_to_remove = Vec::new();
+
+                    for (group_idx, group) in curve.filter.conditions.iter_mut().enumerate() {
+                        ui.horizontal(|ui| {
+                            ui.label(format!("OR Group {}", group_idx + 1));
+                            if ui.button("Remove Group").clicked() {
+                                groups_to_remove.push(group_idx);
+                            }
+                        });
+
+                        ui.indent(format!("group_{}", group_idx), |ui| {
+                            // Add condition to group button
+                            if ui.button("Add Condition").clicked() {
+                                group.push(SQLFilterComparison {
+                                    left: SQLFilterComparisonValue::Number("0".to_string()),
+                                    comparison: SQLFilterComparisonOperation::GreaterThan,
+                                    right: SQLFilterComparisonValue::Number("0".to_string()),
+                                });
+                            }
+
+                            let mut conditions_to_remove = Vec::new();
+
+                            for (cond_idx, condition) in group.iter_mut().enumerate() {
+                                ui.horizontal(|ui| {
 
-        if !name
-            .chars()
-            .all(|c| c.is_ascii_alphanumeric() || ' ' == c|| c == '-' || c == '_' || c == '/' || c == '.' || c == ':')
+                                    if ui.small_button("x").clicked() {
+                                        conditions_to_remove.push(cond_idx);
+                                    }
+
+                                    let mut is_column = matches!(condition.right, SQLFilterComparisonValue::Column(_));
+
+                                    ui.checkbox(&mut is_column, "Column");
+
+
+                                    // Left side (column selection)
+                                    egui::ComboBox::new(format!("left_{}_{}", group_idx, cond_idx), "")
+                                        .selected_text(condition.left.to_string())
+                                        .show_ui(ui, |ui| {
+                                            for col in &columns {
+                                                ui.selectable_value(&mut condition.left, SQLFilterComparisonValue::Column(col.clone()), col.as_str());
+                                            }
+                                        });
+
+                                    // Comparison operator
+                                    egui::ComboBox::new(format!("op_{}_{}", group_idx, cond_idx), "")
+                                        .selected_text(condition.comparison.to_string())
+                                        .show_ui(ui, |ui| {
+                                            for op in SQLFilterComparisonOperation::iter() {
+                                                ui.selectable_value(&mut condition.comparison, op.clone(), op.to_string());
+                                            }
+                                        });
+
+                                    if is_column {
+                                        if let SQLFilterComparisonValue::Number(_) = condition.right {
+                                            // Reset to first column if previously a number
+                                            condition.right = SQLFilterComparisonValue::Column(columns.first().cloned().unwrap_or(ParsedString::parse("0").unwrap()));
+                                        }
+                                        // Column selection dropdown
+                                        let current_col = match &condition.right {
+                                            SQLFilterComparisonValue::Column(col) => col.as_str(),
+                                            SQLFilterComparisonValue::Number(_) => columns.first().map(|c| c.as_str()).unwrap_or(""),
+                                        };
+
+                                        egui::ComboBox::new(format!("right_col_{}_{}", group_idx, cond_idx),"")
+                                            .selected_text(current_col)
+                                            .show_ui(ui, |ui| {
+                                                for col in &columns {
+                                                    ui.selectable_value(&mut condition.right, SQLFilterComparisonValue::Column(col.clone()), col.as_str());
+                                                }
+                                            });
+                                    }
+                                    else {
+                                        if let SQLFilterComparisonValue::Column(_) = condition.right {
+                                            // Reset to 0 if previously a column
+                                            condition.right = SQLFilterComparisonValue::Number("0".to_string());
+                                        }
+                                        // Right side is a number
+                                        let mut value_text = if let SQLFilterComparisonValue::Number(ref num) = condition.right {
+                                            num.clone()
+                                        } else {
+                                            "0".to_string()
+                                        };
+                                        let response = ui.add(
+                                            egui::TextEdit::singleline(&mut value_text)
+                                                .desired_width(50.0)
+                                        );
+                                        editing |= response.has_focus();
+                                        if response.changed() {
+                                            if let Ok(v) = value_text.parse::<f64>() {
+                                                // Valid number
+                                                condition.right = SQLFilterComparisonValue::Number(v.to_string());
+                                            }
+                                            else {
+                                                // Invalid number, reset to 0
+                                                condition.right = SQLFilterComparisonValue::Number("0".to_string());
+                                            }
+                                        }
+                                    }
+
+                                });
+
+                                //if cond_idx < group.len() - 1 {
+                                //    ui.label("OR");
+                                //}
+                            }
+
+                            // Remove conditions in reverse order
+                            for &idx in conditions_to_remove.iter().rev() {
+                                group.remove(idx);
+                            }
+                        });
+
+                        //if group_idx < curve.filter.conditions.len() - 1 {
+                        //    ui.label("AND");
+                        //}
+                    }
+
+                    // Remove groups in reverse order
+                    for &idx in groups_to_remove.iter().rev() {
+                        curve.filter.conditions.remove(idx);
+                    }
+
+                    // Show current filter SQL
+                    if !curve.filter.conditions.is_empty() {
+                        ui.label("Current filter:");
+                        ui.code(curve.filter.to_sql());
+                    }
+                });
+            if editing {
+                self.histogram_view.editing.insert(curve.id);
+            } else {
+                self.histogram_view.editing.remove(&curve.id);
+            }
+
+
+            //ui.label(format!("Selected: {}", self.selected));
+            if self.histogram_view.update {
+                self.histogram_view.stat = Some(get_stat(&mut self.cache, &mut self.sql, &StatInput {
+                        table: parquet_path.clone(),
+                        column: curve.x_key.clone(),
+                        filters: curve.filter.clone(),
+                }, &format!("stat for curve {}", curve.id)));
+            }
+            if let Some(stat) = &self.histogram_view.stat {
+                let units = get_units(&mut self.cache, parquet_path);
+                draw_stat(
+                    ui,
+                    stat,
+                    units.get(curve.x_key.name()).map(String::as_str),
+                );
+            }
+            if curve.x_key != curve.y_key {
+                if self.histogram_view.update {
+                    let xy_stat = get_xy_stat(&mut self.cache, &mut self.sql, &XYStatInput {
+                            table: parquet_path.clone(),
+                            x: curve.x_key.clone(),
+                            y: curve.y_key.clone(),
+                            filters: curve.filter.clone(),
+                    }, &format!("xy stat for curve {}", curve.id));
+                    self.histogram_view.xy_stats.insert(curve.id, xy_stat);
+                }
+                if let Some(xy_stat) = self.histogram_view.xy_stats.get(&curve.id) {
+                    draw_xy_stat(ui, xy_stat);
+                }
+            }
+        });
+    }
+
+    /// Window with the column summary, clicking a column name makes it the x key of the curve
+    fn show_summary(&mut self, ctx: &egui::Context) {
+        let Some(view) = &mut self.summary_view else {
+            return;
+        };
+        let input = SummaryInput { table: view.table.clone() };
+        let mut open = true;
+        let mut x_key = None;
+        egui::Window::new(format!("Column summary of {}", view.table.name()))
+            .open(&mut open)
+            .scroll2([true, true])
+            .show(ctx, |ui| {
+                if !self.cache.summary.contains_key(&input) && !view.spinner_shown {
+                    ui.horizontal(|ui| {
+                        ui.spinner();
+                        ui.label("Summarizing columns…");
+                    });
+                    view.spinner_shown = true;
+                    ctx.request_repaint();
+                    return;
+                }
+                let summary = get_summary(&mut self.cache, &mut self.sql, &input, &format!("summary of {}", view.table));
+                if ui.button("Export CSV").clicked() {
+                    view.export_dialog.save_file();
+                }
+                if let Some(error) = &view.export_error {
+                    ui.colored_label(egui::Color32::RED, error);
+                }
+                draw_summary(ui, &summary, view, &mut x_key);
+            });
+        view.export_dialog.update(ctx);
+        if let Some(path) = view.export_dialog.selected() {
+            let path = path.to_path_buf();
+            view.export_dialog = FileDialog::new();
+            if let Some(summary) = self.cache.summary.get(&input) {
+                view.export_error = std::fs::write(&path, table_to_csv(&SummaryOutput::HEADER, &summary.cells()))
+                    .err()
+                    .map(|e| format!("Could not write {}: {}", path.to_string_lossy(), e));
+            }
+        }
+        if let Some(x_key) = x_key
+            && let Some(curve) = self.histogram_view.input.curves.iter_mut().find(|c| c.id == view.curve_id)
         {
-            return Err(duckdb::Error::InvalidParameterName(format!("Invalid identifier: {}", name)));
+            curve.x_key = x_key;
+        }
+        if !open {
+            self.summary_view = None;
         }
+    }
 
-        // Safe: return the identifier as-is
-        Ok(Self("\"".to_string() + name + "\""))
+    /// Window to rename, delete, export and import filter presets
+    fn show_presets(&mut self, ctx: &egui::Context) {
+        let window = &mut self.preset_window;
+        let presets = &mut self.presets;
+        let mut delete = None;
+        let mut rename = None;
+        egui::Window::new("Filter presets")
+            .open(&mut window.open)
+            .show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    if ui.button("Export JSON").clicked() {
+                        window.export_dialog.save_file();
+                    }
+                    if ui.button("Import JSON").clicked() {
+                        window.import_dialog.select_file();
+                    }
+                });
+                if let Some((None, message)) = &window.message {
+                    ui.label(message);
+                }
+                ui.separator();
+                if presets.presets.is_empty() {
+                    ui.label("No presets saved, use \"Save filter as preset…\" in the filters of a curve");
+                }
+                egui::Grid::new("filter_presets").striped(true).show(ui, |ui| {
+                    for (name, preset) in &presets.presets {
+                        match &mut window.rename {
+                            Some((from, to)) if from == name => {
+                                ui.text_edit_singleline(to);
+                                if ui.button("OK").clicked() {
+                                    rename = Some((from.clone(), to.trim().to_string()));
+                                }
+                            },
+                            _ => {
+                                ui.label(name);
+                                if ui.button("Rename").clicked() {
+                                    window.rename = Some((name.clone(), name.clone()));
+                                }
+                            },
+                        }
+                        if ui.button("Delete").clicked() {
+                            delete = Some(name.clone());
+                        }
+                        ui.code(preset.to_sql());
+                        ui.end_row();
+                    }
+                });
+            });
+        if let Some(name) = delete {
+            presets.presets.remove(&name);
+        }
+        if let Some((from, to)) = rename {
+            if from != to && (to.is_empty() || !presets.rename(&from, &to)) {
+                window.message = Some((None, format!("Could not rename {} to {}", from, to)));
+            }
+            window.rename = None;
+        }
+
+        window.export_dialog.update(ctx);
+        if let Some(path) = window.export_dialog.selected() {
+            let path = path.with_extension("json");
+            window.export_dialog = FileDialog::new();
+            let result = presets.to_json()
+                .map_err(|e| e.to_string())
+                .and_then(|json| std::fs::write(&path, json).map_err(|e| e.to_string()));
+            window.message = Some((None, match result {
+                Ok(()) => format!("Exported {} presets to {}", presets.presets.len(), path.to_string_lossy()),
+                Err(e) => format!("Could not export to {}: {}", path.to_string_lossy(), e),
+            }));
+        }
+        window.import_dialog.update(ctx);
+        if let Some(path) = window.import_dialog.selected() {
+            let path = path.to_path_buf();
+            window.import_dialog = FileDialog::new();
+            let result = std::fs::read_to_string(&path)
+                .map_err(|e| e.to_string())
+                .and_then(|json| FilterPresets::from_json(&json).map_err(|e| e.to_string()));
+            window.message = Some((None, match result {
+                Ok(imported) => {
+                    let count = imported.presets.len();
+                    // imported presets replace ones with the same name
+                    presets.presets.extend(imported.presets);
+                    format!("Imported {} presets from {}", count, path.to_string_lossy())
+                },
+                Err(e) => format!("Could not import {}: {}", path.to_string_lossy(), e),
+            }));
+        }
     }
 
-    /// Optionally, allow read-only access to inner string
-    pub fn as_str(&self) -> &str {
-        &self.0
+    /// Warnings for selected keys that are mostly NULL, only the selected columns are queried
+    fn draw_null_warnings(&mut self, ui: &mut egui::Ui) {
+        let threshold = self.histogram_view.plot_settings.null_warning_threshold;
+        for (i, curve) in self.histogram_view.input.curves.iter().enumerate() {
+            let mut keys = vec![&curve.x_key];
+            if curve.y_key != curve.x_key {
+                keys.push(&curve.y_key);
+            }
+            for key in keys {
+                let input = NullFractionInput { table: curve.table.clone(), column: key.clone() };
+                let fraction = get_null_fraction(&mut self.cache, &mut self.sql, &input, &format!("null fraction of {} in {}", key, curve.table)).fraction;
+                if fraction > threshold {
+                    ui.colored_label(
+                        egui::Color32::YELLOW,
+                        format!("⚠ Curve {}: {} is {:.0}% null, only the remaining rows are shown", i + 1, key.name(), fraction * 100.0),
+                    );
+                }
+            }
+        }
     }
-}
 
-struct Sql {
-    conn: duckdb::Connection,
-    // request + error
-    history : Vec<(usize,String, Option<String>)>,
-    counter: usize,
-    //last_query: String,
-    //last_error: String,
-}
+    /// Series tab, the curves are shared with the histogram and added there
+    fn show_series(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.label("Granularity: ");
+            let mut bucketed = self.series_view.buckets.is_some();
+            ui.selectable_value(&mut bucketed, false, "Raw points");
+            ui.selectable_value(&mut bucketed, true, "Buckets");
+            if bucketed != self.series_view.buckets.is_some() {
+                // buckets start at the histogram bin count
+                self.series_view.buckets = bucketed.then_some(self.histogram_view.input.bins);
+            }
+            if let Some(buckets) = &mut self.series_view.buckets {
+                ui.add(egui::DragValue::new(buckets).clamp_range(1..=MAX_AUTO_BINS));
+            } else {
+                ui.label("Max points per curve: ");
+                ui.add(egui::DragValue::new(&mut self.series_view.limit).speed(100.0).clamp_range(1..=10_000_000));
+            }
+            ui.label("Plot Height: ");
+            ui.add(egui::Slider::new(&mut self.histogram_view.plot_settings.height, 150.0..=2000.0).suffix(" px"));
+        });
+        if self.histogram_view.input.curves.is_empty() {
+            ui.label("Add curves in the Histogram tab");
+            return;
+        }
 
-impl Sql {
-    fn push_history(&mut self, query: String, error: Option<String>) {
-        self.counter += 1;
-        self.history.push((self.counter, query, error));
-    }
+        let mut actions = Vec::new();
+        let groups = self.curve_groups();
+        ui.horizontal(|ui| {
+            for index in 0..self.histogram_view.input.curves.len() {
+                self.curve_controls(ui, index, &groups, &mut actions);
+            }
+        });
+        self.apply_curve_actions(actions);
+
+        let curves: Vec<(usize, HistogramSubInput)> = self.histogram_view.input.curves.iter()
+            .enumerate()
+            .filter(|(_, c)| self.histogram_view.displays.get(&c.id).is_none_or(|d| d.visible))
+            .map(|(i, c)| (i, c.clone()))
+            .collect();
+        let mut lines: Vec<(usize, String, Vec<[f64; 2]>)> = vec![];
+        match self.series_view.buckets {
+            None => {
+                for (i, curve) in &curves {
+                    let input = SeriesInput {
+                        table: curve.table.clone(),
+                        x: curve.x_key.clone(),
+                        y: curve.y_key.clone(),
+                        filters: curve.filter.clone(),
+                        limit: self.series_view.limit,
+                    };
+                    let series = get_series(&mut self.cache, &mut self.sql, &input, &format!("series of curve {}", i + 1));
+                    if series.truncated {
+                        ui.colored_label(
+                            egui::Color32::YELLOW,
+                            format!("⚠ Curve {}: only the first {} points by {} are shown", i + 1, self.series_view.limit, curve.x_key.name()),
+                        );
+                    }
+                    let name = format!("{}. {} vs {} ({})", i + 1, curve.y_key.name(), curve.x_key.name(), table_label(&curve.table));
+                    lines.push((*i, name, series.points.iter().map(|&(x, y)| [x, y]).collect()));
+                }
+            }
+            Some(buckets) => {
+                // one histogram over all curves so the buckets line up on the shared x axis
+                let input = HistogramInput {
+                    bins: buckets,
+                    curves: curves.iter().map(|(_, c)| c.clone()).collect(),
+                    range: self.histogram_view.input.range,
+                };
+                let hist = get_histogram(&mut self.cache, &mut self.sql, &input, "series");
+                for (index, (i, curve)) in curves.iter().enumerate() {
+                    let name = format!("{}. {}", i + 1, curve_title(curve));
+                    lines.push((*i, name, hist.data.iter().map(|(x, _, values)| [*x, values[index].0]).collect()));
+                }
+            }
+        }
 
-    fn prepare(&mut self, query: &str) -> duckdb::Result<duckdb::Statement> {
-        //self.last_query = query.to_string();
-        self.conn.prepare(query)
+        let x_label = curves.iter().map(|(_, c)| c.x_key.name().to_string()).unique().join(" / ");
+        let y_label = curves.iter().map(|(_, c)| match self.series_view.buckets {
+                None => c.y_key.name().to_string(),
+                Some(_) => format!("{}({})", c.value_type.name(), c.y_key.name()),
+            }).unique().join(" / ");
+        Plot::new("series")
+            .height(self.histogram_view.plot_settings.height)
+            .legend(Legend::default())
+            .x_axis_label(x_label)
+            .y_axis_label(y_label)
+            .show(ui, |plot_ui| {
+                for (i, name, points) in lines {
+                    plot_ui.line(Line::new(PlotPoints::new(points)).name(name).color(curve_color(i)));
+                }
+            });
     }
-}
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, EnumIter, Display)]
-enum Operation {
-    //Aggregate,
-    Histogram,
-}
+    /// Box plot tab, the curves are shared with the histogram and added there
+    fn show_box_plot(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.label("Group by x: ");
+            let mut grouped = self.box_plot_view.groups.is_some();
+            ui.selectable_value(&mut grouped, false, "None");
+            ui.selectable_value(&mut grouped, true, "Buckets");
+            if grouped != self.box_plot_view.groups.is_some() {
+                self.box_plot_view.groups = grouped.then_some(self.histogram_view.input.bins);
+            }
+            if let Some(groups) = &mut self.box_plot_view.groups {
+                ui.add(egui::DragValue::new(groups).clamp_range(1..=MAX_AUTO_BINS));
+            }
+            ui.label("Plot Height: ");
+            ui.add(egui::Slider::new(&mut self.histogram_view.plot_settings.height, 150.0..=2000.0).suffix(" px"));
+        });
+        if self.histogram_view.input.curves.is_empty() {
+            ui.label("Add curves in the Histogram tab");
+            return;
+        }
 
-struct MyApp {
-    operation: Operation,
-    filedialog: FileDialog,
-    cache : Cache,
+        let mut actions = Vec::new();
+        let groups = self.curve_groups();
+        ui.horizontal(|ui| {
+            for index in 0..self.histogram_view.input.curves.len() {
+                self.curve_controls(ui, index, &groups, &mut actions);
+            }
+        });
+        self.apply_curve_actions(actions);
+
+        let curves: Vec<(usize, HistogramSubInput)> = self.histogram_view.input.curves.iter()
+            .enumerate()
+            .filter(|(_, c)| self.histogram_view.displays.get(&c.id).is_none_or(|d| d.visible))
+            .map(|(i, c)| (i, c.clone()))
+            .collect();
+        // the buckets are shared by all curves like the bins of a histogram
+        let grid = self.box_plot_view.groups.and_then(|bins| {
+            let input = HistogramInput {
+                bins,
+                curves: curves.iter().map(|(_, c)| c.clone()).collect(),
+                range: self.histogram_view.input.range,
+            };
+            let range = HistogramRangeInput { bins, curves: input.range_curves(), range: input.range };
+            get_histogram_range(&mut self.cache, &mut self.sql, &range, "box plot range").grid
+        });
+        if self.box_plot_view.groups.is_some() && grid.is_none() {
+            ui.label("No rows selected");
+            return;
+        }
 
-    sql : Sql,
+        // boxes of the curves sit side by side within a bucket
+        let slot = grid.map_or(1.0, |grid| grid.width()) / curves.len() as f64;
+        let mut plots = vec![];
+        for (k, (i, curve)) in curves.iter().enumerate() {
+            let input = BoxPlotInput {
+                table: curve.table.clone(),
+                filter: curve.filter.clone(),
+                y_key: curve.y_key.clone(),
+                group: grid.map(|grid| (curve.x_key.clone(), grid)),
+            };
+            let output = get_box_plot(&mut self.cache, &mut self.sql, &input, &format!("box plot of curve {}", i + 1));
+            let elements = output.boxes.iter().map(|(bucket, b)| {
+                let center = match grid {
+                    Some(grid) => grid.midpoint(*bucket) + (k as f64 + 0.5) * slot - grid.width() / 2.,
+                    None => k as f64,
+                };
+                BoxElem::new(center, BoxSpread::new(b.min, b.q1, b.median, b.q3, b.max))
+                    .box_width(slot * 0.8)
+                    .whisker_width(slot * 0.4)
+                    .name(format!("{} rows", b.count))
+            }).collect();
+            let name = match grid {
+                Some(_) => format!("{}. {} by {} ({})", i + 1, curve.y_key.name(), curve.x_key.name(), table_label(&curve.table)),
+                None => format!("{}. {} ({})", i + 1, curve.y_key.name(), table_label(&curve.table)),
+            };
+            plots.push(BoxPlot::new(elements).name(name).color(curve_color(*i)));
+        }
 
+        let x_label = match grid {
+            Some(_) => curves.iter().map(|(_, c)| c.x_key.name().to_string()).unique().join(" / "),
+            None => "Curve".to_string(),
+        };
+        let y_label = curves.iter().map(|(_, c)| c.y_key.name().to_string()).unique().join(" / ");
+        Plot::new("box_plot")
+            .height(self.histogram_view.plot_settings.height)
+            .legend(Legend::default())
+            .x_axis_label(x_label)
+            .y_axis_label(y_label)
+            .show(ui, |plot_ui| {
+                for plot in plots {
+                    plot_ui.box_plot(plot);
+                }
+            });
+    }
 
-    histogram_view : HistogramView,
-    global_id_counter: usize,
-}
+    /// Refresh live curves whose file grew, without waiting for user input
+    fn tick_live(&mut self, ctx: &egui::Context) {
+        let view = &mut self.histogram_view;
+        if view.live.is_empty() {
+            return;
+        }
+        let interval = Duration::from_secs_f64(view.live_interval.max(0.1));
+        let pause_after = Duration::from_secs_f64(view.live_pause_minutes.max(0.0) * 60.0);
+        for curve in &view.input.curves {
+            let Some(live) = view.live.get_mut(&curve.id) else {
+                continue;
+            };
+            // never change a curve under a filter that is being typed
+            if live.paused || live.last_poll.elapsed() < interval || view.editing.contains(&curve.id) {
+                continue;
+            }
+            live.last_poll = Instant::now();
+            match live.refresh() {
+                Ok(Some(stats)) => {
+                    if stats.lost > 0 {
+                        self.skipped_lines.insert(live.output.clone(), stats.lost);
+                    } else {
+                        self.skipped_lines.remove(&live.output);
+                    }
+                    self.cache.invalidate_table(&curve.table);
+                    view.update = true;
+                }
+                Ok(None) => {
+                    if live.last_growth.elapsed() >= pause_after {
+                        live.paused = true;
+                    }
+                }
+                Err(e) => {
+                    self.sql.push_error(&format!("live update of curve {}", curve.id), e);
+                    live.paused = true;
+                }
+            }
+        }
+        ctx.request_repaint_after(interval);
+    }
 
-struct HistogramView {
-    //bin_scale: HistogramBinScale,
-    plot_settings : HistrogramPlotSettings,
-    auto_update: bool,
-    update: bool,
-    input : HistogramInput,
-    stat : Option<StatOutput>,
-    histogram : Option<HistogramOutput>,
-}
+    /// Group names in order of their first curve
+    fn curve_groups(&self) -> Vec<String> {
+        let mut groups: Vec<String> = vec![];
+        for curve in &self.histogram_view.input.curves {
+            if let Some(group) = self.histogram_view.displays.get(&curve.id).and_then(|d| d.group.as_ref())
+                && !groups.contains(group)
+            {
+                groups.push(group.clone());
+            }
+        }
+        groups
+    }
 
-struct HistrogramPlotSettings {
-    //x_axis_scale: HistogramAxisScale,
-    //y_axis_scale: HistogramAxisScale,
+    fn group_of(&self, index: usize) -> Option<&String> {
+        let id = self.histogram_view.input.curves[index].id;
+        self.histogram_view.displays.get(&id).and_then(|d| d.group.as_ref())
+    }
+
+    fn apply_curve_actions(&mut self, actions: Vec<CurveAction>) {
+        let view = &mut self.histogram_view;
+        for action in actions {
+            match action {
+                CurveAction::Clone(id) => {
+                    if let Some(curve) = view.input.curves.iter().find(|c| c.id == id) {
+                        let mut nc = curve.clone();
+                        nc.id = {
+                            self.global_id_counter += 1;
+                            self.global_id_counter
+                        };
+                        if let Some(display) = view.displays.get(&id).cloned() {
+                            view.displays.insert(nc.id, display);
+                        }
+                        view.input.curves.push(nc);
+                    }
+                }
+                CurveAction::Remove(id) => {
+                    view.input.curves.retain(|c| c.id != id);
+                    view.displays.remove(&id);
+                    view.xy_stats.remove(&id);
+                    view.live.remove(&id);
+                }
+                CurveAction::MoveToGroup(id, group) => {
+                    view.displays.entry(id).or_default().group = group;
+                }
+                CurveAction::SetGroupVisible(group, visible) => {
+                    for display in view.displays.values_mut().filter(|d| d.group.as_ref() == Some(&group)) {
+                        display.visible = visible;
+                    }
+                }
+                CurveAction::RemoveGroup(group) => {
+                    let members: Vec<usize> = view.displays.iter()
+                        .filter(|(_, d)| d.group.as_ref() == Some(&group))
+                        .map(|(id, _)| *id)
+                        .collect();
+                    view.input.curves.retain(|c| !members.contains(&c.id));
+                    for id in members {
+                        view.displays.remove(&id);
+                    }
+                }
+                CurveAction::ReloadAsNew(id) => {
+                    let snapshot = view.histogram.as_ref().and_then(|hist| {
+                        let index = hist.input.curves.iter().position(|c| c.id == id)?;
+                        Some(hist.data.iter().map(|(x, w, values)| (*x, *w, values[index].0, values[index].1)).collect::<Vec<_>>())
+                    });
+                    if let Some(curve) = view.input.curves.iter().find(|c| c.id == id) {
+                        if let Some(data) = snapshot {
+                            self.global_id_counter += 1;
+                            view.frozen.push(FrozenCurve {
+                                id: self.global_id_counter,
+                                label: format!("{} of {} vs {} ({}) @ {}", curve.value_type.name(), curve.y_key.name(), curve.x_key.name(), table_label(&curve.table), format_time_of_day(SystemTime::now())),
+                                data,
+                                visible: true,
+                                band: true,
+                            });
+                        }
+                        self.cache.invalidate_table(&curve.table);
+                        view.update = true;
+                    }
+                }
+                CurveAction::RemoveFrozen(id) => {
+                    view.frozen.retain(|f| f.id != id);
+                }
+                CurveAction::RemoveFile(id, file) => {
+                    if let Some(curve) = view.input.curves.iter_mut().find(|c| c.id == id)
+                        && let Ok(table) = curve.table.without_file(&file)
+                    {
+                        curve.table = table;
+                        view.update = true;
+                    }
+                }
+                CurveAction::ApplyFilterToGroup(group, from) => {
+                    let filter = view.input.curves.iter().find(|c| c.id == from).map(|c| c.filter.clone());
+                    if let Some(filter) = filter {
+                        for curve in view.input.curves.iter_mut() {
+                            if view.displays.get(&curve.id).and_then(|d| d.group.as_ref()) == Some(&group) {
+                                curve.filter = filter.clone();
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
 }
 
-
 impl Default for MyApp {
     fn default() -> Self {
         Self {
-            sql : Sql {
-                conn: Connection::open_in_memory().unwrap(),
-                history : vec![],
-                counter: 0,
-            },
+            sql : Sql::open(None),
             filedialog: FileDialog::new(),
+            db_dialog: FileDialog::new(),
+            script_dialog: FileDialog::new(),
+            yoda_dialog: FileDialog::new(),
+            stats_dialog: FileDialog::new(),
+            stats_format: TableFormat::Csv,
+            add_file_dialog: FileDialog::new(),
+            add_file_curve: None,
+            skipped_lines: HashMap::new(),
             operation: Operation::Histogram,
-            cache: Cache {
-                histogram : HashMap::new(),
-                column_names : HashMap::new(),
-                stat: HashMap::new(),
-            },
+            cache: Cache::default(),
             histogram_view : HistogramView {
-                plot_settings : HistrogramPlotSettings {
-                //    x_axis_scale: HistogramAxisScale::Linear,
-                //    y_axis_scale: HistogramAxisScale::Linear,
-                },
+                plot_settings : HistrogramPlotSettings::default(),
+                maximized: false,
                 auto_update: true,
                 update: false,
                 input : HistogramInput {
                     bins: 10,
                     curves : vec![],
+                    range: HistogramRange::default(),
                 },
+                bin_rule : BinRule::Manual,
                 stat : None,
+                xy_stats : HashMap::new(),
                 histogram : None,
+                displays : HashMap::new(),
+                new_group_name : String::new(),
+                frozen : vec![],
+                live : HashMap::new(),
+                live_interval : 2.0,
+                live_pause_minutes : 5.0,
+                editing : HashSet::new(),
+                ratio_denominator : None,
+                pull_curves : None,
                 //bin_scale: HistogramBinScale::Linear,
             },
+            series_view : SeriesView {
+                buckets : None,
+                limit : SERIES_POINT_LIMIT,
+            },
+            box_plot_view : BoxPlotView {
+                groups : None,
+            },
+            summary_view : None,
+            presets : FilterPresets::default(),
+            preset_window : PresetWindow::default(),
             global_id_counter: 0,
+            glob_input: String::new(),
         }
     }
 }
 
 impl eframe::App for MyApp {
+    fn save(&mut self, storage: &mut dyn eframe::Storage) {
+        eframe::set_value(storage, PLOT_SETTINGS_KEY, &self.histogram_view.plot_settings);
+        eframe::set_value(storage, SESSION_KEY, &Session {
+            input: self.histogram_view.input.clone(),
+            displays: self.histogram_view.displays.clone(),
+            frozen: self.histogram_view.frozen.clone(),
+            next_id: self.global_id_counter,
+        });
+        if let Ok(json) = self.presets.to_json() {
+            storage.set_string(FILTER_PRESETS_KEY, json);
+        }
+    }
+
     fn update(&mut self, ctx: &egui::Context, _: &mut eframe::Frame) {
+        self.tick_live(ctx);
+        // F toggles the maximized plot, Escape restores; ignored while typing into a text field
+        if !ctx.wants_keyboard_input() {
+            ctx.input(|i| {
+                if i.key_pressed(egui::Key::F) {
+                    self.histogram_view.maximized = !self.histogram_view.maximized;
+                }
+                if i.key_pressed(egui::Key::Escape) {
+                    self.histogram_view.maximized = false;
+                }
+            });
+        }
+
+        if self.sql.conn.is_none() {
+            self.show_connection_error(ctx);
+            return;
+        }
+
+        self.show_summary(ctx);
+        self.show_presets(ctx);
+
+        if self.histogram_view.maximized && self.operation == Operation::Histogram {
+            egui::CentralPanel::default().show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    if ui.button("🗗 Restore").clicked() {
+                        self.histogram_view.maximized = false;
+                    }
+                    ui.label("Press Escape to restore");
+                });
+                if let Some(hist) = &self.histogram_view.histogram {
+                    let height = ui.available_height();
+                    let units: Vec<_> = hist.input.curves.iter().map(|c| get_units(&mut self.cache, &c.table)).collect();
+                    draw_histogram(ui, hist, &self.histogram_view.frozen, &self.histogram_view.plot_settings, &self.histogram_view.displays, &units, height);
+                }
+            });
+            return;
+        }
+
         egui::CentralPanel::default().show(ctx, |ui| {
             egui::ScrollArea::both().show(ui, |ui| {
                 ui.set_min_width(ui.available_width());
@@ -254,12 +1521,12 @@ impl eframe::App for MyApp {
 
                 match self.operation {
                     Operation::Histogram => {
-                        //ui.horizontal(|ui| {
-                        //    ui.label("Histogram X Axis Scale: ");
-                        //    for op in HistogramAxisScale::iter() {
-                        //        ui.selectable_value(&mut self.histogram_view.plot_settings.x_axis_scale, op, op.to_string());
-                        //    }
-                        //});
+                        ui.horizontal(|ui| {
+                            ui.label("Histogram X Axis Scale: ");
+                            for op in HistogramAxisScale::iter() {
+                                ui.selectable_value(&mut self.histogram_view.plot_settings.x_axis_scale, op, op.to_string());
+                            }
+                        });
                         //ui.horizontal(|ui| {
                         //    ui.label("Histogram Y Axis Scale: ");
                         //    for op in HistogramAxisScale::iter() {
@@ -272,19 +1539,198 @@ impl eframe::App for MyApp {
                         //        ui.selectable_value(&mut self.histogram_view.bin_scale, op, op.to_string());
                         //    }
                         //});
+                        // a newly set bound starts at the current edge of the bins
+                        let edges = self.histogram_view.histogram.as_ref().and_then(|hist| Some((
+                            hist.data.first().map(|(x, w, _)| x - w / 2.)?,
+                            hist.data.last().map(|(x, w, _)| x + w / 2.)?,
+                        )));
                         ui.horizontal(|ui| {
                             ui.label("Histogram Bins: ");
-                            ui.add(egui::DragValue::new(&mut self.histogram_view.input.bins));
+                            if ui.add(egui::DragValue::new(&mut self.histogram_view.input.bins)).changed() {
+                                // editing the count goes back to choosing it by hand
+                                self.histogram_view.bin_rule = BinRule::Manual;
+                            }
+                            egui::ComboBox::new("bin_rule", "Auto")
+                                .selected_text(self.histogram_view.bin_rule.to_string())
+                                .show_ui(ui, |ui| {
+                                    for rule in BinRule::iter() {
+                                        ui.selectable_value(&mut self.histogram_view.bin_rule, rule, rule.to_string());
+                                    }
+                                });
+                            let range = &mut self.histogram_view.input.range;
+                            ui.label("X Min: ");
+                            optional_value(ui, &mut range.min, edges.map_or(0.0, |(min, _)| min));
+                            ui.label("X Max: ");
+                            optional_value(ui, &mut range.max, edges.map_or(1.0, |(_, max)| max));
+                            ui.checkbox(&mut range.clamp, "Clamp outside").on_hover_text("Count rows outside the range in the edge bins instead of dropping them");
+                            ui.label("Plot Height: ");
+                            ui.add(egui::Slider::new(&mut self.histogram_view.plot_settings.height, 150.0..=2000.0).suffix(" px"));
+                            if ui.button("⛶").on_hover_text("Maximize plot (F)").clicked() {
+                                self.histogram_view.maximized = true;
+                            }
+                        });
+                        ui.horizontal(|ui| {
+                            ui.label("Orientation: ");
+                            for op in HistogramOrientation::iter() {
+                                ui.selectable_value(&mut self.histogram_view.plot_settings.orientation, op, op.to_string());
+                            }
+                            ui.label("Display: ");
+                            for op in DisplayMode::iter() {
+                                ui.selectable_value(&mut self.histogram_view.plot_settings.display_mode, op, op.to_string());
+                            }
+                            ui.add_enabled_ui(self.histogram_view.plot_settings.display_mode == DisplayMode::Bars, |ui| {
+                                for op in BarStacking::iter() {
+                                    ui.selectable_value(&mut self.histogram_view.plot_settings.stacking, op, op.to_string());
+                                }
+                            }).response.on_disabled_hover_text("Only bars can be stacked");
+                            ui.checkbox(&mut self.histogram_view.plot_settings.group_legend, "Group in legend");
+                            ui.label("Warn above null fraction: ");
+                            ui.add(egui::Slider::new(&mut self.histogram_view.plot_settings.null_warning_threshold, 0.0..=1.0));
+                            let curves = &self.histogram_view.input.curves;
+                            let mut ratio = self.histogram_view.ratio_denominator.is_some();
+                            if ui.checkbox(&mut ratio, "Ratio panel").changed() {
+                                self.histogram_view.ratio_denominator = if ratio { curves.first().map(|c| c.id) } else { None };
+                            }
+                            if let Some(denominator) = &mut self.histogram_view.ratio_denominator {
+                                let selected = curves.iter().position(|c| c.id == *denominator)
+                                    .map_or("none".to_string(), |i| format!("Curve {}", i + 1));
+                                egui::ComboBox::new("ratio_denominator", "Denominator")
+                                    .selected_text(selected)
+                                    .show_ui(ui, |ui| {
+                                        for (i, curve) in curves.iter().enumerate() {
+                                            ui.selectable_value(denominator, curve.id, format!("{}. {}", i + 1, curve_title(curve)));
+                                        }
+                                    });
+                            }
+                            let mut pull = self.histogram_view.pull_curves.is_some();
+                            if ui.checkbox(&mut pull, "Pull panel").changed() {
+                                self.histogram_view.pull_curves = match (pull, curves.first(), curves.get(1)) {
+                                    (true, Some(a), b) => Some((a.id, b.unwrap_or(a).id)),
+                                    _ => None,
+                                };
+                            }
+                            if let Some((a, b)) = &mut self.histogram_view.pull_curves {
+                                for (label, id) in [("A", a), ("B", b)] {
+                                    let selected = curves.iter().position(|c| c.id == *id)
+                                        .map_or("none".to_string(), |i| format!("Curve {}", i + 1));
+                                    egui::ComboBox::new(format!("pull_{}", label), label)
+                                        .selected_text(selected)
+                                        .show_ui(ui, |ui| {
+                                            for (i, curve) in curves.iter().enumerate() {
+                                                ui.selectable_value(id, curve.id, format!("{}. {}", i + 1, curve_title(curve)));
+                                            }
+                                        });
+                                }
+                            }
                         });
 
                         ui.separator();
 
-                        if ui.button("Add Histogram").clicked() {
-                            self.filedialog.select_file();
-                        };
+                        let mut add_glob = false;
+                        ui.horizontal(|ui| {
+                            if ui.button("Add Histogram").clicked() {
+                                self.filedialog.select_file();
+                            };
+                            if ui.button("Add Directory").on_hover_text("All parquet files in a directory as one curve").clicked() {
+                                self.filedialog.select_directory();
+                            };
+                            ui.add(egui::TextEdit::singleline(&mut self.glob_input).hint_text("out/run_*.parquet").desired_width(160.0));
+                            add_glob = ui.add_enabled(!self.glob_input.trim().is_empty(), egui::Button::new("Add Glob")).clicked();
+                            if ui.add_enabled(!self.histogram_view.input.curves.is_empty(), egui::Button::new("Export SQL script")).clicked() {
+                                self.script_dialog.save_file();
+                            }
+                            if ui.add_enabled(self.histogram_view.histogram.is_some(), egui::Button::new("Export YODA…")).clicked() {
+                                self.yoda_dialog.save_file();
+                            }
+                            if ui.button("Filter presets").clicked() {
+                                self.preset_window.open = true;
+                            }
+                            if ui.add_enabled(!self.histogram_view.input.curves.is_empty(), egui::Button::new("Export stats summary")).clicked() {
+                                self.stats_dialog.save_file();
+                            }
+                            egui::ComboBox::new("stats_format", "")
+                                .selected_text(self.stats_format.to_string())
+                                .show_ui(ui, |ui| {
+                                    for format in TableFormat::iter() {
+                                        ui.selectable_value(&mut self.stats_format, format, format.to_string());
+                                    }
+                                });
+                        });
+
+                        self.script_dialog.update(ctx);
+                        if let Some(path) = self.script_dialog.selected() {
+                            let path = path.with_extension("sql");
+                            self.script_dialog = FileDialog::new();
+                            let script = histogram_sql_script(&mut self.cache, &mut self.sql, &self.histogram_view.input, "histogram");
+                            if let Err(e) = std::fs::write(&path, script) {
+                                self.sql.push_error("export sql script", format!("Could not write {}: {}", path.to_string_lossy(), e));
+                            }
+                        }
+
+                        self.add_file_dialog.update(ctx);
+                        if let Some(path) = self.add_file_dialog.selected() {
+                            let file = path.to_path_buf();
+                            self.add_file_dialog = FileDialog::new();
+                            let curve = self.add_file_curve.take()
+                                .and_then(|id| self.histogram_view.input.curves.iter_mut().find(|c| c.id == id));
+                            if let Some(curve) = curve {
+                                match converted_parquet(&file, &mut self.skipped_lines).and_then(|pp| curve.table.with_file(&pp).map_err(|e| e.to_string())) {
+                                    Ok(table) => {
+                                        curve.table = table;
+                                        self.histogram_view.update = true;
+                                    }
+                                    Err(e) => self.sql.push_error("add file to curve", e),
+                                }
+                            }
+                        }
+
+                        self.stats_dialog.update(ctx);
+                        if let Some(path) = self.stats_dialog.selected() {
+                            let path = path.with_extension(self.stats_format.extension());
+                            self.stats_dialog = FileDialog::new();
+                            // all visible curves, missing statistics are computed through the cache
+                            let curves: Vec<(String, &HistogramSubInput)> = self.histogram_view.input.curves.iter()
+                                .enumerate()
+                                .filter(|(_, c)| self.histogram_view.displays.get(&c.id).is_none_or(|d| d.visible))
+                                .map(|(i, c)| (format!("{}. {}", i + 1, curve_title(c)), c))
+                                .collect();
+                            let rows = stats_summary(&mut self.cache, &mut self.sql, &curves);
+                            let table = match self.stats_format {
+                                TableFormat::Csv => table_to_csv(&STATS_SUMMARY_HEADER, &rows),
+                                TableFormat::Markdown => table_to_markdown(&STATS_SUMMARY_HEADER, &rows),
+                            };
+                            if let Err(e) = std::fs::write(&path, table) {
+                                self.sql.push_error("export stats summary", format!("Could not write {}: {}", path.to_string_lossy(), e));
+                            }
+                        }
+
+                        self.yoda_dialog.update(ctx);
+                        if let Some(path) = self.yoda_dialog.selected() {
+                            let path = path.with_extension("yoda");
+                            self.yoda_dialog = FileDialog::new();
+                            if let Some(hist) = &self.histogram_view.histogram {
+                                // all visible curves of the drawn histogram
+                                let curves: Vec<(usize, String)> = hist.input.curves.iter()
+                                    .enumerate()
+                                    .filter(|(_, c)| self.histogram_view.displays.get(&c.id).is_none_or(|d| d.visible))
+                                    .map(|(i, c)| (i, format!("{}. {}", i + 1, curve_title(c))))
+                                    .collect();
+                                if let Err(e) = std::fs::write(&path, histogram_to_yoda(hist, &curves)) {
+                                    self.sql.push_error("export yoda", format!("Could not write {}: {}", path.to_string_lossy(), e));
+                                }
+                            }
+                        }
 
                         ui.vertical(|ui| {
                             ui.checkbox(&mut self.histogram_view.auto_update, "Auto Update");
+                            if !self.histogram_view.live.is_empty() {
+                                ui.horizontal(|ui| {
+                                    ui.label("Live every");
+                                    ui.add(egui::DragValue::new(&mut self.histogram_view.live_interval).speed(0.1).clamp_range(0.1..=3600.0).suffix(" s"));
+                                    ui.label("pause after");
+                                    ui.add(egui::DragValue::new(&mut self.histogram_view.live_pause_minutes).speed(0.1).clamp_range(0.0..=1440.0).suffix(" min"));
+                                });
+                            }
                             if !self.histogram_view.auto_update {
                                 if ui.button("Update").clicked() {
                                     self.histogram_view.update = true;
@@ -299,28 +1745,33 @@ impl eframe::App for MyApp {
                         // Update the dialog
                         self.filedialog.update(ctx);
 
-                        if let Some(path) = self.filedialog.selected(){
-                            let file = path.to_path_buf();
+                        let mut selected = self.filedialog.selected().map(|path| path.to_path_buf());
+                        if selected.is_some() {
                             self.filedialog = FileDialog::new();
+                        }
+                        if add_glob {
+                            selected = Some(std::path::PathBuf::from(self.glob_input.trim()));
+                        }
+                        if let Some(file) = selected {
                             let parquet_path = 
+                                // directories and globs are read as one table
+                                if file.is_dir() || ParsedString::is_glob(&file.to_string_lossy()) {
+                                    ParsedString::parse_table(&file.to_string_lossy()).ok()
+                                }
                                 // if file does not end in .parquet, convert to parquet
-                                if file.extension().and_then(|s| s.to_str()) != Some("parquet") {
-                                    let pp = format!("{}.parquet", file.to_string_lossy());
-                                    let mut parquet_path = ParsedString::parse(&pp).ok();
-                                    if parquet_path.is_some() 
-                                        && let Ok(st) = StrapTrack::new(&file)
-                                        && st.to_parquet(&pp, 1000).is_err()
-                                    {
-                                        // error converting to parquet
-                                        ui.label("Error converting to parquet");
-                                        parquet_path = None
+                                else if file.extension().and_then(|s| s.to_str()) != Some("parquet") {
+                                    match converted_parquet(&file, &mut self.skipped_lines) {
+                                        Ok(pp) => ParsedString::parse(&pp).ok(),
+                                        Err(e) => {
+                                            ui.label(e);
+                                            None
+                                        }
                                     }
-                                    parquet_path
                                 } else {
                                     ParsedString::parse(&file.to_string_lossy()).ok()
                                 };
                                 if let Some(parquetpath) = &parquet_path {
-                                    let columns = get_column_names(&mut self.cache, &mut self.sql, ColumnNamesInput { table: parquetpath.clone() });
+                                    let columns = get_column_names(&mut self.cache, &mut self.sql, ColumnNamesInput { table: parquetpath.clone() }, &format!("column names of {}", parquetpath));
                                     if columns.is_empty() {
                                         ui.label("No columns found in file");
                                         return;
@@ -348,231 +1799,139 @@ impl eframe::App for MyApp {
                                 }
                         }
 
-                        let mut curves_to_clone = Vec::new();
-                        let mut curves_to_remove = Vec::new();
+                        let mut actions = Vec::new();
+                        let groups = self.curve_groups();
 
                         ui.horizontal(|ui| {
-                            for curve in &mut self.histogram_view.input.curves {
-                                ui.vertical(|ui| {
+                            for index in 0..self.histogram_view.input.curves.len() {
+                                if self.group_of(index).is_none() {
+                                    self.curve_controls(ui, index, &groups, &mut actions);
+                                }
+                            }
+                        });
+
+                        for group in &groups {
+                            let members: Vec<usize> = (0..self.histogram_view.input.curves.len())
+                                .filter(|&index| self.group_of(index) == Some(group))
+                                .collect();
+                            egui::CollapsingHeader::new(format!("{} ({} curves)", group, members.len()))
+                                .id_source(format!("group_{}", group))
+                                .default_open(true)
+                                .show(ui, |ui| {
                                     ui.horizontal(|ui| {
-                                        if ui.button("Clone").clicked() {
-                                            curves_to_clone.push(curve.clone());
+                                        if ui.button("Show all").clicked() {
+                                            actions.push(CurveAction::SetGroupVisible(group.clone(), true));
                                         }
-                                        if ui.button("Remove").clicked() {
-                                            curves_to_remove.push(curve.clone());
+                                        if ui.button("Hide all").clicked() {
+                                            actions.push(CurveAction::SetGroupVisible(group.clone(), false));
                                         }
-                                    });
-                                    let parquet_path = &curve.table;
-                                    let columns = get_column_names(&mut self.cache, &mut self.sql, ColumnNamesInput { table: parquet_path.clone() });
-                                    let filename = curve.table.as_str()
-                                        .trim_matches('"')
-                                        .split('/')
-                                        .next_back()
-                                        .unwrap_or("unknown")
-                                        .replace(".parquet", "");
-                                    ui.label( filename.to_string());
-
-                                    egui::ComboBox::new(format!("x_key_{}", curve.id) ,"X Key")
-                                        .selected_text(curve.x_key.as_str())
-                                        .show_ui(ui, |ui| {
-                                            for name in columns {
-                                                ui.selectable_value(&mut curve.x_key, name.clone(), name.as_str());
-                                            }
-                                    });
-
-                                    egui::ComboBox::new(format!("y_key_{}", curve.id) ,"Y Key")
-                                        .selected_text(curve.y_key.as_str())
-                                        .show_ui(ui, |ui| {
-                                            for name in columns {
-                                                ui.selectable_value(&mut curve.y_key, name.clone(), name.as_str());
+                                        if ui.button("Remove all").clicked() {
+                                            actions.push(CurveAction::RemoveGroup(group.clone()));
+                                        }
+                                        ui.menu_button("Apply filter to all", |ui| {
+                                            for &index in &members {
+                                                let curve = &self.histogram_view.input.curves[index];
+                                                let label = format!("from curve {}: {}", curve.id, curve.filter.to_sql());
+                                                if ui.button(label).clicked() {
+                                                    actions.push(CurveAction::ApplyFilterToGroup(group.clone(), curve.id));
+                                                    ui.close_menu();
+                                                }
                                             }
+                                        });
                                     });
-
-                                    egui::ComboBox::new(format!("type_{}", curve.id),"Type")
-                                        .selected_text(curve.value_type.to_string())
-                                        .show_ui(ui, |ui| {
-                                            for name in HistogramAggregation::iter() {
-                                                ui.selectable_value(&mut curve.value_type, name, name.to_string());
-                                            }
+                                    ui.horizontal(|ui| {
+                                        for &index in &members {
+                                            self.curve_controls(ui, index, &groups, &mut actions);
+                                        }
                                     });
+                                });
+                        }
 
-                                    // Add expandable filter section
-                                    egui::CollapsingHeader::new("Filters")
-                                        .id_source(format!("filters_{}", curve.id))
-                                        .default_open(true)
-                                        .show(ui, |ui| {
-                                            // Add new filter group button
-                                            if ui.button("Add Filter Group").clicked() {
-                                                curve.filter.conditions.push(vec![]);
-                                            }
-
-                                            let mut groups_to_remove = Vec::new();
-
-                                            for (group_idx, group) in curve.filter.conditions.iter_mut().enumerate() {
-                                                ui.horizontal(|ui| {
-                                                    ui.label(format!("OR Group {}", group_idx + 1));
-                                                    if ui.button("Remove Group").clicked() {
-                                                        groups_to_remove.push(group_idx);
-                                                    }
-                                                });
-
-                                                ui.indent(format!("group_{}", group_idx), |ui| {
-                                                    // Add condition to group button
-                                                    if ui.button("Add Condition").clicked() {
-                                                        group.push(SQLFilterComparison {
-                                                            left: SQLFilterComparisonValue::Number("0".to_string()),
-                                                            comparison: SQLFilterComparisonOperation::GreaterThan,
-                                                            right: SQLFilterComparisonValue::Number("0".to_string()),
-                                                        });
-                                                    }
-
-                                                    let mut conditions_to_remove = Vec::new();
-
-                                                    for (cond_idx, condition) in group.iter_mut().enumerate() {
-                                                        ui.horizontal(|ui| {
-
-                                                            if ui.small_button("x").clicked() {
-                                                                conditions_to_remove.push(cond_idx);
-                                                            }
-
-                                                            let mut is_column = matches!(condition.right, SQLFilterComparisonValue::Column(_));
-
-                                                            ui.checkbox(&mut is_column, "Column");
-
-
-                                                            // Left side (column selection)
-                                                            egui::ComboBox::new(format!("left_{}_{}", group_idx, cond_idx), "")
-                                                                .selected_text(condition.left.to_string())
-                                                                .show_ui(ui, |ui| {
-                                                                    for col in columns {
-                                                                        ui.selectable_value(&mut condition.left, SQLFilterComparisonValue::Column(col.clone()), col.as_str());
-                                                                    }
-                                                                });
-                                                            
-                                                            // Comparison operator
-                                                            egui::ComboBox::new(format!("op_{}_{}", group_idx, cond_idx), "")
-                                                                .selected_text(condition.comparison.to_string())
-                                                                .show_ui(ui, |ui| {
-                                                                    for op in SQLFilterComparisonOperation::iter() {
-                                                                        ui.selectable_value(&mut condition.comparison, op.clone(), op.to_string());
-                                                                    }
-                                                                });
-
-                                                            if is_column {
-                                                                if let SQLFilterComparisonValue::Number(_) = condition.right {
-                                                                    // Reset to first column if previously a number
-                                                                    condition.right = SQLFilterComparisonValue::Column(columns.first().cloned().unwrap_or(ParsedString::parse("0").unwrap()));
-                                                                }
-                                                                // Column selection dropdown
-                                                                let current_col = match &condition.right {
-                                                                    SQLFilterComparisonValue::Column(col) => col.as_str(),
-                                                                    SQLFilterComparisonValue::Number(_) => columns.first().map(|c| c.as_str()).unwrap_or(""),
-                                                                };
-        
-                                                                egui::ComboBox::new(format!("right_col_{}_{}", group_idx, cond_idx),"")
-                                                                    .selected_text(current_col)
-                                                                    .show_ui(ui, |ui| {
-                                                                        for col in columns {
-                                                                            ui.selectable_value(&mut condition.right, SQLFilterComparisonValue::Column(col.clone()), col.as_str());
-                                                                        }
-                                                                    });
-                                                            }
-                                                            else {
-                                                                if let SQLFilterComparisonValue::Column(_) = condition.right {
-                                                                    // Reset to 0 if previously a column
-                                                                    condition.right = SQLFilterComparisonValue::Number("0".to_string());
-                                                                }
-                                                                // Right side is a number
-                                                                let mut value_text = if let SQLFilterComparisonValue::Number(ref num) = condition.right {
-                                                                    num.clone()
-                                                                } else {
-                                                                    "0".to_string()
-                                                                };
-                                                                if ui.add(
-                                                                    egui::TextEdit::singleline(&mut value_text)
-                                                                        .desired_width(50.0)
-                                                                ).changed() {
-                                                                    if let Ok(v) = value_text.parse::<f64>() {
-                                                                        // Valid number
-                                                                        condition.right = SQLFilterComparisonValue::Number(v.to_string());
-                                                                    }
-                                                                    else {
-                                                                        // Invalid number, reset to 0
-                                                                        condition.right = SQLFilterComparisonValue::Number("0".to_string());
-                                                                    }
-                                                                }
-                                                            }
-                                                            
-                                                        });
-
-                                                        //if cond_idx < group.len() - 1 {
-                                                        //    ui.label("OR");
-                                                        //}
-                                                    }
-
-                                                    // Remove conditions in reverse order
-                                                    for &idx in conditions_to_remove.iter().rev() {
-                                                        group.remove(idx);
-                                                    }
-                                                });
-
-                                                //if group_idx < curve.filter.conditions.len() - 1 {
-                                                //    ui.label("AND");
-                                                //}
-                                            }
-
-                                            // Remove groups in reverse order
-                                            for &idx in groups_to_remove.iter().rev() {
-                                                curve.filter.conditions.remove(idx);
-                                            }
-
-                                            // Show current filter SQL
-                                            if !curve.filter.conditions.is_empty() {
-                                                ui.label("Current filter:");
-                                                ui.code(curve.filter.to_sql());
+                        if !self.histogram_view.frozen.is_empty() {
+                            egui::CollapsingHeader::new(format!("Frozen curves ({})", self.histogram_view.frozen.len()))
+                                .id_source("frozen_curves")
+                                .default_open(true)
+                                .show(ui, |ui| {
+                                    for frozen in self.histogram_view.frozen.iter_mut() {
+                                        ui.horizontal(|ui| {
+                                            ui.checkbox(&mut frozen.visible, "Show");
+                                            ui.checkbox(&mut frozen.band, "Band");
+                                            ui.add(egui::TextEdit::singleline(&mut frozen.label).desired_width(300.0));
+                                            if ui.button("Remove").clicked() {
+                                                actions.push(CurveAction::RemoveFrozen(frozen.id));
                                             }
                                         });
-
-    
-    
-                                    //ui.label(format!("Selected: {}", self.selected));
-                                    if self.histogram_view.update {
-                                        self.histogram_view.stat = Some(get_stat(&mut self.cache, &mut self.sql, &StatInput {
-                                                table: parquet_path.clone(),
-                                                column: curve.x_key.clone(),
-                                                filters: curve.filter.clone(),
-                                        }));
-                                    }
-                                    if let(Some(stat)) = &self.histogram_view.stat {
-                                        draw_stat(
-                                            ui,
-                                            stat,
-                                        );
                                     }
                                 });
-                            }
-                        });
-
-                        for curve in curves_to_clone {
-                            let mut nc = curve.clone();
-                            nc.id = {
-                                self.global_id_counter += 1;
-                                self.global_id_counter
-                            };
-                            self.histogram_view.input.curves.push(nc);
-                        }
-                        for curve in curves_to_remove {
-                            self.histogram_view.input.curves.retain(|x| *x != curve);
                         }
 
+                        self.apply_curve_actions(actions);
+
 
+                        if self.histogram_view.update && self.histogram_view.bin_rule != BinRule::Manual {
+                            let input = &mut self.histogram_view.input;
+                            let bin_count = BinCountInput { rule: self.histogram_view.bin_rule, curves: input.range_curves(), range: input.range };
+                            if let Some(bins) = get_bin_count(&mut self.cache, &mut self.sql, &bin_count, "bin count").bins {
+                                input.bins = bins;
+                            }
+                        }
                         if self.histogram_view.update {
-                            self.histogram_view.histogram = Some(get_histogram(&mut self.cache, &mut self.sql, &self.histogram_view.input));
+                            self.histogram_view.histogram = Some(get_histogram(&mut self.cache, &mut self.sql, &self.histogram_view.input, "histogram"));
                         }
                         if let Some(hist) = &self.histogram_view.histogram {
-                            draw_histogram(ui, hist, &self.histogram_view.plot_settings);
+                            let units: Vec<_> = hist.input.curves.iter().map(|c| get_units(&mut self.cache, &c.table)).collect();
+                            draw_histogram(ui, hist, &self.histogram_view.frozen, &self.histogram_view.plot_settings, &self.histogram_view.displays, &units, self.histogram_view.plot_settings.height);
+                            let denominator = self.histogram_view.ratio_denominator
+                                .and_then(|id| hist.input.curves.iter().position(|c| c.id == id));
+                            if let Some(denominator) = denominator {
+                                draw_ratio(ui, hist, denominator, &self.histogram_view.plot_settings, &self.histogram_view.displays);
+                            }
+                            let pull = self.histogram_view.pull_curves.and_then(|(a, b)| Some((
+                                hist.input.curves.iter().position(|c| c.id == a)?,
+                                hist.input.curves.iter().position(|c| c.id == b)?,
+                            )));
+                            if let Some((a, b)) = pull {
+                                draw_pull(ui, hist, a, b, &self.histogram_view.plot_settings, &self.histogram_view.displays);
+                            }
                         }
+                        self.draw_null_warnings(ui);
                     }
+                    Operation::Series => self.show_series(ui),
+                    Operation::BoxPlot => self.show_box_plot(ui),
+                }
+
+                ui.separator();
+                // Error log, newest first
+                let errors_header = if self.sql.unseen_errors > 0 {
+                    RichText::new(format!("SQL Errors ({} new)", self.sql.unseen_errors)).color(egui::Color32::RED)
+                } else {
+                    RichText::new(format!("SQL Errors ({})", self.sql.errors.len()))
+                };
+                let errors_response = egui::CollapsingHeader::new(errors_header)
+                    .id_source("sql_errors")
+                    .default_open(false)
+                    .show(ui, |ui| {
+                        if ui.button("Clear").clicked() {
+                            self.sql.clear_errors();
+                        }
+                        for error in self.sql.errors.iter().rev() {
+                            ui.horizontal(|ui| {
+                                ui.label(format_time_of_day(error.time));
+                                if error.resolved {
+                                    ui.colored_label(egui::Color32::GRAY, format!("{} (resolved)", error.context));
+                                } else {
+                                    ui.colored_label(egui::Color32::RED, &error.context);
+                                }
+                            });
+                            ui.label(&error.error);
+                            ui.separator();
+                        }
+                        if self.sql.errors.is_empty() {
+                            ui.label("No SQL errors");
+                        }
+                    });
+                if errors_response.body_returned.is_some() {
+                    self.sql.unseen_errors = 0;
                 }
 
                 ui.separator();
@@ -580,7 +1939,7 @@ impl eframe::App for MyApp {
                 egui::CollapsingHeader::new(format!("SQL History ({} queries)", self.sql.history.len()))
                     .default_open(true)
                     .show(ui, |ui| {
-                                for ((i,query, error)) in self.sql.history.iter().rev() {
+                                for (i, query, error) in self.sql.history.iter().rev() {
                                     ui.push_id(i, |ui| {
                                         // Show query number and status
                                         let status_text = if error.is_some() { 
@@ -624,477 +1983,704 @@ impl eframe::App for MyApp {
     }
 }
 
-fn get_column_names<'a>(cache : &'a mut Cache, sql: &mut Sql, input : ColumnNamesInput) -> &'a Vec<ParsedString> {
-    if ! cache.column_names.contains_key(&input) {
-        cache.column_names.insert(input.clone(),compute_column_names(sql, &input));
-    }
-    if let Some(res) = cache.column_names.get(&input) {
-        &res.names
-    }
-    else {
-        panic!("Column names cache miss");
-    }
+/// Wall-clock time as hh:mm:ss (UTC)
+fn format_time_of_day(time: SystemTime) -> String {
+    let secs = time.duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0) % 86400;
+    format!("{:02}:{:02}:{:02}", secs / 3600, (secs / 60) % 60, secs % 60)
 }
 
-fn compute_column_names(
-    sql: &mut Sql,
-    input : &ColumnNamesInput,
-) -> ColumnNamesOutput {
-    let query = format!(
-        r#"
-        DESCRIBE SELECT * FROM {};
-       "#,&input.table.as_str()
-        ).to_string();
-    // collect errors
-    let result: duckdb::Result<ColumnNamesOutput> = (||{
-        let mut stmt = sql.prepare(&query)?;
-        let column_names = stmt.query_map(params![], |row| {
-            ParsedString::parse(&row.get::<_, String>(0)?)
-            //Ok(row.get::<_, String>(1)?)
-        })?
-        .collect::<duckdb::Result<Vec<_>>>()?;
-        Ok(ColumnNamesOutput { names: column_names })
-    })();
-    match result {
-        Ok(res) => {
-            sql.push_history(
-                query.clone(), None
-            );
-            res
-        },
-        Err(e) => {
-            sql.push_history(
-                query.clone(), Some(format!("Error computing column names: {:?}", e))
-            );
-            ColumnNamesOutput { names : vec![] }
-        }
-    }
+fn draw_stat(ui: &mut egui::Ui, stat : & StatOutput, unit: Option<&str>) {
+    // the count has no unit
+    let unit = unit.filter(|u| *u != "1").map(|u| format!(" {}", u)).unwrap_or_default();
+    ui.label(format!("Sum: {}{}", format_value(stat.sum), unit));
+    ui.label(format!("Count: {}", stat.count));
+    ui.label(format!("Mean: {}{}", format_value(stat.mean), unit));
+    ui.label(format!("Std Dev: {}{}", format_value(stat.stddev), unit));
+    ui.label(format!("Min: {}{}", format_value(stat.min), unit));
+    ui.label(format!("Max: {}{}", format_value(stat.max), unit));
 }
 
-struct ColumnNamesOutput {
-    names : Vec<ParsedString>,
+/// Sortable grid of the column summary, a click on a column name is reported through `x_key`
+fn draw_summary(ui: &mut egui::Ui, summary: &SummaryOutput, view: &mut SummaryView, x_key: &mut Option<ParsedString>) {
+    let mut rows: Vec<_> = summary.rows.iter().zip(summary.cells()).collect();
+    rows.sort_by(|(a, a_cells), (b, b_cells)| {
+        let order = match view.sort_by {
+            2 => a.count.cmp(&b.count),
+            3 => a.null_percentage.total_cmp(&b.null_percentage),
+            6 => a.mean.unwrap_or(f64::NAN).total_cmp(&b.mean.unwrap_or(f64::NAN)),
+            7 => a.stddev.unwrap_or(f64::NAN).total_cmp(&b.stddev.unwrap_or(f64::NAN)),
+            8 => a.approx_distinct.cmp(&b.approx_distinct),
+            i => a_cells[i].cmp(&b_cells[i]),
+        };
+        if view.descending { order.reverse() } else { order }
+    });
+    egui::Grid::new("summary_grid").striped(true).show(ui, |ui| {
+        for (i, title) in SummaryOutput::HEADER.iter().enumerate() {
+            let arrow = match (view.sort_by == i, view.descending) {
+                (true, false) => " ⏶",
+                (true, true) => " ⏷",
+                _ => "",
+            };
+            if ui.button(format!("{}{}", title, arrow)).clicked() {
+                view.descending = view.sort_by == i && !view.descending;
+                view.sort_by = i;
+            }
+        }
+        ui.end_row();
+        for (row, cells) in rows {
+            let response = ui.selectable_label(false, &row.column)
+                .on_hover_text("Use as X key");
+            if response.clicked() {
+                *x_key = ParsedString::parse(&row.column).ok();
+            }
+            for cell in &cells[1..] {
+                ui.label(cell);
+            }
+            ui.end_row();
+        }
+    });
 }
 
-#[derive(Hash, Eq, PartialEq, Clone)]
-struct ColumnNamesInput {
-    table : ParsedString,
+/// Selectable column with its null fraction and NaN/inf count, which are only queried once the entry is visible
+fn column_entry(ui: &mut egui::Ui, cache: &mut Cache, sql: &mut Sql, table: &ParsedString, name: &ParsedString, selected: &mut ParsedString) {
+    let input = NullFractionInput { table: table.clone(), column: name.clone() };
+    let mut text = name.as_str().to_string();
+    if let Some(null) = cache.null_fraction.get(&input) {
+        if null.fraction >= 0.005 {
+            text += &format!(" — {:.0}% null", null.fraction * 100.0);
+        }
+        if null.non_finite > 0 {
+            text += &format!(" — {} NaN/inf", null.non_finite);
+        }
+    }
+    let response = ui.selectable_value(selected, name.clone(), text);
+    if !cache.null_fraction.contains_key(&input) && ui.is_rect_visible(response.rect) {
+        get_null_fraction(cache, sql, &input, &format!("null fraction of {} in {}", name, table));
+    }
 }
 
-
-struct Cache {
-    column_names : HashMap<ColumnNamesInput, ColumnNamesOutput>,
-    histogram : HashMap<HistogramInput, HistogramOutput>,
-    stat : HashMap<StatInput, StatOutput>,
+fn draw_xy_stat(ui: &mut egui::Ui, stat : &XYStatOutput) {
+    match stat.corr {
+        Some(corr) => ui.label(format!("ρ(x,y) = {:.2} ({} pairs)", corr, stat.pairs)),
+        None => ui.label(format!("ρ(x,y) = n/a ({} pairs)", stat.pairs)),
+    };
+    if let Some(covar) = stat.covar {
+        ui.label(format!("Cov(x,y): {:.4}", covar));
+    }
 }
 
+fn transpose<T: Clone>(matrix: Vec<Vec<T>>) -> Vec<Vec<T>> {
+    if matrix.is_empty() || matrix[0].is_empty() {
+        return vec![];
+    }
 
-#[derive(Hash, Eq, PartialEq, Clone)]
-struct HistogramInput {
-    bins: usize,
-    curves : Vec<HistogramSubInput>,
-}
-
+    let n = matrix.len();
+    let m = matrix[0].len();
 
-#[derive(Hash, Eq, PartialEq, Clone)]
-struct HistogramSubInput {
-    id : usize,
-    table : ParsedString,
-    filter : SQLFilter,
-    x_key : ParsedString,
-    value_type: HistogramAggregation,
-    y_key : ParsedString,
+    (0..m)
+        .map(|i| (0..n).map(|j| matrix[j][i].clone()).collect())
+        .collect()
 }
 
-#[derive(Copy, Hash, Eq, PartialEq, Clone, Display,EnumIter)]
-enum HistogramAggregation{
-    Count,
-    Sum,
-    Avg,
+/// Same colors egui_plot picks automatically, made explicit so bands and lines of a curve match
+fn curve_color(i: usize) -> egui::Color32 {
+    let golden_ratio = (5.0_f32.sqrt() - 1.0) / 2.0;
+    egui::ecolor::Hsva::new(i as f32 * golden_ratio, 0.85, 0.5, 1.0).into()
 }
 
-//#[derive(Copy, Hash, Eq, PartialEq, Clone, Display,EnumIter)]
-//enum HistogramAxisScale {
-//    Linear,
-//    //Log, // egui plot not supported yet: https://github.com/emilk/egui_plot/pull/29
-//}
-
-//#[derive(Copy, Hash, Eq, PartialEq, Clone, Display,EnumIter)]
-//enum HistogramBinScale{
-//    Linear,
-//    //Log, // TODO SQL
-//}
-
-//#[derive(Hash, Eq, PartialEq, Clone, Display)]
-//enum HistorgramValueType {
-//    #[strum(to_string = "Count({0})")]
-//    Count(ParsedString),
-//    #[strum(to_string = "Sum({0})")]
-//    Sum(ParsedString),
-//    #[strum(to_string = "Avg({0})")]
-//    Avg(ParsedString),
-//}
-
-#[derive(Clone)]
-struct HistogramOutput {
-    // (bin_center, bin_width, count, stddev)
-    data : Vec<(f64, f64, Vec<(f64, f64)>)>,
-    input : HistogramInput,
+/// Runs of consecutive non-empty bins of a curve as (center, width, value, error),
+/// so a band is split into separate polygons at empty bins
+fn band_segments(hist : &HistogramOutput, curve: usize) -> Vec<Vec<(f64, f64, f64, f64)>> {
+    let bins: Vec<_> = hist.data.iter().map(|(x, w, values)| (*x, *w, values[curve].0, values[curve].1)).collect();
+    split_segments(&bins)
 }
 
-
-fn get_histogram<'a>(cache : & mut Cache, sql: &mut Sql, input : & HistogramInput) -> HistogramOutput {
-    if !cache.histogram.contains_key(input) {
-        cache.histogram.insert(input.clone(), compute_histogram(sql, input));
-    }
-    if let Some(res) = cache.histogram.get(input) {
-        res.clone()
+fn split_segments(bins: &[(f64, f64, f64, f64)]) -> Vec<Vec<(f64, f64, f64, f64)>> {
+    let mut segments = vec![];
+    let mut segment = vec![];
+    for &(x, w, y, h) in bins {
+        if y == 0.0 && h == 0.0 {
+            if !segment.is_empty() {
+                segments.push(std::mem::take(&mut segment));
+            }
+        } else {
+            segment.push((x, w, y, h));
+        }
     }
-    else {
-        panic!("Histogram cache miss");
+    if !segment.is_empty() {
+        segments.push(segment);
     }
+    segments
 }
 
-fn compute_histogram(
-    sql: &mut Sql,
-    hist : &HistogramInput,
-) -> HistogramOutput {
-    if hist.curves.is_empty() {
-        return HistogramOutput { data : vec![], input: hist.clone() };
-    }
-    let mut filters:String= String::new();
-    let mut hists = Vec::new();
-    let mut coalesced = String::new();
-    let mut joins = String::new();
-    for (i, c) in hist.curves.iter().enumerate() {
-        let y_value = match c.value_type {
-            HistogramAggregation::Count => format!("COUNT({})", c.y_key),
-            HistogramAggregation::Sum => format!("SUM({})", c.y_key),
-            HistogramAggregation::Avg => format!("AVG({})", c.y_key),
-        };
-        let y_error= match c.value_type {
-            HistogramAggregation::Count => format!("SQRT(COUNT({}))", c.y_key),
-            HistogramAggregation::Sum => format!("STDDEV({})", c.y_key),
-            HistogramAggregation::Avg => format!("STDDEV({})", c.y_key),
-        };
-        filters.push_str(
-            format!(
-                r#"
-filtered_{} AS (
-    SELECT *
-    FROM {}
-    WHERE ( {} IS NOT NULL AND {} IS NOT NULL ) {} 
-),
-                "#,i, c.table.as_str(), c.x_key.as_str(), c.y_key.as_str(), c.filter.to_sql_and_prefix()
-            ).as_str()
-        );
-
-        hists.push(
-            format!(
-                r#"
-hist_{} AS (
-    SELECT 
-        LEAST(stats.n_bins - 1,
-              CAST(FLOOR((t.{} - stats.min_val) / ((stats.max_val - stats.min_val) / stats.n_bins)) AS INTEGER)
-        ) AS bucket,
-        {} AS yvalue,
-        {} AS yerror,
-    FROM filtered_{} as t
-    JOIN stats ON TRUE
-    GROUP BY bucket
-)
-                "#,i, c.x_key.as_str(), y_value, y_error, i
-            ).to_string()
-        );
-        coalesced.push_str(
-            format!(
-                r#"
-                COALESCE(h{}.yvalue, 0) AS yvalue_{},
-                COALESCE(h{}.yerror, 0) AS yerror_{},
-                "#, i, i, i, i
-            ).as_str()
-        );
-        joins.push_str(
-            format!(
-                r#"
-LEFT JOIN hist_{} AS h{} ON h{}.bucket = b.bucket
-                "#, i, i, i
-            ).as_str()
-        );                
+/// Drag value of an optional bound, the checkbox switches between auto and `initial`
+fn optional_value(ui: &mut egui::Ui, value: &mut Option<f64>, initial: f64) {
+    let mut set = value.is_some();
+    if ui.checkbox(&mut set, "").on_hover_text("Unchecked follows the data").changed() {
+        *value = set.then_some(initial);
     }
-    let x_keys = hist.curves.iter().map(|c| c.x_key.as_str()).collect::<Vec<_>>().join(", ");
-    let combined = hist.curves.iter().enumerate().map(|(i, _c)| 
-            format!(
-                r#"
-SELECT * FROM filtered_{}
-
-                "#, i
-            ).to_string()
-        ).collect::<Vec<_>>().join("UNION ALL");
-    let mid = format!(
-        r#"
-stats AS (
-    SELECT 
-        MIN(LEAST({})) AS min_val,
-        MAX(GREATEST({})) AS max_val,
-    {} AS n_bins
-    FROM combined
-),
-buckets AS (
-    SELECT
-        g.bucket,
-        stats.min_val +
-        (g.bucket + 0.5) * ((stats.max_val - stats.min_val) / stats.n_bins)
-        AS midpoint,
-        (stats.max_val - stats.min_val) / stats.n_bins AS width
-    FROM stats
-    JOIN generate_series(0, stats.n_bins - 1) AS g(bucket)
-    ON TRUE
-),
-        "#,
-        x_keys,
-        x_keys,
-        hist.bins as i64
-    );
-    let query = 
-        format!(
-        r#"
-WITH
-        {}
-combined AS (
-        {}
-),
-        {}
-        {}
-SELECT
-    b.bucket,
-    b.midpoint,
-    b.width,
-    {}
-FROM buckets AS b
-        {}
-ORDER BY b.bucket
-        "#,filters, combined, mid, hists.join(","),coalesced, joins
-    ).to_string();
-    let result :duckdb::Result<HistogramOutput> = (|| {
-        let stmt = sql.prepare(&query)?.query_map(params![ ], |row| {
-            let bin_center = row.get::<_, f64>(1)?;
-            let bin_width = row.get::<_, f64>(2)?;
-            let mut values = Vec::new();
-            let n_curves = hist.curves.len();
-            for i in 0..n_curves {
-                let y_value = row.get::<_, f64>(3 + i * 2)?;
-                let y_error = row.get::<_, f64>(4 + i * 2)?;
-                values.push((y_value, y_error));
-            }
-            Ok((
-                bin_center,
-                bin_width,
-                values,
-            ))
-        })?
-        .collect::<duckdb::Result<Vec<_>>>()?;
-        Ok(HistogramOutput { data: stmt, input: hist.clone() })
-    })();
-    match result {
-        Ok(res) => {
-            sql.push_history(
-                query.clone(), None
-            );
-            res
-        },
-        Err(e) => {
-            sql.push_history(
-                query.clone(), Some(format!("Error computing histogram: {:?}", e))
-            );
-            HistogramOutput { data : vec![], input: hist.clone() }
+    match value {
+        Some(value) => {
+            let speed = (value.abs() * 0.01).max(0.01);
+            ui.add(egui::DragValue::new(value).speed(speed));
+        }
+        None => {
+            ui.label("auto");
         }
     }
 }
 
-#[derive(Hash, Eq, PartialEq, Clone)]
-struct StatInput {
-    table : ParsedString,
-    column : ParsedString,
-    filters : SQLFilter,
+/// Description of a curve as used in the legend and exports, e.g. "Count of y vs x (run)"
+fn curve_title(curve: &HistogramSubInput) -> String {
+    format!("{} of {} vs {} ({})", curve.value_type.name(), curve.y_key.name(), curve.x_key.name(), table_label(&curve.table))
 }
 
-#[derive(Clone)]
-struct StatOutput {
-    sum: f64,
-    count: usize,
-    mean: f64,
-    stddev: f64,
-    min : f64,
-    max : f64,
+/// File name of a table without directory and extension
+fn table_label(table: &ParsedString) -> String {
+    table.files().iter()
+        .map(|name| {
+            // a directory scan is labelled by the directory
+            name.strip_suffix("/*.parquet")
+                .unwrap_or(name)
+                .split('/')
+                .next_back()
+                .unwrap_or("unknown")
+                .replace(".parquet", "")
+        })
+        .collect::<Vec<_>>()
+        .join(" + ")
 }
 
-fn get_stat<'a>(cache : &'a mut Cache, sql: &mut Sql, input: &StatInput) ->  StatOutput {
-    if !cache.stat.contains_key(input) {
-        cache.stat.insert(input.clone(), compute_stat(sql, input));
+/// Parquet file of `file`, STRAP files are converted next to it unless an up-to-date conversion exists.
+/// Row lines a conversion loses are counted in `skipped_lines`.
+fn converted_parquet(file: &std::path::Path, skipped_lines: &mut HashMap<String, usize>) -> Result<String, String> {
+    if file.extension().and_then(|s| s.to_str()) == Some("parquet") {
+        return Ok(file.to_string_lossy().to_string());
+    }
+    let pp = format!("{}.parquet", file.to_string_lossy());
+    // reuse a parquet file converted from the same content
+    if !is_up_to_date(file, std::path::Path::new(&pp)) {
+        let stats = StrapTrack::stream_to_parquet(file, &pp, 1000)
+            .map_err(|e| match e {
+                StrapError::Parse { line, content, reason } => format!("Error at line {}: {} ({})", line, reason, content),
+                StrapError::Io(e) => format!("Error reading {}: {}", file.to_string_lossy(), e),
+                e => format!("Error converting to parquet: {}", e),
+            })?;
+        if stats.lost > 0 {
+            skipped_lines.insert(pp.clone(), stats.lost);
+        } else {
+            skipped_lines.remove(&pp);
+        }
     }
-    if let Some(res) = cache.stat.get(input) {
-        res.clone()
+    Ok(pp)
+}
+
+/// Range of the drawn error bands of the given curves, always including zero.
+/// `bounds` gives the ends of the error bar of a curve's (value, error).
+fn value_range(hist : &HistogramOutput, curves: &[usize], bounds: impl Fn(usize, f64, f64) -> (f64, f64)) -> Option<(f64, f64)> {
+    if curves.is_empty() {
+        return None;
     }
-    else {
-        panic!("Stat cache miss");
+    let mut range = (0.0f64, 0.0f64);
+    for (_, _, values) in &hist.data {
+        for &i in curves {
+            let (y, h) = values[i];
+            let (low, high) = bounds(i, y, h);
+            range.0 = range.0.min(low);
+            range.1 = range.1.max(high);
+        }
     }
+    Some(range)
+}
+
+/// Axis label of a log scale, only whole powers of ten are labelled
+fn power_of_ten(mark: GridMark, _: usize, _: &std::ops::RangeInclusive<f64>) -> String {
+    if mark.value.fract() == 0.0 { format!("10^{}", mark.value as i64) } else { String::new() }
 }
 
-fn compute_stat(
-    sql: &mut Sql,
-    stat_input : &StatInput,
-) -> StatOutput {
-    let query= 
-        format!(
-        r#"
-        SELECT 
-            SUM(t.{}) as sum,
-            COUNT(t.{}) as count, 
-            AVG(t.{}) as mean,
-            STDDEV(t.{}) as stddev,
-            MIN(t.{}) as min,
-            MAX(t.{}) as max
-        FROM {} AS t
-        {}
-       "#,
-        stat_input.column,
-        stat_input.column,
-        stat_input.column,
-        stat_input.column,
-        stat_input.column,
-        stat_input.column,
-        stat_input.table ,
-        stat_input.filters.to_sql_where_prefix()
-        ).to_string();
-    let result = (||{
-        let stmt = sql.prepare(&query)?.query_map(params![ ], |row| {
-            Ok(StatOutput {
-                sum: row.get(0)?,
-                count: row.get(1)?,
-                mean: row.get(2)?,
-                stddev: row.get(3)?,
-                min: row.get(4)?,
-                max: row.get(5)?,
+/// Ratio of the visible curves to curve `denominator` per bin, drawn as points with
+/// errors under the histogram. Bins where the denominator is 0 are left empty.
+fn draw_ratio(ui: &mut egui::Ui,
+              hist : &HistogramOutput,
+              denominator: usize,
+              plot_settings: &HistrogramPlotSettings,
+              displays: &HashMap<usize, CurveDisplay>,
+    ) {
+    if plot_settings.orientation == HistogramOrientation::Horizontal {
+        ui.label("The ratio panel is only drawn below vertical histograms");
+        return;
+    }
+    // the same values as drawn in the histogram
+    let hist = hist.normalized(&normalization_modes(hist, displays));
+    let scale = plot_settings.x_axis_scale;
+
+    let mut lines: Vec<Line> = vec![];
+    let mut points: Vec<Points> = vec![];
+    let numerators = (0..hist.input.curves.len())
+        .filter(|&i| i != denominator && displays.get(&hist.input.curves[i].id).is_none_or(|d| d.visible));
+    for i in numerators {
+        let name = format!("{}. / {}.", i + 1, denominator + 1);
+        let color = curve_color(i);
+        let bins: Vec<(f64, f64, f64)> = hist.data.iter().zip(hist.ratio(i, denominator))
+            .filter_map(|((x, w, _), ratio)| {
+                let (ratio, error) = ratio?;
+                let (center, _) = scale.bin(*x, *w)?;
+                Some((center, ratio, error))
             })
-        })?
-        .next();
+            .collect();
+        points.push(Points::new(PlotPoints::new(bins.iter().map(|&(x, r, _)| [x, r]).collect()))
+            .name(&name)
+            .color(color)
+            .shape(MarkerShape::Circle)
+            .radius(3.0));
+        for &(x, r, e) in &bins {
+            lines.push(Line::new(PlotPoints::new(vec![[x, r - e/2.], [x, r + e/2.]]))
+                .name(&name)
+                .color(color));
+        }
+    }
 
-        if let Some(stat) = stmt {
-            stat
-        } else {
-            Err(duckdb::Error::QueryReturnedNoRows)
+    let mut plot = Plot::new("ratio")
+        .height(plot_settings.height / 3.)
+        .legend(Legend::default())
+        .link_axis(egui::Id::new("histogram_bins"), true, false)
+        .y_axis_label(format!("Ratio to curve {}", denominator + 1));
+    if scale == HistogramAxisScale::Log {
+        plot = plot.x_axis_formatter(power_of_ten);
+    }
+    plot.show(ui, |plot_ui| {
+        plot_ui.hline(HLine::new(1.0).color(egui::Color32::GRAY));
+        for line in lines {
+            plot_ui.line(line);
         }
-    })();
-    match result {
-        Ok(res) => {
-            sql.push_history(
-                query.clone(), None
-            );
-            res
-        },
-        Err(e) => {
-            sql.push_history(
-                query.clone(), Some(format!("Error computing stat: {:?}", e))
-            );
-            StatOutput { sum: 0.0, count: 0, mean: 0.0, stddev: 0.0, min: 0.0, max: 0.0 }
+        for p in points {
+            plot_ui.points(p);
         }
-    }
+    });
+}
 
+/// Pull of curve `a` against curve `b` per bin as bars around zero, with guide lines
+/// at ±1 and ±2. Bins where both errors are 0 are skipped.
+fn draw_pull(ui: &mut egui::Ui,
+             hist : &HistogramOutput,
+             a: usize,
+             b: usize,
+             plot_settings: &HistrogramPlotSettings,
+             displays: &HashMap<usize, CurveDisplay>,
+    ) {
+    if plot_settings.orientation == HistogramOrientation::Horizontal {
+        ui.label("The pull panel is only drawn below vertical histograms");
+        return;
+    }
+    let hist = hist.normalized(&normalization_modes(hist, displays));
+    let scale = plot_settings.x_axis_scale;
+    let bars: Vec<Bar> = hist.data.iter().zip(hist.pull(a, b))
+        .filter_map(|((x, w, _), pull)| {
+            let pull = pull?;
+            let (center, width) = scale.bin(*x, *w)?;
+            Some(Bar::new(center, pull)
+                .width(width)
+                .name(format!("Pull: {:.3}\nRange: [{:.3}, {:.3}]", pull, x - w/2., x + w/2.)))
+        })
+        .collect();
+    let chart = BarChart::new(bars)
+        .name(format!("({}. − {}.) / σ", a + 1, b + 1))
+        .color(curve_color(a))
+        .element_formatter(Box::new(|bar, _chart| bar.name.clone()));
+
+    let mut plot = Plot::new("pull")
+        .height(plot_settings.height / 3.)
+        .legend(Legend::default())
+        .link_axis(egui::Id::new("histogram_bins"), true, false)
+        .y_axis_label("Pull");
+    if scale == HistogramAxisScale::Log {
+        plot = plot.x_axis_formatter(power_of_ten);
+    }
+    plot.show(ui, |plot_ui| {
+        for (level, style) in [(1.0, egui_plot::LineStyle::Solid), (2.0, egui_plot::LineStyle::dashed_loose())] {
+            for sign in [-1.0, 1.0] {
+                plot_ui.hline(HLine::new(sign * level).color(egui::Color32::GRAY).style(style));
+            }
+        }
+        plot_ui.bar_chart(chart);
+    });
 }
 
-fn draw_stat(ui: &mut egui::Ui, stat : & StatOutput ) {
-    ui.label(format!("Sum: {:.4}", stat.sum));
-    ui.label(format!("Count: {}", stat.count));
-    ui.label(format!("Mean: {:.4}", stat.mean));
-    ui.label(format!("Std Dev: {:.4}", stat.stddev));
-    ui.label(format!("Min: {:.4}", stat.min));
-    ui.label(format!("Max: {:.4}", stat.max));
+/// Normalization chosen for each curve of `hist`, as drawn
+fn normalization_modes(hist : &HistogramOutput, displays: &HashMap<usize, CurveDisplay>) -> Vec<Normalization> {
+    hist.input.curves.iter()
+        .map(|c| displays.get(&c.id).map_or(Normalization::None, |display| display.normalization))
+        .collect()
 }
 
-fn transpose<T: Clone>(matrix: Vec<Vec<T>>) -> Vec<Vec<T>> {
-    if matrix.is_empty() || matrix[0].is_empty() {
+/// Evenly spaced 1/2/5 ticks covering [min, max] with their labels
+fn nice_ticks(min: f64, max: f64, target: usize) -> Vec<(f64, String)> {
+    if !min.is_finite() || !max.is_finite() || max <= min {
         return vec![];
     }
-
-    let n = matrix.len();
-    let m = matrix[0].len();
-
-    (0..m)
-        .map(|i| (0..n).map(|j| matrix[j][i].clone()).collect())
-        .collect()
+    let raw_step = (max - min) / target.max(1) as f64;
+    let magnitude = 10f64.powf(raw_step.log10().floor());
+    let step = [1.0, 2.0, 5.0, 10.0].iter()
+        .map(|f| f * magnitude)
+        .find(|s| *s >= raw_step)
+        .unwrap_or(10.0 * magnitude);
+    let decimals = (-step.log10().floor()).max(0.0) as usize;
+    let mut ticks = vec![];
+    let mut tick = (min / step).ceil() * step;
+    while tick <= max {
+        ticks.push((tick, format!("{:.*}", decimals, tick)));
+        tick += step;
+    }
+    ticks
 }
 
-fn draw_histogram<'a>(ui: &mut egui::Ui, 
+fn draw_histogram(ui: &mut egui::Ui, 
                       //cache : &'a mut Cache,
                       //sql: &mut Sql,
                       //input : &'a HistogramInput,
                       hist : &HistogramOutput,
+                      frozen : &[FrozenCurve],
                       plot_settings: &HistrogramPlotSettings,
+                      displays: &HashMap<usize, CurveDisplay>,
+                      // declared column units of each curve of hist
+                      units: &[HashMap<String, String>],
+                      height: f32,
     ) {
-    if hist.input.curves.is_empty() {
+    if hist.input.curves.is_empty() && frozen.is_empty() {
         ui.label("No histogram curves to display");
         return;
     }
+    let modes = normalization_modes(hist, displays);
+    // Poisson intervals are taken of the raw counts and scaled like the values
+    let factors: Vec<f64> = (0..hist.input.curves.len())
+        .map(|i| modes[i].factor(&hist.data.iter().map(|(_, w, values)| (*w, values[i].0)).collect::<Vec<_>>()))
+        .collect();
+    let normalized = hist.normalized(&modes);
+    let hist = &normalized;
+    let horizontal = plot_settings.orientation == HistogramOrientation::Horizontal;
+    // bins are placed on the scale of the bin axis, tooltips show their true range
+    let scale = plot_settings.x_axis_scale;
+    let on_scale = |bins: &[(f64, f64, f64, f64)]| -> Vec<(f64, f64, f64, f64)> {
+        bins.iter().filter_map(|&(x, w, y, h)| scale.bin(x, w).map(|(x, w)| (x, w, y, h))).collect()
+    };
+    let clipped = hist.data.iter().filter(|(x, w, _)| scale.bin(*x, *w).is_none()).count();
+
+    // Right-axis curves are rescaled into the range of the left-axis curves,
+    // only if both axes are in use.
+    let display_of = |i: usize| displays.get(&hist.input.curves[i].id).cloned().unwrap_or_default();
+    let axis_of = |i: usize| display_of(i).axis;
+    let visible: Vec<usize> = (0..hist.input.curves.len()).filter(|&i| display_of(i).visible).collect();
+    let poisson: Vec<bool> = (0..hist.input.curves.len())
+        .map(|i| display_of(i).poisson_errors && hist.input.curves[i].value_type == HistogramAggregation::Count)
+        .collect();
+    // ends of the error bar of a value, the error is the full bar length unless Poisson
+    let bounds = |i: usize, y: f64, h: f64| if poisson[i] {
+        let (low, high) = poisson_interval(y / factors[i]);
+        (low * factors[i], high * factors[i])
+    } else {
+        (y - h/2., y + h/2.)
+    };
+    let (left, right): (Vec<usize>, Vec<usize>) = visible.iter().partition(|&&i| axis_of(i) == ValueAxis::Left);
+    // Stacking sums the values of the left-axis bar curves per bin; all curves of a
+    // histogram share its bins, so they can always be stacked
+    let stacked: Vec<usize> = match (plot_settings.display_mode, plot_settings.stacking) {
+        (DisplayMode::Bars, BarStacking::Stacked) => left.iter().copied().filter(|&i| display_of(i).style == CurveStyle::Bars).collect(),
+        _ => vec![],
+    };
+    let totals: Vec<f64> = hist.data.iter().map(|(_, _, values)| stacked.iter().map(|&i| values[i].0).sum()).collect();
+    let left_range = value_range(hist, &left, bounds)
+        .map(|range| totals.iter().fold(range, |(low, high), &total| (low.min(total), high.max(total))));
+    let mapping = match (left_range, value_range(hist, &right, bounds)) {
+        (Some(left_range), Some(right_range)) => Some(AxisMapping::new(left_range, right_range)),
+        _ => None,
+    };
+    let mapping_of = |i: usize| if axis_of(i) == ValueAxis::Right { mapping } else { None };
+
+    // The bin center is always the bar argument and the error band the bar value,
+    // horizontal bars just lay the argument along the y axis instead of the x axis.
+    // Tooltips always show the true values.
     let bars: Vec<Vec<Bar>> = transpose(hist.data
         .iter()
-        .map(|(x,w , values)| 
-            values.iter().map(|(y, h)| {
-                Bar::new(*x, *h)
-                    .width(*w)
-                    .base_offset(y-h/2.)
-                    .name(format!("Value: {:.3} ± {:.3}\nRange: [{:.3}, {:.3}]\nWidth: {:.3}", 
-                                 y, h, x - w/2., x + w/2., w))
-                } ).collect()
-            )
+        .filter_map(|(x,w , values)| {
+            let (center, width) = scale.bin(*x, *w)?;
+            Some(values.iter().enumerate().map(|(i, (y, h))| {
+                let (low, high) = bounds(i, *y, *h);
+                let (base, size) = match mapping_of(i) {
+                    Some(m) => (m.to_left(low), m.scale * (high - low)),
+                    None => (low, high - low),
+                };
+                let error = if poisson[i] {
+                    format!("+{:.3} −{:.3}", high - y, y - low)
+                } else {
+                    format!("± {:.3}", h)
+                };
+                let bar = Bar::new(center, size)
+                    .width(width)
+                    .base_offset(base)
+                    .name(format!("Value: {:.3} {}\nRange: [{:.3}, {:.3}]\nWidth: {:.3}", 
+                                 y, error, x - w/2., x + w/2., w));
+                if horizontal {
+                    bar.horizontal()
+                } else {
+                    bar
+                }
+                } ).collect())
+            })
         .collect());
 
     // add names
-    let charts: Vec<BarChart> = bars.iter()
-    .enumerate()
-    .map(|(i, bar_group)| {
-        let curve = &hist.input.curves[i];
-        // Extract just the filename without path and extension
-        let filename = curve.table.as_str()
-            .trim_matches('"')
-            .split('/')
-            .next_back()
-            .unwrap_or("unknown")
-            .replace(".parquet", "");
-        let legend_name = format!("{}. {} of {} vs {} ({})", 
-                                 i + 1,
-                                 curve.value_type, 
-                                 curve.y_key.as_str().trim_matches('"'), 
-                                 curve.x_key.as_str().trim_matches('"'),
-                                 filename);
-                            
-        
-        BarChart::new(bar_group.clone())
-            .name(legend_name)  // Each curve gets its own descriptive name
-            .element_formatter(Box::new(|bar, _chart| bar.name.clone()))
-    }).collect();
-
-
-    Plot::new("histogram")
-        .height(400.0)
-        .legend(Legend::default())
-        .x_axis_label(
-            hist.input.curves.iter().map(|c| c.x_key.as_str()).collect::<Vec<_>>().as_slice().join(" / ")
-        )
-        // TODO move axis labels to legend
-        .y_axis_label(
-            hist.input.curves.iter().map(|c| 
-                match c.value_type {
-                    HistogramAggregation::Count => "COUNT(".to_owned() +c.y_key.as_str() + ")",
-                    HistogramAggregation::Avg => "AVG(".to_owned() + c.y_key.as_str() + ")",
-                    HistogramAggregation::Sum => "SUM(".to_owned() + c.y_key.as_str() + ")",
+    let legend_name_of = |i: usize| {
+        let axis_suffix = if mapping_of(i).is_some() { " [right axis]" } else { "" };
+        let group_prefix = match display_of(i).group {
+            Some(group) if plot_settings.group_legend => format!("[{}] ", group),
+            _ => String::new(),
+        };
+        let normalization = match modes[i] {
+            Normalization::None => String::new(),
+            mode => format!(" ({})", mode),
+        };
+        format!("{}{}. {}{}{}", group_prefix, i + 1, curve_title(&hist.input.curves[i]), normalization, axis_suffix)
+    };
+    let style_of = |i: usize| display_of(i).style;
+    let mut charts: Vec<BarChart> = match plot_settings.display_mode {
+        DisplayMode::Bars => bars.iter()
+            .enumerate()
+            .filter(|(i, _)| visible.contains(i) && style_of(*i) == CurveStyle::Bars && !stacked.contains(i))
+            .map(|(i, bar_group)| {
+                BarChart::new(bar_group.clone())
+                    .name(legend_name_of(i))  // Each curve gets its own descriptive name
+                    .color(curve_color(i))
+                    .element_formatter(Box::new(|bar, _chart| bar.name.clone()))
+            }).collect(),
+        DisplayMode::Band | DisplayMode::LineBand => vec![],
+    };
+    // stacked bars are the values themselves, each starting on top of the previous curve
+    let mut base = vec![0.0; hist.data.len()];
+    for &i in &stacked {
+        let mut stack = vec![];
+        for (bin, (x, w, values)) in hist.data.iter().enumerate() {
+            let y = values[i].0;
+            if let Some((center, width)) = scale.bin(*x, *w) {
+                let bar = Bar::new(center, y)
+                    .width(width)
+                    .base_offset(base[bin])
+                    .name(format!("Value: {:.3}\nStack: {:.3}\nRange: [{:.3}, {:.3}]\nWidth: {:.3}",
+                                 y, base[bin] + y, x - w/2., x + w/2., w));
+                stack.push(if horizontal { bar.horizontal() } else { bar });
+            }
+            base[bin] += y;
+        }
+        charts.push(BarChart::new(stack)
+            .name(legend_name_of(i))
+            .color(curve_color(i))
+            .element_formatter(Box::new(|bar, _chart| bar.name.clone())));
+    }
+
+    // Bands, lines and points share the legend name so they toggle together with the curve
+    let mut bands: Vec<Polygon> = vec![];
+    let mut lines: Vec<Line> = vec![];
+    let mut points: Vec<Points> = vec![];
+    // only the total of a stack gets an error bar, the component errors added as independent
+    if !stacked.is_empty() {
+        let point = |x: f64, y: f64| if horizontal { [y, x] } else { [x, y] };
+        for ((x, w, values), total) in hist.data.iter().zip(&totals) {
+            let Some((center, _)) = scale.bin(*x, *w) else { continue };
+            let error = stacked.iter().map(|&i| values[i].1.powi(2)).sum::<f64>().sqrt();
+            lines.push(Line::new(PlotPoints::new(vec![point(center, total - error/2.), point(center, total + error/2.)]))
+                .name("Stack total")
+                .color(ui.visuals().text_color()));
+        }
+    }
+    for &i in &visible {
+        let display = display_of(i);
+        let color = curve_color(i);
+        let name = legend_name_of(i);
+        let to_left = |v: f64| mapping_of(i).map(|m| m.to_left(v)).unwrap_or(v);
+        let point = |x: f64, y: f64| if horizontal { [to_left(y), x] } else { [x, to_left(y)] };
+        let band = match display.style {
+            CurveStyle::Bars => plot_settings.display_mode != DisplayMode::Bars,
+            CurveStyle::Steps => display.band,
+            CurveStyle::Points => false,
+        };
+        if band {
+            for segment in band_segments(hist, i) {
+                let segment = on_scale(&segment);
+                let upper = segment.iter().flat_map(|&(x, w, y, h)| {
+                    let (_, high) = bounds(i, y, h);
+                    [point(x - w/2., high), point(x + w/2., high)]
+                });
+                let lower = segment.iter().rev().flat_map(|&(x, w, y, h)| {
+                    let (low, _) = bounds(i, y, h);
+                    [point(x + w/2., low), point(x - w/2., low)]
+                });
+                bands.push(Polygon::new(PlotPoints::new(upper.chain(lower).collect()))
+                    .name(&name)
+                    .fill_color(color.gamma_multiply(0.25))
+                    .stroke(egui::Stroke::NONE));
+                if display.style == CurveStyle::Bars && plot_settings.display_mode == DisplayMode::LineBand {
+                    let central = segment.iter().flat_map(|&(x, w, y, _)| [point(x - w/2., y), point(x + w/2., y)]);
+                    lines.push(Line::new(PlotPoints::new(central.collect()))
+                        .name(&name)
+                        .color(color));
                 }
-            ).collect::<Vec<_>>().as_slice().join(" / ")
-            )
-        .show(ui, |plot_ui| {
-            for chart in charts {
-                plot_ui.bar_chart(chart);
             }
-        });
+        }
+        match display.style {
+            CurveStyle::Bars => {},
+            CurveStyle::Steps => {
+                // staircase over all bins, closed down to zero at both ends
+                let bins = on_scale(&hist.data.iter().map(|(x, w, values)| (*x, *w, values[i].0, 0.0)).collect::<Vec<_>>());
+                let edges = bins.iter().flat_map(|&(x, w, y, _)| [point(x - w/2., y), point(x + w/2., y)]);
+                let (first, last) = match (bins.first(), bins.last()) {
+                    (Some((x, w, _, _)), Some((x_last, w_last, _, _))) => (point(x - w/2., 0.0), point(x_last + w_last/2., 0.0)),
+                    _ => continue,
+                };
+                let staircase = std::iter::once(first).chain(edges).chain(std::iter::once(last)).collect();
+                lines.push(Line::new(PlotPoints::new(staircase))
+                    .name(&name)
+                    .color(color)
+                    .width(display.stroke_width));
+            },
+            CurveStyle::Points => {
+                let bins = on_scale(&hist.data.iter().map(|(x, w, values)| (*x, *w, values[i].0, values[i].1)).collect::<Vec<_>>());
+                let markers = bins.iter().map(|&(x, _, y, _)| point(x, y)).collect();
+                points.push(Points::new(PlotPoints::new(markers))
+                    .name(&name)
+                    .color(color)
+                    .shape(MarkerShape::Circle)
+                    .radius(3.0));
+                for &(x, _, y, h) in &bins {
+                    let (low, high) = bounds(i, y, h);
+                    lines.push(Line::new(PlotPoints::new(vec![point(x, low), point(x, high)]))
+                        .name(&name)
+                        .color(color));
+                }
+            },
+        }
+    }
+
+    // Frozen curves are drawn as steps on the left axis, colored after the live curves
+    for (k, f) in frozen.iter().enumerate().filter(|(_, f)| f.visible) {
+        let color = curve_color(hist.input.curves.len() + k);
+        let point = |x: f64, y: f64| if horizontal { [y, x] } else { [x, y] };
+        if f.band {
+            for segment in split_segments(&on_scale(&f.data)) {
+                let upper = segment.iter().flat_map(|&(x, w, y, h)| [point(x - w/2., y + h/2.), point(x + w/2., y + h/2.)]);
+                let lower = segment.iter().rev().flat_map(|&(x, w, y, h)| [point(x + w/2., y - h/2.), point(x - w/2., y - h/2.)]);
+                bands.push(Polygon::new(PlotPoints::new(upper.chain(lower).collect()))
+                    .name(&f.label)
+                    .fill_color(color.gamma_multiply(0.25))
+                    .stroke(egui::Stroke::NONE));
+            }
+        }
+        let steps = on_scale(&f.data).iter().flat_map(|&(x, w, y, _)| [point(x - w/2., y), point(x + w/2., y)]).collect();
+        lines.push(Line::new(PlotPoints::new(steps))
+            .name(&f.label)
+            .color(color)
+            .style(egui_plot::LineStyle::dashed_loose()));
+    }
+
+    // Lines and points only know their name and position, so hover labels show the
+    // true value of right-axis curves by mapping the position back
+    let right_names: Vec<String> = right.iter().filter(|_| mapping.is_some()).map(|&i| legend_name_of(i)).collect();
+    let label_formatter = move |name: &str, value: &PlotPoint| {
+        let (bin, position) = if horizontal { (value.y, value.x) } else { (value.x, value.y) };
+        let bin = scale.value(bin);
+        let position = match mapping {
+            Some(m) if right_names.iter().any(|n| n == name) => m.to_right(position),
+            _ => position,
+        };
+        if name.is_empty() {
+            format!("x: {:.3}\ny: {:.3}", value.x, value.y)
+        } else {
+            format!("{}\nBin: {:.3}\nValue: {:.3}", name, bin, position)
+        }
+    };
+
+
+    let unit_of = |i: usize, key: &ParsedString| units[i].get(key.name()).map(String::as_str);
+    let value_label_of = |curves: &[usize]| curves.iter().map(|&i| {
+            let c = &hist.input.curves[i];
+            // normalized values are unitless fractions
+            let unit = modes[i].unit().or(unit_of(i, &c.y_key));
+            match c.value_type {
+                HistogramAggregation::Count => label_with_unit(&("COUNT(".to_owned() +c.y_key.as_str() + ")"), modes[i].unit()),
+                HistogramAggregation::CountDistinct => label_with_unit(&("COUNT(DISTINCT ".to_owned() + c.y_key.as_str() + ")"), modes[i].unit()),
+                HistogramAggregation::Avg => label_with_unit(&("AVG(".to_owned() + c.y_key.as_str() + ")"), unit),
+                HistogramAggregation::Sum => label_with_unit(&("SUM(".to_owned() + c.y_key.as_str() + ")"), unit),
+                HistogramAggregation::Min => label_with_unit(&("MIN(".to_owned() + c.y_key.as_str() + ")"), unit),
+                HistogramAggregation::Max => label_with_unit(&("MAX(".to_owned() + c.y_key.as_str() + ")"), unit),
+                HistogramAggregation::Median => label_with_unit(&("MEDIAN(".to_owned() + c.y_key.as_str() + ")"), unit),
+                HistogramAggregation::Quantile(_) => label_with_unit(&format!("{}({})", c.value_type.name(), c.y_key.as_str()), unit),
+            }
+        }).collect::<Vec<_>>().as_slice().join(" / ");
+    let bin_label = visible.iter()
+        .map(|&i| label_with_unit(hist.input.curves[i].x_key.as_str(), unit_of(i, &hist.input.curves[i].x_key)))
+        .collect::<Vec<_>>().as_slice().join(" / ");
+    // TODO move axis labels to legend
+    let (value_label, right_label) = match mapping {
+        Some(_) => (value_label_of(&left), Some(value_label_of(&right))),
+        None => (value_label_of(&visible), None),
+    };
+    let (x_label, y_label) = if horizontal {
+        (value_label, bin_label)
+    } else {
+        (bin_label, value_label)
+    };
+
+    let mut plot = Plot::new("histogram")
+        .height(height)
+        .legend(Legend::default())
+        .link_axis(egui::Id::new("histogram_bins"), !horizontal, horizontal)
+        .x_axis_label(x_label)
+        .y_axis_label(y_label)
+        .label_formatter(label_formatter);
+    if scale == HistogramAxisScale::Log {
+        plot = if horizontal { plot.y_axis_formatter(power_of_ten) } else { plot.x_axis_formatter(power_of_ten) };
+    }
+    plot.show(ui, |plot_ui| {
+        for band in bands {
+            plot_ui.polygon(band);
+        }
+        for line in lines {
+            plot_ui.line(line);
+        }
+        for p in points {
+            plot_ui.points(p);
+        }
+        for chart in charts {
+            plot_ui.bar_chart(chart);
+        }
+        // Synthetic secondary axis along the far edge, placed using the current
+        // bounds which still are the ones of the previous frame at this point.
+        if let (Some(mapping), Some(right_label)) = (mapping, right_label) {
+            let bounds = plot_ui.plot_bounds();
+            let (low, high) = if horizontal {
+                (bounds.min()[0], bounds.max()[0])
+            } else {
+                (bounds.min()[1], bounds.max()[1])
+            };
+            let color = plot_ui.ctx().style().visuals.text_color();
+            for (tick, label) in nice_ticks(mapping.to_right(low), mapping.to_right(high), 5) {
+                let value = mapping.to_left(tick);
+                let (position, anchor, text) = if horizontal {
+                    (PlotPoint::new(value, bounds.max()[1]), Align2::CENTER_TOP, format!("{}\n|", label))
+                } else {
+                    (PlotPoint::new(bounds.max()[0], value), Align2::RIGHT_CENTER, format!("{} –", label))
+                };
+                plot_ui.text(Text::new(position, text).anchor(anchor).color(color));
+            }
+            let (position, anchor) = if horizontal {
+                (PlotPoint::new(bounds.max()[0], bounds.max()[1]), Align2::RIGHT_BOTTOM)
+            } else {
+                (PlotPoint::new(bounds.max()[0], bounds.max()[1]), Align2::RIGHT_TOP)
+            };
+            plot_ui.text(Text::new(position, format!("right axis: {}", right_label)).anchor(anchor).color(color));
+        }
+    });
+    if clipped > 0 {
+        ui.label(format!("⚠ {} bins at or below zero not shown on the log scale", clipped));
+    }
 }
 
 fn main() -> Result<(), eframe::Error> {
@@ -1102,6 +2688,6 @@ fn main() -> Result<(), eframe::Error> {
     eframe::run_native(
         "STRAP GUI",
         options,
-        Box::new(|_| Box::new(MyApp::default())),
+        Box::new(|cc| Box::new(MyApp::new(cc))),
     )
 }
@@ -0,0 +1,127 @@
+//! HDF5 export of STRAP data
+//!
+//! Every numeric key becomes a 1-D f64 dataset in the given group, all of the same
+//! length, with one entry per row like in `StrapTrack::to_parquet`. Missing entries
+//! are stored as NaN, which is also the fill value of the datasets, so `numpy.isnan`
+//! recovers the mask in h5py.
+use std::collections::BTreeSet;
+use std::str::FromStr;
+
+use ::hdf5::types::VarLenUnicode;
+use ::hdf5::{Dataset, File, Group};
+use itertools::Itertools;
+
+use crate::{StrapTrack, StrapValue};
+
+#[derive(Debug, Clone)]
+pub struct Hdf5Options {
+    /// gzip level 0-9, 0 disables compression
+    pub gzip_level: u8,
+    /// Rows per chunk of every dataset
+    pub chunk_size: usize,
+}
+
+impl Default for Hdf5Options {
+    fn default() -> Self {
+        Self {
+            gzip_level: 4,
+            chunk_size: 65536,
+        }
+    }
+}
+
+fn write_str_attr(group: &Group, name: &str, value: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let value = VarLenUnicode::from_str(value)?;
+    group.new_attr::<VarLenUnicode>().create(name)?.write_scalar(&value)?;
+    Ok(())
+}
+
+impl StrapTrack {
+    /// Write STRAP data into `group` of the HDF5 file at `path`, the file is created if needed.
+    /// The input is read twice, once for the columns and the number of rows and once
+    /// for the values, which are written `chunk_size` rows at a time.
+    pub fn to_hdf5(
+        &self,
+        path: &str,
+        group: &str,
+        options: &Hdf5Options,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let mut column_names = BTreeSet::new();
+        let mut rows = 0;
+        for row in self.iter_values()?.filter_ok(|row| !row.is_empty()) {
+            column_names.extend(row?.into_iter().filter(|(_, value)| value.as_f64().is_some()).map(|(key, _)| key));
+            rows += 1;
+        }
+        let chunk_size = options.chunk_size.clamp(1, rows.max(1));
+
+        let file = File::append(path)?;
+        let group = file.create_group(group)?;
+        let mut datasets: Vec<Dataset> = vec![];
+        for name in &column_names {
+            let mut builder = group.new_dataset::<f64>()
+                .shape(rows)
+                .fill_value(f64::NAN);
+            // chunks may not be larger than the fixed size of the dataset
+            if rows > 0 {
+                builder = builder.chunk(chunk_size);
+                if options.gzip_level > 0 {
+                    builder = builder.deflate(options.gzip_level);
+                }
+            }
+            datasets.push(builder.create(name.as_str())?);
+        }
+
+        let mut start = 0;
+        let mut columns: Vec<Vec<f64>> = vec![Vec::with_capacity(chunk_size); column_names.len()];
+        for chunk in &self.iter_values()?.filter_ok(|row| !row.is_empty()).chunks(chunk_size) {
+            for row in chunk {
+                let row = row?;
+                for (name, values) in column_names.iter().zip(columns.iter_mut()) {
+                    values.push(row.get(name).and_then(StrapValue::as_f64).unwrap_or(f64::NAN));
+                }
+            }
+            let end = start + columns.first().map_or(0, Vec::len);
+            for (dataset, values) in datasets.iter().zip(columns.iter_mut()) {
+                dataset.write_slice(values.as_slice(), start..end)?;
+                values.clear();
+            }
+            start = end;
+        }
+
+        write_str_attr(&group, "format", "strap")?;
+        write_str_attr(&group, "source", &self.name())?;
+        write_str_attr(&group, "missing", "NaN")?;
+        group.new_attr::<u64>().create("rows")?.write_scalar(&(rows as u64))?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use tempfile::NamedTempFile;
+
+    #[test]
+    fn test_to_hdf5_round_trip() {
+        let mut strap = NamedTempFile::with_suffix(".strap").unwrap();
+        write!(strap, "x 1.0 y 2.0\n# comment\nx 3.0\n\ny 4.0 run A17\n").unwrap();
+        let track = StrapTrack::new(strap.path()).unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("out.h5");
+        let path = path.to_str().unwrap();
+        track.to_hdf5(path, "run1", &Hdf5Options { gzip_level: 6, chunk_size: 2 }).unwrap();
+
+        let file = File::open(path).unwrap();
+        let group = file.group("run1").unwrap();
+        assert_eq!(group.member_names().unwrap(), vec!["x", "y"]);
+        let x: Vec<f64> = group.dataset("x").unwrap().read_raw().unwrap();
+        assert_eq!(x[..2], [1.0, 3.0]);
+        assert!(x[2].is_nan());
+        let y = group.dataset("y").unwrap();
+        assert_eq!(y.chunk(), Some(vec![2]));
+        assert!(y.read_raw::<f64>().unwrap()[1].is_nan());
+        assert_eq!(group.attr("rows").unwrap().read_scalar::<u64>().unwrap(), 3);
+        assert_eq!(group.attr("format").unwrap().read_scalar::<VarLenUnicode>().unwrap().as_str(), "strap");
+    }
+}
@@ -1,428 +1,5394 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::hash::{Hash, Hasher};
 
+#[cfg(feature = "std-fs")]
 use std::fs::File;
-use std::io::{BufRead, BufReader};
+use std::io::{BufRead, BufReader, BufWriter, Read, Write};
+#[cfg(feature = "std-fs")]
+use std::ops::Range;
+#[cfg(feature = "std-fs")]
 use std::path::PathBuf;
 
 use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+
+use bzip2::read::MultiBzDecoder;
 
 use flate2::bufread::GzDecoder;
 use itertools::Itertools;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
 
-use arrow::array::{Float64Array, ArrayRef};
-use arrow::datatypes::{DataType, Field, Schema};
+use arrow::array::{Array, BooleanArray, Float64Array, Float64Builder, ArrayRef, Int64Array, ListBuilder, StringArray, UInt64Array};
+use arrow::compute::{cast_with_options, CastOptions};
+use arrow::datatypes::{DataType, Field, Fields, Schema, SchemaRef, TimeUnit};
 use arrow::record_batch::RecordBatch;
+#[cfg(feature = "std-fs")]
+use arrow::array::AsArray;
+#[cfg(feature = "std-fs")]
+use arrow::datatypes::{Float64Type, UInt64Type};
+#[cfg(feature = "std-fs")]
 use parquet::arrow::ArrowWriter;
-use parquet::file::properties::WriterProperties;
+#[cfg(feature = "std-fs")]
+use parquet::file::metadata::KeyValue;
+#[cfg(feature = "std-fs")]
+use parquet::file::properties::{EnabledStatistics, WriterProperties};
+#[cfg(feature = "std-fs")]
+use memmap2::Mmap;
+#[cfg(feature = "std-fs")]
 use zip::ZipArchive;
+#[cfg(feature = "std-fs")]
 use zstd::stream::read::Decoder as ZstdDecoder;
 
+#[cfg(feature = "polars")]
+use polars::prelude::{Column, DataFrame, IntoLazy, LazyFrame, NamedFrom, PolarsResult, Series};
+
+#[cfg(all(feature = "std-fs", feature = "duckdb"))]
+pub mod concat;
+pub mod diff;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+#[cfg(feature = "std-fs")]
+pub mod fingerprint;
+#[cfg(feature = "std-fs")]
+pub mod follow;
+#[cfg(feature = "duckdb")]
+pub mod query;
+#[cfg(feature = "duckdb")]
+pub mod yoda;
+#[cfg(feature = "python")]
+mod python;
+#[cfg(feature = "sqlite")]
+pub mod sqlite;
+#[cfg(feature = "hdf5")]
+pub mod h5;
+
 
 /// Iterator over STRAP file rows
 pub struct StrapTrackIterator {
     all:bool,
-    reader: Box<dyn BufRead>,
+    lines: Lines,
+    compression: InputCompression,
+    // bytes read from the raw input so far
+    offset: Arc<AtomicU64>,
+    mode: ParseMode,
+    // lines read so far
+    line: usize,
+    prefixes: Arc<[String]>,
+    // columns of a `@strap2` header, rows are positional after it
+    header: Option<Vec<(String, DataType)>>,
+    // rows and malformed pairs read so far
+    rows: usize,
+    dropped: usize,
+    special: SpecialValuePolicy,
+    // NaN and inf values read so far per column
+    non_finite: BTreeMap<String, usize>,
+    progress: Option<Progress>,
+    // size of the raw input, None if unknown
+    total: Option<u64>,
+    // keys kept, None for all
+    columns: Option<Arc<[String]>>,
+    filter: Option<RowFilter>,
+    // rows the filter rejected so far
+    filtered: usize,
+    // new name per old name
+    renames: Option<Arc<HashMap<String, String>>>,
+    duplicates: DuplicateKeyPolicy,
+    suffixes: Arc<[UnitSuffix]>,
+    // base unit of each column with suffixed numbers so far
+    suffix_units: BTreeMap<String, String>,
+    // token separator besides whitespace
+    delimiter: Option<char>,
+    // rows to skip before the first one returned, and skipped so far
+    skip_rows: usize,
+    skipped_rows: usize,
+    max_rows: Option<usize>,
+    comment_prefix: Option<String>,
+    // Auto until a number decides, and the numbers looked at so far
+    decimal: DecimalSeparator,
+    numbers: usize,
+    // buffer of the last line read buffered, reused for the next one
+    scratch: String,
+    // emptied token buffer of the last line, reused for the next one
+    tokens: Vec<Token>,
+    // digests of the rows seen with `with_dedupe`, and the duplicates dropped so far
+    dedupe: bool,
+    seen: HashSet<u64>,
+    deduplicated: usize,
+    derived: Arc<[DerivedColumn]>,
+    // row lines without a value left so far, the lines `load_report` counts as skipped
+    lost: usize,
+}
+
+impl StrapTrackIterator {
+    fn read_line(&mut self) -> Option<Result<Line, std::io::Error>> {
+        let read = match &mut self.lines {
+            Lines::Buffered(reader) => {
+                let mut line = std::mem::take(&mut self.scratch);
+                line.clear();
+                reader.read_line(&mut line).map(|n| (n > 0).then_some(Line::Owned(line)))
+            }
+            #[cfg(feature = "std-fs")]
+            Lines::Mapped { map, pos } => {
+                let start = *pos;
+                let end = map[start..].iter().position(|&b| b == b'\n').map_or(map.len(), |n| start + n + 1);
+                *pos = end;
+                self.offset.store(end as u64, Ordering::Relaxed);
+                match std::str::from_utf8(&map[start..end]) {
+                    Ok(_) => Ok((start < end).then(|| Line::Mapped(map.clone(), start..end))),
+                    Err(e) => Err(std::io::Error::new(std::io::ErrorKind::InvalidData, e)),
+                }
+            }
+            Lines::Rows { .. } => Ok(None),
+        };
+        match read {
+            Ok(None) => None, // EOF
+            Ok(Some(line)) => {
+                self.line += 1;
+                Some(Ok(line))
+            }
+            Err(e) => Some(Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!(
+                    "error reading {} input after {} bytes: {}",
+                    self.compression,
+                    self.offset.load(Ordering::Relaxed),
+                    e
+                ),
+            ))),
+        }
+    }
+
+    /// Values of the next line, empty if it is no row. Rows dropped on purpose
+    /// are passed over, see `parse`.
+    fn next_values(&mut self) -> Option<Result<HashMap<String, StrapValue>, std::io::Error>> {
+        loop {
+            if self.max_rows.is_some_and(|max_rows| self.rows >= max_rows) {
+                return None;
+            }
+            if let Lines::Rows { rows, pos } = &mut self.lines {
+                let row: HashMap<String, StrapValue> = rows.get(*pos)?.iter().map(|(key, &value)| (key.clone(), StrapValue::Float(value))).collect();
+                *pos += 1;
+                self.line += 1;
+                let number = self.line;
+                match self.keep_row(row, |reason| StrapError::Parse { line: number, content: String::new(), reason }) {
+                    Ok(Some(values)) => return Some(Ok(values)),
+                    Ok(None) => continue,
+                    Err(e) => return Some(Err(e.into())),
+                }
+            }
+            let line = match self.read_line()? {
+                Ok(line) => line,
+                Err(e) => return Some(Err(e)),
+            };
+            if let Some(progress) = &self.progress
+                && self.line.is_multiple_of(PROGRESS_LINES)
+                && !(progress.0)(self.offset.load(Ordering::Relaxed), self.total, self.rows)
+            {
+                return Some(Err(StrapError::Cancelled.into()));
+            }
+            let values = self.parse(&line);
+            // the next line is read into the same allocation
+            if let Line::Owned(line) = line {
+                self.scratch = line;
+            }
+            match values {
+                Ok(Some(values)) => return Some(Ok(values)),
+                Ok(None) => continue,
+                Err(e) => return Some(Err(e.into())),
+            }
+        }
+    }
+
+    /// Values of `line`, empty if it is no row, e.g. a comment. None for a row
+    /// dropped on purpose by the filter, dedupe, skip_rows or
+    /// `SpecialValuePolicy::DropRow`, which must not show up as an empty line.
+    fn parse(&mut self, line: &str) -> Result<Option<HashMap<String, StrapValue>>, StrapError> {
+        let number = self.line;
+        let error = |reason: String| StrapError::Parse { line: number, content: line.trim().to_string(), reason };
+        if self.comment(line).is_some() {
+            return Ok(Some(HashMap::new()));
+        }
+        if let Some(header) = StrapTrack::parse_header(line, self.delimiter).map_err(error)? {
+            if self.header.is_some() {
+                return Err(error("second @strap2 header".to_string()));
+            }
+            if self.rows > 0 {
+                return Err(error("@strap2 header after key-value rows, files can't mix @strap and @strap2".to_string()));
+            }
+            let header: Vec<(String, DataType)> = header.into_iter().map(|(name, data_type)| (self.renamed(name), data_type)).collect();
+            let mut seen = HashSet::new();
+            if let Some((name, _)) = header.iter().find(|(name, _)| !seen.insert(name)) {
+                return Err(error(format!("two @strap2 header columns named {} after renaming", name)));
+            }
+            if let Some((name, _)) = header.iter().find(|(name, _)| self.derived(name)) {
+                return Err(error(format!("derived column {} is a column of the file already", name)));
+            }
+            self.header = Some(header);
+            return Ok(Some(HashMap::new()));
+        }
+        let mut tokens = std::mem::take(&mut self.tokens);
+        tokens.clear();
+        let values = if StrapTrack::row_tokens_into(line, self.all, &self.prefixes, self.delimiter, &mut tokens) {
+            let values = self.parse_row(line, &tokens);
+            if !tokens.is_empty() && matches!(&values, Ok(Some(values)) if values.is_empty()) {
+                self.lost += 1;
+            }
+            values
+        } else {
+            Ok(Some(HashMap::new()))
+        };
+        self.tokens = tokens;
+        values
+    }
+
+    /// Values of a row line split into `tokens`, None if the row is dropped, see `parse`
+    fn parse_row(&mut self, line: &str, tokens: &[Token]) -> Result<Option<HashMap<String, StrapValue>>, StrapError> {
+        let number = self.line;
+        let error = |reason: String| StrapError::Parse { line: number, content: line.trim().to_string(), reason };
+        let (values, malformed) = match &self.header {
+            Some(header) => {
+                if let Some((word, _)) = StrapTrack::prefix_word(line, &self.prefixes)
+                    && word != STRAP2_PREFIX
+                {
+                    return Err(error(format!("{} line in a @strap2 section, files can't mix @strap and @strap2", word)));
+                }
+                StrapTrack::parse_positional(line, tokens, header)
+            }
+            None => {
+                if self.decimal == DecimalSeparator::Auto {
+                    self.detect_decimal(line, tokens);
+                }
+                let comma = self.decimal == DecimalSeparator::Comma;
+                let (values, malformed, units) = StrapTrack::parse_pairs(line, tokens, self.duplicates, &self.suffixes, comma).map_err(error)?;
+                for (key, unit) in units {
+                    match self.suffix_units.get(&key) {
+                        Some(known) if *known != unit => {
+                            return Err(error(format!("{} has values in {} and in {}", key, known, unit)));
+                        }
+                        Some(_) => {}
+                        None => {
+                            self.suffix_units.insert(key, unit);
+                        }
+                    }
+                }
+                (values, malformed)
+            }
+        };
+        if self.mode == ParseMode::Strict
+            && let Some((token, reason)) = malformed.first()
+        {
+            return Err(error(format!("{} `{}`", reason, token)));
+        }
+        self.dropped += malformed.len();
+        self.keep_row(values, error)
+    }
+
+    /// `values` of a row after renaming and deriving columns, None if the row is
+    /// dropped on purpose, see `parse`
+    fn keep_row(
+        &mut self,
+        mut values: HashMap<String, StrapValue>,
+        error: impl Fn(String) -> StrapError,
+    ) -> Result<Option<HashMap<String, StrapValue>>, StrapError> {
+        if self.renames.is_some() && self.header.is_none() {
+            let mut renamed = HashMap::with_capacity(values.len());
+            for (key, value) in values {
+                match renamed.entry(self.renamed(key)) {
+                    std::collections::hash_map::Entry::Occupied(entry) if entry.get() != &value => {
+                        return Err(error(format!("conflicting values for {} after renaming", entry.key())));
+                    }
+                    std::collections::hash_map::Entry::Occupied(_) => {}
+                    std::collections::hash_map::Entry::Vacant(entry) => {
+                        entry.insert(value);
+                    }
+                }
+            }
+            values = renamed;
+        }
+        if let Some(name) = values.keys().find(|key| self.derived(key)) {
+            return Err(error(format!("derived column {} is a column of the file already", name)));
+        }
+        if !self.derived.is_empty() && !values.is_empty() {
+            let mut numeric: HashMap<String, f64> = values.iter()
+                .filter_map(|(key, value)| value.as_f64().map(|value| (key.clone(), value)))
+                .collect();
+            for DerivedColumn(name, derive) in self.derived.iter() {
+                if let Some(value) = derive(&numeric) {
+                    numeric.insert(name.clone(), value);
+                    values.insert(name.clone(), StrapValue::Float(value));
+                }
+            }
+        }
+        let non_finite: Vec<String> = values.iter()
+            .filter(|(key, _)| self.columns.as_ref().is_none_or(|columns| columns.contains(key)))
+            .filter(|(_, value)| value.as_f64().is_some_and(|value| !value.is_finite()))
+            .map(|(key, _)| key.clone())
+            .sorted()
+            .collect();
+        for key in &non_finite {
+            *self.non_finite.entry(key.clone()).or_default() += 1;
+        }
+        if let Some(first) = non_finite.first() {
+            match self.special {
+                SpecialValuePolicy::Keep => {}
+                SpecialValuePolicy::DropPair => values.retain(|key, _| !non_finite.contains(key)),
+                SpecialValuePolicy::DropRow => return Ok(None),
+                SpecialValuePolicy::Error => {
+                    return Err(error(format!("non-finite value `{}` of {}", values[first], first)));
+                }
+            }
+        }
+        if let Some(filter) = &self.filter
+            && !values.is_empty()
+        {
+            let numeric = values.iter().filter_map(|(key, value)| value.as_f64().map(|value| (key.clone(), value))).collect();
+            if !(filter.0)(&numeric) {
+                self.filtered += 1;
+                return Ok(None);
+            }
+        }
+        if self.dedupe
+            && !values.is_empty()
+            && !self.seen.insert(Self::digest(&values))
+        {
+            self.deduplicated += 1;
+            return Ok(None);
+        }
+        // after the filter, which may look at other columns
+        if let Some(columns) = &self.columns {
+            values.retain(|key, _| columns.contains(key));
+        }
+        if !values.is_empty() && self.skipped_rows < self.skip_rows {
+            self.skipped_rows += 1;
+            return Ok(None);
+        }
+        self.rows += !values.is_empty() as usize;
+        Ok(Some(values))
+    }
+}
+
+impl StrapTrackIterator {
+    /// Hash of the sorted key-value pairs of a row, equal for rows with the same
+    /// keys and bitwise equal values
+    fn digest(values: &HashMap<String, StrapValue>) -> u64 {
+        let mut hasher = std::collections::hash_map::DefaultHasher::new();
+        for (key, value) in values.iter().sorted_by(|a, b| a.0.cmp(b.0)) {
+            key.hash(&mut hasher);
+            match value {
+                StrapValue::Float(value) => (0u8, value.to_bits()).hash(&mut hasher),
+                StrapValue::Text(text) => (1u8, text).hash(&mut hasher),
+                StrapValue::List(values) => (2u8, values.iter().map(|value| value.to_bits()).collect::<Vec<_>>()).hash(&mut hasher),
+            }
+        }
+        hasher.finish()
+    }
+
+    /// Settle `DecimalSeparator::Auto` on the first value with a decimal comma or point
+    fn detect_decimal(&mut self, line: &str, tokens: &[Token]) {
+        for (_, value) in StrapTrack::pairs(line, tokens) {
+            let Some(value) = value else {
+                continue;
+            };
+            if StrapTrack::comma_number(value).is_some_and(|number| number.is_ok()) {
+                self.decimal = DecimalSeparator::Comma;
+                return;
+            }
+            if value.parse::<f64>().is_ok() {
+                self.numbers += 1;
+                if value.contains('.') || self.numbers >= AUTO_DECIMAL_NUMBERS {
+                    self.decimal = DecimalSeparator::Dot;
+                    return;
+                }
+            }
+        }
+    }
+
+    /// Text of a comment line after the comment prefix, None for other lines. Lines
+    /// starting with a STRAP prefix like `#s` for `with_prefixes(["#s"])` are no comments.
+    fn comment<'a>(&self, line: &'a str) -> Option<&'a str> {
+        let line = line.trim();
+        let text = line.strip_prefix(self.comment_prefix.as_deref()?)?;
+        if self.prefixes.iter().any(|prefix| line.starts_with(prefix.as_str())) {
+            return None;
+        }
+        Some(text.trim())
+    }
+
+    /// Whether `name` is a column of `StrapTrack::with_derived_column`
+    fn derived(&self, name: &str) -> bool {
+        self.derived.iter().any(|DerivedColumn(derived, _)| derived == name)
+    }
+
+    /// `name` after the renames of `StrapTrack::with_renames`
+    fn renamed(&self, name: String) -> String {
+        match self.renames.as_ref().and_then(|renames| renames.get(&name)) {
+            Some(new) => new.clone(),
+            None => name,
+        }
+    }
 }
 
 impl Iterator for StrapTrackIterator {
     type Item = Result<HashMap<String, f64>, std::io::Error>;
     
     fn next(&mut self) -> Option<Self::Item> {
-        let mut line = String::new();
-        match self.reader.read_line(&mut line) {
-            Ok(0) => None, // EOF
-            Ok(_) => {
-                let parsed = StrapTrack::parse_line(&line,self.all);
-                Some(Ok(parsed))
+        Some(self.next_values()?.map(StrapTrack::numeric))
+    }
+}
+
+/// Iterator over STRAP file rows including text values
+pub struct StrapValueIterator(StrapTrackIterator);
+
+impl StrapValueIterator {
+    /// 1-based number of the line of the last row, rows dropped on purpose are
+    /// passed over so this is not the number of rows returned
+    fn line(&self) -> usize {
+        self.0.line
+    }
+
+    /// Row lines without a value left so far, see `ConversionStats::lost`
+    fn lost(&self) -> usize {
+        self.0.lost
+    }
+}
+
+impl Iterator for StrapValueIterator {
+    type Item = Result<HashMap<String, StrapValue>, std::io::Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.0.next_values()
+    }
+}
+
+/// Record batches of `StrapTrack::to_record_batches`, all with the same schema
+pub struct StrapBatchIterator {
+    rows: StrapValueIterator,
+    builder: BatchBuilder,
+    chunk_size: usize,
+    // rows left to write, see `ParquetOptions::head`
+    remaining: usize,
+    skip_empty: bool,
+    skipped: usize,
+}
+
+/// Line numbers and values of the rows of one record batch, held column-major in
+/// the order of the fields they are written to instead of a map per row
+struct RowChunk {
+    lines: Vec<usize>,
+    columns: Vec<ChunkColumn>,
+}
+
+/// Values of one column of a `RowChunk`, one per row
+enum ChunkColumn {
+    /// numeric fields, including integers and timestamps cast from the numbers
+    Numbers(Vec<Option<f64>>),
+    /// flag, text and list fields
+    Values(Vec<Option<StrapValue>>),
+}
+
+impl RowChunk {
+    fn new(fields: &Fields) -> Self {
+        let columns = fields.iter()
+            .map(|field| match field.data_type() {
+                DataType::Boolean | DataType::List(_) => ChunkColumn::Values(vec![]),
+                data_type if StrapTrack::is_text(data_type) => ChunkColumn::Values(vec![]),
+                _ => ChunkColumn::Numbers(vec![]),
+            })
+            .collect();
+        Self { lines: vec![], columns }
+    }
+
+    fn len(&self) -> usize {
+        self.lines.len()
+    }
+
+    /// Move the values of `row` into their columns, `index` is the column of each
+    /// field name. Keys without a field are left out, a text or list value of a
+    /// numeric field is an error.
+    fn push(
+        &mut self,
+        line: usize,
+        row: HashMap<String, StrapValue>,
+        index: &HashMap<String, usize>,
+        fields: &Fields,
+    ) -> Result<(), StrapError> {
+        let position = self.len();
+        for column in &mut self.columns {
+            match column {
+                ChunkColumn::Numbers(values) => values.push(None),
+                ChunkColumn::Values(values) => values.push(None),
+            }
+        }
+        for (key, value) in row {
+            let Some(&column) = index.get(&key) else {
+                continue;
+            };
+            match (&mut self.columns[column], value) {
+                (ChunkColumn::Numbers(values), StrapValue::Float(value)) => values[position] = Some(value),
+                (ChunkColumn::Numbers(_), value) => {
+                    return Err(StrapError::Parse {
+                        line,
+                        content: format!("{} {}", key, value),
+                        reason: format!(
+                            "column {}: value {} is not a number and can't be written as {}",
+                            key, value, fields[column].data_type()
+                        ),
+                    });
+                }
+                (ChunkColumn::Values(values), value) => values[position] = Some(value),
             }
-            Err(e) => Some(Err(e)),
         }
+        self.lines.push(line);
+        Ok(())
     }
 }
 
-/// Lazy/streaming parser for STRAP protocol files
-#[derive(Debug)]
-pub struct StrapTrack {
-    file_path: PathBuf,
-    //data : Vec<HashMap<String, f64>>,
+impl StrapBatchIterator {
+    pub fn schema(&self) -> SchemaRef {
+        self.builder.schema.clone()
+    }
 
-    //cached_column_names: Option<Vec<String>>,
-    //cached_columns: HashMap<String, Vec<f64>>,
-}
+    /// Lines without values left out so far, e.g. comments or unit headers, see
+    /// `ParquetOptions::skip_empty`
+    pub fn skipped(&self) -> usize {
+        self.skipped
+    }
 
-impl StrapTrack {
-    pub fn new(file_path: impl Into<PathBuf>) -> std::io::Result<Self> {
-        let path = file_path.into();
-        // Verify file exists
-        File::open(&path)?;
+    /// Values of timestamp columns written as null so far, see `ConversionStats::invalid_timestamps`
+    pub fn invalid_timestamps(&self) -> usize {
+        self.builder.invalid_timestamps.load(Ordering::Relaxed)
+    }
 
-        
-        Ok(Self {
-            file_path: path,
-        })
+    /// Rows dropped as duplicates so far, see `StrapTrack::with_dedupe`
+    pub fn deduplicated(&self) -> usize {
+        self.rows.0.deduplicated
     }
 
-    /// Create a reader that handles compression based on file extension
-    fn create_reader(&self) -> Result<Box<dyn BufRead>, std::io::Error> {
-        let file = File::open(&self.file_path)?;
-        let path_str = self.file_path.to_string_lossy().to_lowercase();
-        
-        if path_str.ends_with(".gz") || path_str.ends_with(".gzip") {
-            // Gzip compressed
-            let decoder = GzDecoder::new(BufReader::new(file));
-            Ok(Box::new(BufReader::new(decoder)))
-        } else if path_str.ends_with(".zst") || path_str.ends_with(".zstd") {
-            // Zstd compressed
-            let decoder = ZstdDecoder::new(file)
-                .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
-            Ok(Box::new(BufReader::new(decoder)))
-        } else if path_str.ends_with(".zip") {
-            // ZIP archive - read first entry into memory
-            let mut archive = ZipArchive::new(file)
-                .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
-            
-            if archive.len() == 0 {
-                return Err(std::io::Error::new(
-                    std::io::ErrorKind::InvalidData,
-                    "ZIP archive is empty"
-                ));
-            }
-            
-            // Read the first file in the archive into memory
-            let mut zip_file = archive.by_index(0)
-                .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
-            let mut contents = Vec::new();
-            std::io::copy(&mut zip_file, &mut contents)?;
-            Ok(Box::new(BufReader::new(std::io::Cursor::new(contents))))
-        } else {
-            // Uncompressed
-            Ok(Box::new(BufReader::new(file)))
+    /// Row lines without a value left so far, see `ConversionStats::lost`
+    pub fn lost(&self) -> usize {
+        self.rows.lost()
+    }
+
+    /// Rows of the next batch, None after the last one
+    fn next_chunk(&mut self) -> Result<Option<RowChunk>, StrapError> {
+        let fields = self.builder.data_schema.fields();
+        let mut chunk = RowChunk::new(fields);
+        while chunk.len() < self.chunk_size && self.remaining > 0 {
+            let Some(row) = self.rows.next() else {
+                break;
+            };
+            let row = row?;
+            // lines without values, e.g. comments or unit headers
+            if row.is_empty() && self.skip_empty {
+                self.skipped += 1;
+                continue;
+            }
+            chunk.push(self.rows.line(), row, &self.builder.index, fields)?;
+            self.remaining -= 1;
         }
+        Ok((chunk.len() > 0).then_some(chunk))
     }
+}
 
-    /// Get column names from all rows
-    pub fn get_column_names(&self) -> Result<Vec<String>, std::io::Error> {
-        let mut unique_keys = std::collections::HashSet::new();
+impl Iterator for StrapBatchIterator {
+    type Item = Result<RecordBatch, StrapError>;
 
-        for hm in self.iter()? {
-            for key in hm?.keys() {
-                unique_keys.insert(key.clone());
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.next_chunk() {
+            Ok(chunk) => chunk.map(|chunk| self.builder.build(&chunk)),
+            Err(e) => Some(Err(e)),
+        }
+    }
+}
+
+/// Record batches of row chunks, separate from the rows to build them on another thread
+#[derive(Debug, Clone)]
+struct BatchBuilder {
+    // column of each field of `data_schema`
+    index: HashMap<String, usize>,
+    // schema without the row index column
+    data_schema: SchemaRef,
+    schema: SchemaRef,
+    row_index: bool,
+    // timestamps written as null so far, shared by the clones
+    invalid_timestamps: Arc<AtomicUsize>,
+}
+
+impl BatchBuilder {
+    fn build(&self, chunk: &RowChunk) -> Result<RecordBatch, StrapError> {
+        let batch = StrapTrack::record_batch(&self.data_schema, chunk)?;
+        for ((column, field), array) in chunk.columns.iter().zip(self.data_schema.fields()).zip(batch.columns()) {
+            if let (ChunkColumn::Numbers(values), DataType::Timestamp(..)) = (column, field.data_type()) {
+                let values = values.iter().flatten().count();
+                self.invalid_timestamps.fetch_add(values - (array.len() - array.null_count()), Ordering::Relaxed);
             }
         }
-        Ok(unique_keys.into_iter().collect())
+        if !self.row_index {
+            return Ok(batch);
+        }
+        let index = UInt64Array::from_iter_values(chunk.lines.iter().map(|&line| line as u64));
+        let columns = std::iter::once(Arc::new(index) as ArrayRef)
+            .chain(batch.columns().iter().cloned())
+            .collect();
+        Ok(RecordBatch::try_new(self.schema.clone(), columns)?)
+    }
+}
+
+/// Rows of `StrapTrack::stream_to_parquet` before they are spilled, column-major
+/// with the columns of these rows in order of first appearance
+#[cfg(feature = "std-fs")]
+#[derive(Debug, Default)]
+struct SpillChunk {
+    lines: Vec<usize>,
+    names: Vec<String>,
+    index: HashMap<String, usize>,
+    // padded with None up to the row of the last value
+    values: Vec<Vec<Option<StrapValue>>>,
+}
+
+#[cfg(feature = "std-fs")]
+impl SpillChunk {
+    fn push(&mut self, line: usize, row: HashMap<String, StrapValue>) {
+        let position = self.lines.len();
+        for (key, value) in row {
+            let column = match self.index.get(&key) {
+                Some(&column) => column,
+                None => {
+                    self.index.insert(key.clone(), self.names.len());
+                    self.names.push(key);
+                    self.values.push(vec![]);
+                    self.names.len() - 1
+                }
+            };
+            let values = &mut self.values[column];
+            values.resize(position, None);
+            values.push(Some(value));
+        }
+        self.lines.push(line);
     }
 
-    
-    /// Parse a single STRAP line into key-value pairs
-    fn parse_line(line: &str, all : bool) -> HashMap<String, f64> {
-        let mut result = HashMap::new();
-        let line = line.trim();
+    /// The line numbers as first UInt64 column and the columns of the rows, Utf8
+    /// for a column with text, List for one with lists and Float64 for the others
+    fn record_batch(self) -> Result<RecordBatch, StrapError> {
+        let rows = self.lines.len();
+        let mut fields = vec![];
+        let mut columns = vec![];
+        for (name, mut values) in self.names.into_iter().zip(self.values) {
+            values.resize(rows, None);
+            let data_type = if values.iter().flatten().any(|value| matches!(value, StrapValue::Text(_))) {
+                DataType::Utf8
+            } else if values.iter().flatten().any(|value| matches!(value, StrapValue::List(_))) {
+                DataType::new_list(DataType::Float64, true)
+            } else {
+                DataType::Float64
+            };
+            columns.push(match data_type {
+                DataType::Float64 => ChunkColumn::Numbers(values.iter().map(|value| value.as_ref().and_then(StrapValue::as_f64)).collect()),
+                _ => ChunkColumn::Values(values),
+            });
+            fields.push(Field::new(name, data_type, true));
+        }
+        let lines = UInt64Array::from_iter_values(self.lines.iter().map(|&line| line as u64));
+        let batch = StrapTrack::record_batch(&Arc::new(Schema::new(fields)), &RowChunk { lines: self.lines, columns })?;
+        let fields = std::iter::once(Arc::new(Field::new("line", DataType::UInt64, false)))
+            .chain(batch.schema().fields().iter().cloned())
+            .collect::<Vec<_>>();
+        let columns = std::iter::once(Arc::new(lines) as ArrayRef).chain(batch.columns().iter().cloned()).collect();
+        Ok(RecordBatch::try_new(Arc::new(Schema::new(fields)), columns)?)
+    }
 
-        // Handle @strap prefix - find first occurrence and continue from there
-        let line = if let Some(pos) = line.find("@strap") {
-            // Skip past "@strap" and any following digit/space
-            let after_strap = &line[pos..]; // Skip "@strap"
-            if let Some(pos) = after_strap.find(char::is_whitespace) {
-                &after_strap[pos..]
+    /// Rows of a spilled `batch` for the fields of `schema`, `index` is the column
+    /// of each field name
+    fn from_record_batch(batch: &RecordBatch, schema: &Schema, index: &HashMap<String, usize>) -> Result<RowChunk, StrapError> {
+        let lines: Vec<usize> = batch.column(0).as_primitive::<UInt64Type>().values().iter().map(|&line| line as usize).collect();
+        let mut chunk = RowChunk::new(schema.fields());
+        for column in &mut chunk.columns {
+            match column {
+                ChunkColumn::Numbers(values) => values.resize(lines.len(), None),
+                ChunkColumn::Values(values) => values.resize(lines.len(), None),
             }
-            else {
-                after_strap
+        }
+        for (field, array) in batch.schema().fields().iter().zip(batch.columns()).skip(1) {
+            let column = index[field.name()];
+            let boolean = schema.field(column).data_type() == &DataType::Boolean;
+            match &mut chunk.columns[column] {
+                // only columns without text and lists stay numeric, see `ColumnTypes`
+                ChunkColumn::Numbers(values) => *values = array.as_primitive::<Float64Type>().iter().collect(),
+                ChunkColumn::Values(values) => *values = match array.data_type() {
+                    DataType::Float64 => array.as_primitive::<Float64Type>().iter().map(|value| value.map(StrapValue::Float)).collect(),
+                    // numbers of a column with text were spilled as text, flags may be either
+                    DataType::Utf8 => array.as_string::<i32>().iter()
+                        .map(|text| text.map(|text| match text.parse() {
+                            Ok(number) if boolean => StrapValue::Float(number),
+                            _ => StrapValue::Text(text.to_string()),
+                        }))
+                        .collect(),
+                    _ => array.as_list::<i32>().iter()
+                        .map(|list| list.map(|list| StrapValue::List(list.as_primitive::<Float64Type>().values().to_vec())))
+                        .collect(),
+                },
             }
-            .trim_start() // Remove any leading whitespace
-        } else {
-            if all {
-                line
-            } else {
-                return result; // Empty
+        }
+        chunk.lines = lines;
+        Ok(chunk)
+    }
+}
+
+/// Columns and types of the values seen, see `StrapTrack::value_columns`
+#[derive(Debug, Default)]
+struct ColumnTypes {
+    booleans: bool,
+    names: HashSet<String>,
+    texts: HashSet<String>,
+    lists: HashSet<String>,
+    // per column whether every value is a flag, and whether false and true were seen
+    flags: HashMap<String, (bool, bool, bool)>,
+}
+
+impl ColumnTypes {
+    /// Columns of only flags become Boolean if `booleans`
+    fn new(booleans: bool) -> Self {
+        Self { booleans, ..Default::default() }
+    }
+
+    fn add(&mut self, key: &str, value: &StrapValue) {
+        // the key is only copied for a new column
+        let insert = |set: &mut HashSet<String>| {
+            if !set.contains(key) {
+                set.insert(key.to_string());
             }
         };
+        if self.booleans {
+            if !self.flags.contains_key(key) {
+                self.flags.insert(key.to_string(), (true, false, false));
+            }
+            let flag = self.flags.get_mut(key).expect("inserted above");
+            match StrapTrack::flag(value) {
+                Some(false) => flag.1 = true,
+                Some(true) => flag.2 = true,
+                None => flag.0 = false,
+            }
+        }
+        match value {
+            StrapValue::Text(_) => insert(&mut self.texts),
+            StrapValue::List(_) => insert(&mut self.lists),
+            StrapValue::Float(_) => {}
+        }
+        insert(&mut self.names);
+    }
+
+    /// Sorted column names and the types of the columns that are not Float64,
+    /// `types` wins over the types of the values
+    fn finish(self, mut types: HashMap<String, DataType>) -> (Vec<String>, HashMap<String, DataType>) {
+        let explicit: HashSet<String> = types.keys().cloned().collect();
+        for key in self.texts {
+            types.entry(key).or_insert(DataType::Utf8);
+        }
+        for key in self.lists {
+            types.entry(key).or_insert_with(|| DataType::new_list(DataType::Float64, true));
+        }
+        for (key, _) in self.flags.into_iter().filter(|(key, flag)| *flag == (true, true, true) && !explicit.contains(key)) {
+            types.insert(key, DataType::Boolean);
+        }
+        (self.names.into_iter().sorted().collect(), types)
+    }
+}
+
+/// Token of a STRAP line, the byte range of its text in the line, see `StrapTrack::tokens`.
+/// Without a borrow of the line the parser keeps one buffer of them for all lines.
+#[derive(Debug, Clone, PartialEq)]
+struct Token {
+    text: Range<usize>,
+    quoted: bool,
+    joined: bool,
+}
+
+impl Token {
+    /// Text of the token in `line`, the line it was split from
+    fn text<'a>(&self, line: &'a str) -> &'a str {
+        &line[self.text.clone()]
+    }
+}
+
+/// How malformed pairs (a key without value, a number as key, a value with
+/// an ambiguous decimal comma) are handled
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ParseMode {
+    /// drop them, see `StrapTrack::dropped_pairs`
+    #[default]
+    Lenient,
+    /// fail with a `StrapError::Parse` on the first one
+    Strict,
+}
+
+/// Unit suffix of a number, e.g. `ms` in `12ms`, see `StrapTrack::with_unit_suffixes`
+#[derive(Debug, Clone, PartialEq)]
+pub struct UnitSuffix {
+    pub suffix: String,
+    /// factor to the base unit, 1e-3 for ms
+    pub factor: f64,
+    /// base unit, empty for plain numbers
+    pub unit: String,
+}
+
+impl UnitSuffix {
+    pub fn new(suffix: &str, factor: f64, unit: &str) -> Self {
+        Self { suffix: suffix.to_string(), factor, unit: unit.to_string() }
+    }
+
+    /// Times ns, us, ms and s in seconds and the SI prefixes k, M and G
+    pub fn defaults() -> Vec<Self> {
+        vec![
+            Self::new("ns", 1e-9, "s"),
+            Self::new("us", 1e-6, "s"),
+            Self::new("ms", 1e-3, "s"),
+            Self::new("s", 1.0, "s"),
+            Self::new("k", 1e3, ""),
+            Self::new("M", 1e6, ""),
+            Self::new("G", 1e9, ""),
+        ]
+    }
+}
+
+/// What to do with a key that appears twice in a line, e.g. `x 1 x 2`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DuplicateKeyPolicy {
+    #[default]
+    LastWins,
+    FirstWins,
+    /// fail with a `StrapError::Parse`
+    Error,
+    /// the largest of the numbers, text values follow LastWins
+    Max,
+    /// the sum of the numbers, text values follow LastWins
+    Sum,
+}
+
+impl std::str::FromStr for DuplicateKeyPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "last-wins" => Ok(Self::LastWins),
+            "first-wins" => Ok(Self::FirstWins),
+            "error" => Ok(Self::Error),
+            "max" => Ok(Self::Max),
+            "sum" => Ok(Self::Sum),
+            _ => Err(format!("unknown policy {}, use last-wins, first-wins, error, max or sum", s)),
+        }
+    }
+}
+
+/// Decimal separator of the numbers in a file
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DecimalSeparator {
+    /// `21.5`, comma separated numbers like `1,2,3` are a `StrapValue::List`
+    #[default]
+    Dot,
+    /// `21,5`, a number with more than one separator like `1,234,5` is malformed
+    /// and there are no lists
+    Comma,
+    /// the first number with a decimal comma or point decides, the point if none of
+    /// the first `AUTO_DECIMAL_NUMBERS` numbers has either
+    Auto,
+}
+
+impl std::str::FromStr for DecimalSeparator {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "dot" => Ok(Self::Dot),
+            "comma" => Ok(Self::Comma),
+            "auto" => Ok(Self::Auto),
+            _ => Err(format!("unknown decimal separator {}, use dot, comma or auto", s)),
+        }
+    }
+}
+
+/// Numbers `DecimalSeparator::Auto` looks at
+pub const AUTO_DECIMAL_NUMBERS: usize = 300;
+
+/// What to do with NaN and inf values, which `parse::<f64>` accepts
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SpecialValuePolicy {
+    #[default]
+    Keep,
+    /// drop the key-value pair
+    DropPair,
+    /// drop the whole row
+    DropRow,
+    /// fail with a `StrapError::Parse`
+    Error,
+}
+
+impl std::str::FromStr for SpecialValuePolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "keep" => Ok(Self::Keep),
+            "drop-pair" => Ok(Self::DropPair),
+            "drop-row" => Ok(Self::DropRow),
+            "error" => Ok(Self::Error),
+            _ => Err(format!("unknown policy {}, use keep, drop-pair, drop-row or error", s)),
+        }
+    }
+}
+
+/// Value of a STRAP field, text where it doesn't parse as a number, e.g. `run A17`
+#[derive(Debug, Clone, PartialEq)]
+pub enum StrapValue {
+    Float(f64),
+    Text(String),
+    /// comma separated numbers like `weights 0.1,0.2,0.3`, read unless numbers
+    /// have a decimal comma
+    List(Vec<f64>),
+}
+
+impl StrapValue {
+    /// The number of a `Float`, None for text and lists
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            Self::Float(value) => Some(*value),
+            Self::Text(_) | Self::List(_) => None,
+        }
+    }
+
+    /// The numbers of a `List`, a `Float` is a list of one number, None for text
+    pub fn as_list(&self) -> Option<Vec<f64>> {
+        match self {
+            Self::Float(value) => Some(vec![*value]),
+            Self::Text(_) => None,
+            Self::List(values) => Some(values.clone()),
+        }
+    }
+}
+
+impl std::fmt::Display for StrapValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Float(value) => write!(f, "{}", value),
+            Self::Text(text) => write!(f, "{}", text),
+            Self::List(values) => write!(f, "{}", values.iter().join(",")),
+        }
+    }
+}
+
+/// Chunks waiting between the threads of `ParquetOptions::threads`
+const PIPELINE_DEPTH: usize = 4;
+
+/// Lines between two calls of the progress callback
+const PROGRESS_LINES: usize = 10_000;
+
+/// Progress callback of `StrapTrack::with_progress`
+#[derive(Clone)]
+struct Progress(Arc<dyn Fn(u64, Option<u64>, usize) -> bool + Send + Sync>);
+
+impl std::fmt::Debug for Progress {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Progress")
+    }
+}
+
+/// How an uncompressed STRAP file is read
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ReadBackend {
+    /// line by line through a `BufReader`
+    #[default]
+    Buffered,
+    /// memory-map the file and parse the lines in place, without a `String` per
+    /// line. Compressed inputs, bytes in memory and anything but a regular file
+    /// are still read buffered. The file must not be truncated while it is read.
+    Mmap,
+}
+
+/// Where a `StrapTrackIterator` reads its lines from
+enum Lines {
+    Buffered(Box<dyn BufRead>),
+    #[cfg(feature = "std-fs")]
+    Mapped { map: Arc<Mmap>, pos: usize },
+    /// No lines, `next_values` takes the rows of `Source::Rows` as they are
+    Rows { rows: Arc<[HashMap<String, f64>]>, pos: usize },
+}
+
+/// Line of the input including its line ending
+enum Line {
+    Owned(String),
+    /// valid UTF-8, checked by `StrapTrackIterator::read_line`
+    #[cfg(feature = "std-fs")]
+    Mapped(Arc<Mmap>, Range<usize>),
+}
+
+impl std::ops::Deref for Line {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        match self {
+            Self::Owned(line) => line,
+            // SAFETY: read_line only returns ranges that passed `str::from_utf8`
+            #[cfg(feature = "std-fs")]
+            Self::Mapped(map, range) => unsafe { std::str::from_utf8_unchecked(&map[range.clone()]) },
+        }
+    }
+}
+
+/// Function of the numbers of a row
+type RowFn<T> = Arc<dyn Fn(&HashMap<String, f64>) -> T + Send + Sync>;
+
+/// Row filter of `StrapTrack::with_filter`
+#[derive(Clone)]
+struct RowFilter(RowFn<bool>);
+
+impl std::fmt::Debug for RowFilter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "RowFilter")
+    }
+}
+
+/// Column computed from the numbers of a row, see `StrapTrack::with_derived_column`
+#[derive(Clone)]
+struct DerivedColumn(String, RowFn<Option<f64>>);
+
+impl std::fmt::Debug for DerivedColumn {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "DerivedColumn({})", self.0)
+    }
+}
+
+/// Comparison of a column with a number, e.g. `ts >= 1640995200`, rows without
+/// the column don't match
+#[derive(Debug, Clone, PartialEq)]
+pub struct Comparison {
+    pub column: String,
+    /// one of `<`, `<=`, `==`, `!=`, `>=`, `>`
+    pub operator: String,
+    pub value: f64,
+}
+
+impl Comparison {
+    pub fn matches(&self, row: &HashMap<String, f64>) -> bool {
+        let Some(&value) = row.get(&self.column) else {
+            return false;
+        };
+        match self.operator.as_str() {
+            "<" => value < self.value,
+            "<=" => value <= self.value,
+            "==" => value == self.value,
+            "!=" => value != self.value,
+            ">=" => value >= self.value,
+            ">" => value > self.value,
+            _ => false,
+        }
+    }
+}
+
+impl std::str::FromStr for Comparison {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        // two-character operators first, so `>=` isn't read as `>`
+        let (pos, operator) = ["<=", ">=", "==", "!=", "<", ">"].iter()
+            .find_map(|operator| s.find(operator).map(|pos| (pos, *operator)))
+            .ok_or_else(|| format!("no comparison in {}, use e.g. `ts >= 5`", s))?;
+        let column = s[..pos].trim();
+        let value = s[pos + operator.len()..].trim();
+        if column.is_empty() {
+            return Err(format!("no column in {}", s));
+        }
+        let value = value.parse().map_err(|e| format!("invalid number {} in {}: {}", value, s, e))?;
+        Ok(Self { column: column.to_string(), operator: operator.to_string(), value })
+    }
+}
+
+/// Counts the bytes read from the raw input, to locate errors in compressed streams
+struct CountingReader<R> {
+    inner: R,
+    count: Arc<AtomicU64>,
+}
+
+impl<R: Read> Read for CountingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.count.fetch_add(n as u64, Ordering::Relaxed);
+        Ok(n)
+    }
+}
+
+/// Compression of a STRAP input
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InputCompression {
+    None,
+    Gzip,
+    Zstd,
+    Bzip2,
+    /// the first entry of a ZIP archive
+    Zip,
+}
+
+impl InputCompression {
+    /// Guess the compression from the file name extension
+    pub fn from_name(name: &str) -> Self {
+        let name = name.to_lowercase();
+        if name.ends_with(".gz") || name.ends_with(".gzip") {
+            Self::Gzip
+        } else if name.ends_with(".zst") || name.ends_with(".zstd") {
+            Self::Zstd
+        } else if name.ends_with(".bz2") || name.ends_with(".bzip2") {
+            Self::Bzip2
+        } else if name.ends_with(".zip") {
+            Self::Zip
+        } else {
+            Self::None
+        }
+    }
+}
+
+impl std::fmt::Display for InputCompression {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Self::None => "uncompressed",
+            Self::Gzip => "gzip",
+            Self::Zstd => "zstd",
+            Self::Bzip2 => "bzip2",
+            Self::Zip => "zip",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl std::str::FromStr for InputCompression {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "none" | "uncompressed" => Ok(Self::None),
+            "gzip" | "gz" => Ok(Self::Gzip),
+            "zstd" | "zst" => Ok(Self::Zstd),
+            "bzip2" | "bz2" => Ok(Self::Bzip2),
+            "zip" => Ok(Self::Zip),
+            _ => Err(format!("unknown compression {}, use none, gzip, zstd, bzip2 or zip", s)),
+        }
+    }
+}
+
+/// Summary statistics of a single column, accumulated while streaming
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ColumnStats {
+    pub count: usize,
+    /// rows with values in other columns only, set by `StrapTrack::describe`
+    pub null_count: usize,
+    pub sum: f64,
+    pub min: f64,
+    pub max: f64,
+    // running mean and sum of squared deviations (Welford)
+    mean: f64,
+    m2: f64,
+}
+
+impl Default for ColumnStats {
+    fn default() -> Self {
+        Self { count: 0, null_count: 0, sum: 0.0, min: f64::INFINITY, max: f64::NEG_INFINITY, mean: 0.0, m2: 0.0 }
+    }
+}
+
+impl ColumnStats {
+    pub fn push(&mut self, value: f64) {
+        self.count += 1;
+        self.sum += value;
+        self.min = self.min.min(value);
+        self.max = self.max.max(value);
+        let delta = value - self.mean;
+        self.mean += delta / self.count as f64;
+        self.m2 += delta * (value - self.mean);
+    }
+
+    /// NaN without values
+    pub fn mean(&self) -> f64 {
+        if self.count == 0 { f64::NAN } else { self.mean }
+    }
+
+    /// Sample standard deviation, NaN for fewer than two values
+    pub fn stddev(&self) -> f64 {
+        if self.count < 2 { f64::NAN } else { (self.m2 / (self.count - 1) as f64).sqrt() }
+    }
+}
+
+/// Default prefix of a STRAP line, anywhere in the line
+const STRAP_PREFIX: &str = "@strap";
+
+/// Prefix of the header line declaring the columns of positional rows
+const STRAP2_PREFIX: &str = "@strap2";
+
+/// Prefix of the header line declaring column units
+const UNITS_PREFIX: &str = "@strap-units";
+
+/// Start of comment lines, see `StrapTrack::metadata`
+const COMMENT_PREFIX: &str = "#";
+
+/// Reason of a malformed pair whose key is the last token of the line
+const NO_VALUE: &str = "odd token count, no value for key";
+
+/// Reason of a malformed number with several decimal separators
+const AMBIGUOUS_COMMA: &str = "ambiguous decimal comma";
+
+/// Field metadata key holding the unit of a column in written schemas
+pub const UNIT_METADATA_KEY: &str = "unit";
+
+/// Axis label of a column, `pt [GeV]`, just the name without a unit or for the dimensionless unit 1
+pub fn label_with_unit(name: &str, unit: Option<&str>) -> String {
+    match unit {
+        Some(unit) if unit != "1" => format!("{} [{}]", name, unit),
+        _ => name.to_string(),
+    }
+}
+
+/// Column units stored in the field metadata of a parquet file written by `to_parquet`
+#[cfg(feature = "std-fs")]
+pub fn parquet_units(path: &std::path::Path) -> Result<HashMap<String, String>, StrapError> {
+    let builder = parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder::try_new(File::open(path)?)?;
+    Ok(builder.schema().fields().iter()
+        .filter_map(|field| Some((field.name().clone(), field.metadata().get(UNIT_METADATA_KEY)?.clone())))
+        .collect())
+}
+
+/// Options of `StrapTrack::to_parquet_with`
+#[derive(Debug, Clone)]
+pub struct ParquetOptions {
+    /// Rows per record batch
+    pub chunk_size: usize,
+    /// Arrow type per column instead of Float64, e.g. Int32 or Dictionary(Int32, Utf8).
+    /// Columns missing from the file are ignored.
+    pub types: HashMap<String, DataType>,
+    /// Store a hash of the whole source file instead of its first and last MiB
+    pub full_fingerprint: bool,
+    /// Leave out lines without values, e.g. comments, counted in `ConversionStats::skipped`,
+    /// instead of writing a row of nulls for them. Rows dropped on purpose, e.g. by
+    /// `StrapTrack::with_filter`, are never written.
+    pub skip_empty: bool,
+    /// Name of a UInt64 column with the 1-based line number of each row in the
+    /// source file, e.g. `ROW_INDEX_COLUMN`, not written if None
+    pub row_index: Option<String>,
+    /// Write only the first rows, e.g. for a quick preview, the schema still has every column
+    pub head: Option<usize>,
+    pub compression: ParquetCompression,
+    /// Maximum rows per row group, `chunk_size` if None
+    pub row_group_size: Option<usize>,
+    /// Bytes per data page, the parquet default (1 MiB) if None
+    pub data_page_size: Option<usize>,
+    /// Dictionary encoding, worth it for few distinct values like status codes
+    pub dictionary: bool,
+    /// Min/max statistics per column chunk and page, used by readers to skip data
+    pub statistics: bool,
+    /// Parse, build the arrays and write on three threads instead of one
+    pub threads: bool,
+    /// Key-value entries added to the footer next to the provenance, e.g. from `StrapTrack::metadata`
+    pub metadata: Vec<(String, String)>,
+}
+
+impl Default for ParquetOptions {
+    fn default() -> Self {
+        Self {
+            chunk_size: 1000,
+            types: HashMap::new(),
+            full_fingerprint: false,
+            skip_empty: true,
+            row_index: None,
+            head: None,
+            compression: ParquetCompression::default(),
+            row_group_size: None,
+            data_page_size: None,
+            dictionary: true,
+            statistics: true,
+            threads: false,
+            metadata: vec![],
+        }
+    }
+}
+
+/// Compression codec of the written parquet files
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ParquetCompression {
+    #[default]
+    Uncompressed,
+    Snappy,
+    /// level 1 to 9
+    Gzip(u32),
+    /// level 1 to 22
+    Zstd(i32),
+}
+
+#[cfg(feature = "std-fs")]
+impl TryFrom<ParquetCompression> for parquet::basic::Compression {
+    type Error = parquet::errors::ParquetError;
+
+    fn try_from(compression: ParquetCompression) -> Result<Self, Self::Error> {
+        use parquet::basic::{GzipLevel, ZstdLevel};
+        Ok(match compression {
+            ParquetCompression::Uncompressed => Self::UNCOMPRESSED,
+            ParquetCompression::Snappy => Self::SNAPPY,
+            ParquetCompression::Gzip(level) => Self::GZIP(GzipLevel::try_new(level)?),
+            ParquetCompression::Zstd(level) => Self::ZSTD(ZstdLevel::try_new(level)?),
+        })
+    }
+}
+
+impl std::str::FromStr for ParquetCompression {
+    type Err = String;
+
+    /// `none`, `snappy`, `gzip`, `zstd` with an optional level, e.g. `zstd:9`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, level) = match s.split_once(':') {
+            Some((name, level)) => (name, Some(level)),
+            None => (s, None),
+        };
+        let invalid = |e: std::num::ParseIntError| format!("invalid level in {}: {}", s, e);
+        match (name.to_lowercase().as_str(), level) {
+            ("none" | "uncompressed", None) => Ok(Self::Uncompressed),
+            ("snappy", None) => Ok(Self::Snappy),
+            ("gzip", level) => Ok(Self::Gzip(level.map_or(Ok(6), str::parse).map_err(invalid)?)),
+            ("zstd", level) => Ok(Self::Zstd(level.map_or(Ok(1), str::parse).map_err(invalid)?)),
+            _ => Err(format!("unknown parquet compression {}, use none, snappy, gzip[:level] or zstd[:level]", s)),
+        }
+    }
+}
+
+/// Options of `StrapTrack::to_csv`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CsvOptions {
+    pub delimiter: char,
+    /// Digits after the decimal point, the shortest exact representation if None
+    pub precision: Option<usize>,
+}
+
+impl Default for CsvOptions {
+    fn default() -> Self {
+        Self { delimiter: ',', precision: None }
+    }
+}
+
+/// Reading options of `StrapTrack::new_with_options`, the defaults read like `StrapTrack::new`
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct StrapTrackOptions {
+    /// Prefixes marking a STRAP line, `@strap` if empty, see `StrapTrack::with_prefixes`
+    pub prefixes: Vec<String>,
+    /// Token separator besides whitespace, see `StrapTrack::with_delimiter`
+    pub delimiter: Option<char>,
+    pub max_rows: Option<usize>,
+    pub skip_rows: usize,
+    /// Whether lines without a prefix are ignored, None to decide by the file extension
+    pub only_prefixed: Option<bool>,
+}
+
+/// Default name of the line number column of `ParquetOptions::row_index`
+pub const ROW_INDEX_COLUMN: &str = "__row_index";
+
+/// Errors of reading and converting STRAP files
+#[derive(Debug)]
+pub enum StrapError {
+    Io(std::io::Error),
+    /// A value in the 1-based `line` of the input that can't be converted
+    Parse { line: usize, content: String, reason: String },
+    Arrow(arrow::error::ArrowError),
+    #[cfg(feature = "std-fs")]
+    Parquet(parquet::errors::ParquetError),
+    #[cfg(feature = "duckdb")]
+    DuckDb(duckdb::Error),
+    /// The progress callback asked to stop
+    Cancelled,
+}
+
+impl std::fmt::Display for StrapError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "{}", e),
+            Self::Parse { line, content, reason } => write!(f, "line {}: {} ({})", line, reason, content),
+            Self::Arrow(e) => write!(f, "{}", e),
+            #[cfg(feature = "std-fs")]
+            Self::Parquet(e) => write!(f, "{}", e),
+            #[cfg(feature = "duckdb")]
+            Self::DuckDb(e) => write!(f, "{}", e),
+            Self::Cancelled => write!(f, "cancelled"),
+        }
+    }
+}
+
+impl std::error::Error for StrapError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(e) => Some(e),
+            Self::Parse { .. } | Self::Cancelled => None,
+            Self::Arrow(e) => Some(e),
+            #[cfg(feature = "std-fs")]
+            Self::Parquet(e) => Some(e),
+            #[cfg(feature = "duckdb")]
+            Self::DuckDb(e) => Some(e),
+        }
+    }
+}
+
+impl From<std::io::Error> for StrapError {
+    fn from(e: std::io::Error) -> Self {
+        // parse errors the row iterators pass on as io::Error
+        if e.get_ref().is_some_and(|inner| inner.is::<StrapError>()) {
+            return *e.into_inner().unwrap().downcast::<StrapError>().unwrap();
+        }
+        Self::Io(e)
+    }
+}
+
+impl From<arrow::error::ArrowError> for StrapError {
+    fn from(e: arrow::error::ArrowError) -> Self {
+        Self::Arrow(e)
+    }
+}
+
+#[cfg(feature = "std-fs")]
+impl From<parquet::errors::ParquetError> for StrapError {
+    fn from(e: parquet::errors::ParquetError) -> Self {
+        Self::Parquet(e)
+    }
+}
+
+#[cfg(feature = "duckdb")]
+impl From<duckdb::Error> for StrapError {
+    fn from(e: duckdb::Error) -> Self {
+        Self::DuckDb(e)
+    }
+}
+
+/// For callers that only deal in `std::io::Result`
+impl From<StrapError> for std::io::Error {
+    fn from(e: StrapError) -> Self {
+        match e {
+            StrapError::Io(e) => e,
+            e @ StrapError::Parse { .. } => std::io::Error::new(std::io::ErrorKind::InvalidData, e),
+            e => std::io::Error::other(e),
+        }
+    }
+}
+
+fn invalid_input(message: String) -> StrapError {
+    StrapError::Io(std::io::Error::new(std::io::ErrorKind::InvalidInput, message))
+}
+
+/// Rows written and lines without values skipped by `StrapTrack::to_parquet_with`
+/// or `StrapTrack::stream_to_parquet`, see `ParquetOptions::skip_empty`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ConversionStats {
+    pub rows: usize,
+    pub skipped: usize,
+    /// Values of timestamp columns out of the range of their unit, written as null
+    pub invalid_timestamps: usize,
+    /// Rows dropped by `StrapTrack::with_dedupe`
+    pub duplicates: usize,
+    /// Row lines without a value left, e.g. with only malformed pairs, counted in
+    /// `skipped` as well. These are the lines `StrapTrack::load_report` reports.
+    pub lost: usize,
+}
+
+/// Comment lines of `StrapTrack::metadata`
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Metadata {
+    /// 1-based line number and text after the comment prefix, in file order
+    pub comments: Vec<(usize, String)>,
+    /// `key: value` comments, the last value of a key wins
+    pub entries: HashMap<String, String>,
+}
+
+/// Problems `StrapTrack::validate` found in an input
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ValidationReport {
+    pub lines: usize,
+    pub rows: usize,
+    /// Non-empty lines without a prefix, see `StrapTrack::skipped_lines`
+    pub unprefixed: usize,
+    /// Lines ending in a key without value
+    pub odd_token_lines: usize,
+    /// Other malformed pairs, e.g. a number as key
+    pub malformed_pairs: usize,
+    /// Lines failing to parse even leniently, e.g. a second `@strap2` header
+    pub errors: usize,
+    /// Line number and description of the first problems
+    pub examples: Vec<(usize, String)>,
+    pub columns: BTreeSet<String>,
+}
+
+impl ValidationReport {
+    /// Lines with a key without value, malformed pairs and lines failing to parse
+    pub fn problems(&self) -> usize {
+        self.odd_token_lines + self.malformed_pairs + self.errors
+    }
+}
+
+/// Rows lenient loading lost, see `StrapTrack::load_report`
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LoadReport {
+    /// Row lines giving no row, e.g. failing to parse or with only malformed pairs
+    pub skipped: usize,
+    /// Malformed pairs dropped from rows, see `StrapTrack::dropped_pairs`
+    pub dropped_pairs: usize,
+    /// Line number and content of the first skipped lines
+    pub examples: Vec<(usize, String)>,
+}
+
+/// A bin of `StrapTrack::group_by_binned`: (bin center, bin width, value, rows)
+pub type BinnedValue = (f64, f64, f64, usize);
+
+/// Value per bin of `StrapTrack::group_by_binned`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Aggregation {
+    /// rows in the bin
+    #[default]
+    Count,
+    Sum,
+    Mean,
+}
+
+/// Bin counts of `StrapTrack::histogram`, `edges` has one entry more than `counts`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Histogram {
+    pub edges: Vec<f64>,
+    pub counts: Vec<usize>,
+}
+
+/// Numeric rows loaded into memory column-major, with the columns in sorted order
+/// like `StrapTrack::get_column_names`. A `StrapTrack` stays lazy and reads its
+/// input on every call; load it once with `StrapTrack::columns` for cheap column
+/// slices and row access.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct StrapColumns {
+    // sorted
+    names: Vec<String>,
+    index: HashMap<String, usize>,
+    values: Vec<Vec<Option<f64>>>,
+    rows: usize,
+}
+
+/// Row of `StrapColumns` borrowed from its column slices, see `StrapColumns::row`
+#[derive(Debug, Clone, Copy)]
+pub struct StrapRow<'a> {
+    columns: &'a StrapColumns,
+    row: usize,
+}
+
+impl<'a> StrapRow<'a> {
+    /// Index of the row in its `StrapColumns`
+    pub fn index(&self) -> usize {
+        self.row
+    }
+
+    /// Value of `column`, None if the row has none or there is no such column
+    pub fn get(&self, column: &str) -> Option<f64> {
+        self.columns.get_column(column).and_then(|values| values[self.row])
+    }
+
+    /// Columns with a value in this row and their values, in column order
+    pub fn iter(&self) -> impl Iterator<Item = (&'a str, f64)> + 'a {
+        let row = self.row;
+        self.columns.names.iter()
+            .zip(&self.columns.values)
+            .filter_map(move |(name, values)| values[row].map(|value| (name.as_str(), value)))
+    }
+
+    /// The row as an owned map like `StrapTrack::iter` yields
+    pub fn to_map(&self) -> HashMap<String, f64> {
+        self.iter().map(|(name, value)| (name.to_string(), value)).collect()
+    }
+}
+
+impl StrapColumns {
+    /// Append a row, columns first seen in it are filled with None for the earlier rows
+    pub fn push(&mut self, row: &HashMap<String, f64>) {
+        let new_keys: Vec<String> = row.keys().filter(|key| !self.index.contains_key(*key)).cloned().collect();
+        for key in new_keys {
+            self.insert_column(key, vec![None; self.rows]);
+        }
+        for values in &mut self.values {
+            values.push(None);
+        }
+        for (key, value) in row {
+            self.values[self.index[key]][self.rows] = Some(*value);
+        }
+        self.rows += 1;
+    }
+
+    /// Add a column at its sorted position
+    fn insert_column(&mut self, name: String, values: Vec<Option<f64>>) {
+        let position = self.names.binary_search(&name).unwrap_or_else(|position| position);
+        self.names.insert(position, name);
+        self.values.insert(position, values);
+        self.index = self.names.iter().enumerate().map(|(i, name)| (name.clone(), i)).collect();
+    }
+
+    pub fn len(&self) -> usize {
+        self.rows
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rows == 0
+    }
+
+    pub fn column_names(&self) -> &[String] {
+        &self.names
+    }
+
+    /// Values of `column`, one per row, None if there is no such column
+    pub fn get_column(&self, column: &str) -> Option<&[Option<f64>]> {
+        self.index.get(column).map(|&i| self.values[i].as_slice())
+    }
+
+    /// Rename `from` to `to`, merging it into an existing column `to`. Fails without
+    /// changes if a row has different values in both.
+    pub fn rename_column(&mut self, from: &str, to: &str) -> Result<(), StrapError> {
+        let Some(&source) = self.index.get(from) else {
+            return Err(invalid_input(format!("no column {}", from)));
+        };
+        if from == to {
+            return Ok(());
+        }
+        let Some(&target) = self.index.get(to) else {
+            let values = self.values.remove(source);
+            self.names.remove(source);
+            self.insert_column(to.to_string(), values);
+            return Ok(());
+        };
+        let conflict = self.values[source].iter().zip(&self.values[target])
+            .position(|(a, b)| a.is_some() && b.is_some() && a != b);
+        if let Some(row) = conflict {
+            return Err(invalid_input(format!("row {} has different values for {} and {}", row, from, to)));
+        }
+        let values = self.values.remove(source);
+        self.names.remove(source);
+        for (merged, value) in self.values[if target > source { target - 1 } else { target }].iter_mut().zip(values) {
+            *merged = merged.or(value);
+        }
+        self.index = self.names.iter().enumerate().map(|(i, name)| (name.clone(), i)).collect();
+        Ok(())
+    }
+
+    /// Values of `column` in the rows `range`, clamped to the rows there are
+    pub fn get_column_range(&self, column: &str, range: std::ops::Range<usize>) -> Option<&[Option<f64>]> {
+        let range = self.clamp(range);
+        self.get_column(column).map(|values| &values[range])
+    }
+
+    /// Row `row` as a map from column to value, None past the last row. Constant time in
+    /// the number of rows; the map is built from the columns, see `row` for a borrowed view.
+    pub fn get_row(&self, row: usize) -> Option<HashMap<String, f64>> {
+        self.row(row).map(|row| row.to_map())
+    }
+
+    /// Row `row` borrowed from the columns, None past the last row
+    pub fn row(&self, row: usize) -> Option<StrapRow<'_>> {
+        (row < self.rows).then_some(StrapRow { columns: self, row })
+    }
+
+    /// Rows `range` like `row`, clamped to the rows there are, for paging through a
+    /// loaded track without copying any of it
+    pub fn get_rows(&self, range: std::ops::Range<usize>) -> impl Iterator<Item = StrapRow<'_>> + '_ {
+        self.clamp(range).map(|row| StrapRow { columns: self, row })
+    }
+
+    /// Every row like `row`, the columns are stored column-major so there is no slice of rows
+    pub fn rows(&self) -> impl Iterator<Item = StrapRow<'_>> + '_ {
+        self.get_rows(0..self.rows)
+    }
+
+    fn clamp(&self, range: std::ops::Range<usize>) -> std::ops::Range<usize> {
+        let end = range.end.min(self.rows);
+        range.start.min(end)..end
+    }
+
+    /// Read the numeric columns of a parquet file, e.g. one written by `StrapTrack::to_parquet`,
+    /// nulls are missing values. Also returns the names of the other columns, which are skipped.
+    ///
+    /// This loads into `StrapColumns` rather than a `StrapTrack`: a track parses STRAP text
+    /// lazily from its source, and parquet has no STRAP text to go back to.
+    #[cfg(feature = "std-fs")]
+    pub fn from_parquet(path: impl AsRef<std::path::Path>) -> Result<(Self, Vec<String>), StrapError> {
+        let builder = parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder::try_new(File::open(path)?)?;
+        let (numeric, skipped): (Vec<_>, Vec<_>) = builder.schema().fields().iter()
+            .enumerate()
+            .partition(|(_, field)| field.data_type().is_numeric());
+        let skipped = skipped.into_iter().map(|(_, field)| field.name().clone()).collect();
+        let numeric: Vec<(usize, String)> = numeric.into_iter().map(|(i, field)| (i, field.name().clone())).collect();
+        let mut columns = Self::default();
+        for (_, name) in &numeric {
+            columns.insert_column(name.clone(), vec![]);
+        }
+        for batch in builder.build()? {
+            let batch = batch?;
+            for (i, name) in &numeric {
+                let array = arrow::compute::cast(batch.column(*i), &DataType::Float64)?;
+                let array = array.as_any().downcast_ref::<Float64Array>().expect("cast to Float64");
+                columns.values[columns.index[name]].extend(array.iter());
+            }
+            columns.rows += batch.num_rows();
+        }
+        Ok((columns, skipped))
+    }
+
+    /// One nullable Float64 column per key in sorted order, like `StrapTrack::to_record_batch`
+    pub fn to_record_batch(&self) -> Result<RecordBatch, StrapError> {
+        let schema = StrapTrack::column_schema(&self.names, &HashMap::new());
+        let arrays: Vec<ArrayRef> = self.values.iter()
+            .map(|values| Arc::new(Float64Array::from(values.clone())) as ArrayRef)
+            .collect();
+        Ok(RecordBatch::try_new(schema, arrays)?)
+    }
+}
+
+/// Where the rows of a StrapTrack are read from
+#[derive(Debug, Clone)]
+enum Source {
+    #[cfg(feature = "std-fs")]
+    Path(PathBuf),
+    /// In-memory content, `name` decides the compression like a file name would
+    Bytes { name: Option<String>, data: Arc<[u8]> },
+    /// Rows of `FromIterator`, kept as they are instead of as text
+    Rows(Arc<[HashMap<String, f64>]>),
+}
+
+/// Lazy/streaming parser for STRAP protocol files
+#[derive(Debug, Clone)]
+pub struct StrapTrack {
+    source: Source,
+    compression: InputCompression,
+    mode: ParseMode,
+    special: SpecialValuePolicy,
+    backend: ReadBackend,
+    prefixes: Arc<[String]>,
+    progress: Option<Progress>,
+    // None to take every line of .strap files and only prefixed lines of others
+    only_prefixed: Option<bool>,
+    // keys kept, None for all
+    columns: Option<Arc<[String]>>,
+    filter: Option<RowFilter>,
+    renames: Option<Arc<HashMap<String, String>>>,
+    duplicates: DuplicateKeyPolicy,
+    suffixes: Arc<[UnitSuffix]>,
+    delimiter: Option<char>,
+    skip_rows: usize,
+    max_rows: Option<usize>,
+    // unit written per timestamp column
+    timestamps: Arc<HashMap<String, TimeUnit>>,
+    // start of comment lines, None if they are read like any other line
+    comment_prefix: Option<String>,
+    decimal: DecimalSeparator,
+    // write 0/1 and true/false columns as Boolean
+    booleans: bool,
+    // drop rows equal to an earlier one
+    dedupe: bool,
+    // computed per row in this order
+    derived: Arc<[DerivedColumn]>,
+    //data : Vec<HashMap<String, f64>>,
+
+    //cached_column_names: Option<Vec<String>>,
+    //cached_columns: HashMap<String, Vec<f64>>,
+}
+
+impl std::str::FromStr for StrapTrack {
+    type Err = std::convert::Infallible;
+
+    /// Uncompressed STRAP content, every line is a row
+    fn from_str(content: &str) -> Result<Self, Self::Err> {
+        Ok(Self::from_source(Source::Bytes { name: None, data: content.as_bytes().into() }, InputCompression::None))
+    }
+}
+
+impl FromIterator<HashMap<String, f64>> for StrapTrack {
+    /// In-memory track of `rows`, e.g. synthetic data for tests, read back in
+    /// insertion order. The rows are kept as they are, not written as text, so any
+    /// key round-trips. Empty rows are no rows. There are no lines, `metadata`,
+    /// `load_report` and `validate` find nothing.
+    fn from_iter<I: IntoIterator<Item = HashMap<String, f64>>>(rows: I) -> Self {
+        Self::from_source(Source::Rows(rows.into_iter().collect()), InputCompression::None)
+    }
+}
+
+impl IntoIterator for &StrapTrack {
+    type Item = Result<HashMap<String, f64>, std::io::Error>;
+    type IntoIter = itertools::Either<StrapTrackIterator, std::iter::Once<Self::Item>>;
+
+    /// Rows in file order like `iter`, an input that can't be opened is the only item.
+    /// Each row is parsed again into an owned HashMap, there are no borrowed rows and
+    /// nothing is cached between iterations, column names included.
+    fn into_iter(self) -> Self::IntoIter {
+        match self.iter() {
+            Ok(iter) => itertools::Either::Left(iter),
+            Err(e) => itertools::Either::Right(std::iter::once(Err(e.into()))),
+        }
+    }
+}
+
+impl IntoIterator for StrapTrack {
+    type Item = Result<HashMap<String, f64>, std::io::Error>;
+    type IntoIter = itertools::Either<StrapTrackIterator, std::iter::Once<Self::Item>>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        (&self).into_iter()
+    }
+}
+
+impl StrapTrack {
+    fn from_source(source: Source, compression: InputCompression) -> Self {
+        Self {
+            source,
+            compression,
+            mode: ParseMode::default(),
+            special: SpecialValuePolicy::default(),
+            backend: ReadBackend::default(),
+            prefixes: Arc::new([STRAP_PREFIX.to_string()]),
+            progress: None,
+            only_prefixed: None,
+            columns: None,
+            filter: None,
+            renames: None,
+            duplicates: DuplicateKeyPolicy::default(),
+            suffixes: Arc::new([]),
+            delimiter: None,
+            skip_rows: 0,
+            max_rows: None,
+            timestamps: Arc::new(HashMap::new()),
+            comment_prefix: Some(COMMENT_PREFIX.to_string()),
+            decimal: DecimalSeparator::default(),
+            booleans: false,
+            dedupe: false,
+            derived: Arc::new([]),
+        }
+    }
+
+    #[cfg(feature = "std-fs")]
+    pub fn new(file_path: impl Into<PathBuf>) -> Result<Self, StrapError> {
+        let path = file_path.into();
+        let compression = InputCompression::from_name(&path.to_string_lossy());
+        Self::new_with_compression(path, compression)
+    }
+
+    /// Like `new` with a progress callback, see `with_progress`
+    #[cfg(feature = "std-fs")]
+    pub fn new_with_progress(
+        file_path: impl Into<PathBuf>,
+        callback: impl Fn(u64, Option<u64>, usize) -> bool + Send + Sync + 'static,
+    ) -> Result<Self, StrapError> {
+        Ok(Self::new(file_path)?.with_progress(callback))
+    }
+
+    /// Like `new` with a row filter, see `with_filter`
+    #[cfg(feature = "std-fs")]
+    pub fn new_with_filter(
+        file_path: impl Into<PathBuf>,
+        predicate: impl Fn(&HashMap<String, f64>) -> bool + Send + Sync + 'static,
+    ) -> Result<Self, StrapError> {
+        Ok(Self::new(file_path)?.with_filter(predicate))
+    }
+
+    /// Like `new` with the reading options of `options`, see `with_options`
+    #[cfg(feature = "std-fs")]
+    pub fn new_with_options(file_path: impl Into<PathBuf>, options: &StrapTrackOptions) -> Result<Self, StrapError> {
+        Ok(Self::new(file_path)?.with_options(options))
+    }
+
+    /// Like `new` with the compression given instead of guessed from the extension
+    #[cfg(feature = "std-fs")]
+    pub fn new_with_compression(file_path: impl Into<PathBuf>, compression: InputCompression) -> Result<Self, StrapError> {
+        let path = file_path.into();
+        // Verify file exists
+        File::open(&path)?;
+
+        
+        Ok(Self::from_source(Source::Path(path), compression))
+    }
+
+    /// In-memory file content, e.g. from a browser upload, `name` is the file name
+    pub fn from_bytes(name: impl Into<String>, data: impl Into<Arc<[u8]>>) -> Self {
+        let name = name.into();
+        let compression = InputCompression::from_name(&name);
+        Self::from_source(Source::Bytes { name: Some(name), data: data.into() }, compression)
+    }
+
+    /// Drop every row with the same keys and values as an earlier row, e.g. of a chunk
+    /// appended twice, see `duplicate_rows`. Only a 64-bit hash of each row is kept.
+    pub fn with_dedupe(mut self, dedupe: bool) -> Self {
+        self.dedupe = dedupe;
+        self
+    }
+
+    /// Lines starting with `prefix` are comments instead of `#`, see `metadata`. With
+    /// None such lines are read like any other line.
+    pub fn with_comment_prefix(mut self, prefix: Option<&str>) -> Self {
+        self.comment_prefix = prefix.map(str::to_string);
+        self
+    }
+
+    /// Write columns with only the values 0 and 1 (or `true` and `false`), both of
+    /// them present, as Boolean instead of Float64 or Utf8. Types given in
+    /// `ParquetOptions::types` and timestamp columns keep their type.
+    pub fn with_boolean_detection(mut self, detect: bool) -> Self {
+        self.booleans = detect;
+        self
+    }
+
+    /// Read numbers with `separator` as decimal separator, a point by default
+    pub fn with_decimal_separator(mut self, separator: DecimalSeparator) -> Self {
+        self.decimal = separator;
+        self
+    }
+
+    /// Decompress the input with `compression` instead of the one guessed from the name
+    pub fn with_compression(mut self, compression: InputCompression) -> Self {
+        self.compression = compression;
+        self
+    }
+
+    /// Use `mode` for malformed pairs, lenient by default
+    pub fn with_parse_mode(mut self, mode: ParseMode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    /// Call `callback(bytes_read, total_bytes, rows_parsed)` every 10000 lines of every
+    /// pass over the input, returning false stops reading with `StrapError::Cancelled`.
+    /// `bytes_read` counts the raw (compressed) input, `total_bytes` is None if the
+    /// input size is unknown, e.g. for a pipe.
+    pub fn with_progress(mut self, callback: impl Fn(u64, Option<u64>, usize) -> bool + Send + Sync + 'static) -> Self {
+        self.progress = Some(Progress(Arc::new(callback)));
+        self
+    }
+
+    /// Add a column `name` computed from the numbers of every row, e.g. `energy`
+    /// from `pt` and `eta`, missing in rows where `derive` returns None. `derive`
+    /// sees the columns derived before, `with_filter` sees every derived column.
+    /// Reading fails if `name` is a column of the file or derived twice.
+    pub fn with_derived_column(
+        mut self,
+        name: &str,
+        derive: impl Fn(&HashMap<String, f64>) -> Option<f64> + Send + Sync + 'static,
+    ) -> Self {
+        self.derived = self.derived.iter().cloned().chain([DerivedColumn(name.to_string(), Arc::new(derive))]).collect();
+        // a projection keeps the columns derived after it
+        if let Some(columns) = &self.columns {
+            self.columns = Some(columns.iter().cloned().chain([name.to_string()]).collect());
+        }
+        self
+    }
+
+    /// Skip rows for which `predicate` of their numeric values is false while
+    /// parsing, see `filtered_rows`
+    pub fn with_filter(mut self, predicate: impl Fn(&HashMap<String, f64>) -> bool + Send + Sync + 'static) -> Self {
+        self.filter = Some(RowFilter(Arc::new(predicate)));
+        self
+    }
+
+    /// Read numbers with a unit suffix like `12ms` or `4k`, scaled to the base unit
+    /// of the suffix, e.g. `UnitSuffix::defaults()`. Without suffixes such values are
+    /// malformed. A column with suffixes of different base units is a parse error.
+    pub fn with_unit_suffixes(mut self, suffixes: impl IntoIterator<Item = UnitSuffix>) -> Self {
+        self.suffixes = suffixes.into_iter().collect();
+        self
+    }
+
+    /// Base unit of each column with numbers written with a unit suffix
+    pub fn suffix_units(&self) -> Result<BTreeMap<String, String>, StrapError> {
+        let mut iter = self.iter()?;
+        while let Some(row) = iter.next_values() {
+            row?;
+        }
+        Ok(iter.suffix_units)
+    }
+
+    /// Use `policy` for a key appearing twice in a line, the last value wins by default
+    pub fn with_duplicate_keys(mut self, policy: DuplicateKeyPolicy) -> Self {
+        self.duplicates = policy;
+        self
+    }
+
+    /// Use `policy` for NaN and inf values, they are kept by default
+    pub fn with_special_values(mut self, policy: SpecialValuePolicy) -> Self {
+        self.special = policy;
+        self
+    }
+
+    /// Rename keys while parsing, e.g. `[("pt", "jet_pt")]` to merge an old key into
+    /// the column of its new name. A row with different values for keys of the same
+    /// name after renaming is a parse error. Filters and `with_columns` see the new names.
+    pub fn with_renames(mut self, renames: &[(&str, &str)]) -> Self {
+        self.renames = Some(Arc::new(renames.iter().map(|(old, new)| (old.to_string(), new.to_string())).collect()));
+        self
+    }
+
+    /// Write `columns` of epoch seconds as timestamps in `unit`, rounded to it, e.g.
+    /// milliseconds. Values out of the range of the unit are written as null, see
+    /// `ConversionStats::invalid_timestamps`. See `detect_timestamp_columns`.
+    pub fn with_timestamp_columns(mut self, columns: &[&str], unit: TimeUnit) -> Self {
+        Arc::make_mut(&mut self.timestamps).extend(columns.iter().map(|column| (column.to_string(), unit)));
+        self
+    }
+
+    /// Keep only the values of `columns` while parsing, rows without any of them
+    /// are skipped. See `missing_columns` for names that are not in the file.
+    pub fn with_columns(mut self, columns: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        self.columns = Some(columns.into_iter().map(Into::into).collect());
+        self
+    }
+
+    /// Projection to `columns`, failing with the names that aren't columns of this track
+    pub fn select_columns(&self, columns: &[&str]) -> Result<StrapTrack, StrapError> {
+        let mut missing: Vec<String> = columns.iter().filter(|name| !self.selected(name)).map(|name| name.to_string()).collect();
+        let projected = self.clone().with_columns(columns.iter().copied().filter(|name| self.selected(name)));
+        missing.extend(projected.missing_columns()?);
+        if !missing.is_empty() {
+            return Err(invalid_input(format!("no column {} in {}", missing.join(", "), self.name())));
+        }
+        Ok(projected)
+    }
+
+    /// Names given to `with_columns` that no row has a value for
+    pub fn missing_columns(&self) -> Result<Vec<String>, StrapError> {
+        let Some(columns) = &self.columns else {
+            return Ok(vec![]);
+        };
+        let mut all = self.clone();
+        all.columns = None;
+        let (names, _) = all.value_columns(&HashMap::new())?;
+        Ok(columns.iter().filter(|name| !names.contains(name)).cloned().collect())
+    }
+
+    /// Names of the columns of `with_derived_column`
+    fn derived_names(&self) -> impl Iterator<Item = String> + '_ {
+        self.derived.iter().map(|DerivedColumn(name, _)| name.clone())
+    }
+
+    /// Whether `name` is kept, see `with_columns`
+    fn selected(&self, name: &str) -> bool {
+        self.columns.as_ref().is_none_or(|columns| columns.iter().any(|column| column == name))
+    }
+
+    /// Read uncompressed files with `backend`, buffered by default
+    pub fn with_backend(mut self, backend: ReadBackend) -> Self {
+        self.backend = backend;
+        self
+    }
+
+    /// Read only lines with one of the prefixes (true) or every line (false)
+    /// instead of every line of `.strap` files and prefixed lines of others
+    pub fn with_only_prefixed(mut self, only_prefixed: bool) -> Self {
+        self.only_prefixed = Some(only_prefixed);
+        self
+    }
+
+    /// Prefixes marking a STRAP line instead of `@strap`, a prefix also matches
+    /// longer words like `@strap1`
+    pub fn with_prefixes(mut self, prefixes: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        self.prefixes = prefixes.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Also split tokens at `delimiter`, e.g. `,` for `@strap a=1,b=2`
+    pub fn with_delimiter(mut self, delimiter: char) -> Self {
+        self.delimiter = Some(delimiter);
+        self
+    }
+
+    /// Skip the first `rows` rows, lines that are no row don't count
+    pub fn with_skip_rows(mut self, rows: usize) -> Self {
+        self.skip_rows = rows;
+        self
+    }
+
+    /// Stop after `rows` rows, counted after `with_skip_rows`
+    pub fn with_max_rows(mut self, rows: usize) -> Self {
+        self.max_rows = Some(rows);
+        self
+    }
+
+    /// Apply all of `options`, unset options keep their current value
+    pub fn with_options(mut self, options: &StrapTrackOptions) -> Self {
+        if !options.prefixes.is_empty() {
+            self = self.with_prefixes(options.prefixes.iter().cloned());
+        }
+        if let Some(only_prefixed) = options.only_prefixed {
+            self = self.with_only_prefixed(only_prefixed);
+        }
+        if let Some(delimiter) = options.delimiter {
+            self = self.with_delimiter(delimiter);
+        }
+        if let Some(max_rows) = options.max_rows {
+            self = self.with_max_rows(max_rows);
+        }
+        self.with_skip_rows(options.skip_rows)
+    }
+
+    /// Read everything from `reader` into memory, `name` is the file name. An empty
+    /// reader has no rows.
+    pub fn from_reader(name: impl Into<String>, mut reader: impl Read) -> Result<Self, StrapError> {
+        let mut data = Vec::new();
+        reader.read_to_end(&mut data)?;
+        Ok(Self::from_bytes(name, data))
+    }
+
+    /// File name or path the rows are read from, empty for `from_str` and collected rows
+    pub fn name(&self) -> String {
+        match &self.source {
+            #[cfg(feature = "std-fs")]
+            Source::Path(path) => path.to_string_lossy().to_string(),
+            Source::Bytes { name, .. } => name.clone().unwrap_or_default(),
+            Source::Rows(_) => String::new(),
+        }
+    }
+
+    /// Create a reader that decompresses according to `self.compression`,
+    /// the bytes read from the raw input are counted in `count`
+    fn create_reader(&self, count: Arc<AtomicU64>) -> Result<Box<dyn BufRead>, std::io::Error> {
+        let file: Box<dyn Read> = match &self.source {
+            #[cfg(feature = "std-fs")]
+            Source::Path(path) => Box::new(File::open(path)?),
+            Source::Bytes { data, .. } => Box::new(std::io::Cursor::new(data.clone())),
+            Source::Rows(_) => Box::new(std::io::empty()),
+        };
+        let file: Box<dyn Read> = Box::new(CountingReader { inner: file, count });
+        
+        match self.compression {
+            InputCompression::Gzip => {
+                let decoder = GzDecoder::new(BufReader::new(file));
+                Ok(Box::new(BufReader::new(decoder)))
+            }
+            InputCompression::Zstd => Self::zstd_reader(file),
+            InputCompression::Bzip2 => {
+                // concatenated streams as written by pbzip2
+                let decoder = MultiBzDecoder::new(BufReader::new(file));
+                Ok(Box::new(BufReader::new(decoder)))
+            }
+            InputCompression::Zip => Self::zip_reader(file),
+            InputCompression::None => Ok(Box::new(BufReader::new(file))),
+        }
+    }
+
+    #[cfg(feature = "std-fs")]
+    fn zstd_reader(file: Box<dyn Read>) -> Result<Box<dyn BufRead>, std::io::Error> {
+        // Zstd compressed
+        let decoder = ZstdDecoder::new(file)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+        Ok(Box::new(BufReader::new(decoder)))
+    }
+
+    #[cfg(feature = "std-fs")]
+    fn zip_reader(mut file: Box<dyn Read>) -> Result<Box<dyn BufRead>, std::io::Error> {
+        // ZIP archive - read first entry into memory
+        let mut bytes = Vec::new();
+        file.read_to_end(&mut bytes)?;
+        let mut archive = ZipArchive::new(std::io::Cursor::new(bytes))
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+        
+        if archive.is_empty() {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                "ZIP archive is empty"
+            ));
+        }
+        
+        // Read the first file in the archive into memory
+        let mut zip_file = archive.by_index(0)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+        let mut contents = Vec::new();
+        std::io::copy(&mut zip_file, &mut contents)?;
+        Ok(Box::new(BufReader::new(std::io::Cursor::new(contents))))
+    }
+
+    // zstd and zip need native libraries or the file system, only gzip and bzip2 are pure Rust
+    #[cfg(not(feature = "std-fs"))]
+    fn zstd_reader(_file: Box<dyn Read>) -> Result<Box<dyn BufRead>, std::io::Error> {
+        Err(std::io::Error::new(std::io::ErrorKind::Unsupported, "zstd requires the std-fs feature"))
+    }
+
+    #[cfg(not(feature = "std-fs"))]
+    fn zip_reader(_file: Box<dyn Read>) -> Result<Box<dyn BufRead>, std::io::Error> {
+        Err(std::io::Error::new(std::io::ErrorKind::Unsupported, "zip requires the std-fs feature"))
+    }
+
+    /// Get column names from all rows in sorted order, numeric, text and list columns,
+    /// the column order of `schema` and all conversions
+    pub fn get_column_names(&self) -> Result<Vec<String>, StrapError> {
+        Ok(self.value_columns(&HashMap::new())?.0)
+    }
+
+    /// Sorted names of numeric and text columns and the type of each column
+    /// written by the conversions, Utf8 if any value is text, otherwise a list of
+    /// Float64 if any value is a list, Boolean for flags with `with_boolean_detection`
+    fn value_columns(&self, types: &HashMap<String, DataType>) -> Result<(Vec<String>, HashMap<String, DataType>), StrapError> {
+        let timestamps = self.timestamps.iter().map(|(name, unit)| (name.clone(), DataType::Timestamp(*unit, None)));
+        if let Some(header) = self.header()? {
+            let mut header_types: HashMap<String, DataType> = header.iter().cloned().collect();
+            header_types.extend(timestamps);
+            header_types.extend(types.iter().map(|(name, data_type)| (name.clone(), data_type.clone())));
+            let names = header.into_iter().map(|(name, _)| name).chain(self.derived_names());
+            return Ok((names.filter(|name| self.selected(name)).sorted().collect(), header_types));
+        }
+        let mut columns = ColumnTypes::new(self.booleans);
+        for row in self.iter_values()? {
+            for (key, value) in &row? {
+                columns.add(key, value);
+            }
+        }
+        Ok(columns.finish(timestamps.chain(types.clone()).collect()))
+    }
+
+    /// `value` as a flag: 0 and 1 or `true` and `false` in any case, None for others
+    fn flag(value: &StrapValue) -> Option<bool> {
+        match value {
+            StrapValue::Float(value) if *value == 0.0 => Some(false),
+            StrapValue::Float(value) if *value == 1.0 => Some(true),
+            StrapValue::Text(text) if text.eq_ignore_ascii_case("false") => Some(false),
+            StrapValue::Text(text) if text.eq_ignore_ascii_case("true") => Some(true),
+            _ => None,
+        }
+    }
+
+    /// Numeric values of `column` aligned with the rows, value `i` belongs to row `i`
+    /// and is None where the row lacks the key or holds text or a list, see
+    /// `get_list_column`. A column without a value in any row is an error.
+    pub fn get_column(&self, column: &str) -> Result<Vec<Option<f64>>, StrapError> {
+        let mut found = false;
+        let values = self.iter_values()?
+            .filter_ok(|row| !row.is_empty())
+            .map_ok(|row| {
+                found |= row.contains_key(column);
+                row.get(column).and_then(StrapValue::as_f64)
+            })
+            .collect::<Result<_, _>>()?;
+        if !found {
+            return Err(invalid_input(format!("no column {}", column)));
+        }
+        Ok(values)
+    }
+
+    /// Numeric values of `column` without the rows that lack one, so value `i` is
+    /// not row `i`, see `get_column`
+    pub fn get_column_dense(&self, column: &str) -> Result<Vec<f64>, StrapError> {
+        Ok(self.get_column(column)?.into_iter().flatten().collect())
+    }
+
+    /// Numbers of a list column like `weights 0.1,0.2,0.3`, one list per row, a
+    /// number is a list of one number and text is None
+    pub fn get_list_column(&self, column: &str) -> Result<Vec<Option<Vec<f64>>>, StrapError> {
+        Ok(self.iter_values()?
+            .filter_ok(|row| !row.is_empty())
+            .map_ok(|row| row.get(column).and_then(StrapValue::as_list))
+            .collect::<Result<_, _>>()?)
+    }
+
+    /// Read every row with a value into memory column by column, the same rows as
+    /// `get_column` and `to_parquet`. Text and list values are left out, a row with
+    /// only those is all None. `StrapColumns::from_parquet` loads the same from a
+    /// converted file.
+    pub fn columns(&self) -> Result<StrapColumns, StrapError> {
+        let mut columns = StrapColumns::default();
+        for row in self.iter_values()?.filter_ok(|row| !row.is_empty()) {
+            columns.push(&Self::numeric(row?));
+        }
+        Ok(columns)
+    }
+
+    /// Values of `column` as text, one per row, numbers are formatted
+    pub fn get_text_column(&self, column: &str) -> Result<Vec<Option<String>>, StrapError> {
+        Ok(self.iter_values()?
+            .filter_ok(|row| !row.is_empty())
+            .map_ok(|row| row.get(column).map(StrapValue::to_string))
+            .collect::<Result<_, _>>()?)
+    }
+    
+    /// Parse a single `@strap` line into its numeric key-value pairs
+    #[cfg(test)]
+    fn parse_line(line: &str, all : bool) -> HashMap<String, f64> {
+        Self::numeric(Self::parse_values(line, all, &[STRAP_PREFIX]))
+    }
+
+    fn numeric(values: HashMap<String, StrapValue>) -> HashMap<String, f64> {
+        values.into_iter()
+            .filter_map(|(key, value)| Some((key, value.as_f64()?)))
+            .collect()
+    }
+
+    /// Key-value part of a STRAP line, None if the line is no row.
+    /// Lines without any of `prefixes` are rows only if `all`.
+    fn row_tokens(line: &str, all: bool, prefixes: &[impl AsRef<str>], delimiter: Option<char>) -> Option<Vec<Token>> {
+        let mut tokens = vec![];
+        Self::row_tokens_into(line, all, prefixes, delimiter, &mut tokens).then_some(tokens)
+    }
+
+    /// Like `row_tokens` into an empty `tokens`, false if the line is no row
+    fn row_tokens_into(
+        row: &str,
+        all: bool,
+        prefixes: &[impl AsRef<str>],
+        delimiter: Option<char>,
+        tokens: &mut Vec<Token>,
+    ) -> bool {
+        let line = row.trim();
+
+        // unit declarations are not a row
+        if line.contains(UNITS_PREFIX) {
+            return false;
+        }
+
+        // Handle @strap prefix - find first occurrence and continue from there
+        let line = if let Some((_, rest)) = Self::prefix_word(line, prefixes) {
+            rest.trim_start() // Remove any leading whitespace
+        } else {
+            if all {
+                line
+            } else {
+                return false;
+            }
+        };
+
+        // key-value pairs separated by whitespace, `line` ends where the trimmed row does
+        let start = row.len() - row.trim_start().len() + row.trim().len() - line.len();
+        Self::tokens_into(line, start, delimiter, tokens);
+        true
+    }
+
+    /// The word starting with the first occurrence of one of `prefixes` in `line`,
+    /// e.g. `@strap1`, and the rest of the line after it
+    fn prefix_word<'a>(line: &'a str, prefixes: &[impl AsRef<str>]) -> Option<(&'a str, &'a str)> {
+        let pos = prefixes.iter().filter_map(|prefix| line.find(prefix.as_ref())).min()?;
+        let after_strap = &line[pos..];
+        Some(after_strap.split_at(after_strap.find(char::is_whitespace).unwrap_or(after_strap.len())))
+    }
+
+    /// Columns and types of a `@strap2 name:type ...` header line, None for other lines,
+    /// e.g. `@strap2 key 1.0`. Types are f64, f32, i64, i32, u64, u32 and str.
+    fn parse_header(line: &str, delimiter: Option<char>) -> Result<Option<Vec<(String, DataType)>>, String> {
+        let line = line.trim();
+        let tokens = Self::tokens(line, delimiter);
+        let Some((first, tokens)) = tokens.split_first() else {
+            return Ok(None);
+        };
+        if first.quoted || first.text(line) != STRAP2_PREFIX || tokens.is_empty() {
+            return Ok(None);
+        }
+        let mut columns = vec![];
+        let mut tokens = tokens.iter().peekable();
+        while let Some(token) = tokens.next() {
+            // the `:type` of `"quoted name":type`
+            let split = match tokens.next_if(|next| token.quoted && next.joined && next.text(line).starts_with(':')) {
+                Some(data_type) => Some((token.text(line), &data_type.text(line)[1..])),
+                None if !token.quoted => token.text(line).rsplit_once(':'),
+                None => None,
+            };
+            let Some((name, data_type)) = split else {
+                return Ok(None);
+            };
+            let data_type = match data_type {
+                "f64" => DataType::Float64,
+                "f32" => DataType::Float32,
+                "i64" => DataType::Int64,
+                "i32" => DataType::Int32,
+                "u64" => DataType::UInt64,
+                "u32" => DataType::UInt32,
+                "str" => DataType::Utf8,
+                _ => return Err(format!("unknown type `{}` of column {}", data_type, name)),
+            };
+            columns.push((name.to_string(), data_type));
+        }
+        Ok(Some(columns))
+    }
+
+    /// Values of a row after a `@strap2` header by position, and the malformed values
+    fn parse_positional(line: &str, tokens: &[Token], header: &[(String, DataType)]) -> (HashMap<String, StrapValue>, Vec<(String, &'static str)>) {
+        let mut values = HashMap::new();
+        let mut malformed = vec![];
+        if tokens.len() != header.len() {
+            let token = tokens.get(header.len()).map_or("", |token| token.text(line));
+            malformed.push((token.to_string(), if tokens.len() > header.len() {
+                "more values than header columns"
+            } else {
+                "fewer values than header columns"
+            }));
+        }
+        for (token, (name, data_type)) in tokens.iter().zip(header) {
+            let token = token.text(line);
+            if data_type == &DataType::Utf8 {
+                values.insert(name.clone(), StrapValue::Text(token.to_string()));
+            } else if let Ok(value) = token.parse::<f64>() {
+                values.insert(name.clone(), StrapValue::Float(value));
+            } else {
+                malformed.push((token.to_string(), "bad float"));
+            }
+        }
+        (values, malformed)
+    }
+
+    /// Whitespace separated tokens, a double-quoted token keeps its inner spaces
+    /// and loses the quotes. A token directly following the previous one without
+    /// whitespace, as in `"jet pt"=5` or `key="a b"`, is marked as joined.
+    /// A `delimiter` separates tokens like whitespace.
+    fn tokens(line: &str, delimiter: Option<char>) -> Vec<Token> {
+        let mut tokens = vec![];
+        Self::tokens_into(line, 0, delimiter, &mut tokens);
+        tokens
+    }
+
+    /// Like `tokens` appended to `tokens`, which the parser reuses from line to line.
+    /// `line` starts at byte `start` of the line the ranges of the tokens refer to.
+    fn tokens_into(line: &str, start: usize, delimiter: Option<char>, tokens: &mut Vec<Token>) {
+        let separator = |c: char| c.is_whitespace() || Some(c) == delimiter;
+        let mut rest = line;
+        loop {
+            let trimmed = rest.trim_start_matches(separator);
+            let joined = !tokens.is_empty() && trimmed.len() == rest.len();
+            if trimmed.is_empty() {
+                return;
+            }
+            let pos = start + line.len() - trimmed.len();
+            let (token, next) = match trimmed.strip_prefix('"') {
+                // an unterminated quote takes the rest of the line
+                Some(quoted) => match quoted.split_once('"') {
+                    Some((text, next)) => (Token { text: pos + 1..pos + 1 + text.len(), quoted: true, joined }, next),
+                    None => (Token { text: pos + 1..pos + trimmed.len(), quoted: true, joined }, ""),
+                },
+                None => {
+                    let end = trimmed.find(|c: char| separator(c) || c == '"').unwrap_or(trimmed.len());
+                    (Token { text: pos..pos + end, quoted: false, joined }, &trimmed[end..])
+                }
+            };
+            tokens.push(token);
+            rest = next;
+        }
+    }
+
+    /// Key-value pairs of the tokens, either `key=value` in one token (split at the
+    /// first `=` of an unquoted token) or `key value` in two, a trailing key has no value
+    fn pairs<'a>(line: &'a str, tokens: &[Token]) -> Vec<(&'a str, Option<&'a str>)> {
+        let mut pairs = vec![];
+        let mut tokens = tokens.iter().peekable();
+        while let Some(token) = tokens.next() {
+            // the `=value` of `"key"=value`
+            let equals = tokens.next_if(|next| token.quoted && next.joined && !next.quoted && next.text(line).starts_with('='));
+            let split = match equals {
+                Some(equals) => Some((token.text(line), &equals.text(line)[1..])),
+                None if !token.quoted => token.text(line).split_once('='),
+                None => None,
+            };
+            match split {
+                Some((key, value)) => {
+                    // `key="quoted value"`
+                    let quoted = tokens.next_if(|next| value.is_empty() && next.joined && next.quoted);
+                    pairs.push((key, Some(quoted.map_or(value, |quoted| quoted.text(line)))));
+                }
+                None => pairs.push((token.text(line), tokens.next().map(|value| value.text(line)))),
+            }
+        }
+        pairs
+    }
+
+    /// Value of a key-value pair, or why the pair is malformed
+    fn parse_pair(key: &str, value: Option<&str>, comma: bool) -> Result<StrapValue, &'static str> {
+        let Some(value) = value else {
+            return Err(NO_VALUE);
+        };
+        if key.is_empty() {
+            return Err("empty key");
+        }
+        if value.is_empty() {
+            return Err("no value for key");
+        }
+        // `inf` and `nan` parse as well but are fine names
+        if key.parse::<f64>().is_ok() && key.trim_start_matches(['+', '-']).starts_with(|c: char| c.is_ascii_digit() || c == '.') {
+            return Err("number as key");
+        }
+        if let Ok(value) = value.parse::<f64>() {
+            return Ok(StrapValue::Float(value));
+        }
+        if comma && let Some(number) = Self::comma_number(value) {
+            return number.map(StrapValue::Float);
+        }
+        if !comma
+            && value.contains(',')
+            && let Ok(values) = value.split(',').map(str::parse).collect::<Result<Vec<f64>, _>>()
+        {
+            return Ok(StrapValue::List(values));
+        }
+        // anything else is text, also if it starts like a number, e.g. `2024-01-01` or `17A`
+        Ok(StrapValue::Text(value.to_string()))
+    }
+
+    /// Number with a decimal comma like `21,5`, an error for one with more separators
+    /// like `1,234,5` or `1.234,5`, None for values without comma or no number
+    fn comma_number(value: &str) -> Option<Result<f64, &'static str>> {
+        let digits = value.trim_start_matches(['+', '-']);
+        if !digits.contains(',') || !digits.starts_with(|c: char| c.is_ascii_digit() || c == ',') {
+            return None;
+        }
+        if digits.matches(',').count() == 1 && !digits.contains('.') {
+            return value.replacen(',', ".", 1).parse().ok().map(Ok);
+        }
+        digits.chars().all(|c| c.is_ascii_digit() || c == ',' || c == '.').then_some(Err(AMBIGUOUS_COMMA))
+    }
+
+    /// Number with one of `suffixes`, scaled to its base unit, and that unit
+    fn parse_suffixed<'a>(value: &str, suffixes: &'a [UnitSuffix]) -> Option<(f64, &'a str)> {
+        suffixes.iter()
+            .filter(|suffix| value.ends_with(&suffix.suffix))
+            .max_by_key(|suffix| suffix.suffix.len())
+            .and_then(|suffix| {
+                let number: f64 = value[..value.len() - suffix.suffix.len()].parse().ok()?;
+                Some((number * suffix.factor, suffix.unit.as_str()))
+            })
+    }
+
+    /// Key-value pairs of a row and the malformed ones, the offending token and why.
+    /// Fails on a duplicate key only with `DuplicateKeyPolicy::Error`. Text values
+    /// that are numbers with one of `suffixes` are scaled, the base unit of each such
+    /// key is returned as well.
+    /// Numbers may have a decimal comma if `comma`, otherwise comma separated numbers
+    /// are lists.
+    #[allow(clippy::type_complexity)]
+    fn parse_pairs(
+        line: &str,
+        tokens: &[Token],
+        duplicates: DuplicateKeyPolicy,
+        suffixes: &[UnitSuffix],
+        comma: bool,
+    ) -> Result<(HashMap<String, StrapValue>, Vec<(String, &'static str)>, Vec<(String, String)>), String> {
+        let mut values: HashMap<String, StrapValue> = HashMap::new();
+        let mut malformed = vec![];
+        let mut units = vec![];
+        for (key, value) in Self::pairs(line, tokens) {
+            let parsed = match Self::parse_pair(key, value, comma) {
+                Ok(StrapValue::Text(text)) => match Self::parse_suffixed(&text, suffixes) {
+                    Some((number, unit)) => {
+                        units.push((key.to_string(), unit.to_string()));
+                        Ok(StrapValue::Float(number))
+                    }
+                    None => Ok(StrapValue::Text(text)),
+                },
+                parsed => parsed,
+            };
+            match parsed {
+                Ok(parsed) => {
+                    let Some(previous) = values.get_mut(key) else {
+                        values.insert(key.to_string(), parsed);
+                        continue;
+                    };
+                    let merged = match (duplicates, &*previous, parsed) {
+                        (DuplicateKeyPolicy::Error, _, _) => return Err(format!("duplicate key {}", key)),
+                        (DuplicateKeyPolicy::FirstWins, _, _) => continue,
+                        (DuplicateKeyPolicy::Max, StrapValue::Float(a), StrapValue::Float(b)) => StrapValue::Float(a.max(b)),
+                        (DuplicateKeyPolicy::Sum, StrapValue::Float(a), StrapValue::Float(b)) => StrapValue::Float(a + b),
+                        (_, _, parsed) => parsed,
+                    };
+                    *previous = merged;
+                }
+                Err(reason) => {
+                    let token = match (reason, value) {
+                        (AMBIGUOUS_COMMA, Some(value)) => value,
+                        _ => key,
+                    };
+                    malformed.push((token.to_string(), reason));
+                }
+            }
+        }
+        Ok((values, malformed, units))
+    }
+
+    /// Malformed pairs of a STRAP line, the offending token and why
+    #[cfg(test)]
+    fn malformed_pairs(line: &str, all: bool, prefixes: &[impl AsRef<str>]) -> Vec<(String, &'static str)> {
+        Self::row_tokens(line, all, prefixes, None)
+            .and_then(|tokens| Self::parse_pairs(line, &tokens, DuplicateKeyPolicy::LastWins, &[], false).ok())
+            .map_or(vec![], |(_, malformed, _)| malformed)
+    }
+
+    /// Parse a single STRAP line into key-value pairs, malformed pairs are dropped
+    /// and the last value of a duplicate key wins
+    fn parse_values(line: &str, all : bool, prefixes: &[impl AsRef<str>]) -> HashMap<String, StrapValue> {
+        Self::row_tokens(line, all, prefixes, None)
+            .and_then(|tokens| Self::parse_pairs(line, &tokens, DuplicateKeyPolicy::LastWins, &[], false).ok())
+            .map_or(HashMap::new(), |(values, _, _)| values)
+    }
+
+    /// Number of malformed pairs lenient parsing drops
+    pub fn dropped_pairs(&self) -> Result<usize, StrapError> {
+        let mut iter = self.iter()?;
+        iter.mode = ParseMode::Lenient;
+        while let Some(row) = iter.next_values() {
+            row?;
+        }
+        Ok(iter.dropped)
+    }
+
+    /// Number of rows `with_dedupe` drops as duplicates of an earlier row
+    pub fn duplicate_rows(&self) -> Result<usize, StrapError> {
+        let mut iter = self.iter()?;
+        while let Some(row) = iter.next_values() {
+            row?;
+        }
+        Ok(iter.deduplicated)
+    }
+
+    /// Number of rows the filter of `with_filter` skips
+    pub fn filtered_rows(&self) -> Result<usize, StrapError> {
+        let mut iter = self.iter()?;
+        while let Some(row) = iter.next_values() {
+            row?;
+        }
+        Ok(iter.filtered)
+    }
+
+    /// Number of NaN and inf values per column, whatever the policy does with them
+    pub fn non_finite_counts(&self) -> Result<BTreeMap<String, usize>, StrapError> {
+        let mut iter = self.iter()?;
+        iter.mode = ParseMode::Lenient;
+        iter.special = SpecialValuePolicy::Keep;
+        while let Some(row) = iter.next_values() {
+            row?;
+        }
+        Ok(iter.non_finite)
+    }
+
+    /// Columns and types declared by a `@strap2` header before the first row, if any
+    pub fn header(&self) -> Result<Option<Vec<(String, DataType)>>, StrapError> {
+        let mut iter = self.iter()?;
+        while iter.header.is_none() && iter.rows == 0 {
+            match iter.next_values() {
+                Some(row) => row?,
+                None => break,
+            };
+        }
+        Ok(iter.header)
+    }
+
+    /// Comment lines anywhere in the input, e.g. `# detector: ALPHA`, which are no rows
+    pub fn metadata(&self) -> Result<Metadata, StrapError> {
+        let mut iter = self.iter()?;
+        let mut metadata = Metadata::default();
+        while let Some(line) = iter.read_line() {
+            let line = line?;
+            let Some(comment) = iter.comment(&line) else {
+                continue;
+            };
+            if let Some((key, value)) = comment.split_once(':')
+                && !key.trim().is_empty()
+            {
+                metadata.entries.insert(key.trim().to_string(), value.trim().to_string());
+            }
+            metadata.comments.push((iter.line, comment.to_string()));
+        }
+        Ok(metadata)
+    }
+
+    /// Number of lines skipped for lacking a prefix, blank lines and unit
+    /// declarations aside, 0 if every line is read
+    pub fn skipped_lines(&self) -> Result<usize, StrapError> {
+        let mut iter = self.iter()?;
+        iter.mode = ParseMode::Lenient;
+        let mut skipped = 0;
+        while let Some(line) = iter.read_line() {
+            let line = line?;
+            if !line.trim().is_empty()
+                && !line.contains(UNITS_PREFIX)
+                && Self::row_tokens(&line, iter.all, &iter.prefixes, iter.delimiter).is_none()
+            {
+                skipped += 1;
+            }
+        }
+        Ok(skipped)
+    }
+
+    /// Lines and pairs lenient loading loses, with at most `examples` skipped lines
+    /// kept. Blank, comment, unit and header lines, lines without prefix and rows
+    /// skipped on purpose by `with_filter`, `with_skip_rows` or `with_dedupe` are
+    /// not counted.
+    pub fn load_report(&self, examples: usize) -> Result<LoadReport, StrapError> {
+        let mut iter = self.iter()?;
+        iter.mode = ParseMode::Lenient;
+        let mut report = LoadReport::default();
+        while let Some(line) = iter.read_line() {
+            let line = line?;
+            if iter.comment(&line).is_some()
+                || line.contains(UNITS_PREFIX)
+                || matches!(Self::parse_header(&line, iter.delimiter), Ok(Some(_)))
+                || Self::row_tokens(&line, iter.all, &iter.prefixes, iter.delimiter).is_none_or(|tokens| tokens.is_empty())
+            {
+                // still parsed for a header the rows after it depend on
+                iter.parse(&line).ok();
+                continue;
+            }
+            let skipped = match iter.parse(&line) {
+                // None is a row dropped on purpose
+                Ok(values) => values.is_some_and(|values| values.is_empty()),
+                Err(StrapError::Parse { .. }) => true,
+                Err(e) => return Err(e),
+            };
+            if skipped {
+                report.skipped += 1;
+                if report.examples.len() < examples {
+                    report.examples.push((iter.line, line.trim().to_string()));
+                }
+            }
+            if iter.max_rows.is_some_and(|max| iter.rows >= max) {
+                break;
+            }
+        }
+        report.dropped_pairs = iter.dropped;
+        Ok(report)
+    }
+
+    /// Check every line without keeping rows and without stopping at a bad line,
+    /// with at most `examples` problems described. Fails only if reading fails.
+    pub fn validate(&self, examples: usize) -> Result<ValidationReport, StrapError> {
+        let mut iter = self.iter()?;
+        iter.mode = ParseMode::Lenient;
+        let mut report = ValidationReport::default();
+        while let Some(line) = iter.read_line() {
+            let line = line?;
+            report.lines += 1;
+            let mut problems = vec![];
+            match iter.parse(&line) {
+                Ok(values) => report.columns.extend(values.into_iter().flat_map(HashMap::into_keys)),
+                Err(StrapError::Parse { reason, .. }) => {
+                    report.errors += 1;
+                    problems.push(reason);
+                }
+                Err(e) => return Err(e),
+            }
+            if line.trim().is_empty() || line.contains(UNITS_PREFIX) || matches!(Self::parse_header(&line, iter.delimiter), Ok(Some(_))) {
+                continue;
+            }
+            let Some(tokens) = Self::row_tokens(&line, iter.all, &iter.prefixes, iter.delimiter) else {
+                report.unprefixed += 1;
+                continue;
+            };
+            if problems.is_empty() {
+                let malformed = match &iter.header {
+                    Some(header) => Self::parse_positional(&line, &tokens, header).1,
+                    None => Self::parse_pairs(&line, &tokens, iter.duplicates, &iter.suffixes, iter.decimal == DecimalSeparator::Comma).map_or(vec![], |(_, malformed, _)| malformed),
+                };
+                report.odd_token_lines += malformed.iter().any(|(_, reason)| *reason == NO_VALUE) as usize;
+                report.malformed_pairs += malformed.iter().filter(|(_, reason)| *reason != NO_VALUE).count();
+                problems.extend(malformed.into_iter().map(|(token, reason)| format!("{} `{}`", reason, token)));
+            }
+            let room = examples.saturating_sub(report.examples.len());
+            report.examples.extend(problems.into_iter().take(room).map(|problem| (iter.line, problem)));
+        }
+        report.rows = iter.rows;
+        Ok(report)
+    }
+    
+    /// Whether every line is a row, otherwise only lines containing @strap are
+    fn is_strap_file(path: &std::path::Path) -> bool {
+        // check if file name contains .strap or .strap.gz etc
+        let path_str = path.to_string_lossy().to_lowercase();
+        path_str.ends_with(".strap") 
+            || path_str.ends_with(".strap.gz") 
+            || path_str.ends_with(".strap.gzip")
+            || path_str.ends_with(".strap.zst")
+            || path_str.ends_with(".strap.zstd")
+            || path_str.ends_with(".strap.zip")
+            || path_str.ends_with(".strap.bz2")
+            || path_str.ends_with(".strap.bzip2")
+    }
+
+    /// Units of a `@strap-units pt GeV eta 1` header line, None for any other line
+    fn parse_units(line: &str) -> Option<HashMap<String, String>> {
+        let pos = line.find(UNITS_PREFIX)?;
+        let tokens: Vec<&str> = line[pos + UNITS_PREFIX.len()..].split_whitespace().collect();
+        Some(tokens.chunks(2)
+            .filter(|chunk| chunk.len() == 2)
+            .map(|chunk| (chunk[0].to_string(), chunk[1].to_string()))
+            .collect())
+    }
+
+    /// Column units declared by `@strap-units` lines before the first row
+    pub fn units(&self) -> Result<HashMap<String, String>, StrapError> {
+        let mut units = HashMap::new();
+        let mut iter = self.iter()?;
+        while let Some(line) = iter.read_line() {
+            let line = line?;
+            if let Some(declared) = Self::parse_units(&line) {
+                units.extend(declared);
+            } else if iter.parse(&line)?.is_none_or(|values| !values.is_empty()) {
+                break;
+            }
+        }
+        Ok(units)
+    }
+
+    /// Adds the declared unit of each column to its field metadata
+    fn with_units(schema: Arc<Schema>, units: &HashMap<String, String>) -> Arc<Schema> {
+        if units.is_empty() {
+            return schema;
+        }
+        let fields: Vec<Field> = schema.fields().iter()
+            .map(|field| match units.get(field.name()) {
+                Some(unit) => field.as_ref().clone()
+                    .with_metadata(HashMap::from([(UNIT_METADATA_KEY.to_string(), unit.clone())])),
+                None => field.as_ref().clone(),
+            })
+            .collect();
+        Arc::new(Schema::new(fields))
+    }
+
+    /// Returns an iterator over all rows
+    pub fn iter(&self) -> Result<StrapTrackIterator, StrapError> {
+        let all = match (&self.source, self.only_prefixed) {
+            (_, Some(only_prefixed)) => !only_prefixed,
+            #[cfg(feature = "std-fs")]
+            (Source::Path(path), None) => Self::is_strap_file(path),
+            (Source::Bytes { name: None, .. }, None) => true,
+            (Source::Bytes { name: Some(name), .. }, None) => Self::is_strap_file(std::path::Path::new(name)),
+            (Source::Rows(_), None) => true,
+        };
+        if let Some(name) = self.derived_names().duplicates().next() {
+            return Err(invalid_input(format!("two derived columns named {}", name)));
+        }
+        let offset = Arc::new(AtomicU64::new(0));
+        let lines = match (&self.source, self.map_file()?) {
+            (Source::Rows(rows), _) => Lines::Rows { rows: rows.clone(), pos: 0 },
+            #[cfg(feature = "std-fs")]
+            (_, Some(map)) => Lines::Mapped { map: Arc::new(map), pos: 0 },
+            _ => Lines::Buffered(self.create_reader(offset.clone())?),
+        };
+        Ok(StrapTrackIterator {
+            all,
+            lines,
+            compression: self.compression,
+            offset,
+            mode: self.mode,
+            line: 0,
+            prefixes: self.prefixes.clone(),
+            header: None,
+            rows: 0,
+            dropped: 0,
+            special: self.special,
+            non_finite: BTreeMap::new(),
+            progress: self.progress.clone(),
+            total: self.total_bytes(),
+            columns: self.columns.clone(),
+            filter: self.filter.clone(),
+            filtered: 0,
+            renames: self.renames.clone(),
+            duplicates: self.duplicates,
+            suffixes: self.suffixes.clone(),
+            suffix_units: BTreeMap::new(),
+            delimiter: self.delimiter,
+            skip_rows: self.skip_rows,
+            skipped_rows: 0,
+            max_rows: self.max_rows,
+            comment_prefix: self.comment_prefix.clone(),
+            decimal: self.decimal,
+            numbers: 0,
+            scratch: String::new(),
+            tokens: vec![],
+            dedupe: self.dedupe,
+            derived: self.derived.clone(),
+            seen: HashSet::new(),
+            deduplicated: 0,
+            lost: 0,
+        })
+    }
+
+    /// Memory map of the input for `ReadBackend::Mmap`, None where it is read buffered
+    #[cfg(feature = "std-fs")]
+    fn map_file(&self) -> Result<Option<Mmap>, StrapError> {
+        let Source::Path(path) = &self.source else {
+            return Ok(None);
+        };
+        if self.backend != ReadBackend::Mmap || self.compression != InputCompression::None {
+            return Ok(None);
+        }
+        let file = File::open(path)?;
+        if !file.metadata()?.is_file() {
+            return Ok(None);
+        }
+        // SAFETY: the map is only read, a file truncated meanwhile is documented on ReadBackend::Mmap
+        Ok(Some(unsafe { Mmap::map(&file)? }))
+    }
+
+    #[cfg(not(feature = "std-fs"))]
+    fn map_file(&self) -> Result<Option<std::convert::Infallible>, StrapError> {
+        Ok(None)
+    }
+
+    /// Size of the raw input, None for anything but a regular file or bytes in memory
+    fn total_bytes(&self) -> Option<u64> {
+        match &self.source {
+            #[cfg(feature = "std-fs")]
+            Source::Path(path) => std::fs::metadata(path).ok().filter(|m| m.is_file()).map(|m| m.len()),
+            Source::Bytes { data, .. } => Some(data.len() as u64),
+            Source::Rows(_) => None,
+        }
+    }
+
+    /// Returns an iterator over all rows keeping the text values
+    pub fn iter_values(&self) -> Result<StrapValueIterator, StrapError> {
+        Ok(StrapValueIterator(self.iter()?))
+    }
+    
+    /// Stream through all rows with a callback
+    pub fn for_each_row<F>(&self, mut callback: F) -> Result<(), StrapError>
+    where
+        F: FnMut(&HashMap<String, f64>) -> bool, // return false to stop
+    {
+        for parsed in self {
+            if !callback(&parsed?) {
+                break;
+            }
+        }
+        Ok(())
+    }
+    
+    /// Filter rows based on a predicate
+    pub fn filter_rows<F>(&self, predicate: F) -> Result<Vec<HashMap<String, f64>>, StrapError>
+    where
+        F: Fn(&HashMap<String, f64>) -> bool,
+    {
+        let mut results = Vec::new();
+        self.for_each_row(| row| {
+            if predicate(row) {
+                results.push(row.clone());
+            }
+            true // continue
+        })?;
+        Ok(results)
+    }
+    
+    /// The first `n` rows with values
+    pub fn head(&self, n: usize) -> Result<Vec<HashMap<String, f64>>, StrapError> {
+        Ok(self.iter()?.filter_ok(|row| !row.is_empty()).take(n).collect::<Result<_, _>>()?)
+    }
+
+    /// The last `n` rows with values
+    pub fn tail(&self, n: usize) -> Result<Vec<HashMap<String, f64>>, StrapError> {
+        let mut rows = std::collections::VecDeque::new();
+        for row in self.iter()?.filter_ok(|row| !row.is_empty()) {
+            rows.push_back(row?);
+            if rows.len() > n {
+                rows.pop_front();
+            }
+        }
+        Ok(rows.into())
+    }
+
+    /// `n` rows with values drawn uniformly in a single pass, in file order,
+    /// the same `seed` draws the same rows
+    pub fn sample(&self, n: usize, seed: u64) -> Result<Vec<HashMap<String, f64>>, StrapError> {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let mut sample = Vec::new();
+        for (i, row) in self.iter()?.filter_ok(|row| !row.is_empty()).enumerate() {
+            let row = row?;
+            if i < n {
+                sample.push((i, row));
+            } else {
+                let j = rng.gen_range(0..=i);
+                if j < n {
+                    sample[j] = (i, row);
+                }
+            }
+        }
+        sample.sort_by_key(|(i, _)| *i);
+        Ok(sample.into_iter().map(|(_, row)| row).collect())
+    }
+
+    /// Aggregate a column with a reduction function
+    pub fn aggregate<F, T>(&self, init: T, reducer: F) -> Result<T, StrapError>
+    where
+        F: Fn(T, &HashMap<String, f64>) -> T,
+    {
+        let mut acc = init;
+
+        for hm in self.iter()? {
+            acc = reducer(acc, &hm?);
+        }
+
+        Ok(acc)
+    }
+
+    /// Rows with a numeric value of `column`, NaN included. Like the other `column_*`
+    /// helpers it streams the input on every call, there is no column cache to reuse.
+    pub fn column_count(&self, column: &str) -> Result<usize, StrapError> {
+        self.fold_column(column, 0, |count, _| count + 1)
+    }
+
+    /// Sum of the values of `column`, None if no row has one. A NaN value makes the sum NaN.
+    pub fn column_sum(&self, column: &str) -> Result<Option<f64>, StrapError> {
+        let (count, sum) = self.fold_column(column, (0, 0.0), |(count, sum), value| (count + 1, sum + value))?;
+        Ok((count > 0).then_some(sum))
+    }
+
+    /// Mean of the values of `column`, None if no row has one. A NaN value makes the mean NaN.
+    pub fn column_mean(&self, column: &str) -> Result<Option<f64>, StrapError> {
+        let (count, sum) = self.fold_column(column, (0, 0.0), |(count, sum), value| (count + 1, sum + value))?;
+        Ok((count > 0).then(|| sum / count as f64))
+    }
+
+    /// Smallest and largest value of `column`. NaN values are skipped, None if there
+    /// are no others.
+    pub fn column_minmax(&self, column: &str) -> Result<Option<(f64, f64)>, StrapError> {
+        self.fold_column(column, None, |minmax, value| match minmax {
+            _ if value.is_nan() => minmax,
+            None => Some((value, value)),
+            Some((min, max)) => Some((value.min(min), value.max(max))),
+        })
+    }
+
+    /// `f` over the values of `column` in one pass, rows without it are skipped
+    fn fold_column<T>(&self, column: &str, init: T, mut f: impl FnMut(T, f64) -> T) -> Result<T, StrapError> {
+        let mut acc = init;
+        for row in self.iter()? {
+            if let Some(&value) = row?.get(column) {
+                acc = f(acc, value);
+            }
+        }
+        Ok(acc)
+    }
+
+    /// Statistics of every column and the number of non-empty rows in a single pass
+    pub fn describe(&self) -> Result<(BTreeMap<String, ColumnStats>, usize), StrapError> {
+        let mut stats: BTreeMap<String, ColumnStats> = BTreeMap::new();
+        let mut rows = 0;
+        for row in self.iter()? {
+            let row = row?;
+            if row.is_empty() {
+                continue;
+            }
+            rows += 1;
+            for (key, value) in row {
+                stats.entry(key).or_default().push(value);
+            }
+        }
+        for stats in stats.values_mut() {
+            stats.null_count = rows - stats.count;
+        }
+        Ok((stats, rows))
+    }
+
+    /// Rows with a value of each column and the number of rows, in one pass and sorted
+    /// by column. Columns selected with `with_columns` but never present have 0.
+    pub fn sparsity_report(&self) -> Result<Vec<(String, usize, usize)>, StrapError> {
+        let mut present: BTreeMap<String, usize> = self.columns.iter().flat_map(|columns| columns.iter())
+            .map(|name| (name.clone(), 0))
+            .collect();
+        let mut rows = 0;
+        for row in self.iter_values()?.filter_ok(|row| !row.is_empty()) {
+            rows += 1;
+            for key in row?.into_keys() {
+                *present.entry(key).or_default() += 1;
+            }
+        }
+        Ok(present.into_iter().map(|(name, count)| (name, count, rows)).collect())
+    }
+
+    /// Columns that look like epoch seconds for `with_timestamp_columns`: named `ts`,
+    /// `time`, `timestamp` or `epoch`, also as suffix like `start_time`, with every
+    /// value a number between 1e8 and 1e10 (1973 to 2286)
+    pub fn detect_timestamp_columns(&self) -> Result<Vec<String>, StrapError> {
+        let is_time_name = |name: &str| {
+            let name = name.to_lowercase();
+            ["ts", "time", "timestamp", "epoch"].iter()
+                .any(|word| name == *word || name.ends_with(&format!("_{}", word)))
+        };
+        let mut candidates: BTreeMap<String, bool> = BTreeMap::new();
+        for row in self.iter_values()? {
+            for (key, value) in row? {
+                if is_time_name(&key) {
+                    let epoch = value.as_f64().is_some_and(|value| (1e8..1e10).contains(&value));
+                    *candidates.entry(key).or_insert(true) &= epoch;
+                }
+            }
+        }
+        Ok(candidates.into_iter().filter(|(_, epoch)| *epoch).map(|(name, _)| name).collect())
+    }
+
+    /// Statistics of `column`, None if it has no numeric values, see `describe`.
+    /// Only this column is folded in a pass over the input, nothing is cached.
+    pub fn column_stats(&self, column: &str) -> Result<Option<ColumnStats>, StrapError> {
+        let mut stats: Option<ColumnStats> = None;
+        let mut rows = 0;
+        for row in self.iter()?.filter_ok(|row| !row.is_empty()) {
+            rows += 1;
+            if let Some(&value) = row?.get(column) {
+                stats.get_or_insert_default().push(value);
+            }
+        }
+        Ok(stats.map(|stats| ColumnStats { null_count: rows - stats.count, ..stats }))
+    }
+
+    /// Statistics of every column in a single pass, see `describe`
+    pub fn all_column_stats(&self) -> Result<BTreeMap<String, ColumnStats>, StrapError> {
+        Ok(self.describe()?.0)
+    }
+
+    /// Equal-width histogram of `column`, over its full range if `range` is None
+    pub fn histogram(&self, column: &str, bins: usize, range: Option<(f64, f64)>) -> Result<Histogram, StrapError> {
+        let (min, max) = match range {
+            Some(range) => range,
+            None => {
+                let stats = self.column_stats(column)?.unwrap_or_default();
+                (stats.min, stats.max)
+            }
+        };
+        let mut histogram = Histogram {
+            edges: (0..=bins).map(|i| min + (max - min) * i as f64 / bins as f64).collect(),
+            counts: vec![0; bins],
+        };
+        if bins == 0 || min.is_nan() || max.is_nan() || min > max {
+            return Ok(histogram);
+        }
+        for row in self.iter()? {
+            if let Some(&value) = row?.get(column)
+                && (min..=max).contains(&value)
+            {
+                // the upper edge belongs to the last bin
+                let bin = if max > min { ((value - min) / (max - min) * bins as f64) as usize } else { 0 };
+                histogram.counts[bin.min(bins - 1)] += 1;
+            }
+        }
+        Ok(histogram)
+    }
+
+    /// Rows binned by `x` into `bins` equal-width bins over `range`, or over the range of
+    /// `x` if None, with `agg` of `y` per bin: (bin center, bin width, value, rows). Bins
+    /// match the GUI histogram, the maximum belongs to the last bin and an empty bin has
+    /// value 0. Rows outside `range` are left out, rows without `x` or `y` are skipped
+    /// and counted in the second value.
+    pub fn group_by_binned(
+        &self,
+        x: &str,
+        bins: usize,
+        range: Option<(f64, f64)>,
+        y: &str,
+        agg: Aggregation,
+    ) -> Result<(Vec<BinnedValue>, usize), StrapError> {
+        let (min, max) = match range {
+            Some(range) => range,
+            None => {
+                let mut range = (f64::INFINITY, f64::NEG_INFINITY);
+                for row in self.iter()? {
+                    let row = row?;
+                    if let (Some(&x), true) = (row.get(x), row.contains_key(y)) {
+                        range = (range.0.min(x), range.1.max(x));
+                    }
+                }
+                range
+            }
+        };
+        let valid = bins > 0 && min <= max;
+        let width = (max - min) / bins as f64;
+        let mut sums = vec![(0.0, 0); bins];
+        let mut skipped = 0;
+        for row in self.iter()? {
+            let row = row?;
+            if row.is_empty() {
+                continue;
+            }
+            let (Some(&x), Some(&y)) = (row.get(x), row.get(y)) else {
+                skipped += 1;
+                continue;
+            };
+            if !valid || !(min..=max).contains(&x) {
+                continue;
+            }
+            // the bucket of the GUI's LEAST(bins - 1, FLOOR((x - min) / width))
+            let bin = if width > 0.0 { ((x - min) / width).floor() as usize } else { 0 };
+            let (sum, count) = &mut sums[bin.min(bins - 1)];
+            *sum += y;
+            *count += 1;
+        }
+        if !valid {
+            return Ok((vec![], skipped));
+        }
+        let binned = sums.into_iter().enumerate()
+            .map(|(i, (sum, count))| {
+                let value = match agg {
+                    Aggregation::Count => count as f64,
+                    Aggregation::Sum => sum,
+                    Aggregation::Mean if count > 0 => sum / count as f64,
+                    Aggregation::Mean => 0.0,
+                };
+                (min + (i as f64 + 0.5) * width, width, value, count)
+            })
+            .collect();
+        Ok((binned, skipped))
+    }
+
+    /// Convert STRAP data to Parquet format, writing a row group per `chunk_size` rows
+    #[cfg(feature = "std-fs")]
+    pub fn to_parquet(
+        &self, 
+        filename: &str, 
+        chunk_size: usize,
+    ) -> Result<(), StrapError> {
+        self.to_parquet_with(filename, &ParquetOptions { chunk_size, ..Default::default() }).map(|_| ())
+    }
+
+    /// Convert `input` to Parquet reading it once, `chunk_size` rows at a time.
+    /// Lines without values are skipped. The columns of a file without `@strap2`
+    /// header are only known at the end, so each chunk is spilled to a temporary
+    /// Arrow IPC file with its own columns and rewritten with all of them.
+    #[cfg(feature = "std-fs")]
+    pub fn stream_to_parquet(
+        input: impl Into<PathBuf>,
+        output: &str,
+        chunk_size: usize,
+    ) -> Result<ConversionStats, StrapError> {
+        let track = Self::new(input)?;
+        let options = ParquetOptions { chunk_size, ..Default::default() };
+        if chunk_size == 0 {
+            return Err(invalid_input("chunk_size must be at least 1".to_string()));
+        }
+        // the header has the columns, which makes value_columns read no rows
+        if track.header()?.is_some() {
+            return track.to_parquet_with(output, &options);
+        }
+
+        let mut spill = BufWriter::new(tempfile::tempfile()?);
+        let mut columns = ColumnTypes::new(track.booleans);
+        let mut stats = ConversionStats::default();
+        let mut chunks = 0;
+        let mut chunk = SpillChunk::default();
+        let mut rows = track.iter_values()?;
+        loop {
+            let row = rows.next().transpose()?;
+            let done = row.is_none();
+            if let Some(row) = row {
+                if row.is_empty() {
+                    stats.skipped += 1;
+                    continue;
+                }
+                for (key, value) in &row {
+                    columns.add(key, value);
+                }
+                chunk.push(rows.line(), row);
+                if chunk.lines.len() < chunk_size {
+                    continue;
+                }
+            }
+            if !chunk.lines.is_empty() {
+                let batch = std::mem::take(&mut chunk).record_batch()?;
+                let mut writer = arrow::ipc::writer::StreamWriter::try_new(&mut spill, &batch.schema())?;
+                writer.write(&batch)?;
+                writer.finish()?;
+                chunks += 1;
+            }
+            if done {
+                break;
+            }
+        }
+
+        let (column_names, types) = columns.finish(HashMap::new());
+        let schema = Self::with_units(Self::column_schema(&column_names, &types), &track.units()?);
+        let index = Self::column_index(&schema);
+        let mut writer = track.parquet_writer(output, schema.clone(), &options)?;
+        let mut spill = spill.into_inner().map_err(|e| e.into_error())?;
+        std::io::Seek::rewind(&mut spill)?;
+        let mut spill = BufReader::new(spill);
+        for _ in 0..chunks {
+            for batch in arrow::ipc::reader::StreamReader::try_new(&mut spill, None)? {
+                let chunk = SpillChunk::from_record_batch(&batch?, &schema, &index)?;
+                let batch = Self::record_batch(&schema, &chunk)?;
+                writer.write(&batch)?;
+                stats.rows += batch.num_rows();
+            }
+        }
+        stats.lost = rows.lost();
+        Self::close_parquet(writer, &stats)?;
+        Ok(stats)
+    }
+
+    /// Convert STRAP data to Parquet format with the given options
+    #[cfg(feature = "std-fs")]
+    pub fn to_parquet_with(
+        &self,
+        filename: &str,
+        options: &ParquetOptions,
+    ) -> Result<ConversionStats, StrapError> {
+        let mut batches = self.record_batches(options)?;
+        let mut writer = self.parquet_writer(filename, batches.schema(), options)?;
+        let write = |batch: &RecordBatch| -> Result<(), StrapError> {
+            writer.write(batch)?;
+            Ok(())
+        };
+        let stats = if options.threads {
+            Self::write_batches_threaded(&mut batches, write)?
+        } else {
+            Self::write_batches(&mut batches, write)?
+        };
+        Self::close_parquet(writer, &stats)?;
+        Ok(stats)
+    }
+
+    /// Write the same schema and record batches as `to_parquet` in the Arrow IPC
+    /// file format (Feather v2), `chunk_size` rows per record batch
+    #[cfg(feature = "std-fs")]
+    pub fn to_arrow_ipc(&self, path: impl AsRef<std::path::Path>, chunk_size: usize) -> Result<ConversionStats, StrapError> {
+        let mut batches = self.to_record_batches(chunk_size)?;
+        let mut writer = arrow::ipc::writer::FileWriter::try_new(File::create(path)?, &batches.schema())?;
+        let stats = Self::write_batches(&mut batches, |batch| {
+            writer.write(batch)?;
+            Ok(())
+        })?;
+        writer.finish()?;
+        Ok(stats)
+    }
+
+    /// Record batches of `chunk_size` rows with the schema of `schema`, the
+    /// conversions write these. Lines without values are no rows.
+    pub fn to_record_batches(&self, chunk_size: usize) -> Result<StrapBatchIterator, StrapError> {
+        self.record_batches(&ParquetOptions { chunk_size, ..Default::default() })
+    }
+
+    /// Record batches of `options.chunk_size` rows, the schema has the row index
+    /// column of `options` first
+    fn record_batches(&self, options: &ParquetOptions) -> Result<StrapBatchIterator, StrapError> {
+        if options.chunk_size == 0 {
+            return Err(invalid_input("chunk_size must be at least 1".to_string()));
+        }
+
+        // 1. Collect all unique column names
+        let (column_names, types) = self.value_columns(&options.types)?;
+
+        // 2. Build schema
+        let data_schema = Self::with_units(Self::column_schema(&column_names, &types), &self.units()?);
+        let schema = match &options.row_index {
+            Some(name) if column_names.contains(name) => {
+                return Err(invalid_input(format!("row index column {} is also a column of the file", name)));
+            }
+            Some(name) => {
+                let fields = std::iter::once(Arc::new(Field::new(name, DataType::UInt64, false)))
+                    .chain(data_schema.fields().iter().cloned())
+                    .collect::<Vec<_>>();
+                Arc::new(Schema::new(fields))
+            }
+            None => data_schema.clone(),
+        };
+        Ok(StrapBatchIterator {
+            rows: self.iter_values()?,
+            builder: BatchBuilder {
+                index: Self::column_index(&data_schema),
+                data_schema,
+                schema,
+                row_index: options.row_index.is_some(),
+                invalid_timestamps: Arc::new(AtomicUsize::new(0)),
+            },
+            chunk_size: options.chunk_size,
+            remaining: options.head.unwrap_or(usize::MAX),
+            skip_empty: options.skip_empty,
+            skipped: 0,
+        })
+    }
+
+    /// Pass every batch to `write`, the stats count the rows written
+    fn write_batches(
+        batches: &mut StrapBatchIterator,
+        mut write: impl FnMut(&RecordBatch) -> Result<(), StrapError>,
+    ) -> Result<ConversionStats, StrapError> {
+        let mut stats = ConversionStats::default();
+        for batch in &mut *batches {
+            let batch = batch?;
+            write(&batch)?;
+            stats.rows += batch.num_rows();
+        }
+        stats.skipped = batches.skipped();
+        stats.invalid_timestamps = batches.invalid_timestamps();
+        stats.duplicates = batches.deduplicated();
+        stats.lost = batches.lost();
+        Ok(stats)
+    }
+
+    /// Like `write_batches`, reading rows on this thread while another builds the
+    /// batches and a third writes them, in the same order
+    fn write_batches_threaded(
+        batches: &mut StrapBatchIterator,
+        mut write: impl FnMut(&RecordBatch) -> Result<(), StrapError> + Send,
+    ) -> Result<ConversionStats, StrapError> {
+        let builder = batches.builder.clone();
+        let (chunk_sender, chunks) = std::sync::mpsc::sync_channel::<RowChunk>(PIPELINE_DEPTH);
+        let (batch_sender, built) = std::sync::mpsc::sync_channel(PIPELINE_DEPTH);
+        std::thread::scope(|scope| {
+            scope.spawn(move || {
+                for chunk in chunks {
+                    if batch_sender.send(builder.build(&chunk)).is_err() {
+                        break;
+                    }
+                }
+            });
+            let writer = scope.spawn(move || {
+                let mut rows = 0;
+                for batch in built {
+                    let batch = batch?;
+                    write(&batch)?;
+                    rows += batch.num_rows();
+                }
+                Ok::<_, StrapError>(rows)
+            });
+            let mut read = Ok(());
+            loop {
+                match batches.next_chunk() {
+                    Ok(Some(chunk)) => {
+                        // a failed send means the writer stopped with an error
+                        if chunk_sender.send(chunk).is_err() {
+                            break;
+                        }
+                    }
+                    Ok(None) => break,
+                    Err(e) => {
+                        read = Err(e);
+                        break;
+                    }
+                }
+            }
+            drop(chunk_sender);
+            let rows = writer.join().unwrap_or_else(|panic| std::panic::resume_unwind(panic))?;
+            read?;
+            Ok(ConversionStats {
+                rows,
+                skipped: batches.skipped(),
+                invalid_timestamps: batches.invalid_timestamps(),
+                duplicates: batches.deduplicated(),
+                lost: batches.lost(),
+            })
+        })
+    }
+
+    /// Parquet writer of `schema` with the writer properties of `options`
+    #[cfg(feature = "std-fs")]
+    fn parquet_writer(&self, filename: &str, schema: SchemaRef, options: &ParquetOptions) -> Result<ArrowWriter<File>, StrapError> {
+        // Setup Parquet writer, with the source fingerprint to skip unchanged reconversions
+        let mut metadata = vec![KeyValue::new(fingerprint::VERSION_KEY.to_string(), env!("CARGO_PKG_VERSION").to_string())];
+        match &self.source {
+            Source::Path(path) => {
+                let fingerprint = if options.full_fingerprint { fingerprint::fingerprint_full(path)? } else { fingerprint::fingerprint(path)? };
+                metadata.push(KeyValue::new(fingerprint::FINGERPRINT_KEY.to_string(), fingerprint));
+                let file = std::fs::metadata(path)?;
+                metadata.push(KeyValue::new(fingerprint::SOURCE_SIZE_KEY.to_string(), file.len().to_string()));
+                if let Some(mtime) = file.modified().ok().and_then(|mtime| mtime.duration_since(std::time::UNIX_EPOCH).ok()) {
+                    metadata.push(KeyValue::new(fingerprint::SOURCE_MTIME_KEY.to_string(), mtime.as_secs().to_string()));
+                }
+            }
+            Source::Bytes { data, .. } => {
+                metadata.push(KeyValue::new(fingerprint::SOURCE_SIZE_KEY.to_string(), data.len().to_string()));
+            }
+            Source::Rows(_) => {}
+        }
+        if let Some(name) = std::path::Path::new(&self.name()).file_name() {
+            metadata.push(KeyValue::new(fingerprint::SOURCE_KEY.to_string(), name.to_string_lossy().to_string()));
+        }
+        metadata.extend(options.metadata.iter().map(|(key, value)| KeyValue::new(key.clone(), value.clone())));
+        let compression = options.compression.try_into()?;
+        let file = File::create(filename)?;
+        let props = WriterProperties::builder()
+            .set_key_value_metadata(Some(metadata))
+            .set_max_row_group_size(options.row_group_size.unwrap_or(options.chunk_size))
+            .set_compression(compression)
+            .set_dictionary_enabled(options.dictionary)
+            .set_statistics_enabled(if options.statistics { EnabledStatistics::Page } else { EnabledStatistics::None });
+        let props = match options.data_page_size {
+            Some(size) => props.set_data_page_size_limit(size),
+            None => props,
+        }.build();
+        Ok(ArrowWriter::try_new(file, schema, Some(props))?)
+    }
+
+    /// Add the row counts of `stats` to the footer and close `writer`
+    #[cfg(feature = "std-fs")]
+    fn close_parquet(mut writer: ArrowWriter<File>, stats: &ConversionStats) -> Result<(), StrapError> {
+        writer.append_key_value_metadata(KeyValue::new(fingerprint::ROWS_KEY.to_string(), stats.rows.to_string()));
+        writer.append_key_value_metadata(KeyValue::new(fingerprint::SKIPPED_KEY.to_string(), stats.skipped.to_string()));
+        writer.close()?;
+        Ok(())
+    }
+
+    /// Write a CSV file with a header of the sorted column names and a line per row,
+    /// missing values are empty cells. Returns the number of rows written.
+    #[cfg(feature = "std-fs")]
+    pub fn to_csv(&self, path: impl AsRef<std::path::Path>, options: &CsvOptions) -> Result<usize, StrapError> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        let rows = self.write_csv(&mut writer, options)?;
+        writer.flush()?;
+        Ok(rows)
+    }
+
+    /// Write a `prefix key value key value ...` line per row with the keys in sorted
+    /// order, e.g. with the `@strap` prefix or without one if `prefix` is empty.
+    /// Numbers are written in their shortest exact form, so reading the output gives
+    /// the same rows. Returns the number of rows written.
+    pub fn to_strap(&self, mut writer: impl Write, prefix: &str) -> Result<usize, StrapError> {
+        let mut rows = 0;
+        for row in self.iter_values()?.filter_ok(|row| !row.is_empty()) {
+            let line = Self::strap_line(&row?);
+            if prefix.is_empty() {
+                writeln!(writer, "{}", line)?;
+            } else {
+                writeln!(writer, "{} {}", prefix, line)?;
+            }
+            rows += 1;
+        }
+        Ok(rows)
+    }
+
+    /// Key-value pairs of `row` as a STRAP line without prefix, keys in sorted order.
+    /// Keys and texts with whitespace or `=` are quoted, double quotes in them dropped.
+    fn strap_line(row: &HashMap<String, StrapValue>) -> String {
+        let token = |text: String| {
+            let text = text.replace('"', "");
+            if text.is_empty() || text.contains(|c: char| c.is_whitespace() || c == '=') {
+                format!("\"{}\"", text)
+            } else {
+                text
+            }
+        };
+        row.iter()
+            .sorted_by(|a, b| a.0.cmp(b.0))
+            .map(|(key, value)| format!("{} {}", token(key.clone()), token(value.to_string())))
+            .join(" ")
+    }
+
+    /// Like `to_csv` to any writer, row by row
+    pub fn write_csv(&self, mut writer: impl Write, options: &CsvOptions) -> Result<usize, StrapError> {
+        let delimiter = options.delimiter.to_string();
+        let (column_names, _) = self.value_columns(&HashMap::new())?;
+        writeln!(writer, "{}", column_names.iter().map(|name| Self::csv_field(name, options.delimiter)).join(&delimiter))?;
+        let mut rows = 0;
+        for row in self.iter_values()?.filter_ok(|row| !row.is_empty()) {
+            let row = row?;
+            let line = column_names.iter()
+                .map(|name| match (row.get(name), options.precision) {
+                    (None, _) => String::new(),
+                    (Some(StrapValue::Float(value)), Some(precision)) => format!("{:.*}", precision, value),
+                    (Some(value), _) => Self::csv_field(&value.to_string(), options.delimiter),
+                })
+                .join(&delimiter);
+            writeln!(writer, "{}", line)?;
+            rows += 1;
+        }
+        Ok(rows)
+    }
+
+    /// Write a JSON object per row and line, with only the keys of the row or with
+    /// every column and null for missing values if `dense`. Numbers round-trip
+    /// exactly, NaN and inf become null as JSON has no such numbers. Returns the
+    /// number of rows written.
+    pub fn to_ndjson(&self, mut writer: impl Write, dense: bool) -> Result<usize, StrapError> {
+        let column_names = if dense { self.value_columns(&HashMap::new())?.0 } else { vec![] };
+        let mut rows = 0;
+        for row in self.iter_values()?.filter_ok(|row| !row.is_empty()) {
+            let mut row = row?;
+            let mut object = serde_json::Map::new();
+            for name in &column_names {
+                object.insert(name.clone(), serde_json::Value::Null);
+            }
+            for (key, value) in row.drain() {
+                let value = match value {
+                    StrapValue::Float(value) => serde_json::Value::from(value),
+                    StrapValue::Text(text) => serde_json::Value::String(text),
+                    StrapValue::List(values) => values.into_iter().map(serde_json::Value::from).collect(),
+                };
+                object.insert(key, value);
+            }
+            serde_json::to_writer(&mut writer, &object).map_err(std::io::Error::from)?;
+            writeln!(writer)?;
+            rows += 1;
+        }
+        Ok(rows)
+    }
+
+    /// `field` quoted with inner quotes doubled if it contains the delimiter, a quote or a line break
+    fn csv_field(field: &str, delimiter: char) -> String {
+        if field.contains([delimiter, '"', '\n', '\r']) {
+            format!("\"{}\"", field.replace('"', "\"\""))
+        } else {
+            field.to_string()
+        }
+    }
+
+    /// Arrow schema `to_parquet` writes, one nullable Float64 or (for text) Utf8 field
+    /// per column in sorted order with the declared unit in the field metadata
+    pub fn schema(&self) -> Result<SchemaRef, StrapError> {
+        let (column_names, types) = self.value_columns(&HashMap::new())?;
+        Ok(Self::with_units(Self::column_schema(&column_names, &types), &self.units()?))
+    }
+
+    /// Nullable field per column, Float64 unless overridden in `types`
+    fn column_schema(column_names: &[String], types: &HashMap<String, DataType>) -> Arc<Schema> {
+        let fields: Vec<Field> = column_names.iter()
+            .map(|name| {
+                let data_type = types.get(name).cloned().unwrap_or(DataType::Float64);
+                Field::new(name, data_type, true) // nullable = true
+            })
+            .collect();
+        Arc::new(Schema::new(fields))
+    }
+
+    /// Column of each field of `schema`, see `RowChunk::push`
+    fn column_index(schema: &Schema) -> HashMap<String, usize> {
+        schema.fields().iter().enumerate().map(|(i, field)| (field.name().clone(), i)).collect()
+    }
+
+    /// Numbers are cast to the field type, failing on values the type can't
+    /// represent instead of truncating them. Text fields take every value as
+    /// text. Errors report the line of a row from the chunk.
+    fn record_batch(
+        schema: &Arc<Schema>,
+        chunk: &RowChunk,
+    ) -> Result<RecordBatch, StrapError> {
+        let parse_error = |i: usize, col: &str, value: &dyn std::fmt::Display, reason: String| StrapError::Parse {
+            line: chunk.lines[i],
+            content: format!("{} {}", col, value),
+            reason,
+        };
+        let mut arrays: Vec<ArrayRef> = Vec::new();
+        for (column, field) in chunk.columns.iter().zip(schema.fields()) {
+            let col = field.name();
+            let values = match column {
+                ChunkColumn::Values(values) => values,
+                ChunkColumn::Numbers(values) => {
+                    arrays.push(Self::number_array(col, field.data_type(), values, &chunk.lines)?);
+                    continue;
+                }
+            };
+            if field.data_type() == &DataType::Boolean {
+                let mut flags = Vec::with_capacity(values.len());
+                for (i, value) in values.iter().enumerate() {
+                    flags.push(match value {
+                        Some(value) => Some(Self::flag(value).ok_or_else(|| parse_error(i, col, value, format!(
+                            "column {}: value {} is no flag and can't be written as Boolean", col, value
+                        )))?),
+                        None => None,
+                    });
+                }
+                arrays.push(Arc::new(BooleanArray::from(flags)));
+                continue;
+            }
+            if Self::is_text(field.data_type()) {
+                let values: StringArray = values.iter()
+                    .map(|value| value.as_ref().map(StrapValue::to_string))
+                    .collect();
+                let array = Arc::new(values) as ArrayRef;
+                arrays.push(cast_with_options(&array, field.data_type(), &CastOptions::default())
+                    .map_err(|e| arrow::error::ArrowError::CastError(format!("column {}: {}", col, e)))?);
+                continue;
+            }
+            // the other fields with `ChunkColumn::Values` are lists
+            let DataType::List(item) = field.data_type() else {
+                unreachable!("column {} of type {} holds values", col, field.data_type());
+            };
+            let mut builder = ListBuilder::new(Float64Builder::with_capacity(values.len())).with_field(item.clone());
+            for (i, value) in values.iter().enumerate() {
+                match value {
+                    Some(value) => {
+                        let list = value.as_list().ok_or_else(|| parse_error(i, col, value, format!(
+                            "column {}: value {} is not a number and can't be written as {}", col, value, field.data_type()
+                        )))?;
+                        builder.values().append_slice(&list);
+                        builder.append(true);
+                    }
+                    None => builder.append(false),
+                }
+            }
+            arrays.push(Arc::new(builder.finish()));
+        }
+        Ok(RecordBatch::try_new(schema.clone(), arrays)?)
+    }
+
+    /// `values` of column `col` as an array of the numeric `data_type`, errors
+    /// report the line from `lines`
+    fn number_array(
+        col: &str,
+        data_type: &DataType,
+        values: &[Option<f64>],
+        lines: &[usize],
+    ) -> Result<ArrayRef, StrapError> {
+        if data_type.is_integer()
+            && let Some((i, value)) = values.iter().enumerate().find_map(|(i, v)| v.filter(|v| v.fract() != 0.0).map(|v| (i, v)))
+        {
+            return Err(StrapError::Parse {
+                line: lines[i],
+                content: format!("{} {}", col, value),
+                reason: format!("column {}: value {} is not an integer and can't be written as {}", col, value, data_type),
+            });
+        }
+        if let DataType::Timestamp(unit, _) = data_type {
+            let per_second = match unit {
+                TimeUnit::Second => 1.0,
+                TimeUnit::Millisecond => 1e3,
+                TimeUnit::Microsecond => 1e6,
+                TimeUnit::Nanosecond => 1e9,
+            };
+            // out of range of i64, NaN and inf are null
+            let ticks: Int64Array = values.iter()
+                .map(|value| value.map(|value| (value * per_second).round())
+                    .filter(|ticks| ticks.is_finite() && *ticks >= i64::MIN as f64 && *ticks < i64::MAX as f64)
+                    .map(|ticks| ticks as i64))
+                .collect();
+            return Ok(cast_with_options(&(Arc::new(ticks) as ArrayRef), data_type, &CastOptions::default())?);
+        }
+        let array = Arc::new(Float64Array::from(values.to_vec())) as ArrayRef;
+        if data_type == &DataType::Float64 {
+            return Ok(array);
+        }
+        let options = CastOptions { safe: false, ..Default::default() };
+        let array = cast_with_options(&array, data_type, &options)
+            .map_err(|e| arrow::error::ArrowError::CastError(format!("column {}: {}", col, e)))?;
+        Ok(array)
+    }
+
+    fn is_text(data_type: &DataType) -> bool {
+        match data_type {
+            DataType::Utf8 | DataType::LargeUtf8 | DataType::Utf8View => true,
+            DataType::Dictionary(_, value) => Self::is_text(value),
+            _ => false,
+        }
+    }
+
+    /// Convert all STRAP data into a single Arrow RecordBatch
+    pub fn to_record_batch(&self) -> Result<RecordBatch, StrapError> {
+        let (schema, chunk) = self.row_chunk()?;
+        Self::record_batch(&schema, &chunk)
+    }
+
+    /// Schema of `to_record_batch` and every row with a value, lines without
+    /// values are no rows
+    fn row_chunk(&self) -> Result<(SchemaRef, RowChunk), StrapError> {
+        let (column_names, types) = self.value_columns(&HashMap::new())?;
+        let schema = Self::with_units(Self::column_schema(&column_names, &types), &self.units()?);
+        let index = Self::column_index(&schema);
+        let mut chunk = RowChunk::new(schema.fields());
+        let mut rows = self.iter_values()?;
+        while let Some(row) = rows.next() {
+            let row = row?;
+            if !row.is_empty() {
+                chunk.push(rows.line(), row, &index, schema.fields())?;
+            }
+        }
+        Ok((schema, chunk))
+    }
+
+    /// Convert STRAP data to a polars DataFrame with the rows and columns of
+    /// `to_record_batch`: String for text columns, Boolean for flags, a list of
+    /// Float64 for lists and nullable Float64 for the others
+    #[cfg(feature = "polars")]
+    pub fn to_polars(&self) -> PolarsResult<DataFrame> {
+        let (schema, chunk) = self.row_chunk().map_err(std::io::Error::from)?;
+        let columns = schema.fields().iter()
+            .zip(chunk.columns)
+            .map(|(field, column)| {
+                let name = field.name().as_str().into();
+                let values = match column {
+                    ChunkColumn::Numbers(values) => return Column::new(name, values),
+                    ChunkColumn::Values(values) => values,
+                };
+                match field.data_type() {
+                    DataType::Boolean => Column::new(name, values.iter().map(|value| value.as_ref().and_then(Self::flag)).collect::<Vec<_>>()),
+                    DataType::List(_) => {
+                        let lists: Vec<Option<Series>> = values.iter()
+                            .map(|value| value.as_ref().and_then(StrapValue::as_list).map(|list| Series::new("".into(), list)))
+                            .collect();
+                        Column::new(name, lists)
+                    }
+                    _ => Column::new(name, values.iter().map(|value| value.as_ref().map(StrapValue::to_string)).collect::<Vec<_>>()),
+                }
+            })
+            .collect();
+        DataFrame::new(columns)
+    }
+
+    /// Same as `to_polars` as a LazyFrame
+    #[cfg(feature = "polars")]
+    pub fn to_lazy(&self) -> PolarsResult<LazyFrame> {
+        Ok(self.to_polars()?.lazy())
+    }
+
+    /// Create or replace the table `table_name` in `conn` with every row, without
+    /// writing a parquet file. Returns the number of rows.
+    #[cfg(feature = "duckdb")]
+    pub fn to_duckdb(&self, conn: &duckdb::Connection, table_name: &str) -> Result<usize, StrapError> {
+        let mut batches = self.to_record_batches(ParquetOptions::default().chunk_size)?;
+        let schema = batches.schema();
+        if schema.fields().is_empty() {
+            return Err(invalid_input(format!("{} has no columns for table {}", self.name(), table_name)));
+        }
+        let quote = |name: &str| format!("\"{}\"", name.replace('"', "\"\""));
+        let columns = schema.fields().iter()
+            .map(|field| match Self::duckdb_type(field.data_type()) {
+                Some(data_type) => Ok(format!("{} {}", quote(field.name()), data_type)),
+                None => Err(invalid_input(format!("column {}: {} can't be stored in DuckDB", field.name(), field.data_type()))),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        conn.execute_batch(&format!("CREATE OR REPLACE TABLE {} ({});", quote(table_name), columns.join(", ")))?;
+        // the appender takes the table name as is, not as SQL
+        let mut appender = conn.appender(table_name)?;
+        let mut rows = 0;
+        for batch in &mut batches {
+            let batch = batch?;
+            rows += batch.num_rows();
+            appender.append_record_batch(batch)?;
+        }
+        appender.flush()?;
+        Ok(rows)
+    }
+
+    /// DuckDB column type of `data_type`, lists become DuckDB LIST types like `DOUBLE[]`
+    #[cfg(feature = "duckdb")]
+    fn duckdb_type(data_type: &DataType) -> Option<String> {
+        let name = match data_type {
+            DataType::Float64 => "DOUBLE",
+            DataType::Float32 => "FLOAT",
+            DataType::Int64 => "BIGINT",
+            DataType::Int32 => "INTEGER",
+            DataType::UInt64 => "UBIGINT",
+            DataType::UInt32 => "UINTEGER",
+            DataType::Utf8 => "VARCHAR",
+            DataType::Boolean => "BOOLEAN",
+            DataType::List(item) => return Self::duckdb_type(item.data_type()).map(|item| format!("{}[]", item)),
+            _ => return None,
+        };
+        Some(name.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use tempfile::NamedTempFile;
+
+    fn create_test_file(suffix: &str, content: &str) -> NamedTempFile {
+        let mut file = NamedTempFile::with_suffix(suffix).unwrap();
+        write!(file, "{}", content).unwrap();
+        file
+    }
+
+    /// All rows of a parquet file as one batch
+    fn read_parquet(path: &std::path::Path) -> RecordBatch {
+        let builder = parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder::try_new(File::open(path).unwrap()).unwrap();
+        let schema = builder.schema().clone();
+        let batches = builder.build().unwrap().collect::<Result<Vec<_>, _>>().unwrap();
+        arrow::compute::concat_batches(&schema, &batches).unwrap()
+    }
+
+    #[test]
+    fn test_parse_simple_line() {
+        let result = StrapTrack::parse_line("alice_sword 2.2 bob_bow 5.0", true);
+        assert_eq!(result.get("alice_sword"), Some(&2.2));
+        assert_eq!(result.get("bob_bow"), Some(&5.0));
+        let result = StrapTrack::parse_line("alice_sword 2.2 bob_bow 5.0", false);
+        // assert empty since no @strap prefix
+        assert!(result.is_empty());
+    }
+
+    #[test]
+    fn test_parse_strap_prefix() {
+        let result = StrapTrack::parse_line("@strap damage 15.0 attacker_alice 1.0", true);
+        assert_eq!(result.get("damage"), Some(&15.0));
+        assert_eq!(result.get("attacker_alice"), Some(&1.0));
+        let result = StrapTrack::parse_line("@strap damage 15.0 attacker_alice 1.0", false);
+        assert_eq!(result.get("damage"), Some(&15.0));
+        assert_eq!(result.get("attacker_alice"), Some(&1.0));
+    }
+
+    #[test]
+    fn test_parse_strap1_prefix() {
+        let result = StrapTrack::parse_line("@strap1 line 5.0", true);
+        assert_eq!(result.get("line"), Some(&5.0));
+        let result = StrapTrack::parse_line("@strap1 line 5.0", false);
+        assert_eq!(result.get("line"), Some(&5.0));
+    }
+
+    #[test]
+    fn test_parse_strap_with_metadata() {
+        let result = StrapTrack::parse_line("DATE TIME OR OTHER_METADATA @strap damage 15.0 attacker_alice 1.0 defender_bob 1.0", false);
+        assert_eq!(result.get("damage"), Some(&15.0));
+        assert_eq!(result.get("attacker_alice"), Some(&1.0));
+        assert_eq!(result.get("defender_bob"), Some(&1.0));
+    }
+
+    #[test]
+    fn test_parse_empty_line() {
+        let result = StrapTrack::parse_line("", true);
+        assert!(result.is_empty());
+    }
+
+    #[test]
+    fn test_parse_whitespace_only() {
+        let result = StrapTrack::parse_line("   \t  ",true);
+        assert!(result.is_empty());
+    }
+
+    #[test]
+    fn test_parse_odd_number_tokens() {
+        let result = StrapTrack::parse_line("key1 1.0 key2",true);
+        assert_eq!(result.get("key1"), Some(&1.0));
+        assert!(!result.contains_key("key2"));
+        let result = StrapTrack::parse_line("key1 1.0 key2",false);
+        assert!(result.is_empty());
+    }
+
+    #[test]
+    fn test_parse_invalid_float() {
+        let result = StrapTrack::parse_line("key1 invalid_float key2 2.0", true);
+        assert!(!result.contains_key("key1"));
+        assert_eq!(result.get("key2"), Some(&2.0));
+        let result = StrapTrack::parse_line("key1 invalid_float key2 2.0", false);
+        assert!(result.is_empty());
+    }
+
+    #[test]
+    fn test_strict_mode() {
+        let content = "x 1.0\nx 1.0 y\nx 1.2.3\n2.0 x\nrun A17 x 1.0\n";
+        let track: StrapTrack = content.parse().unwrap();
+        assert_eq!(track.dropped_pairs().unwrap(), 2);
+        // 1.2.3 is text
+        assert_eq!(track.get_column("x").unwrap(), vec![Some(1.0), Some(1.0), None, Some(1.0)]);
+
+        let track = track.with_parse_mode(ParseMode::Strict);
+        match track.describe().unwrap_err() {
+            StrapError::Parse { line, content, reason } => {
+                assert_eq!(line, 2);
+                assert_eq!(content, "x 1.0 y");
+                assert!(reason.contains("`y`"), "{}", reason);
+            }
+            e => panic!("{:?}", e),
+        }
+        let track: StrapTrack = "2.0 x\n".parse().unwrap();
+        let error = track.with_parse_mode(ParseMode::Strict).to_record_batch().unwrap_err();
+        assert!(error.to_string().contains("number as key `2.0`"), "{}", error);
+        let track: StrapTrack = "run A17 x 1.0\n".parse().unwrap();
+        assert!(track.with_parse_mode(ParseMode::Strict).describe().is_ok());
+    }
+
+    #[test]
+    fn test_only_prefixed() {
+        let content = "INFO 3.2\n@strap x 1.0\n\n@strap-units x m\nSTRAP: x 2.0\n";
+        let track = StrapTrack::from_bytes("run.log", content.as_bytes().to_vec());
+        assert_eq!(track.get_column("x").unwrap(), vec![Some(1.0)]);
+        assert_eq!(track.skipped_lines().unwrap(), 2);
+
+        let track = track.with_prefixes(["@strap", "STRAP:"]);
+        assert_eq!(track.get_column("x").unwrap(), vec![Some(1.0), Some(2.0)]);
+        assert_eq!(track.skipped_lines().unwrap(), 1);
+
+        // .strap files take every line unless told otherwise
+        let track: StrapTrack = content.parse().unwrap();
+        // `STRAP: x` is a text pair there
+        assert_eq!(track.get_column_names().unwrap(), vec!["INFO", "STRAP:", "x"]);
+        assert_eq!(track.skipped_lines().unwrap(), 0);
+        let track = track.with_only_prefixed(true);
+        assert_eq!(track.get_column_names().unwrap(), vec!["x"]);
+        assert_eq!(track.skipped_lines().unwrap(), 2);
+    }
+
+    #[test]
+    fn test_parse_key_equals_value() {
+        let result = StrapTrack::parse_line("@strap ts=1640995200 price=150.25", false);
+        assert_eq!(result.get("ts"), Some(&1640995200.0));
+        assert_eq!(result.get("price"), Some(&150.25));
+    }
+
+    #[test]
+    fn test_parse_mixed_pair_syntax() {
+        let result = StrapTrack::parse_line("a=1 b 2 c=3", true);
+        assert_eq!(result, HashMap::from([("a".to_string(), 1.0), ("b".to_string(), 2.0), ("c".to_string(), 3.0)]));
+        let result = StrapTrack::parse_values("x 1 query=a=b", true, &[STRAP_PREFIX]);
+        assert_eq!(result.get("query"), Some(&StrapValue::Text("a=b".to_string())));
+        assert_eq!(
+            StrapTrack::malformed_pairs("=1 x 2 y=", true, &[STRAP_PREFIX]),
+            vec![("".to_string(), "empty key"), ("y".to_string(), "no value for key")]
+        );
+    }
+
+    #[test]
+    fn test_parse_quoted_keys() {
+        let result = StrapTrack::parse_line(r#"@strap "jet pt" 45.5 "missing ET"=12.0 eta 0.3"#, false);
+        assert_eq!(result.get("jet pt"), Some(&45.5));
+        assert_eq!(result.get("missing ET"), Some(&12.0));
+        assert_eq!(result.get("eta"), Some(&0.3));
+        let result = StrapTrack::parse_values(r#"x 1 label "a b=c" note="two words""#, true, &[STRAP_PREFIX]);
+        assert_eq!(result.get("label"), Some(&StrapValue::Text("a b=c".to_string())));
+        assert_eq!(result.get("note"), Some(&StrapValue::Text("two words".to_string())));
+    }
+
+    #[test]
+    fn test_quoted_keys_round_trip() {
+        let track: StrapTrack = "\"jet pt\" 45.5 eta 0.3\n\"jet pt\" 30.0\n".parse().unwrap();
+        let batch = track.to_record_batch().unwrap();
+        assert_eq!(batch.schema().field(1).name(), "jet pt");
+        assert_eq!(track.get_column("jet pt").unwrap(), vec![Some(45.5), Some(30.0)]);
+    }
+
+    #[test]
+    fn test_strap2_header() {
+        let content = "@strap-units price USD\n@strap2 ts:i64 price:f64 symbol:str\n1640995200 150.25 AAPL\n1640995260 150.5 123\n";
+        let track: StrapTrack = content.parse().unwrap();
+        assert_eq!(track.header().unwrap().unwrap()[2], ("symbol".to_string(), DataType::Utf8));
+        assert_eq!(track.get_column_names().unwrap(), vec!["price", "symbol", "ts"]);
+        assert_eq!(track.get_column("price").unwrap(), vec![Some(150.25), Some(150.5)]);
+        assert_eq!(track.get_text_column("symbol").unwrap(), vec![Some("AAPL".to_string()), Some("123".to_string())]);
+        assert_eq!(track.units().unwrap()["price"], "USD");
+
+        let batch = track.to_record_batch().unwrap();
+        let schema = batch.schema();
+        assert_eq!(schema.field_with_name("ts").unwrap().data_type(), &DataType::Int64);
+        assert_eq!(schema.field_with_name("symbol").unwrap().data_type(), &DataType::Utf8);
+
+        // a plain @strap2 line is still key-value
+        let track: StrapTrack = "@strap2 key 1.0\n".parse().unwrap();
+        assert_eq!(track.header().unwrap(), None);
+        assert_eq!(track.get_column("key").unwrap(), vec![Some(1.0)]);
+    }
+
+    #[test]
+    fn test_strap2_mixed() {
+        let track: StrapTrack = "@strap2 x:f64\n1.0\n@strap y 2.0\n".parse().unwrap();
+        let error = track.describe().unwrap_err();
+        assert!(matches!(error, StrapError::Parse { line: 3, .. }), "{:?}", error);
+        let track: StrapTrack = "@strap y 2.0\n@strap2 x:f64\n1.0\n".parse().unwrap();
+        let error = track.describe().unwrap_err();
+        assert!(error.to_string().contains("can't mix"), "{}", error);
+        let track: StrapTrack = "@strap2 x:date\n".parse().unwrap();
+        assert!(track.header().unwrap_err().to_string().contains("unknown type"));
+
+        // missing and extra values are malformed
+        let track: StrapTrack = "@strap2 x:f64 y:f64\n1.0\n1.0 2.0 3.0\n".parse().unwrap();
+        assert_eq!(track.dropped_pairs().unwrap(), 2);
+        assert!(track.with_parse_mode(ParseMode::Strict).describe().is_err());
+    }
+
+    #[test]
+    fn test_special_values() {
+        let track: StrapTrack = "x NaN y 1.0\nx inf\nx 2.0 y -inf\n".parse().unwrap();
+        assert_eq!(
+            track.non_finite_counts().unwrap(),
+            BTreeMap::from([("x".to_string(), 2), ("y".to_string(), 1)])
+        );
+        assert_eq!(track.describe().unwrap().1, 3);
+
+        let track = track.with_special_values(SpecialValuePolicy::DropPair);
+        let (stats, rows) = track.describe().unwrap();
+        assert_eq!((stats["x"].count, stats["y"].count, rows), (1, 1, 2));
+        let track = track.with_special_values(SpecialValuePolicy::DropRow);
+        assert_eq!(track.describe().unwrap().1, 0);
+        // a dropped row is not written, the empty line is
+        let dropping: StrapTrack = "x 1.0\nx NaN\n\nx 2.0\n".parse().unwrap();
+        let dropping = dropping.with_special_values(SpecialValuePolicy::DropRow);
+        let out = NamedTempFile::with_suffix(".parquet").unwrap();
+        let options = ParquetOptions { skip_empty: false, ..Default::default() };
+        assert_eq!(dropping.to_parquet_with(out.path().to_str().unwrap(), &options).unwrap().rows, 3);
+        let x = read_parquet(out.path()).column_by_name("x").unwrap().clone();
+        assert_eq!(x.as_any().downcast_ref::<Float64Array>().unwrap().iter().collect::<Vec<_>>(), vec![Some(1.0), None, Some(2.0)]);
+        let track = track.with_special_values(SpecialValuePolicy::Error);
+        let error = track.to_record_batch().unwrap_err();
+        assert!(matches!(error, StrapError::Parse { line: 1, .. }), "{:?}", error);
+    }
+
+    #[test]
+    fn test_progress() {
+        use std::sync::Mutex;
+        let content = "x 1.0\n".repeat(25_000);
+        let file = create_test_file(".strap", &content);
+        let calls = Arc::new(Mutex::new(vec![]));
+        let seen = calls.clone();
+        let track = StrapTrack::new_with_progress(file.path(), move |read, total, rows| {
+            seen.lock().unwrap().push((read, total, rows));
+            true
+        }).unwrap();
+        assert_eq!(track.describe().unwrap().1, 25_000);
+        let calls = calls.lock().unwrap().clone();
+        assert_eq!(calls.len(), 2);
+        assert_eq!(calls[1].1, Some(content.len() as u64));
+        assert_eq!(calls[1].2, 19_999);
+        assert!(calls[1].0 > calls[0].0);
+
+        let track = StrapTrack::new(file.path()).unwrap().with_progress(|_, _, rows| rows < 10_000);
+        assert!(matches!(track.describe(), Err(StrapError::Cancelled)));
+    }
+
+    #[test]
+    fn test_mmap_backend() {
+        let contents = [
+            "a 1.0 b 2.0\nc 3.0\n",
+            "a 1.0 b 2.0\r\nc 3.0\r\n",
+            "a 1.0 b 2.0\nc 3.0",
+            "a 1.0 b 2.0\r\nc 3.0\r",
+            // a short line after a long one read into the same buffer
+            "a 1.0 b 2.0 \"long key\" long_value\nc 3.0\n",
+            "",
+        ];
+        for content in contents {
+            let file = create_test_file(".strap", content);
+            let buffered: Vec<_> = StrapTrack::new(file.path()).unwrap().iter_values().unwrap().map(Result::unwrap).collect();
+            let track = StrapTrack::new(file.path()).unwrap().with_backend(ReadBackend::Mmap);
+            let mapped: Vec<_> = track.iter_values().unwrap().map(Result::unwrap).collect();
+            assert_eq!(mapped, buffered, "{:?}", content);
+            if !content.is_empty() {
+                assert_eq!(track.get_column("c").unwrap(), vec![None, Some(3.0)]);
+            }
+        }
+
+        let mut file = create_test_file(".strap", "a 1.0\n");
+        file.write_all(b"\xff\n").unwrap();
+        let track = StrapTrack::new(file.path()).unwrap().with_backend(ReadBackend::Mmap);
+        let rows: Vec<_> = track.iter().unwrap().take(2).collect();
+        assert!(rows[0].is_ok());
+        assert!(rows[1].is_err());
+    }
+
+    #[test]
+    fn test_mmap_backend_compressed() {
+        let file = create_test_file(".strap.gz", "");
+        let mut encoder = flate2::write::GzEncoder::new(File::create(file.path()).unwrap(), flate2::Compression::default());
+        encoder.write_all(b"a 1.0\r\na 2.0").unwrap();
+        encoder.finish().unwrap();
+        let track = StrapTrack::new(file.path()).unwrap().with_backend(ReadBackend::Mmap);
+        assert_eq!(track.get_column("a").unwrap(), vec![Some(1.0), Some(2.0)]);
+    }
+
+    #[test]
+    fn test_iterator() {
+        let content = "alice_sword 2.2 bob_bow 5.0\ndamage 2.0 attacker_alice 1.0\n";
+        let file = create_test_file(".strap", content);
+        let track = StrapTrack::new(file.path()).unwrap();
+        
+        let rows: Result<Vec<_>, _> = track.iter().unwrap().collect();
+        let rows = rows.unwrap();
+        
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[0].get("alice_sword"), Some(&2.2));
+        assert_eq!(rows[1].get("damage"), Some(&2.0));
+    }
+
+    #[test]
+    fn test_get_column_names() {
+        let content = "a 1.0 b 2.0\nc 3.0 d 4.0\na 5.0 e 6.0\n";
+        let file = create_test_file(".strap", content);
+        let track = StrapTrack::new(file.path()).unwrap();
+        
+        let mut columns = track.get_column_names().unwrap();
+        columns.sort();
+        
+        assert_eq!(columns, vec!["a", "b", "c", "d", "e"]);
+
+        let track: StrapTrack = "x 1.0 run A17\nweights 1,2\n".parse().unwrap();
+        assert_eq!(track.get_column_names().unwrap(), vec!["run", "weights", "x"]);
+    }
+
+    #[test]
+    fn test_filter_rows() {
+        let content = "type 1.0 value 10.0\ntype 2.0 value 20.0\ntype 1.0 value 15.0\n";
+        let file = create_test_file(".strap", content);
+        let track = StrapTrack::new(file.path()).unwrap();
+        
+        let filtered = track.filter_rows(|row| {
+            row.get("type") == Some(&1.0)
+        }).unwrap();
+        
+        assert_eq!(filtered.len(), 2);
+        assert_eq!(filtered[0].get("value"), Some(&10.0));
+        assert_eq!(filtered[1].get("value"), Some(&15.0));
+    }
+
+    #[test]
+    fn test_aggregate() {
+        let content = "@strap value 10.0\n@strap value 20.0\n@strap value 15.0\n";
+        let file = create_test_file(".log", content);
+        let track = StrapTrack::new(file.path()).unwrap();
+        
+        let sum = track.aggregate(0.0, |acc, row| {
+            acc + row.get("value").unwrap_or(&0.0)
+        }).unwrap();
+        
+        assert_eq!(sum, 45.0);
+    }
+
+    #[test]
+    fn test_mixed_strap_formats() {
+        let content = "@strap a 1.0\n@strap1 b 2.0\nNOISE @strap c 3.0\nregular 4.0\n";
+        let file = create_test_file(".strap", content);
+        let track = StrapTrack::new(file.path()).unwrap();
+        
+        let rows: Result<Vec<_>, _> = track.iter().unwrap().collect();
+        let rows = rows.unwrap();
+        
+        assert_eq!(rows.len(), 4);
+        assert_eq!(rows[0].get("a"), Some(&1.0));
+        assert_eq!(rows[1].get("b"), Some(&2.0));
+        assert_eq!(rows[2].get("c"), Some(&3.0));
+        assert_eq!(rows[3].get("regular"), Some(&4.0));
+    }
+
+    #[test]
+    fn test_strap_with_digits() {
+        let result = StrapTrack::parse_line("@strap2 key 1.0", false);
+        assert_eq!(result.get("key"), Some(&1.0));
+    }
+
+    #[test]
+    fn test_scientific_notation() {
+        let result = StrapTrack::parse_line("temp 3.14e2 pressure 1.01e5", true);
+        assert_eq!(result.get("temp"), Some(&314.0));
+        assert_eq!(result.get("pressure"), Some(&101000.0));
+    }
+
+    #[test]
+    fn test_negative_values() {
+        let result = StrapTrack::parse_line("deficit -42.5 surplus 100.0", true);
+        assert_eq!(result.get("deficit"), Some(&-42.5));
+        assert_eq!(result.get("surplus"), Some(&100.0));
+    }
+
+    #[cfg(feature = "duckdb")]
+    #[test]
+    fn test_to_duckdb() {
+        let track: StrapTrack = "x 1.0 run A17\nx 2.0 \"y z\" 3.0\n".parse().unwrap();
+        let conn = duckdb::Connection::open_in_memory().unwrap();
+        assert_eq!(track.to_duckdb(&conn, "my \"strap\" table").unwrap(), 2);
+        let (count, sum, y): (i64, f64, f64) = conn
+            .query_row(r#"SELECT COUNT(*), SUM(x), MAX("y z") FROM "my ""strap"" table""#, [], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))
+            .unwrap();
+        assert_eq!((count, sum, y), (2, 3.0, 3.0));
+
+        // registering again replaces the table
+        let track: StrapTrack = "w 5.0\n".parse().unwrap();
+        assert_eq!(track.to_duckdb(&conn, "my \"strap\" table").unwrap(), 1);
+        let w: f64 = conn.query_row(r#"SELECT w FROM "my ""strap"" table""#, [], |row| row.get(0)).unwrap();
+        assert_eq!(w, 5.0);
+    }
+
+    #[cfg(feature = "duckdb")]
+    #[test]
+    fn test_to_duckdb_list_column() {
+        let track: StrapTrack = "x 1.0 weights 0.1,0.2,0.3
+x 2.0
+weights 1.5,2.5
+".parse().unwrap();
+        let conn = duckdb::Connection::open_in_memory().unwrap();
+        assert_eq!(track.to_duckdb(&conn, "t").unwrap(), 3);
+        let data_type: String = conn
+            .query_row("SELECT data_type FROM information_schema.columns WHERE table_name = 't' AND column_name = 'weights'", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(data_type, "DOUBLE[]");
+        let (lengths, nulls): (String, i64) = conn
+            .query_row("SELECT string_agg(len(weights)::VARCHAR, ',' ORDER BY x NULLS LAST), COUNT(*) - COUNT(weights) FROM t", [], |row| Ok((row.get(0)?, row.get(1)?)))
+            .unwrap();
+        assert_eq!((lengths.as_str(), nulls), ("3,2", 1));
+        let values: Vec<f64> = conn.prepare("SELECT UNNEST(weights) FROM t ORDER BY x NULLS LAST").unwrap()
+            .query_map([], |row| row.get(0)).unwrap()
+            .collect::<Result<_, _>>().unwrap();
+        assert_eq!(values, vec![0.1, 0.2, 0.3, 1.5, 2.5]);
+    }
+
+    #[cfg(feature = "duckdb")]
+    #[test]
+    fn test_column_stats_match_duckdb() {
+        let mut rng = StdRng::seed_from_u64(3);
+        let content: String = (0..1000)
+            .map(|_| match rng.gen_range(0..3) {
+                0 => format!("x {} y {}\n", rng.gen_range(-1e3..1e3), rng.gen_range(0.0..1.0)),
+                1 => format!("x {}\n", rng.gen_range(1e8..1e8 + 1.0)),
+                _ => format!("y {}\n", rng.gen_range(-5.0..5.0)),
+            })
+            .collect();
+        let track: StrapTrack = content.parse().unwrap();
+        let conn = duckdb::Connection::open_in_memory().unwrap();
+        track.to_duckdb(&conn, "t").unwrap();
+        for (name, stats) in track.all_column_stats().unwrap() {
+            let query = format!("SELECT COUNT({0}), COUNT(*) - COUNT({0}), MIN({0}), MAX({0}), AVG({0}), STDDEV_SAMP({0}) FROM t", name);
+            let expected: (usize, usize, f64, f64, f64, f64) = conn.query_row(&query, [], |row| {
+                Ok((row.get::<_, i64>(0)? as usize, row.get::<_, i64>(1)? as usize, row.get(2)?, row.get(3)?, row.get(4)?, row.get(5)?))
+            }).unwrap();
+            assert_eq!((stats.count, stats.null_count, stats.min, stats.max), (expected.0, expected.1, expected.2, expected.3));
+            assert!((stats.mean() - expected.4).abs() <= 1e-9 * expected.4.abs().max(1.0), "{}", name);
+            assert!((stats.stddev() - expected.5).abs() <= 1e-9 * expected.5.max(1.0), "{}", name);
+        }
+    }
+
+    #[cfg(feature = "polars")]
+    #[test]
+    fn test_to_polars_matches_aggregate() {
+        use polars::prelude::ChunkAgg;
+        let content = "x 1.0 y 2.0\nx 3.0\ny 4.0\nx 5.0 y 6.0\n";
+        let file = create_test_file(".strap", content);
+        let track = StrapTrack::new(file.path()).unwrap();
+        let df = track.to_polars().unwrap();
+        assert_eq!(df.height(), 4);
+        assert_eq!(df.get_column_names_str(), vec!["x", "y"]);
+
+        for name in ["x", "y"] {
+            let (sum, count) = track.aggregate((0.0, 0), |(sum, count), row| match row.get(name) {
+                Some(v) => (sum + v, count + 1),
+                None => (sum, count),
+            }).unwrap();
+            let column = df.column(name).unwrap().f64().unwrap();
+            assert_eq!(column.len() - column.null_count(), count);
+            assert_eq!(column.mean(), Some(sum / count as f64));
+        }
+        assert_eq!(track.to_lazy().unwrap().collect().unwrap().height(), 4);
+
+        // comments are no rows
+        let file = create_test_file(".strap", "x 1.0\n# comment\nx 2.0 y 3.0\n");
+        let df = StrapTrack::new(file.path()).unwrap().to_polars().unwrap();
+        assert_eq!(df.height(), 2);
+        assert_eq!(df.column("x").unwrap().f64().unwrap().sum(), Some(3.0));
+        assert_eq!(df.column("y").unwrap().f64().unwrap().null_count(), 1);
+
+        // text columns are strings
+        let file = create_test_file(".strap", "run A17 x 1.0 w 0.5,1.5\nrun 18 x 2.0\nx 3.0\n");
+        let df = StrapTrack::new(file.path()).unwrap().to_polars().unwrap();
+        let run = df.column("run").unwrap().str().unwrap();
+        assert_eq!(run.into_iter().collect::<Vec<_>>(), vec![Some("A17"), Some("18"), None]);
+        assert_eq!(df.column("x").unwrap().f64().unwrap().sum(), Some(6.0));
+        assert_eq!(df.column("w").unwrap().list().unwrap().null_count(), 2);
+    }
+
+    #[test]
+    fn test_to_record_batch() {
+        use arrow::array::Array;
+        let content = "b 1.0 a 2.0\na 3.0\n";
+        let file = create_test_file(".strap", content);
+        let batch = StrapTrack::new(file.path()).unwrap().to_record_batch().unwrap();
+        assert_eq!(batch.num_rows(), 2);
+        assert_eq!(batch.schema().field(0).name(), "a");
+        let b = batch.column(1).as_any().downcast_ref::<Float64Array>().unwrap();
+        assert_eq!(b.value(0), 1.0);
+        assert!(b.is_null(1));
+    }
+
+    #[test]
+    fn test_columns() {
+        let content = "b 1.0 c 2.0\nnoise\nrun A17 a 4.0\nc 3.0\n";
+        let file = create_test_file(".strap", content);
+        let track = StrapTrack::new(file.path()).unwrap();
+        let columns = track.columns().unwrap();
+        assert_eq!(columns.len(), 3);
+        assert_eq!(columns.column_names(), ["a", "b", "c"]);
+        assert_eq!(columns.get_column("c").unwrap(), track.get_column("c").unwrap());
+        assert_eq!(columns.get_column("a").unwrap(), [None, Some(4.0), None]);
+        assert_eq!(columns.get_column("run"), None);
+        assert_eq!(columns.get_row(0).unwrap(), HashMap::from([("c".to_string(), 2.0), ("b".to_string(), 1.0)]));
+        assert_eq!(columns.get_row(3), None);
+        let rows: Vec<_> = columns.get_rows(1..10).map(|row| row.to_map()).collect();
+        assert_eq!(rows, [columns.get_row(1).unwrap(), columns.get_row(2).unwrap()]);
+        assert_eq!(columns.get_rows(5..10).count(), 0);
+        let row = columns.row(0).unwrap();
+        assert_eq!((row.index(), row.get("b"), row.get("a")), (0, Some(1.0), None));
+        assert_eq!(row.iter().collect::<Vec<_>>(), [("b", 1.0), ("c", 2.0)]);
+        assert_eq!(columns.rows().count(), 3);
+        assert_eq!(columns.get_column_range("c", 1..10).unwrap(), [None, Some(3.0)]);
+        assert!(columns.get_column_range("c", 5..9).unwrap().is_empty());
+
+        let batch = columns.to_record_batch().unwrap();
+        assert_eq!(batch.num_rows(), 3);
+        assert_eq!(batch.schema().field(2).name(), "c");
+    }
+
+    #[test]
+    fn test_head_tail_sample() {
+        let content: String = (0..10).map(|i| format!("x {}\nnoise\n", i)).collect();
+        let file = create_test_file(".strap", &content);
+        let track = StrapTrack::new(file.path()).unwrap();
+        let x = |rows: Vec<HashMap<String, f64>>| rows.iter().map(|row| row["x"]).collect::<Vec<_>>();
+        assert_eq!(x(track.head(3).unwrap()), [0.0, 1.0, 2.0]);
+        assert_eq!(x(track.tail(2).unwrap()), [8.0, 9.0]);
+        assert_eq!(track.head(20).unwrap().len(), 10);
+        assert_eq!(track.tail(20).unwrap().len(), 10);
+        assert!(track.tail(0).unwrap().is_empty());
+
+        let sample = x(track.sample(4, 7).unwrap());
+        assert_eq!(sample.len(), 4);
+        assert!(sample.is_sorted());
+        assert_eq!(sample, x(track.sample(4, 7).unwrap()));
+        assert_eq!(x(track.sample(20, 7).unwrap()).len(), 10);
+
+        let empty = create_test_file(".strap", "");
+        let track = StrapTrack::new(empty.path()).unwrap();
+        assert!(track.head(3).unwrap().is_empty());
+        assert!(track.tail(3).unwrap().is_empty());
+        assert!(track.sample(3, 0).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_with_columns() {
+        let content = "ts 1 price 2.5 noise 7\nnoise 8\nts 2 volume 10\n";
+        let file = create_test_file(".strap", content);
+        let track = StrapTrack::new(file.path()).unwrap().with_columns(["ts", "price", "bid"]);
+        assert_eq!(track.get_column_names().unwrap(), vec!["price", "ts"]);
+        assert_eq!(track.get_column("ts").unwrap(), vec![Some(1.0), Some(2.0)]);
+        assert_eq!(track.missing_columns().unwrap(), vec!["bid"]);
+        assert_eq!(track.schema().unwrap().fields().len(), 2);
+
+        let track = StrapTrack::new(file.path()).unwrap();
+        let projected = track.select_columns(&["volume"]).unwrap();
+        assert_eq!(projected.describe().unwrap().1, 1);
+        assert!(projected.select_columns(&["ts"]).is_err());
+        let error = track.select_columns(&["ts", "bid"]).unwrap_err();
+        assert!(error.to_string().contains("bid"), "{}", error);
+
+        let track: StrapTrack = "@strap2 ts:i64 price:f64\n1 2.5\n".parse().unwrap();
+        let track = track.with_columns(["price"]);
+        assert_eq!(track.get_column_names().unwrap(), vec!["price"]);
+        assert_eq!(track.to_record_batch().unwrap().num_columns(), 1);
+    }
+
+    #[test]
+    fn test_with_filter() {
+        let content = "status 1 ts 10\nstatus 0 ts 20\nnoise\nts 30\nstatus 1 ts 40\n";
+        let file = create_test_file(".strap", content);
+        let track = StrapTrack::new_with_filter(file.path(), |row| row.get("status") == Some(&1.0)).unwrap();
+        assert_eq!(track.get_column("ts").unwrap(), vec![Some(10.0), Some(40.0)]);
+        assert_eq!(track.filtered_rows().unwrap(), 2);
+
+        let comparison: Comparison = "ts >= 20".parse().unwrap();
+        assert_eq!(comparison, Comparison { column: "ts".to_string(), operator: ">=".to_string(), value: 20.0 });
+        let track = StrapTrack::new(file.path()).unwrap().with_filter(move |row| comparison.matches(row));
+        assert_eq!(track.get_column("ts").unwrap(), vec![Some(20.0), Some(30.0), Some(40.0)]);
+        assert_eq!(track.filtered_rows().unwrap(), 1);
+        assert!("ts 20".parse::<Comparison>().is_err());
+        assert!("ts > x".parse::<Comparison>().is_err());
+        assert!(!"x < 1".parse::<Comparison>().unwrap().matches(&HashMap::new()));
+    }
+
+    #[test]
+    fn test_column_order() {
+        let content: String = (0..50).rev().map(|i| format!("k{} 1.0 label x\n", i)).collect();
+        let file = create_test_file(".strap", &content);
+        let names = StrapTrack::new(file.path()).unwrap().get_column_names().unwrap();
+        let mut sorted = names.clone();
+        sorted.sort();
+        assert_eq!(names, sorted);
+        assert_eq!(names, StrapTrack::new(file.path()).unwrap().get_column_names().unwrap());
+        let schema = StrapTrack::new(file.path()).unwrap().schema().unwrap();
+        let schema_names: Vec<&String> = schema.fields().iter().map(|field| field.name()).collect();
+        assert_eq!(schema_names, names.iter().collect::<Vec<_>>());
+
+        let track: StrapTrack = "@strap2 b:f64 a:f64\n1 2\n".parse().unwrap();
+        assert_eq!(track.get_column_names().unwrap(), vec!["a", "b"]);
+    }
+
+    #[test]
+    fn test_with_renames() {
+        let content = "pt 1.0 eta 0.1\njet_pt 2.0\nphi 3.0\n";
+        let file = create_test_file(".strap", content);
+        let track = StrapTrack::new(file.path()).unwrap().with_renames(&[("pt", "jet_pt")]);
+        assert_eq!(track.get_column_names().unwrap(), vec!["eta", "jet_pt", "phi"]);
+        assert_eq!(track.get_column("jet_pt").unwrap(), vec![Some(1.0), Some(2.0), None]);
+        assert_eq!(track.to_record_batch().unwrap().num_columns(), 3);
+
+        // a row with both keys is fine with equal values and an error otherwise
+        let track: StrapTrack = "pt 1.0 jet_pt 1.0\npt 1.0 jet_pt 2.0\n".parse().unwrap();
+        let track = track.with_renames(&[("pt", "jet_pt")]);
+        let error = track.get_column("jet_pt").unwrap_err();
+        assert!(matches!(error, StrapError::Parse { line: 2, .. }), "{:?}", error);
+
+        let track: StrapTrack = "@strap2 pt:f64 eta:f64\n1 2\n".parse().unwrap();
+        let track = track.with_renames(&[("pt", "jet_pt")]);
+        assert_eq!(track.get_column("jet_pt").unwrap(), vec![Some(1.0)]);
+        let track = track.with_renames(&[("pt", "eta")]);
+        assert!(track.header().is_err());
+    }
+
+    #[test]
+    fn test_rename_column() {
+        let track: StrapTrack = "pt 1.0 eta 0.1\njet_pt 2.0\npt 3.0 jet_pt 3.0\n".parse().unwrap();
+        let mut columns = track.columns().unwrap();
+        columns.rename_column("pt", "jet_pt").unwrap();
+        assert_eq!(columns.column_names(), ["eta", "jet_pt"]);
+        assert_eq!(columns.get_column("jet_pt").unwrap(), [Some(1.0), Some(2.0), Some(3.0)]);
+        assert_eq!(columns.get_row(0).unwrap()["jet_pt"], 1.0);
+        columns.rename_column("eta", "y").unwrap();
+        assert_eq!(columns.column_names(), ["jet_pt", "y"]);
+        assert_eq!(columns.get_column("y").unwrap(), [Some(0.1), None, None]);
+        assert!(columns.rename_column("missing", "y").is_err());
+
+        let track: StrapTrack = "pt 1.0 jet_pt 2.0\n".parse().unwrap();
+        let mut columns = track.columns().unwrap();
+        let before = columns.clone();
+        assert!(columns.rename_column("pt", "jet_pt").is_err());
+        assert_eq!(columns, before);
+    }
+
+    #[test]
+    fn test_duplicate_keys() {
+        let content = "x 1.0 y 5.0 x 3.0\nx 2.0\n";
+        let x = |policy| {
+            let track: StrapTrack = content.parse().unwrap();
+            track.with_duplicate_keys(policy).get_column("x")
+        };
+        assert_eq!(x(DuplicateKeyPolicy::LastWins).unwrap(), vec![Some(3.0), Some(2.0)]);
+        assert_eq!(x(DuplicateKeyPolicy::FirstWins).unwrap(), vec![Some(1.0), Some(2.0)]);
+        assert_eq!(x(DuplicateKeyPolicy::Max).unwrap(), vec![Some(3.0), Some(2.0)]);
+        assert_eq!(x(DuplicateKeyPolicy::Sum).unwrap(), vec![Some(4.0), Some(2.0)]);
+        let error = x(DuplicateKeyPolicy::Error).unwrap_err();
+        assert!(matches!(&error, StrapError::Parse { line: 1, reason, .. } if reason == "duplicate key x"), "{:?}", error);
+
+        // strict mode doesn't change the policy
+        let track: StrapTrack = content.parse().unwrap();
+        let track = track.with_parse_mode(ParseMode::Strict).with_duplicate_keys(DuplicateKeyPolicy::FirstWins);
+        assert_eq!(track.get_column("x").unwrap(), vec![Some(1.0), Some(2.0)]);
+        assert_eq!("first-wins".parse(), Ok(DuplicateKeyPolicy::FirstWins));
+    }
+
+    #[test]
+    fn test_unit_suffixes() {
+        let content = "latency 12ms size 4k\nlatency 0.5s size 2M\nlatency 3\n";
+        let track: StrapTrack = content.parse().unwrap();
+        // opt-in, without suffixes the values are text
+        assert_eq!(track.get_column("latency").unwrap(), vec![None, None, Some(3.0)]);
+        assert_eq!(track.get_text_column("size").unwrap(), vec![Some("4k".to_string()), Some("2M".to_string()), None]);
+
+        let track = track.with_unit_suffixes(UnitSuffix::defaults());
+        assert_eq!(track.get_column("latency").unwrap(), vec![Some(0.012), Some(0.5), Some(3.0)]);
+        assert_eq!(track.get_column("size").unwrap(), vec![Some(4e3), Some(2e6), None]);
+        let units = track.suffix_units().unwrap();
+        assert_eq!(units["latency"], "s");
+        assert_eq!(units["size"], "");
+
+        let track: StrapTrack = "t 5ms\nt 2k\n".parse().unwrap();
+        let error = track.with_unit_suffixes(UnitSuffix::defaults()).get_column("t").unwrap_err();
+        assert!(matches!(error, StrapError::Parse { line: 2, .. }), "{:?}", error);
+    }
+
+    #[test]
+    fn test_sparsity_report() {
+        let track: StrapTrack = "x 1.0 run A\n# comment\nx 2.0 y 1.0\nx 3.0\n".parse().unwrap();
+        let report = track.sparsity_report().unwrap();
+        assert_eq!(report, vec![("run".to_string(), 1, 3), ("x".to_string(), 3, 3), ("y".to_string(), 1, 3)]);
+        let report = track.with_columns(["y", "z"]).sparsity_report().unwrap();
+        assert_eq!(report, vec![("y".to_string(), 1, 1), ("z".to_string(), 0, 1)]);
+    }
+
+    #[test]
+    fn test_validate() {
+        let content = "@strap x 1.0 y\nno prefix\n@strap x 1 2 3 y 2\n\n@strap x 2 z 3\n@strap x 3 x 4\n";
+        let file = create_test_file(".log", content);
+        let track = StrapTrack::new(file.path()).unwrap().with_duplicate_keys(DuplicateKeyPolicy::Error);
+        let report = track.validate(2).unwrap();
+        assert_eq!((report.lines, report.rows, report.unprefixed), (6, 3, 1));
+        assert_eq!((report.odd_token_lines, report.malformed_pairs, report.errors), (1, 1, 1));
+        assert_eq!(report.problems(), 3);
+        assert_eq!(report.examples, vec![
+            (1, "odd token count, no value for key `y`".to_string()),
+            (3, "number as key `2`".to_string()),
+        ]);
+        assert_eq!(report.columns.into_iter().collect::<Vec<_>>(), vec!["x", "y", "z"]);
+    }
+
+    #[test]
+    fn test_load_report() {
+        let content = "@strap x 1.0 y\nno prefix\n@strap x\n\n# note\n@strap x 2\n@strap x 3 x 4\n";
+        let file = create_test_file(".log", content);
+        let track = StrapTrack::new(file.path()).unwrap()
+            .with_duplicate_keys(DuplicateKeyPolicy::Error)
+            .with_filter(|row| row.get("x") != Some(&2.0));
+        let report = track.load_report(1).unwrap();
+        assert_eq!((report.skipped, report.dropped_pairs), (2, 2));
+        assert_eq!(report.examples, vec![(3, "@strap x".to_string())]);
+        assert_eq!(track.load_report(5).unwrap().examples.len(), 2);
+
+        // conversions count the same lines without a second pass
+        let track = StrapTrack::new(file.path()).unwrap();
+        let out = NamedTempFile::with_suffix(".parquet").unwrap();
+        let stats = StrapTrack::stream_to_parquet(file.path(), out.path().to_str().unwrap(), 1).unwrap();
+        assert_eq!(stats.lost, 1);
+        assert_eq!(stats.lost, track.load_report(0).unwrap().skipped);
+        assert_eq!(track.to_parquet_with(out.path().to_str().unwrap(), &ParquetOptions::default()).unwrap().lost, 1);
+    }
+
+    #[test]
+    fn test_to_strap() {
+        let content = "x 0.1 \"jet pt\"=3e-7 run \"A 1\"\n# comment\ny -0.30000000000000004 x inf\n";
+        let track: StrapTrack = content.parse().unwrap();
+        let rows: Vec<_> = track.iter_values().unwrap().filter_ok(|row| !row.is_empty()).collect::<Result<_, _>>().unwrap();
+
+        let mut output = vec![];
+        assert_eq!(track.to_strap(&mut output, "@strap").unwrap(), 2);
+        let output = String::from_utf8(output).unwrap();
+        assert_eq!(output, "@strap \"jet pt\" 0.0000003 run \"A 1\" x 0.1\n@strap x inf y -0.30000000000000004\n");
+        let file = create_test_file(".txt", &output);
+        let read = StrapTrack::new(file.path()).unwrap();
+        let round_trip: Vec<_> = read.iter_values().unwrap().filter_ok(|row| !row.is_empty()).collect::<Result<_, _>>().unwrap();
+        assert_eq!(round_trip, rows);
+
+        let mut raw = vec![];
+        track.to_strap(&mut raw, "").unwrap();
+        assert!(String::from_utf8(raw).unwrap().starts_with("\"jet pt\" 0.0000003 run"));
+    }
+
+    #[test]
+    fn test_from_iterator() {
+        let rows = [
+            HashMap::from([("x".to_string(), 1.0), ("jet pt".to_string(), 0.1 + 0.2)]),
+            HashMap::new(),
+            HashMap::from([("x".to_string(), f64::INFINITY), ("a=b".to_string(), -2.0)]),
+        ];
+        let track: StrapTrack = rows.iter().cloned().collect();
+        let mut read = vec![];
+        for row in &track {
+            read.push(row.unwrap());
+        }
+        read.retain(|row| !row.is_empty());
+        assert_eq!(read, vec![rows[0].clone(), rows[2].clone()]);
+        assert_eq!(track.get_column_names().unwrap(), vec!["a=b", "jet pt", "x"]);
+
+        let owned: Vec<_> = track.into_iter().filter_ok(|row| !row.is_empty()).collect::<Result<_, _>>().unwrap();
+        assert_eq!(owned, read);
+
+        // keys no STRAP line could hold
+        let odd = HashMap::from([("say \"hi\"".to_string(), 1.0), ("# n".to_string(), 2.0), ("1.5".to_string(), 3.0)]);
+        let track: StrapTrack = [odd.clone()].into_iter().collect();
+        assert_eq!(track.iter().unwrap().collect::<Result<Vec<_>, _>>().unwrap(), vec![odd]);
+        assert_eq!(track.get_column("# n").unwrap(), vec![Some(2.0)]);
+        let track = track.with_columns(["1.5"]);
+        assert_eq!(track.iter().unwrap().next().unwrap().unwrap(), HashMap::from([("1.5".to_string(), 3.0)]));
+    }
+
+    #[test]
+    fn test_options() {
+        let content = "log\n#s x=1,y=2\n#s x=2\n#s x=3,y=4\n#s x=4\n";
+        let file = create_test_file(".log", content);
+        let options = StrapTrackOptions {
+            prefixes: vec!["#s".to_string()],
+            delimiter: Some(','),
+            max_rows: Some(2),
+            skip_rows: 1,
+            ..Default::default()
+        };
+        let track = StrapTrack::new_with_options(file.path(), &options).unwrap();
+        assert_eq!(track.get_column("x").unwrap(), vec![Some(2.0), Some(3.0)]);
+        assert_eq!(track.get_column("y").unwrap(), vec![None, Some(4.0)]);
+        // the skipped row is no empty line, unlike the first line without prefix
+        let out = NamedTempFile::with_suffix(".parquet").unwrap();
+        let parquet = ParquetOptions { skip_empty: false, row_index: Some(ROW_INDEX_COLUMN.to_string()), ..Default::default() };
+        assert_eq!(track.to_parquet_with(out.path().to_str().unwrap(), &parquet).unwrap().rows, 3);
+        let batch = read_parquet(out.path());
+        let lines = batch.column_by_name(ROW_INDEX_COLUMN).unwrap().as_any().downcast_ref::<UInt64Array>().unwrap();
+        assert_eq!(lines.values(), &[1, 3, 4]);
+
+        // the defaults read like `new`
+        let track = StrapTrack::new_with_options(file.path(), &StrapTrackOptions::default()).unwrap();
+        assert!(track.get_column_names().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_describe() {
+        let content = "x 1.0 y 2.0\nnoise\nx 3.0\nx -1.0\n";
+        let file = create_test_file(".strap", content);
+        let (stats, rows) = StrapTrack::new(file.path()).unwrap().describe().unwrap();
+        assert_eq!(rows, 3);
+        let x = stats["x"];
+        assert_eq!((x.count, x.sum, x.min, x.max), (3, 3.0, -1.0, 3.0));
+        assert_eq!(x.mean(), 1.0);
+        assert_eq!(x.stddev(), 2.0);
+        assert_eq!(x.null_count, 0);
+        assert_eq!(stats["y"].count, 1);
+        assert_eq!(stats["y"].null_count, 2);
+        assert!(stats["y"].stddev().is_nan());
+        let track = StrapTrack::new(file.path()).unwrap();
+        assert_eq!(track.column_stats("x").unwrap(), Some(x));
+        assert_eq!(track.column_stats("y").unwrap(), Some(stats["y"]));
+        assert_eq!(track.column_stats("z").unwrap(), None);
+        // without the @strap prefix nothing is read from a .log file
+        let log = create_test_file(".log", content);
+        assert!(StrapTrack::new(log.path()).unwrap().describe().unwrap().0.is_empty());
+    }
+
+    #[test]
+    fn test_from_str() {
+        let track: StrapTrack = "x 1.0 y 2.0\nx 3.0\n".parse().unwrap();
+        assert_eq!(track.get_column_names().unwrap(), vec!["x", "y"]);
+        assert_eq!(track.name(), "");
+        // named content follows the file name rules
+        let log = StrapTrack::from_bytes("run.log", b"x 1.0\n@strap x 2.0\n".to_vec());
+        assert_eq!(log.describe().unwrap().1, 1);
+    }
+
+    #[test]
+    fn test_from_reader_gzip() {
+        use flate2::write::GzEncoder;
+        let mut encoder = GzEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(b"x 1.0\nx 2.0\n").unwrap();
+        let bytes = encoder.finish().unwrap();
+        let track = StrapTrack::from_reader("run.strap.gz", bytes.as_slice()).unwrap();
+        assert_eq!(track.describe().unwrap().0["x"].sum, 3.0);
+    }
 
-        
-        // Parse key-value pairs separated by whitespace
-        let tokens: Vec<&str> = line.split_whitespace().collect();
-        for chunk in tokens.chunks(2) {
-            if chunk.len() == 2 {
-                if let Ok(value) = chunk[1].parse::<f64>() {
-                    result.insert(chunk[0].to_string(), value);
-                }
-            }
-        }
-        
-        result
+    #[test]
+    fn test_boolean_detection() {
+        let content = "triggered 1 x 0 ok true constant 1\ntriggered 0 x 2 ok FALSE constant 1\ny 5\ntriggered 1 x 1 ok true\n";
+        let track: StrapTrack = content.parse().unwrap();
+        let schema = track.schema().unwrap();
+        assert_eq!(schema.field_with_name("triggered").unwrap().data_type(), &DataType::Float64);
+
+        let track = track.with_boolean_detection(true);
+        let schema = track.schema().unwrap();
+        let types: Vec<_> = schema.fields().iter().map(|field| (field.name().as_str(), field.data_type().clone())).collect();
+        assert_eq!(types, vec![
+            ("constant", DataType::Float64),
+            ("ok", DataType::Boolean),
+            ("triggered", DataType::Boolean),
+            ("x", DataType::Float64),
+            ("y", DataType::Float64),
+        ]);
+        let batch = track.to_record_batch().unwrap();
+        let triggered = batch.column_by_name("triggered").unwrap().as_any().downcast_ref::<BooleanArray>().unwrap();
+        assert_eq!(triggered.iter().collect::<Vec<_>>(), vec![Some(true), Some(false), None, Some(true)]);
+        let ok = batch.column_by_name("ok").unwrap().as_any().downcast_ref::<BooleanArray>().unwrap();
+        assert_eq!(ok.iter().collect::<Vec<_>>(), vec![Some(true), Some(false), None, Some(true)]);
+
+        let out = NamedTempFile::with_suffix(".parquet").unwrap();
+        track.to_parquet(out.path().to_str().unwrap(), 2).unwrap();
     }
-    
-    /// Returns an iterator over all rows
-    pub fn iter(&self) -> Result<StrapTrackIterator, std::io::Error> {
-        // check if file name contains .strap or .strap.gz etc
-        let path_str = self.file_path.to_string_lossy().to_lowercase();
-        let all = path_str.ends_with(".strap") 
-            || path_str.ends_with(".strap.gz") 
-            || path_str.ends_with(".strap.gzip")
-            || path_str.ends_with(".strap.zst")
-            || path_str.ends_with(".strap.zstd")
-            || path_str.ends_with(".strap.zip");
-        let reader = self.create_reader()?;
-        Ok(StrapTrackIterator { all, reader })
+
+    #[test]
+    fn test_decimal_separator() {
+        let content = "temp 21,5 n 3 list 1,2,3\ntemp=-0,25 label a,b\ntemp 1.234,5\n";
+        let track: StrapTrack = content.parse().unwrap();
+        // with a decimal point the values with commas are lists
+        assert_eq!(track.get_column("temp").unwrap(), vec![None, None, None]);
+        assert_eq!(track.get_list_column("temp").unwrap(), vec![Some(vec![21.0, 5.0]), Some(vec![-0.0, 25.0]), Some(vec![1.234, 5.0])]);
+
+        let comma = track.clone().with_decimal_separator(DecimalSeparator::Comma);
+        assert_eq!(comma.get_column("temp").unwrap(), vec![Some(21.5), Some(-0.25)]);
+        assert_eq!(comma.get_text_column("label").unwrap(), vec![None, Some("a,b".to_string())]);
+        assert_eq!(comma.dropped_pairs().unwrap(), 2);
+        let error = comma.with_parse_mode(ParseMode::Strict).get_column("temp").unwrap_err();
+        assert!(error.to_string().contains("ambiguous decimal comma `1,2,3`"), "{}", error);
+
+        // the first decimal decides
+        let auto = track.clone().with_decimal_separator(DecimalSeparator::Auto);
+        assert_eq!(auto.get_column("temp").unwrap(), vec![Some(21.5), Some(-0.25)]);
+        let auto: StrapTrack = "x 1.5\nx 2,5\n".parse().unwrap();
+        let auto = auto.with_decimal_separator(DecimalSeparator::Auto);
+        assert_eq!(auto.get_column("x").unwrap(), vec![Some(1.5), None]);
+        assert_eq!("comma".parse::<DecimalSeparator>().unwrap(), DecimalSeparator::Comma);
     }
-    
-    /// Stream through all rows with a callback
-    pub fn for_each_row<F>(&self, mut callback: F) -> Result<(), std::io::Error>
-    where
-        F: FnMut(&HashMap<String, f64>) -> bool, // return false to stop
-    {
-        for parsed in self.iter()? {
-            if !callback(&parsed?) {
-                break;
-            }
-        }
-        Ok(())
+
+    #[test]
+    fn test_list_values() {
+        use arrow::array::{AsArray, ListArray};
+        let content = "weights 0.1,0.2,0.3 x 1\nweights 2 x 2\nx 3\nx 4 weights\n";
+        let track: StrapTrack = content.parse().unwrap();
+        assert_eq!(track.get_column("weights").unwrap(), vec![None, Some(2.0), None, None]);
+        assert_eq!(track.get_list_column("weights").unwrap(), vec![Some(vec![0.1, 0.2, 0.3]), Some(vec![2.0]), None, None]);
+        assert_eq!(track.dropped_pairs().unwrap(), 1);
+
+        let batch = track.to_record_batch().unwrap();
+        assert_eq!(batch.schema().field_with_name("weights").unwrap().data_type(), &DataType::new_list(DataType::Float64, true));
+        let weights = batch.column_by_name("weights").unwrap().as_any().downcast_ref::<ListArray>().unwrap();
+        assert_eq!(weights.value(0).as_primitive::<arrow::datatypes::Float64Type>().values().to_vec(), vec![0.1, 0.2, 0.3]);
+        assert_eq!(weights.value(1).len(), 1);
+        assert!(weights.is_null(2) && weights.is_null(3));
+
+        let mut output = vec![];
+        track.to_ndjson(&mut output, false).unwrap();
+        assert!(String::from_utf8(output).unwrap().starts_with("{\"weights\":[0.1,0.2,0.3],\"x\":1.0}\n"));
+        let mut output = vec![];
+        track.to_strap(&mut output, "").unwrap();
+        assert!(String::from_utf8(output).unwrap().starts_with("weights 0.1,0.2,0.3 x 1\n"));
     }
-    
-    /// Filter rows based on a predicate
-    pub fn filter_rows<F>(&self, predicate: F) -> Result<Vec<HashMap<String, f64>>, std::io::Error>
-    where
-        F: Fn(&HashMap<String, f64>) -> bool,
-    {
-        let mut results = Vec::new();
-        self.for_each_row(| row| {
-            if predicate(&row) {
-                results.push(row.clone());
-            }
-            true // continue
-        })?;
-        Ok(results)
+
+    #[test]
+    fn test_derived_column() {
+        let content = "pt 10 eta 0\npt 20 eta 1\neta 2\nx 1\n";
+        let track: StrapTrack = content.parse().unwrap();
+        let energy = |row: &HashMap<String, f64>| Some(row.get("pt")? * row.get("eta")?.cosh());
+        let derived = track.clone().with_derived_column("energy", energy);
+        assert_eq!(derived.get_column_names().unwrap(), vec!["energy", "eta", "pt", "x"]);
+        assert_eq!(derived.get_column("energy").unwrap(), vec![Some(10.0), Some(20.0 * 1f64.cosh()), None, None]);
+        let batch = derived.to_record_batch().unwrap();
+        assert_eq!(batch.column_by_name("energy").unwrap().null_count(), 2);
+
+        // collisions fail when reading
+        let error = track.clone().with_derived_column("x", |_| Some(1.0)).get_column_names().unwrap_err();
+        assert!(matches!(error, StrapError::Parse { line: 4, .. }), "{:?}", error);
+        assert!(derived.clone().with_derived_column("energy", |_| Some(1.0)).get_column("energy").is_err());
+        let header: StrapTrack = "@strap2 pt:f64\n10\n".parse().unwrap();
+        assert!(header.with_derived_column("pt", |_| Some(1.0)).schema().is_err());
+
+        // projections before and after deriving
+        let projected = derived.select_columns(&["energy"]).unwrap();
+        assert_eq!(projected.get_column_names().unwrap(), vec!["energy"]);
+        let projected = track.select_columns(&["pt"]).unwrap().with_derived_column("energy", energy);
+        assert_eq!(projected.get_column_names().unwrap(), vec!["energy", "pt"]);
+
+        let filtered = derived.with_filter(|row| row.get("energy").is_some_and(|energy| *energy > 15.0));
+        assert_eq!(filtered.get_column("pt").unwrap(), vec![Some(20.0)]);
     }
-    
-    /// Aggregate a column with a reduction function
-    pub fn aggregate<F, T>(&self, init: T, reducer: F) -> Result<T, std::io::Error>
-    where
-        F: Fn(T, &HashMap<String, f64>) -> T,
-    {
-        let mut acc = init;
 
-        for hm in self.iter()? {
-            acc = reducer(acc, &hm?);
-        }
+    #[test]
+    fn test_dedupe() {
+        let content = "x 1 y 2\ny 2 x 1.0\nx 1 y 2 run A\nx 1 y 2\nx -0 y 2\nx 0 y 2\n";
+        let track: StrapTrack = content.parse().unwrap();
+        assert_eq!(track.get_column("x").unwrap().len(), 6);
+        assert_eq!(track.duplicate_rows().unwrap(), 0);
 
-        Ok(acc)
+        let track = track.with_dedupe(true);
+        assert_eq!(track.get_column("x").unwrap(), vec![Some(1.0), Some(1.0), Some(-0.0), Some(0.0)]);
+        assert_eq!(track.duplicate_rows().unwrap(), 2);
+        let out = NamedTempFile::with_suffix(".parquet").unwrap();
+        let stats = track.to_parquet_with(out.path().to_str().unwrap(), &ParquetOptions { chunk_size: 2, ..Default::default() }).unwrap();
+        assert_eq!((stats.rows, stats.duplicates), (4, 2));
+        // duplicates are no empty lines
+        let options = ParquetOptions { chunk_size: 2, skip_empty: false, ..Default::default() };
+        let stats = track.to_parquet_with(out.path().to_str().unwrap(), &options).unwrap();
+        assert_eq!((stats.rows, stats.duplicates), (4, 2));
+        assert_eq!(read_parquet(out.path()).column_by_name("x").unwrap().null_count(), 0);
     }
 
-    /// Convert STRAP data to Parquet format
-    pub fn to_parquet(
-        &self, 
-        filename: &str, 
-        chunk_size: usize,
-    ) -> Result<(), Box<dyn std::error::Error>> {
+    #[test]
+    fn test_metadata() {
+        let content = "# detector: ALPHA\n#units: energy=GeV\nx 1 y 2\n  # run 17 done\n#s x 2\n# detector: BETA\n";
+        let track = StrapTrack::from_bytes("run.strap", content.as_bytes().to_vec());
+        let metadata = track.metadata().unwrap();
+        assert_eq!(metadata.comments, vec![
+            (1, "detector: ALPHA".to_string()),
+            (2, "units: energy=GeV".to_string()),
+            (4, "run 17 done".to_string()),
+            (5, "s x 2".to_string()),
+            (6, "detector: BETA".to_string()),
+        ]);
+        assert_eq!(metadata.entries["detector"], "BETA");
+        assert_eq!(metadata.entries["units"], "energy=GeV");
+        assert_eq!(track.dropped_pairs().unwrap(), 0);
+        assert_eq!(track.get_column_names().unwrap(), vec!["x", "y"]);
 
+        // a STRAP prefix starting like a comment
+        let track = track.with_prefixes(["#s"]);
+        assert_eq!(track.metadata().unwrap().comments.len(), 4);
+        assert_eq!(track.get_column("x").unwrap(), vec![Some(1.0), Some(2.0)]);
+        let track = track.with_comment_prefix(None);
+        assert!(track.metadata().unwrap().comments.is_empty());
+    }
 
-        // 1. Collect all unique column names
-        let mut column_names = self.get_column_names()?;
+    #[test]
+    fn test_from_reader_empty() {
+        let track = StrapTrack::from_reader("stdin.strap", std::io::empty()).unwrap();
+        assert!(track.get_column_names().unwrap().is_empty());
+        assert_eq!(track.describe().unwrap().1, 0);
+        let track = StrapTrack::from_reader("stdin.strap", b"x 1\n".as_slice()).unwrap().with_compression(InputCompression::None);
+        assert_eq!(track.get_column("x").unwrap(), vec![Some(1.0)]);
+    }
 
-        column_names.sort(); // optional: deterministic column order
+    #[test]
+    fn test_histogram() {
+        let track: StrapTrack = "x 0.0\nx 0.5\nx 1.0\nx 2.0\ny 5.0\n".parse().unwrap();
+        let hist = track.histogram("x", 2, None).unwrap();
+        assert_eq!(hist.edges, vec![0.0, 1.0, 2.0]);
+        assert_eq!(hist.counts, vec![2, 2]);
+        let hist = track.histogram("x", 2, Some((0.0, 1.0))).unwrap();
+        assert_eq!(hist.counts, vec![1, 2]);
+        assert_eq!(track.histogram("z", 2, None).unwrap().counts, vec![0, 0]);
+    }
 
-        // 2. Build schema
-        let fields: Vec<Field> = column_names.iter()
-            .map(|name| Field::new(name, DataType::Float64, true)) // nullable = true
-            .collect();
-        let schema = Arc::new(Schema::new(fields));
+    #[test]
+    fn test_to_parquet_type_override() {
+        use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+        let file = create_test_file(".strap", "run 1.0 x 0.5\nrun 2.0 x 1.5\n");
+        let track = StrapTrack::new(file.path()).unwrap();
+        assert_eq!(track.schema().unwrap().field_with_name("run").unwrap().data_type(), &DataType::Float64);
+
+        let out = NamedTempFile::with_suffix(".parquet").unwrap();
+        let options = ParquetOptions {
+            types: HashMap::from([("run".to_string(), DataType::Int32)]),
+            ..Default::default()
+        };
+        track.to_parquet_with(out.path().to_str().unwrap(), &options).unwrap();
+        let batch = ParquetRecordBatchReaderBuilder::try_new(File::open(out.path()).unwrap()).unwrap()
+            .build().unwrap()
+            .next().unwrap().unwrap();
+        let run = batch.column_by_name("run").unwrap();
+        assert_eq!(run.data_type(), &DataType::Int32);
+        assert_eq!(run.as_any().downcast_ref::<arrow::array::Int32Array>().unwrap().values(), &[1, 2]);
+    }
 
+    #[test]
+    fn test_to_parquet_chunks() {
+        use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+        let file = create_test_file(".strap", &(1..=5).map(|i| format!("x {}\n", i)).collect::<String>());
+        let track = StrapTrack::new(file.path()).unwrap();
+        let out = NamedTempFile::with_suffix(".parquet").unwrap();
+        let filename = out.path().to_str().unwrap();
+        assert!(track.to_parquet(filename, 0).is_err());
 
-        // Setup Parquet writer
-        let file = File::create(filename)?;
-        let props = WriterProperties::builder().build();
-        let mut writer = ArrowWriter::try_new(file, schema.clone(), Some(props))?;
-
-        for vhm in &self.iter()?.chunks(chunk_size) {
-            let chunk_data: Result<Vec<_>, _> = vhm.collect();
-            let chunk_data = chunk_data?;
-            
-            // 3. Build arrays
-            let mut arrays: Vec<ArrayRef> = Vec::new();
-            for col in &column_names {
-                let values: Vec<Option<f64>> = chunk_data.iter()
-                    .map(|row| row.get(col).copied())
-                    .collect();
-                arrays.push(Arc::new(Float64Array::from(values)) as ArrayRef);
-            }
+        track.to_parquet(filename, 2).unwrap();
+        let builder = ParquetRecordBatchReaderBuilder::try_new(File::open(out.path()).unwrap()).unwrap();
+        assert_eq!(builder.metadata().num_row_groups(), 3);
+        let rows: usize = builder.build().unwrap().map(|batch| batch.unwrap().num_rows()).sum();
+        assert_eq!(rows, 5);
+    }
 
+    #[test]
+    fn test_stream_to_parquet() {
+        let file = create_test_file(".strap", "@strap-units x m\nx 1.0\n\n# comment\nx 2.0 y 3.0\n");
+        let out = NamedTempFile::with_suffix(".parquet").unwrap();
+        let stats = StrapTrack::stream_to_parquet(file.path(), out.path().to_str().unwrap(), 1).unwrap();
+        assert_eq!(stats, ConversionStats { rows: 2, skipped: 3, invalid_timestamps: 0, duplicates: 0, lost: 0 });
+        assert_eq!(parquet_units(out.path()).unwrap()["x"], "m");
 
-            // 4. Build RecordBatch
-            let batch = RecordBatch::try_new(schema.clone(), arrays)?;
-            // 5. Write Parquet
-            writer.write(&batch)?;
-        }
-        writer.close()?;
-        println!("Sparse Parquet written!");
-        Ok(())
+        // a row of nulls per line without values unless they are skipped
+        let options = ParquetOptions { chunk_size: 2, skip_empty: false, ..Default::default() };
+        StrapTrack::new(file.path()).unwrap().to_parquet_with(out.path().to_str().unwrap(), &options).unwrap();
+        let batch = read_parquet(out.path());
+        assert_eq!(batch.num_rows(), 5);
+        assert_eq!(batch.column_by_name("x").unwrap().null_count(), 3);
     }
 
-}
+    #[test]
+    fn test_stream_to_parquet_late_columns() {
+        use arrow::array::ListArray;
+        let file = create_test_file(".strap", "x 1.0\nx 2.0 n 3\nn abc w 0.5,1.5\nw 2.0 flag true\n");
+        let out = NamedTempFile::with_suffix(".parquet").unwrap();
+        let stats = StrapTrack::stream_to_parquet(file.path(), out.path().to_str().unwrap(), 1).unwrap();
+        assert_eq!(stats.rows, 4);
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use std::io::Write;
-    use tempfile::NamedTempFile;
+        let batch = read_parquet(out.path());
+        let expected = StrapTrack::new(file.path()).unwrap().to_record_batch().unwrap();
+        assert_eq!(batch.schema().fields(), expected.schema().fields());
+        assert_eq!(batch.columns(), expected.columns());
+        let names = batch.column_by_name("n").unwrap().as_any().downcast_ref::<StringArray>().unwrap();
+        assert_eq!(names.iter().collect::<Vec<_>>(), vec![None, Some("3"), Some("abc"), None]);
+        let lists = batch.column_by_name("w").unwrap().as_any().downcast_ref::<ListArray>().unwrap();
+        assert_eq!(lists.value(3).as_any().downcast_ref::<Float64Array>().unwrap().values(), &[2.0]);
+    }
 
-    fn create_test_file(suffix: &str, content: &str) -> NamedTempFile {
-        let mut file = NamedTempFile::with_suffix(suffix).unwrap();
-        write!(file, "{}", content).unwrap();
-        file
+    #[test]
+    fn test_columns_rows_line_up() {
+        let file = create_test_file(".strap", "run A17\n# comment\nx 1.0\nx 2.0 weights 1,2\n");
+        let track = StrapTrack::new(file.path()).unwrap();
+        let out = NamedTempFile::with_suffix(".parquet").unwrap();
+        track.to_parquet(out.path().to_str().unwrap(), 2).unwrap();
+        let (parquet, _) = StrapColumns::from_parquet(out.path()).unwrap();
+        let columns = track.columns().unwrap();
+        let expected = [None, Some(1.0), Some(2.0)];
+        assert_eq!(track.get_column("x").unwrap(), expected);
+        assert_eq!(columns.get_column("x").unwrap(), expected);
+        assert_eq!(parquet.get_column("x").unwrap(), expected);
+        assert_eq!(columns.get_row(0).unwrap(), HashMap::new());
     }
 
     #[test]
-    fn test_parse_simple_line() {
-        let result = StrapTrack::parse_line("alice_sword 2.2 bob_bow 5.0", true);
-        assert_eq!(result.get("alice_sword"), Some(&2.2));
-        assert_eq!(result.get("bob_bow"), Some(&5.0));
-        let result = StrapTrack::parse_line("alice_sword 2.2 bob_bow 5.0", false);
-        // assert empty since no @strap prefix
-        assert!(result.is_empty());
+    fn test_to_parquet_head() {
+        use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+        let file = create_test_file(".strap", "x 1.0\nx 2.0\nx 3.0 y 1.0\n");
+        let out = NamedTempFile::with_suffix(".parquet").unwrap();
+        let options = ParquetOptions { head: Some(2), ..Default::default() };
+        let stats = StrapTrack::new(file.path()).unwrap().to_parquet_with(out.path().to_str().unwrap(), &options).unwrap();
+        assert_eq!(stats.rows, 2);
+        let builder = ParquetRecordBatchReaderBuilder::try_new(File::open(out.path()).unwrap()).unwrap();
+        assert_eq!(builder.schema().fields().len(), 2);
+
+        // rows the filter rejects don't count towards the head
+        let track = StrapTrack::new(file.path()).unwrap().with_filter(|row| row["x"] > 1.5);
+        let options = ParquetOptions { head: Some(1), skip_empty: false, ..Default::default() };
+        let stats = track.to_parquet_with(out.path().to_str().unwrap(), &options).unwrap();
+        assert_eq!(stats.rows, 1);
+        let x = read_parquet(out.path()).column_by_name("x").unwrap().clone();
+        assert_eq!(x.as_any().downcast_ref::<Float64Array>().unwrap().values(), &[2.0]);
     }
 
     #[test]
-    fn test_parse_strap_prefix() {
-        let result = StrapTrack::parse_line("@strap damage 15.0 attacker_alice 1.0", true);
-        assert_eq!(result.get("damage"), Some(&15.0));
-        assert_eq!(result.get("attacker_alice"), Some(&1.0));
-        let result = StrapTrack::parse_line("@strap damage 15.0 attacker_alice 1.0", false);
-        assert_eq!(result.get("damage"), Some(&15.0));
-        assert_eq!(result.get("attacker_alice"), Some(&1.0));
+    fn test_to_csv() {
+        let content = "@strap b 1.5 a 2.0\n# comment\n@strap \"x;y\" 3.0 run \"A 17\"\n";
+        let file = create_test_file(".txt", content);
+        let track = StrapTrack::new(file.path()).unwrap();
+        let out = NamedTempFile::with_suffix(".csv").unwrap();
+        assert_eq!(track.to_csv(out.path(), &CsvOptions::default()).unwrap(), 2);
+        assert_eq!(std::fs::read_to_string(out.path()).unwrap(), "a,b,run,x;y\n2,1.5,,\n,,A 17,3\n");
+
+        let mut csv = Vec::new();
+        let options = CsvOptions { delimiter: ';', precision: Some(2) };
+        track.write_csv(&mut csv, &options).unwrap();
+        assert_eq!(String::from_utf8(csv).unwrap(), "a;b;run;\"x;y\"\n2.00;1.50;;\n;;A 17;3.00\n");
     }
 
     #[test]
-    fn test_parse_strap1_prefix() {
-        let result = StrapTrack::parse_line("@strap1 line 5.0", true);
-        assert_eq!(result.get("line"), Some(&5.0));
-        let result = StrapTrack::parse_line("@strap1 line 5.0", false);
-        assert_eq!(result.get("line"), Some(&5.0));
+    fn test_to_ndjson() {
+        let content = "a 1.0 b -0.0\nnoise\nb 0.1 c 123456789.123456789 run A17\na 1e300 c -2.5e-310\n";
+        let track: StrapTrack = content.parse().unwrap();
+        let mut json = Vec::new();
+        assert_eq!(track.to_ndjson(&mut json, false).unwrap(), 3);
+        let json = String::from_utf8(json).unwrap();
+        assert_eq!(json.lines().next(), Some(r#"{"a":1.0,"b":-0.0}"#));
+        let parsed: Vec<HashMap<String, serde_json::Value>> = json.lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+        let rows: Vec<_> = track.iter_values().unwrap().map(Result::unwrap).filter(|row| !row.is_empty()).collect();
+        for (parsed, row) in parsed.iter().zip(&rows) {
+            assert_eq!(parsed.len(), row.len());
+            for (key, value) in row {
+                match value {
+                    StrapValue::Float(value) => assert_eq!(parsed[key].as_f64().unwrap().to_bits(), value.to_bits()),
+                    StrapValue::Text(text) => assert_eq!(parsed[key], *text),
+                    StrapValue::List(_) => unreachable!(),
+                }
+            }
+        }
+
+        let mut json = Vec::new();
+        track.to_ndjson(&mut json, true).unwrap();
+        let first = String::from_utf8(json).unwrap().lines().next().unwrap().to_string();
+        assert_eq!(first, r#"{"a":1.0,"b":-0.0,"c":null,"run":null}"#);
     }
 
     #[test]
-    fn test_parse_strap_with_metadata() {
-        let result = StrapTrack::parse_line("DATE TIME OR OTHER_METADATA @strap damage 15.0 attacker_alice 1.0 defender_bob 1.0", false);
-        assert_eq!(result.get("damage"), Some(&15.0));
-        assert_eq!(result.get("attacker_alice"), Some(&1.0));
-        assert_eq!(result.get("defender_bob"), Some(&1.0));
+    fn test_to_parquet_compression() {
+        use parquet::basic::Compression;
+        use parquet::file::reader::{FileReader, SerializedFileReader};
+        let content: String = (0..100).map(|i| format!("x {}\n", i)).collect();
+        let file = create_test_file(".strap", &content);
+        let out = NamedTempFile::with_suffix(".parquet").unwrap();
+        let options = ParquetOptions {
+            compression: "zstd:9".parse().unwrap(),
+            row_group_size: Some(40),
+            ..Default::default()
+        };
+        StrapTrack::new(file.path()).unwrap().to_parquet_with(out.path().to_str().unwrap(), &options).unwrap();
+        let reader = SerializedFileReader::new(File::open(out.path()).unwrap()).unwrap();
+        assert_eq!(reader.metadata().num_row_groups(), 3);
+        // the file has the codec but not its level
+        let column = reader.metadata().row_group(0).column(0);
+        assert!(matches!(column.compression(), Compression::ZSTD(_)));
+
+        assert_eq!("gzip".parse(), Ok(ParquetCompression::Gzip(6)));
+        assert_eq!("none".parse(), Ok(ParquetCompression::Uncompressed));
+        assert!("snappy:3".parse::<ParquetCompression>().is_err());
+        let options = ParquetOptions { compression: ParquetCompression::Zstd(99), ..Default::default() };
+        assert!(StrapTrack::new(file.path()).unwrap().to_parquet_with(out.path().to_str().unwrap(), &options).is_err());
     }
 
     #[test]
-    fn test_parse_empty_line() {
-        let result = StrapTrack::parse_line("", true);
-        assert!(result.is_empty());
+    fn test_to_parquet_statistics() {
+        use parquet::basic::Encoding;
+        use parquet::file::reader::{FileReader, SerializedFileReader};
+        let content: String = (0..100).map(|i| format!("status {}\n", i % 3)).collect();
+        let file = create_test_file(".strap", &content);
+        let out = NamedTempFile::with_suffix(".parquet").unwrap();
+        let track = StrapTrack::new(file.path()).unwrap();
+        track.to_parquet_with(out.path().to_str().unwrap(), &ParquetOptions::default()).unwrap();
+        let reader = SerializedFileReader::new(File::open(out.path()).unwrap()).unwrap();
+        let column = reader.metadata().row_group(0).column(0);
+        let statistics = column.statistics().unwrap();
+        // a zero minimum is written as -0.0, see the parquet format spec
+        assert_eq!(statistics.min_bytes_opt(), Some((-0.0f64).to_le_bytes().as_slice()));
+        assert_eq!(statistics.max_bytes_opt(), Some(2.0f64.to_le_bytes().as_slice()));
+        assert!(column.encodings().contains(&Encoding::RLE_DICTIONARY));
+
+        let options = ParquetOptions { dictionary: false, statistics: false, ..Default::default() };
+        track.to_parquet_with(out.path().to_str().unwrap(), &options).unwrap();
+        let reader = SerializedFileReader::new(File::open(out.path()).unwrap()).unwrap();
+        let column = reader.metadata().row_group(0).column(0);
+        assert!(column.statistics().is_none_or(|statistics| statistics.min_bytes_opt().is_none()));
+        assert!(!column.encodings().contains(&Encoding::RLE_DICTIONARY));
     }
 
     #[test]
-    fn test_parse_whitespace_only() {
-        let result = StrapTrack::parse_line("   \t  ",true);
-        assert!(result.is_empty());
+    fn test_group_by_binned() {
+        let track: StrapTrack = "x 0 y 1\nx 1 y 2\nx 2 y 4\nx 4 y 8\nx 3\ny 5\nx 5 y 16\n".parse().unwrap();
+        let (binned, skipped) = track.group_by_binned("x", 2, Some((0.0, 4.0)), "y", Aggregation::Mean).unwrap();
+        assert_eq!(skipped, 2);
+        // x 5 is out of range, x 4 is in the last bin
+        assert_eq!(binned, vec![(1.0, 2.0, 1.5, 2), (3.0, 2.0, 6.0, 2)]);
+        let (binned, _) = track.group_by_binned("x", 5, None, "y", Aggregation::Sum).unwrap();
+        assert_eq!(binned.iter().map(|bin| bin.2).collect::<Vec<_>>(), vec![1.0, 2.0, 4.0, 0.0, 24.0]);
+        assert!(track.group_by_binned("z", 2, None, "y", Aggregation::Count).unwrap().0.is_empty());
     }
 
     #[test]
-    fn test_parse_odd_number_tokens() {
-        let result = StrapTrack::parse_line("key1 1.0 key2",true);
-        assert_eq!(result.get("key1"), Some(&1.0));
-        assert!(!result.contains_key("key2"));
-        let result = StrapTrack::parse_line("key1 1.0 key2",false);
-        assert!(result.is_empty());
+    fn test_column_aggregates() {
+        let track: StrapTrack = "x 1 y nan\nz 2\nx -3 y nan\nx 5\n".parse().unwrap();
+        assert_eq!(track.column_count("x").unwrap(), 3);
+        assert_eq!(track.column_sum("x").unwrap(), Some(3.0));
+        assert_eq!(track.column_mean("x").unwrap(), Some(1.0));
+        assert_eq!(track.column_minmax("x").unwrap(), Some((-3.0, 5.0)));
+
+        // absent
+        assert_eq!(track.column_count("w").unwrap(), 0);
+        assert_eq!(track.column_sum("w").unwrap(), None);
+        assert_eq!(track.column_mean("w").unwrap(), None);
+        assert_eq!(track.column_minmax("w").unwrap(), None);
+
+        // all NaN
+        assert_eq!(track.column_count("y").unwrap(), 2);
+        assert!(track.column_sum("y").unwrap().unwrap().is_nan());
+        assert!(track.column_mean("y").unwrap().unwrap().is_nan());
+        assert_eq!(track.column_minmax("y").unwrap(), None);
     }
 
     #[test]
-    fn test_parse_invalid_float() {
-        let result = StrapTrack::parse_line("key1 invalid_float key2 2.0", true);
-        assert!(!result.contains_key("key1"));
-        assert_eq!(result.get("key2"), Some(&2.0));
-        let result = StrapTrack::parse_line("key1 invalid_float key2 2.0", false);
-        assert!(result.is_empty());
+    fn test_get_column_aligned() {
+        let track: StrapTrack = "a 1 b 2\n# comment\nb 4\na 3 b 8 name x\n".parse().unwrap();
+        let a = track.get_column("a").unwrap();
+        let b = track.get_column("b").unwrap();
+        let ratios: Vec<_> = a.iter().zip(&b).map(|(a, b)| Some(a.as_ref()? / b.as_ref()?)).collect();
+        assert_eq!(ratios, vec![Some(0.5), None, Some(0.375)]);
+        assert_eq!(track.get_column_dense("a").unwrap(), vec![1.0, 3.0]);
+        assert_eq!(track.get_column("name").unwrap(), vec![None, None, None]);
+        assert!(track.get_column("c").is_err());
+        assert!(track.get_column_dense("c").is_err());
     }
 
     #[test]
-    fn test_iterator() {
-        let content = "alice_sword 2.2 bob_bow 5.0\ndamage 2.0 attacker_alice 1.0\n";
+    fn test_timestamp_columns() {
+        let content = "ts 1700000000.1234 x 1.0 start_time 1e9\nts 1700000001 x 2.0 start_time 2\nts 1e300 x 3.0\n";
         let file = create_test_file(".strap", content);
         let track = StrapTrack::new(file.path()).unwrap();
-        
-        let rows: Result<Vec<_>, _> = track.iter().unwrap().collect();
-        let rows = rows.unwrap();
-        
-        assert_eq!(rows.len(), 2);
-        assert_eq!(rows[0].get("alice_sword"), Some(&2.2));
-        assert_eq!(rows[1].get("damage"), Some(&2.0));
+        // 1e300 is no epoch second, nor is 2
+        assert!(track.detect_timestamp_columns().unwrap().is_empty());
+        let epochs: StrapTrack = "ts 1700000000.1234 x 1.0 start_time 1e9\nts 1700000001 x 2.0 start_time 2\n".parse().unwrap();
+        assert_eq!(epochs.detect_timestamp_columns().unwrap(), vec!["ts"]);
+
+        let track = track.with_timestamp_columns(&["ts"], TimeUnit::Millisecond);
+        let schema = track.schema().unwrap();
+        assert_eq!(schema.field_with_name("ts").unwrap().data_type(), &DataType::Timestamp(TimeUnit::Millisecond, None));
+        assert_eq!(schema.field_with_name("x").unwrap().data_type(), &DataType::Float64);
+
+        let out = NamedTempFile::with_suffix(".parquet").unwrap();
+        let stats = track.to_parquet_with(out.path().to_str().unwrap(), &ParquetOptions::default()).unwrap();
+        assert_eq!((stats.rows, stats.invalid_timestamps), (3, 1));
+        let batch = track.to_record_batch().unwrap();
+        let ts = batch.column_by_name("ts").unwrap().as_any().downcast_ref::<arrow::array::TimestampMillisecondArray>().unwrap();
+        assert_eq!(ts.iter().collect::<Vec<_>>(), vec![Some(1_700_000_000_123), Some(1_700_000_001_000), None]);
     }
 
     #[test]
-    fn test_get_column_names() {
-        let content = "a 1.0 b 2.0\nc 3.0 d 4.0\na 5.0 e 6.0\n";
-        let file = create_test_file(".strap", content);
+    fn test_to_parquet_threads() {
+        let content: String = (0..1000).map(|i| if i % 10 == 3 { "# comment\n".to_string() } else { format!("x {} y{} 0.5\n", i, i % 3) }).collect();
+        let file = create_test_file(".strap", &content);
         let track = StrapTrack::new(file.path()).unwrap();
-        
-        let mut columns = track.get_column_names().unwrap();
-        columns.sort();
-        
-        assert_eq!(columns, vec!["a", "b", "c", "d", "e"]);
+        let sequential = NamedTempFile::with_suffix(".parquet").unwrap();
+        let threaded = NamedTempFile::with_suffix(".parquet").unwrap();
+        let options = ParquetOptions { chunk_size: 7, row_index: Some(ROW_INDEX_COLUMN.to_string()), ..Default::default() };
+        let stats = track.to_parquet_with(sequential.path().to_str().unwrap(), &options).unwrap();
+        let threaded_stats = track.to_parquet_with(threaded.path().to_str().unwrap(), &ParquetOptions { threads: true, ..options }).unwrap();
+        assert_eq!(threaded_stats, stats);
+        assert_eq!((stats.rows, stats.skipped), (900, 100));
+        assert_eq!(std::fs::read(threaded.path()).unwrap(), std::fs::read(sequential.path()).unwrap());
+        let (columns, _) = StrapColumns::from_parquet(threaded.path()).unwrap();
+        assert_eq!(columns.len(), 900);
+        assert_eq!(columns.get_row(3).unwrap()["x"], 4.0);
+        assert_eq!(columns.get_row(899).unwrap()["y0"], 0.5);
+
+        // a parse error stops the pipeline
+        let file = create_test_file(".strap", &format!("{}x 1 y\n", content));
+        let track = StrapTrack::new(file.path()).unwrap().with_parse_mode(ParseMode::Strict);
+        let options = ParquetOptions { threads: true, ..Default::default() };
+        let error = track.to_parquet_with(threaded.path().to_str().unwrap(), &options).unwrap_err();
+        assert!(matches!(error, StrapError::Parse { line: 1001, .. }), "{:?}", error);
     }
 
     #[test]
-    fn test_filter_rows() {
-        let content = "type 1.0 value 10.0\ntype 2.0 value 20.0\ntype 1.0 value 15.0\n";
+    fn test_to_parquet_round_trip() {
+        let content = "a 1.5 b 2 label x\na -0.0 label y\n# comment\na 1e300 b -7 label z\n";
         let file = create_test_file(".strap", content);
         let track = StrapTrack::new(file.path()).unwrap();
-        
-        let filtered = track.filter_rows(|row| {
-            row.get("type") == Some(&1.0)
-        }).unwrap();
-        
-        assert_eq!(filtered.len(), 2);
-        assert_eq!(filtered[0].get("value"), Some(&10.0));
-        assert_eq!(filtered[1].get("value"), Some(&15.0));
+        let out = NamedTempFile::with_suffix(".parquet").unwrap();
+        let options = ParquetOptions {
+            chunk_size: 2,
+            types: HashMap::from([("b".to_string(), DataType::Int64)]),
+            ..Default::default()
+        };
+        track.to_parquet_with(out.path().to_str().unwrap(), &options).unwrap();
+        let (columns, skipped) = StrapColumns::from_parquet(out.path()).unwrap();
+        assert_eq!(skipped, ["label"]);
+        assert_eq!(columns.column_names(), ["a", "b"]);
+        assert_eq!(columns.len(), 3);
+        let expected = track.columns().unwrap();
+        for name in ["a", "b"] {
+            let bits = |values: &[Option<f64>]| values.iter().map(|v| v.map(f64::to_bits)).collect::<Vec<_>>();
+            assert_eq!(bits(columns.get_column(name).unwrap()), bits(expected.get_column(name).unwrap()));
+        }
+        assert_eq!(columns.get_row(1).unwrap(), HashMap::from([("a".to_string(), -0.0)]));
     }
 
     #[test]
-    fn test_aggregate() {
-        let content = "@strap value 10.0\n@strap value 20.0\n@strap value 15.0\n";
-        let file = create_test_file(".log", content);
+    fn test_to_record_batches() {
+        let file = create_test_file(".strap", "x 1.0\n# comment\nx 2.0 y 3.0\nx 4.0\nx 5.0\n");
         let track = StrapTrack::new(file.path()).unwrap();
-        
-        let sum = track.aggregate(0.0, |acc, row| {
-            acc + row.get("value").unwrap_or(&0.0)
-        }).unwrap();
-        
-        assert_eq!(sum, 45.0);
+        let mut batches = track.to_record_batches(2).unwrap();
+        assert_eq!(batches.schema(), track.schema().unwrap());
+        let collected = batches.by_ref().collect::<Result<Vec<_>, _>>().unwrap();
+        assert_eq!(collected.iter().map(RecordBatch::num_rows).collect::<Vec<_>>(), [2, 2]);
+        // the last batch has no y values but still the y column
+        assert!(collected.iter().all(|batch| batch.schema() == track.schema().unwrap()));
+        assert_eq!(collected[1].column_by_name("y").unwrap().null_count(), 2);
+        assert_eq!(batches.skipped(), 1);
+        assert!(track.to_record_batches(0).is_err());
     }
 
     #[test]
-    fn test_mixed_strap_formats() {
-        let content = "@strap a 1.0\n@strap1 b 2.0\nNOISE @strap c 3.0\nregular 4.0\n";
-        let file = create_test_file(".strap", content);
+    fn test_to_arrow_ipc() {
+        use arrow::ipc::reader::FileReader;
+        let file = create_test_file(".strap", "@strap-units x m\nx 1.0\nx 2.0 y 3.0\nx 4.0\n");
+        let out = NamedTempFile::with_suffix(".arrow").unwrap();
+        let stats = StrapTrack::new(file.path()).unwrap().to_arrow_ipc(out.path(), 2).unwrap();
+        assert_eq!(stats.rows, 3);
+        let reader = FileReader::try_new(File::open(out.path()).unwrap(), None).unwrap();
+        assert_eq!(reader.schema(), StrapTrack::new(file.path()).unwrap().schema().unwrap());
+        let batches = reader.collect::<Result<Vec<_>, _>>().unwrap();
+        assert_eq!(batches.iter().map(RecordBatch::num_rows).collect::<Vec<_>>(), [2, 1]);
+
+        let empty = create_test_file(".strap", "");
+        StrapTrack::new(empty.path()).unwrap().to_arrow_ipc(out.path(), 2).unwrap();
+        let reader = FileReader::try_new(File::open(out.path()).unwrap(), None).unwrap();
+        assert!(reader.schema().fields().is_empty());
+        assert_eq!(reader.count(), 0);
+    }
+
+    #[test]
+    fn test_text_values() {
+        use arrow::array::Array;
+        let content = "@strap run A17 energy 13.6\n@strap run 18 energy 12.1\n@strap energy 11.0\n@strap run A18\n";
+        let track: StrapTrack = content.parse().unwrap();
+        assert_eq!(track.get_column("energy").unwrap(), vec![Some(13.6), Some(12.1), Some(11.0), None]);
+        assert_eq!(track.get_column("run").unwrap(), vec![None, Some(18.0), None, None]);
+        assert_eq!(
+            track.get_text_column("run").unwrap(),
+            vec![Some("A17".to_string()), Some("18".to_string()), None, Some("A18".to_string())]
+        );
+        // numeric views ignore the text
+        assert_eq!(track.describe().unwrap().0["run"].count, 1);
+
+        let batch = track.to_record_batch().unwrap();
+        assert_eq!(batch.num_rows(), 4);
+        let run = batch.column_by_name("run").unwrap();
+        assert_eq!(run.data_type(), &DataType::Utf8);
+        assert_eq!(run.as_any().downcast_ref::<StringArray>().unwrap().value(0), "A17");
+
+        let out = NamedTempFile::with_suffix(".parquet").unwrap();
+        let options = ParquetOptions {
+            types: HashMap::from([("run".to_string(), DataType::Int32)]),
+            ..Default::default()
+        };
+        let error = track.to_parquet_with(out.path().to_str().unwrap(), &options).unwrap_err();
+        assert!(error.to_string().contains("not a number"), "{}", error);
+    }
+
+    #[test]
+    fn test_text_fallback() {
+        let content = "@strap run A17\n@strap date 2024-01-01 id 17A version 1.2.3 x 1\n@strap inf 1 nan 2 -inf 3\n";
+        let track: StrapTrack = content.parse().unwrap();
+        // a row with only text is a row
+        assert_eq!(track.get_text_column("run").unwrap(), vec![Some("A17".to_string()), None, None]);
+        // text starting like a number
+        for (column, text) in [("date", "2024-01-01"), ("id", "17A"), ("version", "1.2.3")] {
+            assert_eq!(track.get_text_column(column).unwrap()[1].as_deref(), Some(text));
+        }
+        assert_eq!(track.get_column("x").unwrap(), vec![None, Some(1.0), None]);
+        assert_eq!(track.dropped_pairs().unwrap(), 0);
+        // keys that parse as non-finite numbers
+        assert_eq!(track.get_column("inf").unwrap(), vec![None, None, Some(1.0)]);
+        assert_eq!(track.get_column("nan").unwrap(), vec![None, None, Some(2.0)]);
+        assert_eq!(track.get_column("-inf").unwrap(), vec![None, None, Some(3.0)]);
+        assert_eq!(StrapTrack::malformed_pairs("1.5 x -2 y", true, &[STRAP_PREFIX]), vec![
+            ("1.5".to_string(), "number as key"),
+            ("-2".to_string(), "number as key"),
+        ]);
+    }
+
+    #[test]
+    fn test_to_parquet_row_index() {
+        use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+        let file = create_test_file(".strap", "x 1.0\nx\n\nx 2.0\n");
         let track = StrapTrack::new(file.path()).unwrap();
-        
-        let rows: Result<Vec<_>, _> = track.iter().unwrap().collect();
-        let rows = rows.unwrap();
-        
-        assert_eq!(rows.len(), 4);
-        assert_eq!(rows[0].get("a"), Some(&1.0));
-        assert_eq!(rows[1].get("b"), Some(&2.0));
-        assert_eq!(rows[2].get("c"), Some(&3.0));
-        assert_eq!(rows[3].get("regular"), Some(&4.0));
+        let out = NamedTempFile::with_suffix(".parquet").unwrap();
+        let options = ParquetOptions { chunk_size: 1, row_index: Some(ROW_INDEX_COLUMN.to_string()), ..Default::default() };
+        track.to_parquet_with(out.path().to_str().unwrap(), &options).unwrap();
+        let batches = ParquetRecordBatchReaderBuilder::try_new(File::open(out.path()).unwrap()).unwrap()
+            .build().unwrap()
+            .collect::<Result<Vec<_>, _>>().unwrap();
+        let lines: Vec<u64> = batches.iter()
+            .flat_map(|batch| {
+                let index = batch.column_by_name(ROW_INDEX_COLUMN).unwrap();
+                index.as_any().downcast_ref::<UInt64Array>().unwrap().values().to_vec()
+            })
+            .collect();
+        assert_eq!(lines, vec![1, 4]);
+
+        let options = ParquetOptions { row_index: Some("x".to_string()), ..Default::default() };
+        assert!(track.to_parquet_with(out.path().to_str().unwrap(), &options).is_err());
     }
 
     #[test]
-    fn test_strap_with_digits() {
-        let result = StrapTrack::parse_line("@strap2 key 1.0", false);
-        assert_eq!(result.get("key"), Some(&1.0));
+    fn test_to_parquet_type_override_fails() {
+        let out = NamedTempFile::with_suffix(".parquet").unwrap();
+        let options = ParquetOptions {
+            types: HashMap::from([("run".to_string(), DataType::Int32)]),
+            ..Default::default()
+        };
+        let file = create_test_file(".strap", "run 1e300\n");
+        let error = StrapTrack::new(file.path()).unwrap()
+            .to_parquet_with(out.path().to_str().unwrap(), &options).unwrap_err();
+        assert!(error.to_string().contains("column run"), "{}", error);
+
+        let file = create_test_file(".strap", "run 1\n# comment\nrun 1.5\n");
+        let error = StrapTrack::new(file.path()).unwrap()
+            .to_parquet_with(out.path().to_str().unwrap(), &options).unwrap_err();
+        assert!(error.to_string().contains("not an integer"), "{}", error);
+        assert!(matches!(error, StrapError::Parse { line: 3, ref content, .. } if content == "run 1.5"), "{:?}", error);
     }
 
     #[test]
-    fn test_scientific_notation() {
-        let result = StrapTrack::parse_line("temp 3.14e2 pressure 1.01e5", true);
-        assert_eq!(result.get("temp"), Some(&314.0));
-        assert_eq!(result.get("pressure"), Some(&101000.0));
+    fn test_units() {
+        let content = "@strap-units pt GeV eta 1\npt 10.0 eta 0.5\n@strap-units ts s\n";
+        let file = create_test_file(".strap", content);
+        let track = StrapTrack::new(file.path()).unwrap();
+        // the header is not a row, later declarations are ignored
+        assert_eq!(track.describe().unwrap().1, 1);
+        let units = track.units().unwrap();
+        assert_eq!(units, HashMap::from([("pt".to_string(), "GeV".to_string()), ("eta".to_string(), "1".to_string())]));
+
+        let out = NamedTempFile::with_suffix(".parquet").unwrap();
+        track.to_parquet(out.path().to_str().unwrap(), 10).unwrap();
+        let stored = parquet_units(out.path()).unwrap();
+        assert_eq!(stored, units);
+        assert_eq!(label_with_unit("pt", stored.get("pt").map(String::as_str)), "pt [GeV]");
+        assert_eq!(label_with_unit("eta", stored.get("eta").map(String::as_str)), "eta");
+        assert_eq!(label_with_unit("phi", stored.get("phi").map(String::as_str)), "phi");
+
+        // without a header nothing changes
+        let file = create_test_file(".strap", "pt 10.0\n");
+        let track = StrapTrack::new(file.path()).unwrap();
+        assert!(track.units().unwrap().is_empty());
+        assert!(track.schema().unwrap().field(0).metadata().is_empty());
     }
 
     #[test]
-    fn test_negative_values() {
-        let result = StrapTrack::parse_line("deficit -42.5 surplus 100.0", true);
-        assert_eq!(result.get("deficit"), Some(&-42.5));
-        assert_eq!(result.get("surplus"), Some(&100.0));
+    fn test_compressions() {
+        let content = "x 1.0\nx 2.0\n";
+        let gz = {
+            let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+            encoder.write_all(content.as_bytes()).unwrap();
+            encoder.finish().unwrap()
+        };
+        let zst = zstd::stream::encode_all(content.as_bytes(), 3).unwrap();
+        let bz2 = {
+            let mut encoder = bzip2::write::BzEncoder::new(Vec::new(), bzip2::Compression::default());
+            encoder.write_all(content.as_bytes()).unwrap();
+            encoder.finish().unwrap()
+        };
+        for (suffix, bytes) in [(".strap.gz", gz), (".strap.zst", zst.clone()), (".strap.bz2", bz2)] {
+            let mut file = NamedTempFile::with_suffix(suffix).unwrap();
+            file.write_all(&bytes).unwrap();
+            let track = StrapTrack::new(file.path()).unwrap();
+            assert_eq!(track.describe().unwrap().0["x"].sum, 3.0, "{}", suffix);
+        }
+
+        // the extension can be overridden
+        let mut file = NamedTempFile::with_suffix(".strap").unwrap();
+        file.write_all(&zst).unwrap();
+        let track = StrapTrack::new_with_compression(file.path(), InputCompression::Zstd).unwrap();
+        assert_eq!(track.describe().unwrap().1, 2);
+        assert_eq!("bz2".parse::<InputCompression>().unwrap(), InputCompression::Bzip2);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_truncated_zstd() {
+        let content = "x 1.0\n".repeat(1000);
+        let zst = zstd::stream::encode_all(content.as_bytes(), 3).unwrap();
+        let mut file = NamedTempFile::with_suffix(".strap.zst").unwrap();
+        file.write_all(&zst[..zst.len() / 2]).unwrap();
+        let error = StrapTrack::new(file.path()).unwrap().describe().unwrap_err();
+        assert!(matches!(&error, StrapError::Io(e) if e.kind() == std::io::ErrorKind::InvalidData), "{:?}", error);
+        assert!(error.to_string().contains("zstd input after"), "{}", error);
+    }
+}
@@ -0,0 +1,85 @@
+//! Python module `strap`, built with maturin (see pyproject.toml)
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use arrow::pyarrow::ToPyArrow;
+use pyo3::exceptions::{PyIOError, PyValueError};
+use pyo3::prelude::*;
+
+use crate::{StrapError, StrapTrack, StrapValueIterator};
+
+/// ValueError for lines that don't parse, OSError for everything else
+fn py_error(e: StrapError) -> PyErr {
+    match e {
+        StrapError::Parse { .. } => PyValueError::new_err(e.to_string()),
+        e => PyIOError::new_err(e.to_string()),
+    }
+}
+
+/// Lazy/streaming parser for STRAP protocol files
+#[pyclass(name = "StrapTrack")]
+struct PyStrapTrack {
+    inner: StrapTrack,
+}
+
+#[pymethods]
+impl PyStrapTrack {
+    #[new]
+    fn new(path: PathBuf) -> PyResult<Self> {
+        Ok(Self { inner: StrapTrack::new(path).map_err(py_error)? })
+    }
+
+    /// Sorted column names of all rows
+    fn column_names(&self) -> PyResult<Vec<String>> {
+        self.inner.get_column_names().map_err(py_error)
+    }
+
+    #[pyo3(signature = (path, chunk_size = 1000))]
+    fn to_parquet(&self, path: &str, chunk_size: usize) -> PyResult<()> {
+        self.inner.to_parquet(path, chunk_size).map_err(py_error)
+    }
+
+    /// All rows as a pyarrow Table, passed through the Arrow C data interface
+    fn to_arrow(&self, py: Python<'_>) -> PyResult<PyObject> {
+        let batch = self.inner.to_record_batch().map_err(py_error)?.to_pyarrow(py)?;
+        let table = py.import("pyarrow")?
+            .getattr("Table")?
+            .call_method1("from_batches", (vec![batch],))?;
+        Ok(table.unbind())
+    }
+
+    /// Iterator over the numeric values of the rows as dicts, reading the file lazily.
+    /// Lines without values are no rows, like in `to_parquet`.
+    fn rows(&self) -> PyResult<PyRows> {
+        Ok(PyRows { inner: self.inner.iter_values().map_err(py_error)? })
+    }
+}
+
+#[pyclass(name = "Rows", unsendable)]
+struct PyRows {
+    inner: StrapValueIterator,
+}
+
+#[pymethods]
+impl PyRows {
+    fn __iter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    fn __next__(&mut self) -> PyResult<Option<HashMap<String, f64>>> {
+        for row in self.inner.by_ref() {
+            let row = row.map_err(|e| py_error(e.into()))?;
+            if !row.is_empty() {
+                return Ok(Some(StrapTrack::numeric(row)));
+            }
+        }
+        Ok(None)
+    }
+}
+
+#[pymodule]
+fn strap(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<PyStrapTrack>()?;
+    m.add_class::<PyRows>()?;
+    Ok(())
+}
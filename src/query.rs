@@ -0,0 +1,2791 @@
+use core::hash;
+use std::time::SystemTime;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::{self, Display, Formatter, Result as FmtResult};
+use std::ops::Deref;
+use std::path::PathBuf;
+
+use duckdb::{Connection, params};
+use duckdb::ffi::ErrorCode;
+use serde::{Deserialize, Serialize};
+use strum_macros::{Display, EnumIter};
+
+#[derive(Hash, Eq, PartialEq, Clone, Serialize, Deserialize)]
+pub struct SQLFilter {
+    // Each Vec<SQLFilterComparison> is an OR group
+    // All groups must be satisfied (AND between groups)
+    pub conditions  : Vec<Vec<SQLFilterComparison >>,
+}
+
+impl SQLFilter {
+    pub fn is_empty(&self) -> bool {
+        self.conditions.is_empty() || self.conditions.iter().all(|group| group.is_empty())
+    }
+
+    /// Check that all numbers are valid, needed for filters not built through the GUI
+    pub fn validate(&self) -> Result<(), String> {
+        for comparison in self.conditions.iter().flatten() {
+            for value in [&comparison.left, &comparison.right] {
+                if let SQLFilterComparisonValue::Number(num) = value
+                    && num.parse::<f64>().is_err()
+                {
+                    return Err(format!("Invalid number in filter: {}", num));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Columns referenced on either side of any comparison, without duplicates
+    pub fn columns(&self) -> Vec<&ParsedString> {
+        let mut columns: Vec<&ParsedString> = vec![];
+        for comparison in self.conditions.iter().flatten() {
+            for value in [&comparison.left, &comparison.right] {
+                if let SQLFilterComparisonValue::Column(column) = value
+                    && !columns.contains(&column)
+                {
+                    columns.push(column);
+                }
+            }
+        }
+        columns
+    }
+
+    /// Columns of the filter that are not in `available`
+    pub fn missing_columns(&self, available: &[ParsedString]) -> Vec<ParsedString> {
+        self.columns().into_iter()
+            .filter(|c| !available.contains(c))
+            .cloned()
+            .collect()
+    }
+
+    /// AND the groups of `other` to this filter
+    pub fn merge(&mut self, other: &SQLFilter) {
+        self.conditions.extend(other.conditions.iter().filter(|group| !group.is_empty()).cloned());
+    }
+
+    pub fn to_sql(&self) -> String {
+        self.conditions.iter()
+        .filter(|group| !group.is_empty())
+        .map(|group| {
+            "(".to_string()
+            + group.iter().map(|c| c.to_sql()).collect::<Vec<_>>().join(" OR ").as_str()
+            + ")"
+        }).collect::<Vec<_>>().join(" AND ")
+    }
+
+    pub fn to_sql_and_prefix(&self) -> String {
+        let mut query = String::new();
+        if !self.is_empty() {
+            query.push_str(" AND ");
+            query.push_str(self.to_sql().as_str());
+        }
+        query
+    }
+
+    pub fn to_sql_where_prefix(&self) -> String {
+        let mut query = String::new();
+        if !self.is_empty() {
+            query.push_str(" WHERE ");
+            query.push_str(self.to_sql().as_str());
+        }
+        query
+    }
+}
+
+#[derive(Hash, Eq, PartialEq, Clone, Serialize, Deserialize)]
+pub struct SQLFilterComparison {
+    pub left: SQLFilterComparisonValue,
+    pub comparison: SQLFilterComparisonOperation,
+    pub right: SQLFilterComparisonValue,
+}
+
+#[derive(Hash, Eq, PartialEq, Clone, Serialize, Deserialize)]
+pub enum SQLFilterComparisonValue {
+    Column(ParsedString),
+    Number(String),
+}
+
+impl fmt::Display for SQLFilterComparisonValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Column(col) => write!(f, "{}", col),
+            Self::Number(num) => write!(f, "{}", num),
+        }
+    }
+}
+
+impl SQLFilterComparison {
+    pub fn to_sql(&self) -> String {
+        format!("{} {} {}", self.left, self.comparison, self.right)
+    }
+}
+
+#[derive(Hash, Eq, PartialEq, Clone, EnumIter, Serialize, Deserialize)]
+pub enum SQLFilterComparisonOperation {
+    Equal,
+    NotEqual,
+    GreaterThan,
+    LessThan,
+    GreaterThanOrEqual,
+    LessThanOrEqual,
+}
+
+impl fmt::Display for SQLFilterComparisonOperation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            Self::Equal => "=",
+            Self::NotEqual => "!=",
+            Self::GreaterThan => ">",
+            Self::LessThan => "<",
+            Self::GreaterThanOrEqual => ">=",
+            Self::LessThanOrEqual => "<=",
+        };
+        write!(f, "{s}")
+    }
+}
+
+
+/// Named filters that can be applied to any curve, stored as JSON
+#[derive(Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FilterPresets {
+    pub presets: BTreeMap<String, SQLFilter>,
+}
+
+impl FilterPresets {
+    pub fn to_json(&self) -> serde_json::Result<String> {
+        serde_json::to_string_pretty(self)
+    }
+
+    /// Column names are checked while parsing, numbers afterwards
+    pub fn from_json(json: &str) -> serde_json::Result<Self> {
+        let presets: Self = serde_json::from_str(json)?;
+        for (name, filter) in &presets.presets {
+            filter.validate()
+                .map_err(|e| <serde_json::Error as serde::de::Error>::custom(format!("preset {}: {}", name, e)))?;
+        }
+        Ok(presets)
+    }
+
+    pub fn rename(&mut self, from: &str, to: &str) -> bool {
+        if from == to || self.presets.contains_key(to) {
+            return false;
+        }
+        match self.presets.remove(from) {
+            Some(filter) => {
+                self.presets.insert(to.to_string(), filter);
+                true
+            },
+            None => false,
+        }
+    }
+}
+
+// Serialized as the plain name and validated again when deserialized
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct ParsedString(String);
+
+impl TryFrom<String> for ParsedString {
+    type Error = String;
+
+    fn try_from(name: String) -> Result<Self, Self::Error> {
+        if name.contains(',') {
+            ParsedString::parse_files(&name.split(',').collect::<Vec<_>>()).map_err(|e| e.to_string())
+        } else if ParsedString::is_glob(&name) {
+            ParsedString::parse_glob(&name).map_err(|e| e.to_string())
+        } else {
+            ParsedString::parse(&name).map_err(|e| e.to_string())
+        }
+    }
+}
+
+impl From<ParsedString> for String {
+    fn from(parsed: ParsedString) -> Self {
+        parsed.files().join(",")
+    }
+}
+
+impl Deref for ParsedString {
+    type Target = str;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+impl Display for ParsedString {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        write!(f, "{}", self.0)
+    }
+}
+impl ParsedString {
+    pub fn parse(name: &str) -> duckdb::Result<ParsedString> {
+        // Allow only letters, numbers, slash, double dot and underscores
+        if name.is_empty() {
+            return Err(duckdb::Error::InvalidParameterName("Identifier cannot be empty".to_owned()));
+        }
+
+        if !name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || ' ' == c|| c == '-' || c == '_' || c == '/' || c == '.' || c == ':')
+        {
+            return Err(duckdb::Error::InvalidParameterName(format!("Invalid identifier: {}", name)));
+        }
+
+        // Safe: return the identifier as-is
+        Ok(Self("\"".to_string() + name + "\""))
+    }
+
+    /// Whether `name` is a glob pattern rather than a single file
+    pub fn is_glob(name: &str) -> bool {
+        name.contains(['*', '?', '['])
+    }
+
+    // same characters as parse plus the glob ones, quotes and commas can't appear
+    fn is_valid_path(path: &str) -> bool {
+        !path.is_empty() && path
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || ' ' == c|| c == '-' || c == '_' || c == '/' || c == '.' || c == ':'
+                || c == '*' || c == '?' || c == '[' || c == ']')
+    }
+
+    /// Scan of all parquet files matching `glob` as one table, columns missing in a file are NULL
+    pub fn parse_glob(glob: &str) -> duckdb::Result<ParsedString> {
+        if !Self::is_valid_path(glob) {
+            return Err(duckdb::Error::InvalidPath(glob.into()));
+        }
+        Ok(Self(format!("read_parquet('{}', union_by_name = true)", glob)))
+    }
+
+    /// Scan of several files, globs or directories as one table.
+    /// The list is sorted, so the same files in any order give the same table.
+    pub fn parse_files(files: &[&str]) -> duckdb::Result<ParsedString> {
+        let mut files: Vec<String> = files.iter()
+            .map(|file| if std::path::Path::new(file).is_dir() {
+                format!("{}/*.parquet", file.trim_end_matches('/'))
+            } else {
+                file.to_string()
+            })
+            .collect();
+        files.sort();
+        files.dedup();
+        match files.as_slice() {
+            [] => Err(duckdb_failure(ErrorCode::ApiMisuse, "File list cannot be empty".to_owned())),
+            [file] => Self::parse_table(file),
+            _ => {
+                if let Some(file) = files.iter().find(|f| !Self::is_valid_path(f)) {
+                    return Err(duckdb::Error::InvalidPath(file.into()));
+                }
+                let list = files.iter().map(|f| format!("'{}'", f)).collect::<Vec<_>>().join(", ");
+                Ok(Self(format!("read_parquet([{}], union_by_name = true)", list)))
+            }
+        }
+    }
+
+    /// Table of a file, a glob or a directory, which stands for all parquet files in it
+    pub fn parse_table(path: &str) -> duckdb::Result<ParsedString> {
+        if std::path::Path::new(path).is_dir() {
+            Self::parse_glob(&format!("{}/*.parquet", path.trim_end_matches('/')))
+        } else if Self::is_glob(path) {
+            Self::parse_glob(path)
+        } else {
+            Self::parse(path)
+        }
+    }
+
+    /// Optionally, allow read-only access to inner string
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    /// The glob pattern of a table created by `parse_glob`
+    pub fn glob(&self) -> Option<&str> {
+        self.0.strip_prefix("read_parquet('")?
+            .split_once('\'')
+            .map(|(glob, _)| glob)
+    }
+
+    fn file_list(&self) -> Option<&str> {
+        self.0.strip_prefix("read_parquet([")?
+            .split_once(']')
+            .map(|(list, _)| list)
+    }
+
+    /// Files or globs a table reads, a single entry unless created by `parse_files`
+    pub fn files(&self) -> Vec<&str> {
+        match self.file_list() {
+            Some(list) => list.split(", ").map(|f| f.trim_matches('\'')).collect(),
+            None => vec![self.name()],
+        }
+    }
+
+    /// The same table with `file` added to its files
+    pub fn with_file(&self, file: &str) -> duckdb::Result<ParsedString> {
+        let mut files = self.files();
+        files.push(file);
+        Self::parse_files(&files)
+    }
+
+    /// The same table without `file`, fails if it is the only one
+    pub fn without_file(&self, file: &str) -> duckdb::Result<ParsedString> {
+        let files: Vec<&str> = self.files().into_iter().filter(|f| *f != file).collect();
+        Self::parse_files(&files)
+    }
+
+    /// The identifier without the surrounding quotes, the glob pattern or the file list of a scan
+    pub fn name(&self) -> &str {
+        self.glob()
+            .or_else(|| self.file_list())
+            .unwrap_or_else(|| self.0.trim_matches('"'))
+    }
+}
+
+pub struct Sql {
+    // None if the database could not be opened, see connection_error
+    pub conn: Option<duckdb::Connection>,
+    pub connection_error: Option<String>,
+    // database file, in memory if None
+    pub db_path: Option<PathBuf>,
+    // request + error
+    pub history : Vec<(usize,String, Option<String>)>,
+    pub counter: usize,
+    // newest last, bounded by MAX_SQL_ERRORS
+    pub errors : Vec<SqlError>,
+    // errors pushed since the log was last viewed
+    pub unseen_errors : usize,
+}
+
+pub const MAX_SQL_ERRORS: usize = 100;
+
+/// A failed query together with the operation it was serving
+pub struct SqlError {
+    pub time: SystemTime,
+    // e.g. "histogram for curve 3"
+    pub context: String,
+    pub error: String,
+    // set once the same context succeeds again
+    pub resolved: bool,
+}
+
+impl Sql {
+    /// Open a database at `db_path` (in memory if None), failures are kept in `connection_error`
+    pub fn open(db_path: Option<PathBuf>) -> Self {
+        let mut sql = Self {
+            conn: None,
+            connection_error: None,
+            db_path,
+            history: vec![],
+            counter: 0,
+            errors: vec![],
+            unseen_errors: 0,
+        };
+        sql.connect();
+        sql
+    }
+
+    /// (Re)try to open the database, returns whether a connection is available
+    pub fn connect(&mut self) -> bool {
+        let conn = match &self.db_path {
+            Some(path) => Connection::open(path),
+            None => Connection::open_in_memory(),
+        };
+        match conn {
+            Ok(conn) => {
+                self.conn = Some(conn);
+                self.connection_error = None;
+                true
+            }
+            Err(e) => {
+                self.conn = None;
+                self.connection_error = Some(format!("{}", e));
+                false
+            }
+        }
+    }
+
+    pub fn push_history(&mut self, query: String, error: Option<String>) {
+        self.counter += 1;
+        self.history.push((self.counter, query, error));
+    }
+
+    /// Record a query in the history and update the error log of its context
+    pub fn log(&mut self, query: String, context: &str, error: Option<String>) {
+        match &error {
+            Some(error) => self.push_error(context, error.clone()),
+            None => self.resolve_errors(context),
+        }
+        self.push_history(query, error);
+    }
+
+    pub fn push_error(&mut self, context: &str, error: String) {
+        self.errors.push(SqlError {
+            time: SystemTime::now(),
+            context: context.to_string(),
+            error,
+            resolved: false,
+        });
+        self.unseen_errors += 1;
+        if self.errors.len() > MAX_SQL_ERRORS {
+            self.errors.drain(0..(self.errors.len() - MAX_SQL_ERRORS));
+        }
+    }
+
+    /// Mark all earlier errors of this context as resolved
+    pub fn resolve_errors(&mut self, context: &str) {
+        for error in self.errors.iter_mut().filter(|e| e.context == context) {
+            error.resolved = true;
+        }
+    }
+
+    pub fn clear_errors(&mut self) {
+        self.errors.clear();
+        self.unseen_errors = 0;
+    }
+
+    pub fn prepare(&mut self, query: &str) -> duckdb::Result<duckdb::Statement<'_>> {
+        //self.last_query = query.to_string();
+        match &self.conn {
+            Some(conn) => conn.prepare(query),
+            None => Err(duckdb_failure(ErrorCode::CannotOpen, format!(
+                "Database unavailable: {}",
+                self.connection_error.as_deref().unwrap_or("not connected")
+            ))),
+        }
+    }
+}
+
+/// Error raised before a query reaches DuckDB, displayed as `message`
+fn duckdb_failure(code: ErrorCode, message: String) -> duckdb::Error {
+    duckdb::Error::DuckDBFailure(duckdb::ffi::Error { code, extended_code: duckdb::ffi::duckdb_state_DuckDBError }, Some(message))
+}
+
+pub fn get_column_names<'a>(cache : &'a mut Cache, sql: &mut Sql, input : ColumnNamesInput, context: &str) -> &'a Vec<ParsedString> {
+    if ! cache.column_names.contains_key(&input) {
+        cache.column_names.insert(input.clone(),compute_column_names(sql, &input, context));
+    }
+    if let Some(res) = cache.column_names.get(&input) {
+        &res.names
+    }
+    else {
+        panic!("Column names cache miss");
+    }
+}
+
+pub fn compute_column_names(
+    sql: &mut Sql,
+    input : &ColumnNamesInput,
+    context: &str,
+) -> ColumnNamesOutput {
+    let query = format!(
+        r#"
+        DESCRIBE SELECT * FROM {};
+       "#,&input.table.as_str()
+        ).to_string();
+    // collect errors
+    let result: duckdb::Result<ColumnNamesOutput> = (||{
+        let mut stmt = sql.prepare(&query)?;
+        let column_names = stmt.query_map(params![], |row| {
+            ParsedString::parse(&row.get::<_, String>(0)?)
+            //Ok(row.get::<_, String>(1)?)
+        })?
+        .collect::<duckdb::Result<Vec<_>>>()?;
+        Ok(ColumnNamesOutput { names: column_names })
+    })();
+    match result {
+        Ok(res) => {
+            sql.log(query.clone(), context, None);
+            res
+        },
+        Err(e) => {
+            sql.log(query.clone(), context, Some(format!("Error computing column names: {:?}", e)));
+            ColumnNamesOutput { names : vec![] }
+        }
+    }
+}
+
+/// Units of the columns of `table` by column name, from every file of the table that is not a glob
+pub fn get_units(cache : &mut Cache, table: &ParsedString) -> HashMap<String, String> {
+    cache.units.entry(table.clone())
+        .or_insert_with(|| {
+            table.files().iter()
+                .filter(|file| !ParsedString::is_glob(file))
+                .filter_map(|file| crate::parquet_units(std::path::Path::new(file)).ok())
+                .flatten()
+                .collect()
+        })
+        .clone()
+}
+
+#[derive(Hash, Eq, PartialEq, Clone)]
+pub struct FileCountInput {
+    pub table : ParsedString,
+}
+
+#[derive(Clone)]
+pub struct FileCountOutput {
+    // None if the table is a single file
+    pub files : Option<usize>,
+}
+
+/// Number of files matched by a glob table, looked up again after `Cache::invalidate_table`
+pub fn get_file_count(cache : &mut Cache, sql: &mut Sql, input: &FileCountInput, context: &str) -> FileCountOutput {
+    if !cache.file_count.contains_key(input) {
+        cache.file_count.insert(input.clone(), compute_file_count(sql, input, context));
+    }
+    if let Some(res) = cache.file_count.get(input) {
+        res.clone()
+    }
+    else {
+        panic!("File count cache miss");
+    }
+}
+
+pub fn compute_file_count(
+    sql: &mut Sql,
+    input: &FileCountInput,
+    context: &str,
+) -> FileCountOutput {
+    let Some(glob) = input.table.glob() else {
+        return FileCountOutput { files: None };
+    };
+    let query = format!("SELECT count(*) FROM glob('{}');", glob);
+    let result: duckdb::Result<usize> = (|| {
+        let mut stmt = sql.prepare(&query)?;
+        stmt.query_row(params![], |row| row.get::<_, usize>(0))
+    })();
+    match result {
+        Ok(files) => {
+            sql.log(query.clone(), context, None);
+            FileCountOutput { files: Some(files) }
+        },
+        Err(e) => {
+            sql.log(query.clone(), context, Some(format!("Error computing file count: {:?}", e)));
+            FileCountOutput { files: None }
+        }
+    }
+}
+
+pub struct ColumnNamesOutput {
+    pub names : Vec<ParsedString>,
+}
+
+#[derive(Hash, Eq, PartialEq, Clone)]
+pub struct ColumnNamesInput {
+    pub table : ParsedString,
+}
+
+
+#[derive(Default)]
+pub struct Cache {
+    pub column_names : HashMap<ColumnNamesInput, ColumnNamesOutput>,
+    pub histogram : HashMap<HistogramInput, HistogramOutput>,
+    pub histogram_range : HashMap<HistogramRangeInput, HistogramRangeOutput>,
+    pub histogram_curve : HashMap<HistogramCurveInput, HistogramCurveOutput>,
+    pub stat : HashMap<StatInput, StatOutput>,
+    pub xy_stat : HashMap<XYStatInput, XYStatOutput>,
+    pub summary : HashMap<SummaryInput, SummaryOutput>,
+    pub null_fraction : HashMap<NullFractionInput, NullFractionOutput>,
+    pub file_count : HashMap<FileCountInput, FileCountOutput>,
+    pub bin_count : HashMap<BinCountInput, BinCountOutput>,
+    pub series : HashMap<SeriesInput, SeriesOutput>,
+    pub box_plot : HashMap<BoxPlotInput, BoxPlotOutput>,
+    // declared column units per table, read from the parquet metadata
+    pub units : HashMap<ParsedString, HashMap<String, String>>,
+}
+
+impl Cache {
+    /// Drop every cached result that depends on the given table, e.g. after its file changed
+    pub fn invalidate_table(&mut self, table: &ParsedString) {
+        self.column_names.retain(|k, _| &k.table != table);
+        self.histogram.retain(|k, _| k.curves.iter().all(|c| &c.table != table));
+        self.histogram_range.retain(|k, _| k.curves.iter().all(|c| &c.table != table));
+        self.histogram_curve.retain(|k, _| &k.table != table);
+        self.stat.retain(|k, _| &k.table != table);
+        self.xy_stat.retain(|k, _| &k.table != table);
+        self.summary.retain(|k, _| &k.table != table);
+        self.null_fraction.retain(|k, _| &k.table != table);
+        self.file_count.retain(|k, _| &k.table != table);
+        self.bin_count.retain(|k, _| k.curves.iter().all(|c| &c.table != table));
+        self.series.retain(|k, _| &k.table != table);
+        self.box_plot.retain(|k, _| &k.table != table);
+        self.units.remove(table);
+    }
+}
+
+#[derive(Hash, Eq, PartialEq, Clone, Serialize, Deserialize)]
+pub struct HistogramInput {
+    pub bins: usize,
+    pub curves : Vec<HistogramSubInput>,
+    #[serde(default)]
+    pub range: HistogramRange,
+}
+
+impl HistogramInput {
+    /// The part of every curve that selects the rows binned along x
+    pub fn range_curves(&self) -> Vec<HistogramRangeCurve> {
+        self.curves.iter().map(|c| HistogramRangeCurve {
+            table: c.table.clone(),
+            filter: c.filter.clone(),
+            x_key: c.x_key.clone(),
+            y_key: c.y_key.clone(),
+        }).collect()
+    }
+}
+
+/// Manual x range of a histogram, unset bounds are the MIN and MAX of the data.
+/// Rows outside are dropped, or counted in the first and last bucket if `clamp`.
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize)]
+pub struct HistogramRange {
+    pub min: Option<f64>,
+    pub max: Option<f64>,
+    pub clamp: bool,
+}
+
+// Compared bitwise like BucketGrid, so the range is part of the cache keys
+impl PartialEq for HistogramRange {
+    fn eq(&self, other: &Self) -> bool {
+        self.min.map(f64::to_bits) == other.min.map(f64::to_bits)
+            && self.max.map(f64::to_bits) == other.max.map(f64::to_bits)
+            && self.clamp == other.clamp
+    }
+}
+
+impl Eq for HistogramRange {}
+
+impl hash::Hash for HistogramRange {
+    fn hash<H: hash::Hasher>(&self, state: &mut H) {
+        self.min.map(f64::to_bits).hash(state);
+        self.max.map(f64::to_bits).hash(state);
+        self.clamp.hash(state);
+    }
+}
+
+
+#[derive(Hash, Eq, PartialEq, Clone, Serialize, Deserialize)]
+pub struct HistogramSubInput {
+    pub id : usize,
+    pub table : ParsedString,
+    pub filter : SQLFilter,
+    pub x_key : ParsedString,
+    pub value_type: HistogramAggregation,
+    pub y_key : ParsedString,
+}
+
+#[derive(Copy, Hash, Eq, PartialEq, Clone, Display,EnumIter, Serialize, Deserialize)]
+pub enum HistogramAggregation{
+    Count,
+    /// Number of distinct values in a bin
+    #[strum(to_string = "Count distinct")]
+    CountDistinct,
+    Sum,
+    Avg,
+    Min,
+    Max,
+    Median,
+    /// Quantile of the values in a bin, in per mille so the aggregation stays hashable (950 = p95)
+    Quantile(u16),
+}
+
+/// Quantile preselected when switching a curve to [`HistogramAggregation::Quantile`]
+pub const DEFAULT_QUANTILE: u16 = 950;
+
+impl HistogramAggregation {
+    /// Short name used in labels, quantiles are written as `p95`
+    pub fn name(&self) -> String {
+        match self {
+            HistogramAggregation::Quantile(per_mille) if per_mille % 10 == 0 => format!("p{}", per_mille / 10),
+            HistogramAggregation::Quantile(per_mille) => format!("p{}.{}", per_mille / 10, per_mille % 10),
+            HistogramAggregation::CountDistinct => "N distinct".to_string(),
+            other => other.to_string(),
+        }
+    }
+}
+
+//#[derive(Copy, Hash, Eq, PartialEq, Clone, Display,EnumIter)]
+//enum HistogramAxisScale {
+//    Linear,
+//    //Log, // egui plot not supported yet: https://github.com/emilk/egui_plot/pull/29
+//}
+
+//#[derive(Copy, Hash, Eq, PartialEq, Clone, Display,EnumIter)]
+//enum HistogramBinScale{
+//    Linear,
+//    //Log, // TODO SQL
+//}
+
+//#[derive(Hash, Eq, PartialEq, Clone, Display)]
+//enum HistorgramValueType {
+//    #[strum(to_string = "Count({0})")]
+//    Count(ParsedString),
+//    #[strum(to_string = "Sum({0})")]
+//    Sum(ParsedString),
+//    #[strum(to_string = "Avg({0})")]
+//    Avg(ParsedString),
+//}
+
+/// A histogram bin: (bin_center, bin_width, (value, error) of each curve)
+pub type HistogramBin = (f64, f64, Vec<(f64, f64)>);
+
+#[derive(Clone)]
+pub struct HistogramOutput {
+    // (bin_center, bin_width, count, stddev)
+    pub data : Vec<HistogramBin>,
+    pub input : HistogramInput,
+}
+
+impl HistogramOutput {
+    /// Copy with the values and errors of curve `i` scaled by `modes[i]`, curves
+    /// without a mode are left as they are
+    pub fn normalized(&self, modes: &[Normalization]) -> HistogramOutput {
+        let mut output = self.clone();
+        for (i, mode) in modes.iter().enumerate() {
+            let bins: Vec<(f64, f64)> = self.data.iter().map(|(_, w, values)| (*w, values[i].0)).collect();
+            let factor = mode.factor(&bins);
+            for (_, _, values) in &mut output.data {
+                values[i] = (values[i].0 * factor, values[i].1 * factor);
+            }
+        }
+        output
+    }
+
+    /// (ratio, error) of curve `numerator` over curve `denominator` per bin, None where
+    /// the denominator is 0. The errors of both curves are propagated as independent.
+    pub fn ratio(&self, numerator: usize, denominator: usize) -> Vec<Option<(f64, f64)>> {
+        self.data.iter().map(|(_, _, values)| {
+            let ((a, a_error), (b, b_error)) = (values[numerator], values[denominator]);
+            (b != 0.0).then(|| (a / b, ((a_error / b).powi(2) + (a * b_error / (b * b)).powi(2)).sqrt()))
+        }).collect()
+    }
+
+    /// Pull (a - b) / sqrt(error_a² + error_b²) of curve `a` against curve `b` per bin,
+    /// None where both errors are 0
+    pub fn pull(&self, a: usize, b: usize) -> Vec<Option<f64>> {
+        self.data.iter().map(|(_, _, values)| {
+            let ((a, a_error), (b, b_error)) = (values[a], values[b]);
+            let error = a_error.hypot(b_error);
+            (error != 0.0).then(|| (a - b) / error)
+        }).collect()
+    }
+}
+
+/// Scaling of the values of a histogram curve, e.g. to compare files of different size
+#[derive(Copy, Hash, Eq, PartialEq, Clone, Debug, Default, Display, EnumIter, Serialize, Deserialize)]
+pub enum Normalization {
+    #[default]
+    None,
+    /// the sum of value times bin width is 1
+    #[strum(to_string = "Area = 1")]
+    Area,
+    /// the largest absolute value is 1
+    #[strum(to_string = "Max = 1")]
+    Max,
+    /// the values add up to 100
+    #[strum(to_string = "Percent")]
+    Percent,
+}
+
+impl Normalization {
+    /// Factor of the values of the `(width, value)` bins, 1 if they can't be scaled
+    /// e.g. because all values are 0
+    pub fn factor(self, bins: &[(f64, f64)]) -> f64 {
+        let (scale, total) = match self {
+            Self::None => return 1.0,
+            Self::Area => (1.0, bins.iter().map(|(w, y)| w * y).sum::<f64>()),
+            Self::Max => (1.0, bins.iter().map(|(_, y)| y.abs()).fold(0.0, f64::max)),
+            Self::Percent => (100.0, bins.iter().map(|(_, y)| y).sum::<f64>()),
+        };
+        if total != 0.0 && total.is_finite() { scale / total } else { 1.0 }
+    }
+
+    /// Unit of the normalized values for axis labels, None without normalization
+    pub fn unit(self) -> Option<&'static str> {
+        match self {
+            Self::None => None,
+            Self::Area => Some("fraction / x unit"),
+            Self::Max => Some("fraction of max"),
+            Self::Percent => Some("% of total"),
+        }
+    }
+}
+
+/// Probability outside a central 68.27% interval on either side
+const ONE_SIGMA_TAIL: f64 = 0.15865525393145707;
+
+/// Counts above which the Poisson interval is approximated instead of solved for
+const POISSON_EXACT_LIMIT: u64 = 100;
+
+/// Garwood central 68.27% interval (lower, upper) of a Poisson mean for `n` observed
+/// counts, rounded to a whole count. An empty bin gives (0, 1.84) instead of no error.
+pub fn poisson_interval(n: f64) -> (f64, f64) {
+    let k = n.round().max(0.0) as u64;
+    if k > POISSON_EXACT_LIMIT {
+        // Wilson-Hilferty approximation of the chi-squared quantiles
+        let chi2 = |dof: f64, z: f64| dof * (1.0 - 2.0 / (9.0 * dof) + z * (2.0 / (9.0 * dof)).sqrt()).powi(3);
+        return (0.5 * chi2(2.0 * k as f64, -1.0), 0.5 * chi2(2.0 * k as f64 + 2.0, 1.0));
+    }
+    // P(X <= k) for a Poisson mean mu, falls with mu
+    let cdf = |k: u64, mu: f64| {
+        let mut term = (-mu).exp();
+        let mut sum = term;
+        for j in 1..=k {
+            term *= mu / j as f64;
+            sum += term;
+        }
+        sum
+    };
+    let solve = |k: u64, target: f64| {
+        let (mut low, mut high) = (0.0, k as f64 + 10.0 * (k as f64 + 1.0).sqrt() + 10.0);
+        for _ in 0..100 {
+            let mid = 0.5 * (low + high);
+            if cdf(k, mid) > target { low = mid } else { high = mid }
+        }
+        0.5 * (low + high)
+    };
+    let lower = if k == 0 { 0.0 } else { solve(k - 1, 1.0 - ONE_SIGMA_TAIL) };
+    (lower, solve(k, ONE_SIGMA_TAIL))
+}
+
+pub fn get_histogram(cache : & mut Cache, sql: &mut Sql, input : & HistogramInput, context: &str) -> HistogramOutput {
+    if !cache.histogram.contains_key(input) {
+        let output = compute_histogram(cache, sql, input, context);
+        cache.histogram.insert(input.clone(), output);
+    }
+    if let Some(res) = cache.histogram.get(input) {
+        res.clone()
+    }
+    else {
+        panic!("Histogram cache miss");
+    }
+}
+
+/// Assemble a histogram from the shared bucket grid and the independently cached curves
+pub fn compute_histogram(
+    cache : &mut Cache,
+    sql: &mut Sql,
+    hist : &HistogramInput,
+    context: &str,
+) -> HistogramOutput {
+    if hist.curves.is_empty() || hist.bins == 0 {
+        return HistogramOutput { data : vec![], input: hist.clone() };
+    }
+    let range_input = HistogramRangeInput {
+        bins: hist.bins,
+        range: hist.range,
+        curves: hist.range_curves(),
+    };
+    let grid = match get_histogram_range(cache, sql, &range_input, &format!("{} range", context)).grid {
+        Some(grid) => grid,
+        None => return HistogramOutput { data : vec![], input: hist.clone() },
+    };
+    let curves = hist.curves.iter().map(|c| {
+        get_histogram_curve(cache, sql, &HistogramCurveInput {
+            table: c.table.clone(),
+            filter: c.filter.clone(),
+            x_key: c.x_key.clone(),
+            value_type: c.value_type,
+            y_key: c.y_key.clone(),
+            grid,
+            clamp: hist.range.clamp,
+        }, &format!("{} for curve {}", context, c.id)).values
+    }).collect::<Vec<_>>();
+    let data = (0..grid.bins).map(|bucket| (
+        grid.midpoint(bucket),
+        grid.width(),
+        curves.iter().map(|values| values[bucket]).collect(),
+    )).collect();
+    HistogramOutput { data, input: hist.clone() }
+}
+
+/// Standalone DuckDB script reproducing the histogram and the per-curve stats.
+/// The bucket grid is written as exact literals, so the result matches the cached output.
+pub fn histogram_sql_script(cache : &mut Cache, sql: &mut Sql, hist : &HistogramInput, context: &str) -> String {
+    let mut script = format!("-- STRAP GUI histogram with {} bins\n", hist.bins);
+    for (i, c) in hist.curves.iter().enumerate() {
+        script.push_str(&format!(
+            "-- Curve {}: {} of {} vs {} in {}\n--   filter: {}\n",
+            i + 1,
+            c.value_type,
+            c.y_key.name(),
+            c.x_key.name(),
+            c.table.name(),
+            if c.filter.is_empty() { "none".to_string() } else { c.filter.to_sql() },
+        ));
+    }
+    let range_input = HistogramRangeInput {
+        bins: hist.bins,
+        range: hist.range,
+        curves: hist.range_curves(),
+    };
+    script.push_str(&format!("\n-- Range of the x keys, the buckets below span it\n{};\n", histogram_range_query(&range_input).trim()));
+    match get_histogram_range(cache, sql, &range_input, &format!("{} range", context)).grid {
+        Some(grid) => {
+            for (i, c) in hist.curves.iter().enumerate() {
+                let query = histogram_curve_query(&HistogramCurveInput {
+                    table: c.table.clone(),
+                    filter: c.filter.clone(),
+                    x_key: c.x_key.clone(),
+                    value_type: c.value_type,
+                    y_key: c.y_key.clone(),
+                    grid,
+                    clamp: hist.range.clamp,
+                });
+                script.push_str(&format!(
+                    "\n-- Curve {}: bucket i covers [{:?} + i * {:?}, {:?} + (i + 1) * {:?}), empty buckets are 0\n{};\n",
+                    i + 1, grid.min, grid.width(), grid.min, grid.width(), query.trim()
+                ));
+            }
+        },
+        None => script.push_str("\n-- No rows are selected, so there are no buckets\n"),
+    }
+    for (i, c) in hist.curves.iter().enumerate() {
+        let query = stat_query(&StatInput {
+            table: c.table.clone(),
+            column: c.x_key.clone(),
+            filters: c.filter.clone(),
+        });
+        script.push_str(&format!("\n-- Stats of {} for curve {}\n{};\n", c.x_key.name(), i + 1, query.trim()));
+    }
+    script
+}
+
+/// Equal-width buckets spanning [min, max] shared by all curves of a histogram
+#[derive(Clone, Copy, Debug)]
+pub struct BucketGrid {
+    pub min: f64,
+    pub max: f64,
+    pub bins: usize,
+}
+
+impl BucketGrid {
+    pub fn width(&self) -> f64 {
+        (self.max - self.min) / self.bins as f64
+    }
+
+    pub fn midpoint(&self, bucket: usize) -> f64 {
+        self.min + (bucket as f64 + 0.5) * self.width()
+    }
+
+    /// SQL expression mapping the column `x` onto its bucket index, the last bucket includes max
+    pub fn bucket_sql(&self, x: &str) -> String {
+        format!(
+            "LEAST({} - 1, CAST(FLOOR(({} - {}) / (({} - {}) / {})) AS INTEGER))",
+            self.bins, x, sql_double(self.min), sql_double(self.max), sql_double(self.min), self.bins
+        )
+    }
+}
+
+// Grids are compared bitwise so they can be part of cache keys
+impl PartialEq for BucketGrid {
+    fn eq(&self, other: &Self) -> bool {
+        self.min.to_bits() == other.min.to_bits()
+            && self.max.to_bits() == other.max.to_bits()
+            && self.bins == other.bins
+    }
+}
+
+impl Eq for BucketGrid {}
+
+impl hash::Hash for BucketGrid {
+    fn hash<H: hash::Hasher>(&self, state: &mut H) {
+        self.min.to_bits().hash(state);
+        self.max.to_bits().hash(state);
+        self.bins.hash(state);
+    }
+}
+
+/// Exact SQL literal for a double, DuckDB would read a plain `1.5` as DECIMAL
+pub fn sql_double(value: f64) -> String {
+    format!("CAST('{:?}' AS DOUBLE)", value)
+}
+
+#[derive(Hash, Eq, PartialEq, Clone)]
+pub struct HistogramRangeInput {
+    pub bins: usize,
+    pub curves: Vec<HistogramRangeCurve>,
+    // bounds replacing the ones of the data
+    pub range: HistogramRange,
+}
+
+#[derive(Hash, Eq, PartialEq, Clone)]
+pub struct HistogramRangeCurve {
+    pub table : ParsedString,
+    pub filter : SQLFilter,
+    pub x_key : ParsedString,
+    pub y_key : ParsedString,
+}
+
+#[derive(Clone)]
+pub struct HistogramRangeOutput {
+    // None if no curve has any selected rows
+    pub grid: Option<BucketGrid>,
+}
+
+pub fn get_histogram_range(cache : &mut Cache, sql: &mut Sql, input : &HistogramRangeInput, context: &str) -> HistogramRangeOutput {
+    if !cache.histogram_range.contains_key(input) {
+        cache.histogram_range.insert(input.clone(), compute_histogram_range(sql, input, context));
+    }
+    if let Some(res) = cache.histogram_range.get(input) {
+        res.clone()
+    }
+    else {
+        panic!("Histogram range cache miss");
+    }
+}
+
+/// MIN and MAX of the x keys over the selected rows of all curves
+pub fn histogram_range_query(input : &HistogramRangeInput) -> String {
+    let ranges = input.curves.iter().map(|c|
+            format!(
+                r#"
+    SELECT MIN({}) AS min_val, MAX({}) AS max_val
+    FROM {}
+    WHERE ( {} IS NOT NULL AND {} IS NOT NULL ) {}
+                "#, c.x_key, c.x_key, c.table, c.x_key, c.y_key, c.filter.to_sql_and_prefix()
+            )
+        ).collect::<Vec<_>>().join("UNION ALL");
+    format!(
+        r#"
+SELECT MIN(min_val), MAX(max_val)
+FROM (
+        {}
+)
+        "#, ranges
+    )
+}
+
+pub fn compute_histogram_range(
+    sql: &mut Sql,
+    input : &HistogramRangeInput,
+    context: &str,
+) -> HistogramRangeOutput {
+    let query = histogram_range_query(input);
+    let result: duckdb::Result<HistogramRangeOutput> = (|| {
+        let (min, max) = sql.prepare(&query)?.query_row(params![], |row| {
+            Ok((row.get::<_, Option<f64>>(0)?, row.get::<_, Option<f64>>(1)?))
+        })?;
+        let grid = match (min, max) {
+            (Some(min), Some(max)) => {
+                let (min, max) = (input.range.min.unwrap_or(min), input.range.max.unwrap_or(max));
+                (min <= max).then_some(BucketGrid { min, max, bins: input.bins })
+            }
+            _ => None,
+        };
+        Ok(HistogramRangeOutput { grid })
+    })();
+    match result {
+        Ok(res) => {
+            sql.log(query.clone(), context, None);
+            res
+        },
+        Err(e) => {
+            sql.log(query.clone(), context, Some(format!("Error computing histogram range: {:?}", e)));
+            HistogramRangeOutput { grid: None }
+        }
+    }
+}
+
+/// How the number of bins of a histogram is chosen
+#[derive(Copy, Hash, Eq, PartialEq, Clone, Debug, Default, Display, EnumIter, Serialize, Deserialize)]
+pub enum BinRule {
+    /// as set by hand
+    #[default]
+    Manual,
+    /// log2(n) + 1 bins for n rows
+    Sturges,
+    /// bins of width 2 IQR / n^(1/3), Sturges if the IQR is zero
+    #[strum(to_string = "Freedman–Diaconis")]
+    FreedmanDiaconis,
+}
+
+/// Upper bound of the bins chosen by a `BinRule`, a few outliers could ask for millions
+pub const MAX_AUTO_BINS: usize = 1000;
+
+impl BinRule {
+    /// Bins for `rows` rows spanning `range` with the given interquartile range,
+    /// None for `Manual`
+    pub fn bins(self, rows: usize, range: f64, iqr: f64) -> Option<usize> {
+        let sturges = (rows.max(1) as f64).log2().ceil() as usize + 1;
+        let bins = match self {
+            Self::Manual => return None,
+            Self::Sturges => sturges,
+            Self::FreedmanDiaconis => {
+                let width = 2.0 * iqr / (rows.max(1) as f64).cbrt();
+                if width > 0.0 && range.is_finite() {
+                    (range / width).ceil() as usize
+                } else {
+                    sturges
+                }
+            }
+        };
+        Some(bins.clamp(1, MAX_AUTO_BINS))
+    }
+}
+
+/// Rows of all curves whose combined x keys decide the bins of a `BinRule`
+#[derive(Hash, Eq, PartialEq, Clone)]
+pub struct BinCountInput {
+    pub rule: BinRule,
+    pub curves: Vec<HistogramRangeCurve>,
+    // a manual bound replaces the one of the data
+    pub range: HistogramRange,
+}
+
+#[derive(Clone)]
+pub struct BinCountOutput {
+    // None for `BinRule::Manual` and if no curve has any selected rows
+    pub bins: Option<usize>,
+}
+
+pub fn get_bin_count(cache : &mut Cache, sql: &mut Sql, input : &BinCountInput, context: &str) -> BinCountOutput {
+    if !cache.bin_count.contains_key(input) {
+        cache.bin_count.insert(input.clone(), compute_bin_count(sql, input, context));
+    }
+    if let Some(res) = cache.bin_count.get(input) {
+        res.clone()
+    }
+    else {
+        panic!("Bin count cache miss");
+    }
+}
+
+/// Rows, MIN, MAX and the quartiles of the x keys over the selected rows of all curves
+pub fn bin_count_query(input : &BinCountInput) -> String {
+    let values = input.curves.iter().map(|c|
+            format!(
+                r#"
+    SELECT {} AS x
+    FROM {}
+    WHERE ( {} IS NOT NULL AND {} IS NOT NULL ) {}
+                "#, c.x_key, c.table, c.x_key, c.y_key, c.filter.to_sql_and_prefix()
+            )
+        ).collect::<Vec<_>>().join("UNION ALL");
+    format!(
+        r#"
+SELECT COUNT(x), MIN(x), MAX(x), approx_quantile(x, 0.25), approx_quantile(x, 0.75)
+FROM (
+        {}
+)
+        "#, values
+    )
+}
+
+pub fn compute_bin_count(
+    sql: &mut Sql,
+    input : &BinCountInput,
+    context: &str,
+) -> BinCountOutput {
+    if input.rule == BinRule::Manual || input.curves.is_empty() {
+        return BinCountOutput { bins: None };
+    }
+    let query = bin_count_query(input);
+    let result: duckdb::Result<BinCountOutput> = (|| {
+        let (rows, min, max, q1, q3) = sql.prepare(&query)?.query_row(params![], |row| {
+            Ok((
+                row.get::<_, i64>(0)?,
+                row.get::<_, Option<f64>>(1)?,
+                row.get::<_, Option<f64>>(2)?,
+                row.get::<_, Option<f64>>(3)?,
+                row.get::<_, Option<f64>>(4)?,
+            ))
+        })?;
+        let bins = match (min, max, q1, q3) {
+            (Some(min), Some(max), Some(q1), Some(q3)) if rows > 0 => {
+                let range = input.range.max.unwrap_or(max) - input.range.min.unwrap_or(min);
+                input.rule.bins(rows as usize, range, q3 - q1)
+            }
+            _ => None,
+        };
+        Ok(BinCountOutput { bins })
+    })();
+    match result {
+        Ok(res) => {
+            sql.log(query.clone(), context, None);
+            res
+        },
+        Err(e) => {
+            sql.log(query.clone(), context, Some(format!("Error computing bin count: {:?}", e)));
+            BinCountOutput { bins: None }
+        }
+    }
+}
+
+/// A single curve binned into a given grid, independent of the other curves
+#[derive(Hash, Eq, PartialEq, Clone)]
+pub struct HistogramCurveInput {
+    pub table : ParsedString,
+    pub filter : SQLFilter,
+    pub x_key : ParsedString,
+    pub value_type: HistogramAggregation,
+    pub y_key : ParsedString,
+    pub grid: BucketGrid,
+    // rows outside the grid go to the edge buckets instead of being dropped
+    pub clamp: bool,
+}
+
+#[derive(Clone)]
+pub struct HistogramCurveOutput {
+    // (value, error) per bucket of the grid
+    pub values: Vec<(f64, f64)>,
+}
+
+pub fn get_histogram_curve(cache : &mut Cache, sql: &mut Sql, input : &HistogramCurveInput, context: &str) -> HistogramCurveOutput {
+    if !cache.histogram_curve.contains_key(input) {
+        cache.histogram_curve.insert(input.clone(), compute_histogram_curve(sql, input, context));
+    }
+    if let Some(res) = cache.histogram_curve.get(input) {
+        res.clone()
+    }
+    else {
+        panic!("Histogram curve cache miss");
+    }
+}
+
+/// Value and error per bucket index, buckets without rows are missing
+pub fn histogram_curve_query(c : &HistogramCurveInput) -> String {
+    let x = format!("t.{}", c.x_key);
+    let (bucket, outside) = if c.clamp {
+        (format!("GREATEST(0, {})", c.grid.bucket_sql(&x)), String::new())
+    } else {
+        (c.grid.bucket_sql(&x), format!(" AND {} BETWEEN {} AND {}", x, sql_double(c.grid.min), sql_double(c.grid.max)))
+    };
+    // COUNT is BIGINT in DuckDB, cast everything so the values are always read as doubles
+    let y_value = match c.value_type {
+        HistogramAggregation::Count => format!("CAST(COUNT({}) AS DOUBLE)", c.y_key),
+        HistogramAggregation::CountDistinct => format!("CAST(COUNT(DISTINCT {}) AS DOUBLE)", c.y_key),
+        HistogramAggregation::Sum => format!("SUM({})", c.y_key),
+        HistogramAggregation::Avg => format!("AVG({})", c.y_key),
+        HistogramAggregation::Min => format!("MIN({})", c.y_key),
+        HistogramAggregation::Max => format!("MAX({})", c.y_key),
+        HistogramAggregation::Median => format!("MEDIAN({})", c.y_key),
+        HistogramAggregation::Quantile(per_mille) => format!("QUANTILE_CONT({}, {})", c.y_key, per_mille as f64 / 1000.0),
+    };
+    let y_error= match c.value_type {
+        HistogramAggregation::Count => format!("SQRT(CAST(COUNT({}) AS DOUBLE))", c.y_key),
+        HistogramAggregation::Sum => format!("STDDEV({})", c.y_key),
+        HistogramAggregation::Avg => format!("STDDEV({})", c.y_key),
+        // the median is drawn with a band as wide as the interquartile range
+        HistogramAggregation::Median => format!("QUANTILE_CONT({0}, 0.75) - QUANTILE_CONT({0}, 0.25)", c.y_key),
+        // an extreme value or an arbitrary quantile has no error, drawn without an error bar
+        HistogramAggregation::Min | HistogramAggregation::Max | HistogramAggregation::Quantile(_) => "CAST(0 AS DOUBLE)".to_string(),
+        // the number of distinct values is exact, not a sampled count
+        HistogramAggregation::CountDistinct => "CAST(0 AS DOUBLE)".to_string(),
+    };
+    format!(
+        r#"
+SELECT
+    {} AS bucket,
+    {} AS yvalue,
+    {} AS yerror
+FROM {} AS t
+WHERE ( t.{} IS NOT NULL AND t.{} IS NOT NULL ){} {}
+GROUP BY bucket
+ORDER BY bucket
+        "#,
+        bucket,
+        y_value,
+        y_error,
+        c.table,
+        c.x_key,
+        c.y_key,
+        outside,
+        c.filter.to_sql_and_prefix()
+    )
+}
+
+pub fn compute_histogram_curve(
+    sql: &mut Sql,
+    c : &HistogramCurveInput,
+    context: &str,
+) -> HistogramCurveOutput {
+    let query = histogram_curve_query(c);
+    let result: duckdb::Result<HistogramCurveOutput> = (|| {
+        let mut values = vec![(0.0, 0.0); c.grid.bins];
+        let rows = sql.prepare(&query)?.query_map(params![], |row| {
+            Ok((
+                row.get::<_, Option<i64>>(0)?,
+                row.get::<_, Option<f64>>(1)?,
+                row.get::<_, Option<f64>>(2)?,
+            ))
+        })?
+        .collect::<duckdb::Result<Vec<_>>>()?;
+        for (bucket, y_value, y_error) in rows {
+            // a degenerate grid (min == max) yields NULL buckets, same as the empty join before
+            if let Some(bucket) = bucket
+                && bucket >= 0
+                && (bucket as usize) < values.len()
+            {
+                values[bucket as usize] = (y_value.unwrap_or(0.0), y_error.unwrap_or(0.0));
+            }
+        }
+        Ok(HistogramCurveOutput { values })
+    })();
+    match result {
+        Ok(res) => {
+            sql.log(query.clone(), context, None);
+            res
+        },
+        Err(e) => {
+            sql.log(query.clone(), context, Some(format!("Error computing histogram curve: {:?}", e)));
+            HistogramCurveOutput { values: vec![(0.0, 0.0); c.grid.bins] }
+        }
+    }
+}
+
+#[derive(Hash, Eq, PartialEq, Clone)]
+pub struct StatInput {
+    pub table : ParsedString,
+    pub column : ParsedString,
+    pub filters : SQLFilter,
+}
+
+#[derive(Clone)]
+pub struct StatOutput {
+    pub sum: f64,
+    pub count: usize,
+    pub mean: f64,
+    pub stddev: f64,
+    pub min : f64,
+    pub max : f64,
+}
+
+pub fn get_stat(cache : &mut Cache, sql: &mut Sql, input: &StatInput, context: &str) ->  StatOutput {
+    if !cache.stat.contains_key(input) {
+        cache.stat.insert(input.clone(), compute_stat(sql, input, context));
+    }
+    if let Some(res) = cache.stat.get(input) {
+        res.clone()
+    }
+    else {
+        panic!("Stat cache miss");
+    }
+}
+
+pub fn stat_query(stat_input : &StatInput) -> String {
+    format!(
+        r#"
+        SELECT 
+            SUM(t.{}) as sum,
+            COUNT(t.{}) as count, 
+            AVG(t.{}) as mean,
+            STDDEV(t.{}) as stddev,
+            MIN(t.{}) as min,
+            MAX(t.{}) as max
+        FROM {} AS t
+        {}
+       "#,
+        stat_input.column,
+        stat_input.column,
+        stat_input.column,
+        stat_input.column,
+        stat_input.column,
+        stat_input.column,
+        stat_input.table ,
+        stat_input.filters.to_sql_where_prefix()
+    )
+}
+
+pub fn compute_stat(
+    sql: &mut Sql,
+    stat_input : &StatInput,
+    context: &str,
+) -> StatOutput {
+    let query = stat_query(stat_input);
+    let result = (||{
+        let stmt = sql.prepare(&query)?.query_map(params![ ], |row| {
+            Ok(StatOutput {
+                sum: row.get(0)?,
+                count: row.get(1)?,
+                mean: row.get(2)?,
+                stddev: row.get(3)?,
+                min: row.get(4)?,
+                max: row.get(5)?,
+            })
+        })?
+        .next();
+
+        if let Some(stat) = stmt {
+            stat
+        } else {
+            Err(duckdb::Error::QueryReturnedNoRows)
+        }
+    })();
+    match result {
+        Ok(res) => {
+            sql.log(query.clone(), context, None);
+            res
+        },
+        Err(e) => {
+            sql.log(query.clone(), context, Some(format!("Error computing stat: {:?}", e)));
+            StatOutput { sum: 0.0, count: 0, mean: 0.0, stddev: 0.0, min: 0.0, max: 0.0 }
+        }
+    }
+
+}
+/// Joint statistics of two columns over the rows where both are set
+#[derive(Hash, Eq, PartialEq, Clone)]
+pub struct XYStatInput {
+    pub table : ParsedString,
+    pub x : ParsedString,
+    pub y : ParsedString,
+    pub filters : SQLFilter,
+}
+
+#[derive(Clone)]
+pub struct XYStatOutput {
+    pub pairs: usize,
+    // None if there are too few pairs or a column is constant
+    pub corr: Option<f64>,
+    pub covar: Option<f64>,
+}
+
+pub fn get_xy_stat(cache : &mut Cache, sql: &mut Sql, input: &XYStatInput, context: &str) -> XYStatOutput {
+    if !cache.xy_stat.contains_key(input) {
+        cache.xy_stat.insert(input.clone(), compute_xy_stat(sql, input, context));
+    }
+    if let Some(res) = cache.xy_stat.get(input) {
+        res.clone()
+    }
+    else {
+        panic!("XY stat cache miss");
+    }
+}
+
+pub fn compute_xy_stat(
+    sql: &mut Sql,
+    input : &XYStatInput,
+    context: &str,
+) -> XYStatOutput {
+    let query = format!(
+        r#"
+        SELECT
+            COUNT(*) as pairs,
+            CORR(t.{}, t.{}) as corr,
+            COVAR_SAMP(t.{}, t.{}) as covar
+        FROM {} AS t
+        WHERE ( t.{} IS NOT NULL AND t.{} IS NOT NULL ) {}
+       "#,
+        input.y, input.x,
+        input.y, input.x,
+        input.table,
+        input.x, input.y,
+        input.filters.to_sql_and_prefix()
+    );
+    let result: duckdb::Result<XYStatOutput> = (|| {
+        sql.prepare(&query)?.query_row(params![], |row| {
+            Ok(XYStatOutput {
+                pairs: row.get::<_, i64>(0)? as usize,
+                // DuckDB yields NaN instead of NULL for constant columns
+                corr: row.get::<_, Option<f64>>(1)?.filter(|v| v.is_finite()),
+                covar: row.get::<_, Option<f64>>(2)?.filter(|v| v.is_finite()),
+            })
+        })
+    })();
+    match result {
+        Ok(res) => {
+            sql.log(query.clone(), context, None);
+            res
+        },
+        Err(e) => {
+            sql.log(query.clone(), context, Some(format!("Error computing xy stat: {:?}", e)));
+            XYStatOutput { pairs: 0, corr: None, covar: None }
+        }
+    }
+}
+/// Raw points of a series fetched per curve before it is cut off
+pub const SERIES_POINT_LIMIT: usize = 100_000;
+
+/// Raw (x, y) points of a table ordered by x, for a line plot
+#[derive(Hash, Eq, PartialEq, Clone)]
+pub struct SeriesInput {
+    pub table : ParsedString,
+    pub x : ParsedString,
+    pub y : ParsedString,
+    pub filters : SQLFilter,
+    pub limit : usize,
+}
+
+#[derive(Clone)]
+pub struct SeriesOutput {
+    pub points: Vec<(f64, f64)>,
+    // more rows matched than the limit, only the first by x were fetched
+    pub truncated: bool,
+}
+
+pub fn get_series(cache : &mut Cache, sql: &mut Sql, input: &SeriesInput, context: &str) -> SeriesOutput {
+    if !cache.series.contains_key(input) {
+        cache.series.insert(input.clone(), compute_series(sql, input, context));
+    }
+    if let Some(res) = cache.series.get(input) {
+        res.clone()
+    }
+    else {
+        panic!("Series cache miss");
+    }
+}
+
+pub fn compute_series(
+    sql: &mut Sql,
+    input : &SeriesInput,
+    context: &str,
+) -> SeriesOutput {
+    // one row past the limit tells whether the series was cut off
+    let query = format!(
+        r#"
+        SELECT
+            CAST(t.{} AS DOUBLE) as x,
+            CAST(t.{} AS DOUBLE) as y
+        FROM {} AS t
+        WHERE ( t.{} IS NOT NULL AND t.{} IS NOT NULL ) {}
+        ORDER BY x
+        LIMIT {}
+       "#,
+        input.x, input.y,
+        input.table,
+        input.x, input.y,
+        input.filters.to_sql_and_prefix(),
+        input.limit + 1
+    );
+    let result: duckdb::Result<SeriesOutput> = (|| {
+        let mut points = sql.prepare(&query)?.query_map(params![], |row| {
+            Ok((row.get::<_, f64>(0)?, row.get::<_, f64>(1)?))
+        })?
+        .collect::<duckdb::Result<Vec<_>>>()?;
+        let truncated = points.len() > input.limit;
+        points.truncate(input.limit);
+        Ok(SeriesOutput { points, truncated })
+    })();
+    match result {
+        Ok(res) => {
+            sql.log(query.clone(), context, None);
+            res
+        },
+        Err(e) => {
+            sql.log(query.clone(), context, Some(format!("Error computing series: {:?}", e)));
+            SeriesOutput { points: vec![], truncated: false }
+        }
+    }
+}
+/// Five-number summary of a column, over all rows or per bucket of another column
+#[derive(Hash, Eq, PartialEq, Clone)]
+pub struct BoxPlotInput {
+    pub table : ParsedString,
+    pub filter : SQLFilter,
+    pub y_key : ParsedString,
+    // column and buckets the boxes are grouped by, None for a single box
+    pub group : Option<(ParsedString, BucketGrid)>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct BoxSummary {
+    pub min: f64,
+    pub q1: f64,
+    pub median: f64,
+    pub q3: f64,
+    pub max: f64,
+    pub count: usize,
+}
+
+#[derive(Clone)]
+pub struct BoxPlotOutput {
+    // summary per bucket index, buckets without rows are missing; a single box is bucket 0
+    pub boxes: Vec<(usize, BoxSummary)>,
+}
+
+pub fn get_box_plot(cache : &mut Cache, sql: &mut Sql, input: &BoxPlotInput, context: &str) -> BoxPlotOutput {
+    if !cache.box_plot.contains_key(input) {
+        cache.box_plot.insert(input.clone(), compute_box_plot(sql, input, context));
+    }
+    if let Some(res) = cache.box_plot.get(input) {
+        res.clone()
+    }
+    else {
+        panic!("Box plot cache miss");
+    }
+}
+
+pub fn box_plot_query(input : &BoxPlotInput) -> String {
+    let (bucket, grouped) = match &input.group {
+        Some((x_key, grid)) => {
+            let x = format!("t.{}", x_key);
+            (grid.bucket_sql(&x), format!(" AND {} BETWEEN {} AND {}", x, sql_double(grid.min), sql_double(grid.max)))
+        }
+        None => ("0".to_string(), String::new()),
+    };
+    format!(
+        r#"
+SELECT
+    {bucket} AS bucket,
+    MIN(t.{y}) AS min,
+    QUANTILE_CONT(t.{y}, 0.25) AS q1,
+    MEDIAN(t.{y}) AS median,
+    QUANTILE_CONT(t.{y}, 0.75) AS q3,
+    MAX(t.{y}) AS max,
+    COUNT(t.{y}) AS count
+FROM {table} AS t
+WHERE ( t.{y} IS NOT NULL ){grouped} {filter}
+GROUP BY bucket
+ORDER BY bucket
+        "#,
+        y = input.y_key,
+        table = input.table,
+        filter = input.filter.to_sql_and_prefix()
+    )
+}
+
+pub fn compute_box_plot(
+    sql: &mut Sql,
+    input : &BoxPlotInput,
+    context: &str,
+) -> BoxPlotOutput {
+    let query = box_plot_query(input);
+    let result: duckdb::Result<BoxPlotOutput> = (|| {
+        let rows = sql.prepare(&query)?.query_map(params![], |row| {
+            Ok((
+                row.get::<_, Option<i64>>(0)?,
+                BoxSummary {
+                    min: row.get(1)?,
+                    q1: row.get(2)?,
+                    median: row.get(3)?,
+                    q3: row.get(4)?,
+                    max: row.get(5)?,
+                    count: row.get::<_, i64>(6)? as usize,
+                },
+            ))
+        })?
+        .collect::<duckdb::Result<Vec<_>>>()?;
+        // a degenerate grid (min == max) yields NULL buckets
+        let boxes = rows.into_iter()
+            .filter_map(|(bucket, summary)| Some((usize::try_from(bucket?).ok()?, summary)))
+            .collect();
+        Ok(BoxPlotOutput { boxes })
+    })();
+    match result {
+        Ok(res) => {
+            sql.log(query.clone(), context, None);
+            res
+        },
+        Err(e) => {
+            sql.log(query.clone(), context, Some(format!("Error computing box plot: {:?}", e)));
+            BoxPlotOutput { boxes: vec![] }
+        }
+    }
+}
+
+/// Overview of all columns of a table
+#[derive(Hash, Eq, PartialEq, Clone)]
+pub struct SummaryInput {
+    pub table : ParsedString,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct SummaryRow {
+    pub column: String,
+    pub column_type: String,
+    pub count: usize,
+    pub null_percentage: f64,
+    // min and max are strings since they are not numeric for every column type
+    pub min: Option<String>,
+    pub max: Option<String>,
+    pub mean: Option<f64>,
+    pub stddev: Option<f64>,
+    pub approx_distinct: usize,
+}
+
+#[derive(Clone)]
+pub struct SummaryOutput {
+    pub rows: Vec<SummaryRow>,
+}
+
+impl SummaryOutput {
+    pub const HEADER: [&'static str; 9] = ["column", "type", "count", "null %", "min", "max", "mean", "stddev", "approx distinct"];
+
+    /// Cells of every row in the order of `HEADER`
+    pub fn cells(&self) -> Vec<Vec<String>> {
+        let optional = |v: &Option<f64>| v.map(|v| v.to_string()).unwrap_or_default();
+        self.rows.iter().map(|r| vec![
+            r.column.clone(),
+            r.column_type.clone(),
+            r.count.to_string(),
+            r.null_percentage.to_string(),
+            r.min.clone().unwrap_or_default(),
+            r.max.clone().unwrap_or_default(),
+            optional(&r.mean),
+            optional(&r.stddev),
+            r.approx_distinct.to_string(),
+        ]).collect()
+    }
+}
+
+pub fn get_summary(cache : &mut Cache, sql: &mut Sql, input: &SummaryInput, context: &str) -> SummaryOutput {
+    if !cache.summary.contains_key(input) {
+        cache.summary.insert(input.clone(), compute_summary(sql, input, context));
+    }
+    if let Some(res) = cache.summary.get(input) {
+        res.clone()
+    }
+    else {
+        panic!("Summary cache miss");
+    }
+}
+
+pub fn compute_summary(
+    sql: &mut Sql,
+    input : &SummaryInput,
+    context: &str,
+) -> SummaryOutput {
+    // the SUMMARIZE column types differ between DuckDB versions, so cast them explicitly
+    let query = format!(
+        r#"
+        SELECT
+            column_name,
+            column_type,
+            CAST(count AS BIGINT),
+            CAST(null_percentage AS DOUBLE),
+            CAST(min AS VARCHAR),
+            CAST(max AS VARCHAR),
+            TRY_CAST(avg AS DOUBLE),
+            TRY_CAST(std AS DOUBLE),
+            CAST(approx_unique AS BIGINT)
+        FROM (SUMMARIZE SELECT * FROM {})
+       "#,
+        input.table
+    );
+    let result: duckdb::Result<SummaryOutput> = (|| {
+        let rows = sql.prepare(&query)?.query_map(params![], |row| {
+            Ok(SummaryRow {
+                column: row.get(0)?,
+                column_type: row.get(1)?,
+                count: row.get::<_, Option<i64>>(2)?.unwrap_or(0) as usize,
+                null_percentage: row.get::<_, Option<f64>>(3)?.unwrap_or(0.0),
+                min: row.get(4)?,
+                max: row.get(5)?,
+                mean: row.get(6)?,
+                stddev: row.get(7)?,
+                approx_distinct: row.get::<_, Option<i64>>(8)?.unwrap_or(0) as usize,
+            })
+        })?
+        .collect::<duckdb::Result<Vec<_>>>()?;
+        Ok(SummaryOutput { rows })
+    })();
+    match result {
+        Ok(res) => {
+            sql.log(query.clone(), context, None);
+            res
+        },
+        Err(e) => {
+            sql.log(query.clone(), context, Some(format!("Error computing summary: {:?}", e)));
+            SummaryOutput { rows: vec![] }
+        }
+    }
+}
+
+/// Fraction of NULL rows and number of NaN/inf values of a single column, ignoring any filter
+#[derive(Hash, Eq, PartialEq, Clone)]
+pub struct NullFractionInput {
+    pub table : ParsedString,
+    pub column : ParsedString,
+}
+
+#[derive(Clone)]
+pub struct NullFractionOutput {
+    // 0 for an empty table
+    pub fraction: f64,
+    // NaN and inf values, which poison MIN/MAX and sums
+    pub non_finite: usize,
+}
+
+pub fn get_null_fraction(cache : &mut Cache, sql: &mut Sql, input: &NullFractionInput, context: &str) -> NullFractionOutput {
+    if !cache.null_fraction.contains_key(input) {
+        cache.null_fraction.insert(input.clone(), compute_null_fraction(sql, input, context));
+    }
+    if let Some(res) = cache.null_fraction.get(input) {
+        res.clone()
+    }
+    else {
+        panic!("Null fraction cache miss");
+    }
+}
+
+pub fn compute_null_fraction(
+    sql: &mut Sql,
+    input : &NullFractionInput,
+    context: &str,
+) -> NullFractionOutput {
+    let query = format!(
+        r#"
+        SELECT COUNT(*), COUNT(t.{0}), COUNT(*) FILTER (WHERE NOT isfinite(TRY_CAST(t.{0} AS DOUBLE)))
+        FROM {1} AS t
+       "#,
+        input.column,
+        input.table
+    );
+    let result: duckdb::Result<NullFractionOutput> = (|| {
+        let (rows, set, non_finite) = sql.prepare(&query)?.query_row(params![], |row| {
+            Ok((row.get::<_, i64>(0)?, row.get::<_, i64>(1)?, row.get::<_, i64>(2)?))
+        })?;
+        let fraction = if rows > 0 { 1.0 - set as f64 / rows as f64 } else { 0.0 };
+        Ok(NullFractionOutput { fraction, non_finite: non_finite as usize })
+    })();
+    match result {
+        Ok(res) => {
+            sql.log(query.clone(), context, None);
+            res
+        },
+        Err(e) => {
+            sql.log(query.clone(), context, Some(format!("Error computing null fraction: {:?}", e)));
+            NullFractionOutput { fraction: 0.0, non_finite: 0 }
+        }
+    }
+}
+
+/// Render a table as CSV, quoting cells that contain a separator, quote or line break
+pub fn table_to_csv(header: &[&str], rows: &[Vec<String>]) -> String {
+    let escape = |cell: &str| {
+        if cell.contains([',', '"', '\n', '\r']) {
+            format!("\"{}\"", cell.replace('"', "\"\""))
+        } else {
+            cell.to_string()
+        }
+    };
+    let mut csv = header.iter().map(|h| escape(h)).collect::<Vec<_>>().join(",");
+    csv.push('\n');
+    for row in rows {
+        csv.push_str(&row.iter().map(|c| escape(c)).collect::<Vec<_>>().join(","));
+        csv.push('\n');
+    }
+    csv
+}
+
+/// Render a table as Markdown, pipes are escaped and line breaks flattened so every row stays one line
+pub fn table_to_markdown(header: &[&str], rows: &[Vec<String>]) -> String {
+    let escape = |cell: &str| cell.replace('\\', "\\\\").replace('|', "\\|").replace(['\n', '\r'], " ");
+    let line = |cells: Vec<String>| format!("| {} |\n", cells.join(" | "));
+    let mut md = line(header.iter().map(|h| escape(h)).collect());
+    md.push_str(&line(header.iter().map(|_| "---".to_string()).collect()));
+    for row in rows {
+        md.push_str(&line(row.iter().map(|c| escape(c)).collect()));
+    }
+    md
+}
+
+/// Number formatting shared by the displayed and exported statistics
+pub fn format_value(value: f64) -> String {
+    format!("{:.4}", value)
+}
+
+pub const STATS_SUMMARY_HEADER: [&str; 7] = ["curve", "count", "filtered fraction", "mean", "stddev", "min", "max"];
+
+/// One row per curve in the order of `STATS_SUMMARY_HEADER`, statistics of the x key
+/// through the stat cache. The first cell holds the label, the file and the filter.
+pub fn stats_summary(cache: &mut Cache, sql: &mut Sql, curves: &[(String, &HistogramSubInput)]) -> Vec<Vec<String>> {
+    curves.iter().map(|(label, curve)| {
+        let mut input = StatInput {
+            table: curve.table.clone(),
+            column: curve.x_key.clone(),
+            filters: curve.filter.clone(),
+        };
+        let stat = get_stat(cache, sql, &input, &format!("stat for curve {}", curve.id));
+        input.filters = SQLFilter { conditions: vec![] };
+        let total = get_stat(cache, sql, &input, &format!("unfiltered stat for curve {}", curve.id));
+        let filter = if curve.filter.conditions.is_empty() { "no filter".to_string() } else { curve.filter.to_sql() };
+        let fraction = if total.count > 0 { format_value(stat.count as f64 / total.count as f64) } else { String::new() };
+        vec![
+            format!("{}; file {}; filter {}", label, curve.table.name(), filter),
+            stat.count.to_string(),
+            fraction,
+            format_value(stat.mean),
+            format_value(stat.stddev),
+            format_value(stat.min),
+            format_value(stat.max),
+        ]
+    }).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::StrapTrack;
+
+    fn new_sql() -> Sql {
+        let sql = Sql::open(None);
+        assert!(sql.conn.is_some());
+        sql
+    }
+
+    fn write_parquet(dir: &tempfile::TempDir, name: &str, content: &str) -> ParsedString {
+        let strap = dir.path().join(format!("{}.strap", name));
+        std::fs::write(&strap, content).unwrap();
+        let parquet = dir.path().join(format!("{}.parquet", name));
+        StrapTrack::new(&strap).unwrap().to_parquet(parquet.to_str().unwrap(), 2).unwrap();
+        ParsedString::parse(parquet.to_str().unwrap()).unwrap()
+    }
+
+    fn curve(id: usize, table: &ParsedString, value_type: HistogramAggregation, filter: SQLFilter) -> HistogramSubInput {
+        HistogramSubInput {
+            id,
+            table: table.clone(),
+            filter,
+            x_key: ParsedString::parse("x").unwrap(),
+            value_type,
+            y_key: ParsedString::parse("y").unwrap(),
+        }
+    }
+
+    fn filter(column: &str, comparison: SQLFilterComparisonOperation, value: &str) -> SQLFilter {
+        SQLFilter {
+            conditions: vec![vec![SQLFilterComparison {
+                left: SQLFilterComparisonValue::Column(ParsedString::parse(column).unwrap()),
+                comparison,
+                right: SQLFilterComparisonValue::Number(value.to_string()),
+            }]],
+        }
+    }
+
+    /// The single multi-CTE query the histogram was computed with before per-curve caching
+    fn legacy_histogram(sql: &mut Sql, hist: &HistogramInput) -> Vec<HistogramBin> {
+        let mut filters = String::new();
+        let mut hists = Vec::new();
+        let mut coalesced = String::new();
+        let mut joins = String::new();
+        for (i, c) in hist.curves.iter().enumerate() {
+            let (y_value, y_error) = match c.value_type {
+                HistogramAggregation::Count => (format!("COUNT({})", c.y_key), format!("SQRT(COUNT({}))", c.y_key)),
+                HistogramAggregation::CountDistinct => (format!("COUNT(DISTINCT {})", c.y_key), "0.0".to_string()),
+                HistogramAggregation::Sum => (format!("SUM({})", c.y_key), format!("STDDEV({})", c.y_key)),
+                HistogramAggregation::Avg => (format!("AVG({})", c.y_key), format!("STDDEV({})", c.y_key)),
+                HistogramAggregation::Min => (format!("MIN({})", c.y_key), "0.0".to_string()),
+                HistogramAggregation::Max => (format!("MAX({})", c.y_key), "0.0".to_string()),
+                HistogramAggregation::Median => (format!("MEDIAN({})", c.y_key), format!("QUANTILE_CONT({0}, 0.75) - QUANTILE_CONT({0}, 0.25)", c.y_key)),
+                HistogramAggregation::Quantile(per_mille) => (format!("QUANTILE_CONT({}, {})", c.y_key, per_mille as f64 / 1000.0), "0.0".to_string()),
+            };
+            filters.push_str(&format!(
+                "filtered_{} AS ( SELECT * FROM {} WHERE ( {} IS NOT NULL AND {} IS NOT NULL ) {} ),\n",
+                i, c.table, c.x_key, c.y_key, c.filter.to_sql_and_prefix()
+            ));
+            hists.push(format!(
+                "hist_{} AS ( SELECT LEAST(stats.n_bins - 1, CAST(FLOOR((t.{} - stats.min_val) / ((stats.max_val - stats.min_val) / stats.n_bins)) AS INTEGER)) AS bucket, {} AS yvalue, {} AS yerror, FROM filtered_{} as t JOIN stats ON TRUE GROUP BY bucket )\n",
+                i, c.x_key, y_value, y_error, i
+            ));
+            coalesced.push_str(&format!("COALESCE(h{}.yvalue, 0) AS yvalue_{}, COALESCE(h{}.yerror, 0) AS yerror_{},\n", i, i, i, i));
+            joins.push_str(&format!("LEFT JOIN hist_{} AS h{} ON h{}.bucket = b.bucket\n", i, i, i));
+        }
+        let x_keys = hist.curves.iter().map(|c| c.x_key.as_str()).collect::<Vec<_>>().join(", ");
+        let combined = (0..hist.curves.len()).map(|i| format!("SELECT * FROM filtered_{}\n", i)).collect::<Vec<_>>().join("UNION ALL ");
+        let query = format!(
+            "WITH {} combined AS ( {} ), \
+             stats AS ( SELECT MIN(LEAST({})) AS min_val, MAX(GREATEST({})) AS max_val, {} AS n_bins FROM combined ), \
+             buckets AS ( SELECT g.bucket, stats.min_val + (g.bucket + 0.5) * ((stats.max_val - stats.min_val) / stats.n_bins) AS midpoint, \
+             (stats.max_val - stats.min_val) / stats.n_bins AS width FROM stats JOIN generate_series(0, stats.n_bins - 1) AS g(bucket) ON TRUE ), \
+             {} SELECT b.bucket, b.midpoint, b.width, {} FROM buckets AS b {} ORDER BY b.bucket",
+            filters, combined, x_keys, x_keys, hist.bins as i64, hists.join(","), coalesced, joins
+        );
+        let n_curves = hist.curves.len();
+        sql.prepare(&query).unwrap().query_map(params![], |row| {
+            let mut values = Vec::new();
+            for i in 0..n_curves {
+                values.push((row.get::<_, f64>(3 + i * 2)?, row.get::<_, f64>(4 + i * 2)?));
+            }
+            Ok((row.get::<_, f64>(1)?, row.get::<_, f64>(2)?, values))
+        }).unwrap()
+        .collect::<duckdb::Result<Vec<_>>>()
+        .unwrap()
+    }
+
+    #[test]
+    fn test_parsed_string_quoting() {
+        assert_eq!(ParsedString::parse("pt").unwrap().as_str(), "\"pt\"");
+        assert_eq!(ParsedString::parse("missing ET").unwrap().as_str(), "\"missing ET\"");
+        assert_eq!(ParsedString::parse("/data/run-1/out.parquet").unwrap().as_str(), "\"/data/run-1/out.parquet\"");
+        assert!(ParsedString::parse("").is_err());
+        assert!(ParsedString::parse("pt\"; DROP TABLE t; --").is_err());
+        assert!(ParsedString::parse("pt'").is_err());
+    }
+
+    #[test]
+    fn test_parsed_string_serde_revalidates() {
+        let parsed = ParsedString::parse("jet pt").unwrap();
+        let json = serde_json::to_string(&parsed).unwrap();
+        assert_eq!(json, "\"jet pt\"");
+        assert_eq!(serde_json::from_str::<ParsedString>(&json).unwrap(), parsed);
+        assert!(serde_json::from_str::<ParsedString>("\"x; DROP TABLE t\"").is_err());
+    }
+
+    #[test]
+    fn test_filter_validate() {
+        let mut f = filter("x", SQLFilterComparisonOperation::GreaterThan, "1.5e3");
+        assert!(f.validate().is_ok());
+        f.conditions[0][0].right = SQLFilterComparisonValue::Number("1; DROP TABLE t".to_string());
+        assert!(f.validate().is_err());
+    }
+
+    #[test]
+    fn test_filter_merge_and_missing_columns() {
+        let mut f = filter("x", SQLFilterComparisonOperation::GreaterThan, "1");
+        let mut preset = filter("nhits", SQLFilterComparisonOperation::GreaterThanOrEqual, "8");
+        preset.conditions[0][0].right = SQLFilterComparisonValue::Column(ParsedString::parse("x").unwrap());
+        preset.conditions.push(vec![]);
+        f.merge(&preset);
+        assert_eq!(f.to_sql(), "(\"x\" > 1) AND (\"nhits\" >= \"x\")");
+        assert_eq!(f.conditions.len(), 2);
+        let available = [ParsedString::parse("x").unwrap(), ParsedString::parse("y").unwrap()];
+        assert!(f.missing_columns(&available) == vec![ParsedString::parse("nhits").unwrap()]);
+    }
+
+    #[test]
+    fn test_filter_presets_round_trip() {
+        let mut presets = FilterPresets::default();
+        presets.presets.insert("quality".to_string(), filter("pt", SQLFilterComparisonOperation::GreaterThan, "20"));
+        presets.presets.insert("empty".to_string(), SQLFilter { conditions: vec![] });
+        let json = presets.to_json().unwrap();
+        assert!(json.contains("\"pt\""));
+        assert!(FilterPresets::from_json(&json).unwrap() == presets);
+
+        assert!(presets.rename("quality", "cuts"));
+        assert!(!presets.rename("missing", "other"));
+        assert!(!presets.rename("cuts", "empty"));
+        assert!(presets.presets.contains_key("cuts"));
+
+        // invalid column names and numbers are rejected
+        assert!(FilterPresets::from_json(&json.replace("\"pt\"", "\"pt; --\"")).is_err());
+        assert!(FilterPresets::from_json(&json.replace("\"20\"", "\"20 OR 1\"")).is_err());
+    }
+
+    #[test]
+    fn test_filter_sql() {
+        let mut f = filter("x", SQLFilterComparisonOperation::GreaterThan, "1");
+        assert!(!f.is_empty());
+        assert_eq!(f.to_sql(), "(\"x\" > 1)");
+        f.conditions[0].push(SQLFilterComparison {
+            left: SQLFilterComparisonValue::Column(ParsedString::parse("y").unwrap()),
+            comparison: SQLFilterComparisonOperation::LessThanOrEqual,
+            right: SQLFilterComparisonValue::Column(ParsedString::parse("x").unwrap()),
+        });
+        f.conditions.push(vec![]);
+        f.conditions.push(vec![SQLFilterComparison {
+            left: SQLFilterComparisonValue::Column(ParsedString::parse("z").unwrap()),
+            comparison: SQLFilterComparisonOperation::NotEqual,
+            right: SQLFilterComparisonValue::Number("0".to_string()),
+        }]);
+        assert_eq!(f.to_sql(), "(\"x\" > 1 OR \"y\" <= \"x\") AND (\"z\" != 0)");
+        assert_eq!(f.to_sql_where_prefix(), format!(" WHERE {}", f.to_sql()));
+        assert_eq!(f.to_sql_and_prefix(), format!(" AND {}", f.to_sql()));
+
+        let empty = SQLFilter { conditions: vec![vec![]] };
+        assert!(empty.is_empty());
+        assert_eq!(empty.to_sql_where_prefix(), "");
+    }
+
+    #[test]
+    fn test_column_names_with_spaces() {
+        let mut sql = new_sql();
+        sql.conn.as_ref().unwrap().execute_batch(r#"CREATE TABLE t AS SELECT 1.0 AS "missing ET", 2.0 AS pt"#).unwrap();
+        let mut cache = Cache::default();
+        let names = get_column_names(&mut cache, &mut sql, ColumnNamesInput { table: ParsedString::parse("t").unwrap() }, "columns");
+        assert_eq!(names, &vec![ParsedString::parse("missing ET").unwrap(), ParsedString::parse("pt").unwrap()]);
+    }
+
+    #[test]
+    fn test_error_log_resolves_on_success() {
+        let mut sql = new_sql();
+        let mut cache = Cache::default();
+        let missing = ColumnNamesInput { table: ParsedString::parse("missing").unwrap() };
+        assert!(get_column_names(&mut cache, &mut sql, missing, "columns of t").is_empty());
+        assert_eq!(sql.errors.len(), 1);
+        assert_eq!(sql.unseen_errors, 1);
+        assert_eq!(sql.errors[0].context, "columns of t");
+        assert!(!sql.errors[0].resolved);
+
+        sql.conn.as_ref().unwrap().execute_batch("CREATE TABLE t AS SELECT 1.0 AS x").unwrap();
+        let table = ColumnNamesInput { table: ParsedString::parse("t").unwrap() };
+        assert_eq!(get_column_names(&mut cache, &mut sql, table, "columns of t").len(), 1);
+        assert_eq!(sql.errors.len(), 1);
+        assert!(sql.errors[0].resolved);
+
+        for _ in 0..(MAX_SQL_ERRORS + 5) {
+            sql.push_error("other", "error".to_string());
+        }
+        assert_eq!(sql.errors.len(), MAX_SQL_ERRORS);
+        sql.clear_errors();
+        assert!(sql.errors.is_empty());
+        assert_eq!(sql.unseen_errors, 0);
+    }
+
+    #[test]
+    fn test_unavailable_database() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut sql = Sql::open(Some(dir.path().join("missing").join("strap.duckdb")));
+        assert!(sql.conn.is_none());
+        assert!(sql.connection_error.is_some());
+
+        let mut cache = Cache::default();
+        let table = ColumnNamesInput { table: ParsedString::parse("t").unwrap() };
+        assert!(get_column_names(&mut cache, &mut sql, table, "columns of t").is_empty());
+        assert!(sql.errors[0].error.contains("Database unavailable"));
+        assert!(matches!(sql.prepare("SELECT 1"), Err(duckdb::Error::DuckDBFailure(e, _)) if e.code == ErrorCode::CannotOpen));
+
+        // retrying with a writable location recovers
+        sql.db_path = Some(dir.path().join("strap.duckdb"));
+        assert!(sql.connect());
+        assert!(sql.connection_error.is_none());
+    }
+
+    #[test]
+    fn test_stat() {
+        let dir = tempfile::tempdir().unwrap();
+        let a = write_parquet(&dir, "a", "x 1.0 y 1.0\nx 2.0 y 1.0\nx 3.0\nx 6.0 y 0.0\ny 5.0\n");
+        let mut sql = new_sql();
+        let mut cache = Cache::default();
+        let stat = get_stat(&mut cache, &mut sql, &StatInput {
+            table: a.clone(),
+            column: ParsedString::parse("x").unwrap(),
+            filters: SQLFilter { conditions: vec![] },
+        }, "stat");
+        assert_eq!(stat.count, 4);
+        assert_eq!(stat.sum, 12.0);
+        assert_eq!(stat.mean, 3.0);
+        assert!((stat.stddev - (14.0f64 / 3.0).sqrt()).abs() < 1e-12);
+        assert_eq!(stat.min, 1.0);
+        assert_eq!(stat.max, 6.0);
+
+        let stat = get_stat(&mut cache, &mut sql, &StatInput {
+            table: a,
+            column: ParsedString::parse("x").unwrap(),
+            filters: filter("y", SQLFilterComparisonOperation::GreaterThan, "0"),
+        }, "stat");
+        assert_eq!(stat.count, 2);
+        assert_eq!(stat.sum, 3.0);
+        assert!(sql.history.iter().all(|(_, _, error)| error.is_none()));
+    }
+
+    #[test]
+    fn test_xy_stat() {
+        let dir = tempfile::tempdir().unwrap();
+        // y = 2x on the complete rows, the incomplete ones must not count
+        let a = write_parquet(&dir, "a", "x 1.0 y 2.0\nx 2.0 y 4.0\nx 3.0\nx 4.0 y 8.0\ny 5.0\n");
+        let mut sql = new_sql();
+        let mut cache = Cache::default();
+        let input = XYStatInput {
+            table: a.clone(),
+            x: ParsedString::parse("x").unwrap(),
+            y: ParsedString::parse("y").unwrap(),
+            filters: SQLFilter { conditions: vec![] },
+        };
+        let stat = get_xy_stat(&mut cache, &mut sql, &input, "xy stat");
+        assert_eq!(stat.pairs, 3);
+        assert!((stat.corr.unwrap() - 1.0).abs() < 1e-12);
+        // x = 1, 2, 4 has sample variance 7/3, covar(x, 2x) = 2 var(x)
+        assert!((stat.covar.unwrap() - 14.0 / 3.0).abs() < 1e-12);
+
+        let stat = get_xy_stat(&mut cache, &mut sql, &XYStatInput {
+            filters: filter("x", SQLFilterComparisonOperation::LessThan, "2"),
+            ..input
+        }, "xy stat");
+        assert_eq!(stat.pairs, 1);
+        assert!(stat.corr.is_none());
+        assert!(sql.history.iter().all(|(_, _, error)| error.is_none()));
+    }
+
+    #[test]
+    fn test_summary() {
+        let dir = tempfile::tempdir().unwrap();
+        let a = write_parquet(&dir, "a", "x 1.0 y 2.0\nx 3.0\nx 5.0\ny 4.0\n");
+        let mut sql = new_sql();
+        let mut cache = Cache::default();
+        let summary = get_summary(&mut cache, &mut sql, &SummaryInput { table: a }, "summary");
+        assert!(sql.history.iter().all(|(_, _, error)| error.is_none()));
+        assert_eq!(summary.rows.len(), 2);
+        let x = &summary.rows[0];
+        assert_eq!(x.column, "x");
+        assert_eq!(x.count, 4);
+        assert!((x.null_percentage - 25.0).abs() < 1e-9);
+        assert_eq!(x.mean, Some(3.0));
+        assert_eq!(x.min.as_deref().and_then(|v| v.parse::<f64>().ok()), Some(1.0));
+        assert_eq!(x.max.as_deref().and_then(|v| v.parse::<f64>().ok()), Some(5.0));
+        assert_eq!(summary.rows[1].column, "y");
+        assert_eq!(summary.cells()[0].len(), SummaryOutput::HEADER.len());
+    }
+
+    #[test]
+    fn test_null_fraction() {
+        let dir = tempfile::tempdir().unwrap();
+        let a = write_parquet(&dir, "a", "x 1.0 y 2.0\nx 3.0\nx 5.0\ny 4.0\n");
+        let mut sql = new_sql();
+        let mut cache = Cache::default();
+        let input = |column: &str| NullFractionInput { table: a.clone(), column: ParsedString::parse(column).unwrap() };
+        assert_eq!(get_null_fraction(&mut cache, &mut sql, &input("x"), "null x").fraction, 0.25);
+        assert_eq!(get_null_fraction(&mut cache, &mut sql, &input("y"), "null y").fraction, 0.5);
+        // only the requested columns are scanned
+        assert_eq!(cache.null_fraction.len(), 2);
+        assert_eq!(get_null_fraction(&mut cache, &mut sql, &input("x"), "null x").non_finite, 0);
+
+        let b = write_parquet(&dir, "b", "x NaN\nx 1.0\nx inf\n");
+        let input = NullFractionInput { table: b, column: ParsedString::parse("x").unwrap() };
+        assert_eq!(get_null_fraction(&mut cache, &mut sql, &input, "nan x").non_finite, 2);
+        assert!(sql.history.iter().all(|(_, _, error)| error.is_none()));
+    }
+
+    #[test]
+    fn test_histogram_normalized() {
+        let hist = HistogramOutput {
+            data: vec![
+                (0.25, 0.5, vec![(2.0, 1.0), (1.0, 0.5), (0.0, 0.0)]),
+                (0.75, 0.5, vec![(6.0, 2.0), (-4.0, 1.0), (0.0, 0.0)]),
+            ],
+            input: HistogramInput { bins: 2, curves: vec![], range: HistogramRange::default() },
+        };
+        let normalized = hist.normalized(&[Normalization::Area, Normalization::Max, Normalization::Percent]);
+        let values: Vec<_> = normalized.data.iter().map(|(_, _, values)| values.clone()).collect();
+        assert_eq!(values, vec![
+            vec![(0.5, 0.25), (0.25, 0.125), (0.0, 0.0)],
+            vec![(1.5, 0.5), (-1.0, 0.25), (0.0, 0.0)],
+        ]);
+        // the area of the first curve is 1, curves without a mode are unchanged
+        assert_eq!(normalized.data.iter().map(|(_, w, values)| w * values[0].0).sum::<f64>(), 1.0);
+        let values: Vec<_> = hist.normalized(&[Normalization::Percent]).data.iter().map(|(_, _, values)| values.clone()).collect();
+        assert_eq!(values[1], vec![(75.0, 25.0), (-4.0, 1.0), (0.0, 0.0)]);
+    }
+
+    #[test]
+    fn test_histogram_ratio() {
+        let hist = HistogramOutput {
+            data: vec![
+                (0.5, 1.0, vec![(6.0, 3.0), (2.0, 0.5)]),
+                (1.5, 1.0, vec![(0.0, 1.0), (4.0, 2.0)]),
+                (2.5, 1.0, vec![(5.0, 1.0), (0.0, 0.0)]),
+            ],
+            input: HistogramInput { bins: 3, curves: vec![], range: HistogramRange::default() },
+        };
+        let ratio = hist.ratio(0, 1);
+        // sqrt((3 / 2)^2 + (6 * 0.5 / 4)^2) = sqrt(2.25 + 0.5625)
+        assert_eq!(ratio[0], Some((3.0, 2.8125f64.sqrt())));
+        // an empty numerator keeps its own error
+        assert_eq!(ratio[1], Some((0.0, 0.25)));
+        assert_eq!(ratio[2], None);
+        assert_eq!(hist.ratio(1, 1)[0], Some((1.0, (2.0 * 0.0625f64).sqrt())));
+    }
+
+    #[test]
+    fn test_histogram_pull() {
+        let hist = HistogramOutput {
+            data: vec![
+                (0.5, 1.0, vec![(10.0, 3.0), (2.0, 4.0)]),
+                (1.5, 1.0, vec![(1.0, 0.0), (2.0, 1.0)]),
+                (2.5, 1.0, vec![(5.0, 0.0), (0.0, 0.0)]),
+            ],
+            input: HistogramInput { bins: 3, curves: vec![], range: HistogramRange::default() },
+        };
+        assert_eq!(hist.pull(0, 1), vec![Some(1.6), Some(-1.0), None]);
+        assert_eq!(hist.pull(1, 0)[0], Some(-1.6));
+    }
+
+    #[test]
+    fn test_bin_rule() {
+        assert_eq!(BinRule::Manual.bins(100, 1.0, 0.5), None);
+        assert_eq!(BinRule::Sturges.bins(100, 1.0, 0.5), Some(8));
+        assert_eq!(BinRule::Sturges.bins(0, 1.0, 0.5), Some(1));
+        // width 2 * 0.5 / 10 = 0.1
+        assert_eq!(BinRule::FreedmanDiaconis.bins(1000, 1.05, 0.5), Some(11));
+        assert_eq!(BinRule::FreedmanDiaconis.bins(1000, 1.0, 0.0), Some(11));
+        assert_eq!(BinRule::FreedmanDiaconis.bins(1000, 1e12, 0.5), Some(MAX_AUTO_BINS));
+
+        let dir = tempfile::tempdir().unwrap();
+        let content: String = (0..100).map(|i| format!("x {} y 1\n", i)).collect();
+        let a = write_parquet(&dir, "a", &content);
+        let hist = HistogramInput {
+            bins: 10,
+            curves: vec![curve(1, &a, HistogramAggregation::Count, SQLFilter { conditions: vec![] })],
+            range: HistogramRange::default(),
+        };
+        let input = |rule| BinCountInput { rule, curves: hist.range_curves(), range: hist.range };
+        let mut cache = Cache::default();
+        let mut sql = new_sql();
+        assert_eq!(get_bin_count(&mut cache, &mut sql, &input(BinRule::Sturges), "sturges").bins, Some(8));
+        // IQR about 50 of a range of 99 rows
+        let bins = get_bin_count(&mut cache, &mut sql, &input(BinRule::FreedmanDiaconis), "fd").bins.unwrap();
+        assert!((4..=6).contains(&bins), "{}", bins);
+        assert_eq!(get_bin_count(&mut cache, &mut sql, &input(BinRule::Manual), "manual").bins, None);
+    }
+
+    #[test]
+    fn test_histogram_median_quantile() {
+        assert_eq!(HistogramAggregation::Quantile(950).name(), "p95");
+        assert_eq!(HistogramAggregation::Quantile(995).name(), "p99.5");
+        assert_eq!(HistogramAggregation::Median.name(), "Median");
+
+        let dir = tempfile::tempdir().unwrap();
+        let a = write_parquet(&dir, "a", "x 0 y 1\nx 0 y 5\nx 0 y 2\nx 1 y 4\nx 1 y 3\n");
+        let hist = HistogramInput {
+            bins: 1,
+            curves: vec![
+                curve(1, &a, HistogramAggregation::Median, SQLFilter { conditions: vec![] }),
+                curve(2, &a, HistogramAggregation::Quantile(900), SQLFilter { conditions: vec![] }),
+            ],
+            range: HistogramRange::default(),
+        };
+        let output = get_histogram(&mut Cache::default(), &mut new_sql(), &hist, "quantiles");
+        let values = &output.data[0].2;
+        // the median error is the interquartile range 4 - 2
+        assert_eq!(values[0], (3.0, 2.0));
+        assert!((values[1].0 - 4.6).abs() < 1e-9, "{}", values[1].0);
+        assert_eq!(values[1].1, 0.0);
+    }
+
+    #[test]
+    fn test_histogram_count_distinct() {
+        assert_eq!(HistogramAggregation::CountDistinct.name(), "N distinct");
+        let dir = tempfile::tempdir().unwrap();
+        let a = write_parquet(&dir, "a", "x 0 y 7\nx 0 y 7\nx 0 y 8\nx 1 y 7\nx 1 y 7\nx 1 y 7\nx 1 y 9\nx 1 y 9\nx 1 y 10\n");
+        let hist = HistogramInput {
+            bins: 2,
+            curves: vec![curve(1, &a, HistogramAggregation::CountDistinct, SQLFilter { conditions: vec![] })],
+            range: HistogramRange::default(),
+        };
+        let output = get_histogram(&mut Cache::default(), &mut new_sql(), &hist, "distinct");
+        assert_eq!(output.data.iter().map(|(_, _, values)| values[0]).collect::<Vec<_>>(), vec![(2.0, 0.0), (3.0, 0.0)]);
+    }
+
+    #[test]
+    fn test_series_ordered_and_capped() {
+        let dir = tempfile::tempdir().unwrap();
+        let a = write_parquet(&dir, "a", "ts 3 y 30\nts 1 y 10\nts 2 y 20\ny 5\nts 4 y 40\n");
+        let input = |limit| SeriesInput {
+            table: a.clone(),
+            x: ParsedString::parse("ts").unwrap(),
+            y: ParsedString::parse("y").unwrap(),
+            filters: SQLFilter { conditions: vec![] },
+            limit,
+        };
+        let mut cache = Cache::default();
+        let mut sql = new_sql();
+        let all = get_series(&mut cache, &mut sql, &input(10), "series");
+        assert_eq!(all.points, vec![(1.0, 10.0), (2.0, 20.0), (3.0, 30.0), (4.0, 40.0)]);
+        assert!(!all.truncated);
+        let capped = get_series(&mut cache, &mut sql, &input(2), "capped series");
+        assert_eq!(capped.points, vec![(1.0, 10.0), (2.0, 20.0)]);
+        assert!(capped.truncated);
+        assert!(!get_series(&mut cache, &mut sql, &input(4), "exact series").truncated);
+    }
+
+    #[test]
+    fn test_box_plot() {
+        let dir = tempfile::tempdir().unwrap();
+        let a = write_parquet(&dir, "a", "x 0 y 1\nx 0 y 2\nx 0 y 3\nx 0 y 4\nx 0 y 5\nx 1 y 10\nx 1 y 20\nx 1 y 30\nx 3 y 7\n");
+        let input = |group| BoxPlotInput {
+            table: a.clone(),
+            filter: SQLFilter { conditions: vec![] },
+            y_key: ParsedString::parse("y").unwrap(),
+            group,
+        };
+        let summary = |min, q1, median, q3, max, count| BoxSummary { min, q1, median, q3, max, count };
+        let mut cache = Cache::default();
+        let mut sql = new_sql();
+        let overall = get_box_plot(&mut cache, &mut sql, &input(None), "box plot");
+        assert_eq!(overall.boxes, vec![(0, summary(1.0, 3.0, 5.0, 10.0, 30.0, 9))]);
+        // the row at x 3 is outside the grid
+        let grid = BucketGrid { min: 0.0, max: 2.0, bins: 2 };
+        let grouped = get_box_plot(&mut cache, &mut sql, &input(Some((ParsedString::parse("x").unwrap(), grid))), "grouped box plot");
+        assert_eq!(grouped.boxes, vec![
+            (0, summary(1.0, 2.0, 3.0, 4.0, 5.0, 5)),
+            (1, summary(10.0, 15.0, 20.0, 25.0, 30.0, 3)),
+        ]);
+    }
+
+    #[test]
+    fn test_poisson_interval() {
+        let (low, high) = poisson_interval(0.0);
+        assert_eq!(low, 0.0);
+        assert!((high - 1.8410).abs() < 1e-4, "{}", high);
+        // exp(-low) = 1 - tail and exp(-high) * (1 + high) = tail
+        let (low, high) = poisson_interval(1.0);
+        assert!((low - 0.17275).abs() < 1e-4, "{}", low);
+        assert!((high - 3.2995).abs() < 1e-3, "{}", high);
+        let (low, high) = poisson_interval(2.0);
+        assert!((low - 0.7083).abs() < 1e-3 && (high - 4.6384).abs() < 1e-3, "{} {}", low, high);
+        // large counts approach the symmetric sqrt(n), the approximation joins the exact intervals
+        let (low, high) = poisson_interval(400.0);
+        assert!((400.0 - low - 20.0).abs() < 0.5 && (high - 400.0 - 20.0).abs() < 1.5, "{} {}", low, high);
+        let (exact, approximated) = (poisson_interval(100.0), poisson_interval(101.0));
+        assert!(exact.0 < approximated.0 && exact.1 < approximated.1);
+        assert!((approximated.0 - exact.0 - 1.0).abs() < 0.1 && (approximated.1 - exact.1 - 1.0).abs() < 0.1);
+    }
+
+    #[test]
+    fn test_histogram_range_override() {
+        let dir = tempfile::tempdir().unwrap();
+        let a = write_parquet(&dir, "a", "x -5 y 1\nx 0.1 y 1\nx 0.7 y 1\nx 1.3 y 1\nx 2.9 y 1\nx 1e9 y 1\n");
+        let mut hist = HistogramInput {
+            bins: 3,
+            curves: vec![curve(1, &a, HistogramAggregation::Count, SQLFilter { conditions: vec![] })],
+            range: HistogramRange { min: Some(0.0), max: Some(3.0), clamp: false },
+        };
+        let mut cache = Cache::default();
+        let mut sql = new_sql();
+        let counts = |output: HistogramOutput| output.data.iter().map(|(x, w, values)| (*x, *w, values[0].0)).collect::<Vec<_>>();
+        assert_eq!(counts(get_histogram(&mut cache, &mut sql, &hist, "dropped")), vec![(0.5, 1.0, 2.0), (1.5, 1.0, 1.0), (2.5, 1.0, 1.0)]);
+        // the same cache recomputes for another range
+        hist.range.clamp = true;
+        assert_eq!(counts(get_histogram(&mut cache, &mut sql, &hist, "clamped")), vec![(0.5, 1.0, 3.0), (1.5, 1.0, 1.0), (2.5, 1.0, 2.0)]);
+        hist.range = HistogramRange { min: Some(-5.0), max: None, clamp: false };
+        let output = get_histogram(&mut cache, &mut sql, &hist, "auto max");
+        assert_eq!(output.data.last().unwrap().0 + output.data.last().unwrap().1 / 2., 1e9);
+        assert_eq!(output.data.iter().map(|(_, _, values)| values[0].0).sum::<f64>(), 6.0);
+    }
+
+    #[test]
+    fn test_sql_script_reproduces_histogram() {
+        let dir = tempfile::tempdir().unwrap();
+        let a = write_parquet(&dir, "a", "x 0.1 y 1.0\nx 0.7 y 2.0\nx 1.3 y 4.0\nx 2.9 y 0.5\n");
+        let hist = HistogramInput {
+            range: HistogramRange::default(),
+            bins: 3,
+            curves: vec![
+                curve(1, &a, HistogramAggregation::Count, SQLFilter { conditions: vec![] }),
+                curve(2, &a, HistogramAggregation::Avg, filter("y", SQLFilterComparisonOperation::GreaterThan, "0.75")),
+            ],
+        };
+        let mut sql = new_sql();
+        let mut cache = Cache::default();
+        let output = get_histogram(&mut cache, &mut sql, &hist, "histogram");
+        let script = histogram_sql_script(&mut cache, &mut sql, &hist, "histogram");
+        assert!(script.contains("-- Curve 2: Avg of y vs x"));
+
+        // statements in order: range, one per curve, one stat per curve
+        let statements: Vec<&str> = script.split(";\n").filter(|s| s.contains("SELECT")).collect();
+        assert_eq!(statements.len(), 1 + 2 * hist.curves.len());
+        for (i, statement) in statements[1..=hist.curves.len()].iter().enumerate() {
+            let mut values = vec![(0.0, 0.0); hist.bins];
+            let rows = sql.prepare(statement).unwrap().query_map(params![], |row| {
+                Ok((row.get::<_, i64>(0)?, row.get::<_, Option<f64>>(1)?, row.get::<_, Option<f64>>(2)?))
+            }).unwrap().collect::<duckdb::Result<Vec<_>>>().unwrap();
+            for (bucket, y, e) in rows {
+                values[bucket as usize] = (y.unwrap_or(0.0), e.unwrap_or(0.0));
+            }
+            let cached: Vec<(f64, f64)> = output.data.iter().map(|(_, _, v)| v[i]).collect();
+            assert_eq!(values, cached);
+        }
+        let count: i64 = sql.prepare(statements[1 + hist.curves.len()]).unwrap()
+            .query_row(params![], |row| row.get(1)).unwrap();
+        assert_eq!(count, 4);
+    }
+
+    #[test]
+    fn test_table_to_csv() {
+        let csv = table_to_csv(&["a", "b"], &[
+            vec!["1".to_string(), "x,y".to_string()],
+            vec!["say \"hi\"".to_string(), String::new()],
+        ]);
+        assert_eq!(csv, "a,b\n1,\"x,y\"\n\"say \"\"hi\"\"\",\n");
+    }
+
+    #[test]
+    fn test_single_curve_histogram() {
+        let dir = tempfile::tempdir().unwrap();
+        let a = write_parquet(&dir, "a", "x 0.0 y 1.0\nx 1.0 y 1.0\nx 2.0 y 1.0\nx 3.0 y 1.0\nx 4.0 y 1.0\n");
+        let hist = HistogramInput {
+            range: HistogramRange::default(),
+            bins: 2,
+            curves: vec![curve(1, &a, HistogramAggregation::Count, SQLFilter { conditions: vec![] })],
+        };
+        let mut sql = new_sql();
+        let mut cache = Cache::default();
+        let output = get_histogram(&mut cache, &mut sql, &hist, "histogram");
+        // the maximum falls into the last bin
+        assert_eq!(output.data, vec![
+            (1.0, 2.0, vec![(2.0, 2.0f64.sqrt())]),
+            (3.0, 2.0, vec![(3.0, 3.0f64.sqrt())]),
+        ]);
+    }
+
+    #[test]
+    fn test_per_curve_histogram_matches_legacy_query() {
+        let dir = tempfile::tempdir().unwrap();
+        let a = write_parquet(&dir, "a", "x 0.5 y 1.0\nx 1.5 y 2.0\nx 2.25 y 4.0\nx 3.0 y 0.5\ny 7.0\n");
+        let b = write_parquet(&dir, "b", "x -1.0 y 3.0\nx 0.75 y 1.5\nx 2.5 y 2.5\nx 9.0\n");
+        let hist = HistogramInput {
+            range: HistogramRange::default(),
+            bins: 4,
+            curves: vec![
+                curve(1, &a, HistogramAggregation::Count, SQLFilter { conditions: vec![] }),
+                curve(2, &b, HistogramAggregation::Avg, filter("y", SQLFilterComparisonOperation::GreaterThan, "1")),
+                curve(3, &a, HistogramAggregation::Sum, filter("x", SQLFilterComparisonOperation::LessThan, "3")),
+                curve(4, &a, HistogramAggregation::Min, SQLFilter { conditions: vec![] }),
+                curve(5, &b, HistogramAggregation::Max, SQLFilter { conditions: vec![] }),
+            ],
+        };
+
+        let mut sql = new_sql();
+        let mut cache = Cache::default();
+        let expected = legacy_histogram(&mut sql, &hist);
+        let output = get_histogram(&mut cache, &mut sql, &hist, "histogram");
+
+        assert_eq!(expected.len(), 4);
+        assert_eq!(output.data, expected);
+        assert!(sql.history.iter().all(|(_, _, error)| error.is_none()));
+    }
+
+    #[test]
+    fn test_list_column_unnest() {
+        let dir = tempfile::tempdir().unwrap();
+        let strap = dir.path().join("lists.strap");
+        std::fs::write(&strap, "weights 0.1,0.2,0.3 x 1\nx 2\nweights 4 x 3\n").unwrap();
+        let parquet = dir.path().join("lists.parquet");
+        StrapTrack::new(&strap).unwrap().to_parquet(parquet.to_str().unwrap(), 2).unwrap();
+        let mut sql = new_sql();
+        let query = format!("SELECT x, UNNEST(weights) FROM read_parquet('{}') ORDER BY x, 2", parquet.to_string_lossy());
+        let rows = sql.prepare(&query).unwrap().query_map(params![], |row| {
+            Ok((row.get::<_, f64>(0)?, row.get::<_, f64>(1)?))
+        }).unwrap().collect::<duckdb::Result<Vec<_>>>().unwrap();
+        assert_eq!(rows, vec![(1.0, 0.1), (1.0, 0.2), (1.0, 0.3), (3.0, 4.0)]);
+    }
+
+    #[test]
+    fn test_group_by_binned_matches_histogram() {
+        let dir = tempfile::tempdir().unwrap();
+        // x on multiples of 0.1, many of them on bin edges
+        let content: String = (0..=30)
+            .map(|i| match i % 7 {
+                3 => format!("x {}\n", i as f64 * 0.1),
+                5 => format!("y {}\n", i),
+                _ => format!("x {} y {}\n", i as f64 * 0.1, (i * i) as f64 * 0.5),
+            })
+            .collect();
+        let strap = dir.path().join("a.strap");
+        std::fs::write(&strap, &content).unwrap();
+        let a = write_parquet(&dir, "a", &content);
+        let track = StrapTrack::new(&strap).unwrap();
+        for (value_type, agg) in [
+            (HistogramAggregation::Count, crate::Aggregation::Count),
+            (HistogramAggregation::Sum, crate::Aggregation::Sum),
+            (HistogramAggregation::Avg, crate::Aggregation::Mean),
+        ] {
+            let hist = HistogramInput { range: HistogramRange::default(), bins: 7, curves: vec![curve(1, &a, value_type, SQLFilter { conditions: vec![] })] };
+            let mut sql = new_sql();
+            let output = get_histogram(&mut Cache::default(), &mut sql, &hist, "histogram");
+            let (binned, skipped) = track.group_by_binned("x", 7, None, "y", agg).unwrap();
+            assert_eq!(skipped, 8);
+            assert_eq!(binned.len(), output.data.len());
+            for ((center, width, value, _), (expected_center, expected_width, values)) in binned.iter().zip(&output.data) {
+                assert_eq!((center, width), (expected_center, expected_width));
+                assert!((value - values[0].0).abs() <= 1e-9 * value.abs().max(1.0), "{} {} {}", value_type, value, values[0].0);
+            }
+        }
+    }
+
+    #[test]
+    fn test_editing_one_curve_reuses_other_curves() {
+        let dir = tempfile::tempdir().unwrap();
+        let a = write_parquet(&dir, "a", "x 0.0 y 1.0\nx 1.0 y 2.0\nx 2.0 y 3.0\nx 4.0 y 4.0\n");
+        let mut hist = HistogramInput {
+            range: HistogramRange::default(),
+            bins: 2,
+            curves: vec![
+                curve(1, &a, HistogramAggregation::Count, SQLFilter { conditions: vec![] }),
+                curve(2, &a, HistogramAggregation::Sum, SQLFilter { conditions: vec![] }),
+            ],
+        };
+
+        let mut sql = new_sql();
+        let mut cache = Cache::default();
+        get_histogram(&mut cache, &mut sql, &hist, "histogram");
+        assert_eq!(cache.histogram_curve.len(), 2);
+
+        // the range is unchanged since the first curve still covers all rows
+        hist.curves[1].filter = filter("y", SQLFilterComparisonOperation::GreaterThan, "2");
+        let queries = sql.counter;
+        let output = get_histogram(&mut cache, &mut sql, &hist, "histogram");
+        assert_eq!(cache.histogram_curve.len(), 3);
+        // one range query plus one query for the edited curve
+        assert_eq!(sql.counter, queries + 2);
+        assert_eq!(output.data[0].2, vec![(2.0, 2.0f64.sqrt()), (0.0, 0.0)]);
+        assert_eq!(output.data[1].2[1].0, 7.0);
+        assert!((output.data[1].2[1].1 - 0.5f64.sqrt()).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_invalidate_table_requeries_changed_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let a = write_parquet(&dir, "a", "x 0.0 y 1.0\nx 1.0 y 1.0\n");
+        let b = write_parquet(&dir, "b", "x 0.0 y 1.0\nx 1.0 y 1.0\n");
+        let hist = |table: &ParsedString| HistogramInput {
+            range: HistogramRange::default(),
+            bins: 1,
+            curves: vec![curve(1, table, HistogramAggregation::Count, SQLFilter { conditions: vec![] })],
+        };
+        let mut sql = new_sql();
+        let mut cache = Cache::default();
+        get_histogram(&mut cache, &mut sql, &hist(&a), "histogram a");
+        get_histogram(&mut cache, &mut sql, &hist(&b), "histogram b");
+
+        write_parquet(&dir, "a", "x 0.0 y 1.0\nx 1.0 y 1.0\nx 0.5 y 1.0\n");
+        // stale until invalidated
+        assert_eq!(get_histogram(&mut cache, &mut sql, &hist(&a), "histogram a").data[0].2[0].0, 2.0);
+        cache.invalidate_table(&a);
+        assert_eq!(cache.histogram.len(), 1);
+        assert_eq!(cache.histogram_curve.len(), 1);
+        assert_eq!(get_histogram(&mut cache, &mut sql, &hist(&a), "histogram a").data[0].2[0].0, 3.0);
+    }
+
+    #[test]
+    fn test_avg_histogram_is_not_integer_truncated() {
+        let dir = tempfile::tempdir().unwrap();
+        let a = write_parquet(&dir, "a", "x 0.1 y 0.25\nx 0.2 y 0.5\nx 0.7 y 1.75\nx 0.8 y 1.5\n");
+        let hist = HistogramInput {
+            range: HistogramRange::default(),
+            bins: 2,
+            curves: vec![curve(1, &a, HistogramAggregation::Avg, SQLFilter { conditions: vec![] })],
+        };
+
+        let mut sql = new_sql();
+        let mut cache = Cache::default();
+        let output = get_histogram(&mut cache, &mut sql, &hist, "histogram");
+
+        assert_eq!(output.data.len(), 2);
+        let (center, width, values) = &output.data[0];
+        assert!((center - 0.275).abs() < 1e-12);
+        assert!((width - 0.35).abs() < 1e-12);
+        assert!((values[0].0 - 0.375).abs() < 1e-12);
+        let (center, _, values) = &output.data[1];
+        assert!((center - 0.625).abs() < 1e-12);
+        assert!((values[0].0 - 1.625).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_histogram_without_rows_is_empty() {
+        let dir = tempfile::tempdir().unwrap();
+        let a = write_parquet(&dir, "a", "x 1.0 y 1.0\n");
+        let hist = HistogramInput {
+            range: HistogramRange::default(),
+            bins: 3,
+            curves: vec![curve(1, &a, HistogramAggregation::Count, filter("x", SQLFilterComparisonOperation::GreaterThan, "5"))],
+        };
+
+        let mut sql = new_sql();
+        let mut cache = Cache::default();
+        let output = get_histogram(&mut cache, &mut sql, &hist, "histogram");
+        assert!(output.data.is_empty());
+    }
+
+    #[test]
+    fn test_directory_table() {
+        let dir = tempfile::tempdir().unwrap();
+        write_parquet(&dir, "run_1", "x 1.0 a 1.0\nx 2.0 a 1.0\n");
+        write_parquet(&dir, "run_2", "x 3.0 b 2.0\n");
+        // the STRAP sources next to them are not matched
+        let table = ParsedString::parse_table(dir.path().to_str().unwrap()).unwrap();
+        assert_eq!(table.glob().unwrap(), format!("{}/*.parquet", dir.path().to_str().unwrap()));
+        assert_eq!(table.name(), table.glob().unwrap());
+
+        let mut sql = new_sql();
+        let mut cache = Cache::default();
+        let mut columns: Vec<String> = get_column_names(&mut cache, &mut sql, ColumnNamesInput { table: table.clone() }, "columns")
+            .iter().map(|c| c.name().to_string()).collect();
+        columns.sort();
+        assert_eq!(columns, vec!["a", "b", "x"]);
+
+        let stat = get_stat(&mut cache, &mut sql, &StatInput {
+            table: table.clone(),
+            column: ParsedString::parse("b").unwrap(),
+            filters: SQLFilter { conditions: vec![] },
+        }, "stat of b");
+        assert_eq!(stat.count, 1);
+        let files = FileCountInput { table: table.clone() };
+        assert_eq!(get_file_count(&mut cache, &mut sql, &files, "files").files, Some(2));
+
+        // new files are picked up once the table is invalidated
+        write_parquet(&dir, "run_3", "x 4.0\n");
+        assert_eq!(get_file_count(&mut cache, &mut sql, &files, "files").files, Some(2));
+        cache.invalidate_table(&table);
+        assert_eq!(get_file_count(&mut cache, &mut sql, &files, "files").files, Some(3));
+
+        // the glob survives serialization
+        let json = serde_json::to_string(&table).unwrap();
+        assert_eq!(serde_json::from_str::<ParsedString>(&json).unwrap(), table);
+        assert!(ParsedString::parse_glob("out/'*.parquet").is_err());
+        assert!(matches!(ParsedString::parse_glob("out/'*.parquet"), Err(duckdb::Error::InvalidPath(_))));
+    }
+
+    #[test]
+    fn test_file_list_table() {
+        let dir_a = tempfile::tempdir().unwrap();
+        let dir_b = tempfile::tempdir().unwrap();
+        let a = write_parquet(&dir_a, "run_1", "x 1.0 a 1.0\n");
+        let b = write_parquet(&dir_b, "run_2", "x 2.0 b 2.0\nx 3.0\n");
+        let c = write_parquet(&dir_b, "run_3", "x 4.0\n");
+        let (a, b, c) = (a.name(), b.name(), c.name());
+
+        let table = ParsedString::parse_files(&[a, b, c]).unwrap();
+        assert_eq!(table.files(), { let mut f = vec![a, b, c]; f.sort(); f });
+        // order-insensitive
+        assert_eq!(table, ParsedString::parse_files(&[c, a, b]).unwrap());
+        assert_eq!(table, ParsedString::parse_files(&[a, c]).unwrap().with_file(b).unwrap());
+        // down to one file it is a plain table again
+        assert_eq!(ParsedString::parse_files(&[a, b]).unwrap().without_file(b).unwrap(), ParsedString::parse(a).unwrap());
+
+        let mut sql = new_sql();
+        let mut cache = Cache::default();
+        let mut columns: Vec<String> = get_column_names(&mut cache, &mut sql, ColumnNamesInput { table: table.clone() }, "columns")
+            .iter().map(|c| c.name().to_string()).collect();
+        columns.sort();
+        assert_eq!(columns, vec!["a", "b", "x"]);
+        let stat = get_stat(&mut cache, &mut sql, &StatInput {
+            table: table.clone(),
+            column: ParsedString::parse("x").unwrap(),
+            filters: SQLFilter { conditions: vec![] },
+        }, "stat of x");
+        assert_eq!((stat.count, stat.sum), (4, 10.0));
+
+        let json = serde_json::to_string(&table).unwrap();
+        assert_eq!(serde_json::from_str::<ParsedString>(&json).unwrap(), table);
+    }
+
+    #[test]
+    fn test_quoted_key_columns() {
+        let dir = tempfile::tempdir().unwrap();
+        let table = write_parquet(&dir, "run", "\"jet pt\" 45.5 eta 0.3\n\"jet pt\" 30.5\n");
+        let mut sql = new_sql();
+        let mut cache = Cache::default();
+        let columns: Vec<String> = get_column_names(&mut cache, &mut sql, ColumnNamesInput { table: table.clone() }, "columns")
+            .iter().map(|c| c.name().to_string()).collect();
+        assert!(columns.contains(&"jet pt".to_string()), "{:?}", columns);
+        let stat = get_stat(&mut cache, &mut sql, &StatInput {
+            table,
+            column: ParsedString::parse("jet pt").unwrap(),
+            filters: SQLFilter { conditions: vec![] },
+        }, "stat of jet pt");
+        assert_eq!((stat.count, stat.sum), (2, 76.0));
+    }
+
+    #[test]
+    fn test_units() {
+        let dir = tempfile::tempdir().unwrap();
+        let table = write_parquet(&dir, "run", "@strap-units x GeV\nx 1.0 y 2.0\n");
+        let mut cache = Cache::default();
+        let units = get_units(&mut cache, &table);
+        assert_eq!(units.get("x").map(String::as_str), Some("GeV"));
+        assert_eq!(units.get("y"), None);
+        cache.invalidate_table(&table);
+        assert!(cache.units.is_empty());
+    }
+
+    #[test]
+    fn test_table_to_markdown() {
+        let rows = vec![vec!["x | y".to_string(), "a\\b\nc".to_string()]];
+        let md = table_to_markdown(&["name", "filter"], &rows);
+        assert_eq!(md, "| name | filter |\n| --- | --- |\n| x \\| y | a\\\\b c |\n");
+    }
+
+    #[test]
+    fn test_stats_summary() {
+        let dir = tempfile::tempdir().unwrap();
+        let table = write_parquet(&dir, "run", "x 1.0\nx 2.0\nx 3.0\nx 4.0\n");
+        let c = curve(1, &table, HistogramAggregation::Count, filter("x", SQLFilterComparisonOperation::GreaterThan, "2"));
+        let mut sql = new_sql();
+        let mut cache = Cache::default();
+        let rows = stats_summary(&mut cache, &mut sql, &[("1. x".to_string(), &c)]);
+        assert_eq!(rows[0][1..], ["2", "0.5000", "3.5000", "0.7071", "3.0000", "4.0000"]);
+        assert!(rows[0][0].starts_with("1. x; file "));
+        assert!(rows[0][0].ends_with(&c.filter.to_sql()));
+        let md = table_to_markdown(&STATS_SUMMARY_HEADER, &rows);
+        assert_eq!(md.lines().count(), 3);
+    }
+}
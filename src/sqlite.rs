@@ -0,0 +1,234 @@
+//! SQLite export of STRAP data
+use arrow::datatypes::DataType;
+use itertools::Itertools;
+use rusqlite::Connection;
+use rusqlite::types::Value;
+
+use crate::{StrapTrack, StrapValue};
+
+/// What to do if the target table already exists
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IfExists {
+    Fail,
+    Replace,
+    /// Insert into the existing table, adding columns it does not have yet
+    Append,
+}
+
+#[derive(Debug, Clone)]
+pub struct SqliteOptions {
+    pub if_exists: IfExists,
+    /// Rows inserted per transaction
+    pub batch_size: usize,
+    /// Column to create an index on, e.g. `ts`
+    pub index: Option<String>,
+}
+
+impl Default for SqliteOptions {
+    fn default() -> Self {
+        Self {
+            if_exists: IfExists::Fail,
+            batch_size: 10000,
+            index: None,
+        }
+    }
+}
+
+/// Quote an identifier, STRAP keys may contain any non-whitespace character
+fn quote(name: &str) -> String {
+    format!("\"{}\"", name.replace('"', "\"\""))
+}
+
+fn quote_literal(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "''"))
+}
+
+/// Column type of a column written as `data_type` by the parquet conversion
+fn affinity(data_type: &DataType) -> &'static str {
+    match data_type {
+        DataType::Utf8 | DataType::List(_) => "TEXT",
+        DataType::Boolean => "INTEGER",
+        data_type if data_type.is_integer() => "INTEGER",
+        _ => "REAL",
+    }
+}
+
+/// `value` of a column of type `data_type`, flags are 0 and 1, lists are text like
+/// `1,2` and numbers in text columns are text like in parquet
+fn sql_value(value: &StrapValue, data_type: &DataType) -> Value {
+    match (value, data_type) {
+        (value, DataType::Boolean) => StrapTrack::flag(value).map_or(Value::Null, |flag| Value::Integer(flag.into())),
+        (value, DataType::Utf8) => Value::Text(value.to_string()),
+        (StrapValue::Float(value), _) => Value::Real(*value),
+        (value, _) => Value::Text(value.to_string()),
+    }
+}
+
+impl StrapTrack {
+    /// Write STRAP data into an SQLite table with one nullable column per key, REAL,
+    /// INTEGER or TEXT after the type the parquet conversion infers. Lines without
+    /// values are left out like in `to_parquet`.
+    pub fn to_sqlite(
+        &self,
+        path: &str,
+        table: &str,
+        options: &SqliteOptions,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let (column_names, types) = self.value_columns(&Default::default())?;
+        if column_names.is_empty() {
+            return Err(format!("no columns to write to table {}, the input has no values", table).into());
+        }
+        let column_types: Vec<DataType> = column_names.iter().map(|c| types.get(c).cloned().unwrap_or(DataType::Float64)).collect();
+        let mut conn = Connection::open(path)?;
+
+        let exists: bool = conn.query_row(
+            "SELECT COUNT(*) > 0 FROM sqlite_master WHERE type = 'table' AND name = ?1",
+            [table],
+            |row| row.get(0),
+        )?;
+        if exists {
+            match options.if_exists {
+                IfExists::Fail => return Err(format!("table {} already exists", table).into()),
+                IfExists::Replace => {
+                    conn.execute(&format!("DROP TABLE {}", quote(table)), [])?;
+                },
+                IfExists::Append => {
+                    let existing = conn.prepare(&format!("SELECT name FROM pragma_table_info({})", quote_literal(table)))?
+                        .query_map([], |row| row.get::<_, String>(0))?
+                        .collect::<Result<Vec<_>, _>>()?;
+                    for (col, data_type) in column_names.iter().zip(&column_types).filter(|(c, _)| !existing.contains(c)) {
+                        conn.execute(&format!("ALTER TABLE {} ADD COLUMN {} {}", quote(table), quote(col), affinity(data_type)), [])?;
+                    }
+                },
+            }
+        }
+        if !exists || options.if_exists == IfExists::Replace {
+            let columns = column_names.iter().zip(&column_types)
+                .map(|(c, data_type)| format!("{} {}", quote(c), affinity(data_type)))
+                .collect::<Vec<_>>()
+                .join(", ");
+            conn.execute(&format!("CREATE TABLE {} ({})", quote(table), columns), [])?;
+        }
+
+        let insert = format!(
+            "INSERT INTO {} ({}) VALUES ({})",
+            quote(table),
+            column_names.iter().map(|c| quote(c)).collect::<Vec<_>>().join(", "),
+            (1..=column_names.len()).map(|i| format!("?{}", i)).collect::<Vec<_>>().join(", "),
+        );
+        let mut rows = self.iter_values()?.filter_ok(|row| !row.is_empty()).peekable();
+        while rows.peek().is_some() {
+            let tx = conn.transaction()?;
+            {
+                let mut stmt = tx.prepare_cached(&insert)?;
+                for row in rows.by_ref().take(options.batch_size.max(1)) {
+                    let row = row?;
+                    let values: Vec<Value> = column_names.iter().zip(&column_types)
+                        .map(|(c, data_type)| row.get(c).map_or(Value::Null, |value| sql_value(value, data_type)))
+                        .collect();
+                    stmt.execute(rusqlite::params_from_iter(values))?;
+                }
+            }
+            tx.commit()?;
+        }
+
+        if let Some(index) = &options.index {
+            conn.execute(
+                &format!(
+                    "CREATE INDEX IF NOT EXISTS {} ON {} ({})",
+                    quote(&format!("{}_{}_idx", table, index)),
+                    quote(table),
+                    quote(index)
+                ),
+                [],
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use tempfile::NamedTempFile;
+
+    fn track(content: &str) -> (NamedTempFile, StrapTrack) {
+        let mut file = NamedTempFile::with_suffix(".strap").unwrap();
+        write!(file, "{}", content).unwrap();
+        let track = StrapTrack::new(file.path()).unwrap();
+        (file, track)
+    }
+
+    #[test]
+    fn test_to_sqlite_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = dir.path().join("out.sqlite");
+        let db = db.to_str().unwrap();
+        let (_file, track) = track("ts 1.0 x 2.5\n# comment\nts 2.0\n\nts 3.0 x -1.0\n");
+        let options = SqliteOptions { batch_size: 2, index: Some("ts".to_string()), ..Default::default() };
+        track.to_sqlite(db, "strap", &options).unwrap();
+
+        let conn = Connection::open(db).unwrap();
+        let count: i64 = conn.query_row("SELECT COUNT(*) FROM strap", [], |row| row.get(0)).unwrap();
+        assert_eq!(count, 3);
+        let x: Vec<Option<f64>> = conn.prepare("SELECT x FROM strap ORDER BY ts").unwrap()
+            .query_map([], |row| row.get(0)).unwrap()
+            .collect::<Result<_, _>>().unwrap();
+        assert_eq!(x, vec![Some(2.5), None, Some(-1.0)]);
+        let indexes: i64 = conn.query_row("SELECT COUNT(*) FROM sqlite_master WHERE type = 'index'", [], |row| row.get(0)).unwrap();
+        assert_eq!(indexes, 1);
+    }
+
+    #[test]
+    fn test_to_sqlite_affinities() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = dir.path().join("out.sqlite");
+        let db = db.to_str().unwrap();
+        let (_file, track) = track("@strap2 n:i64 x:f64 run:str\n1 2.5 A17\n2 0.5 18\n");
+        track.to_sqlite(db, "strap", &SqliteOptions::default()).unwrap();
+
+        let conn = Connection::open(db).unwrap();
+        let types: Vec<(String, String)> = conn.prepare("SELECT name, type FROM pragma_table_info('strap')").unwrap()
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?))).unwrap()
+            .collect::<Result<_, _>>().unwrap();
+        assert_eq!(types, [("n", "INTEGER"), ("run", "TEXT"), ("x", "REAL")].map(|(n, t)| (n.to_string(), t.to_string())));
+        let rows: Vec<(i64, String)> = conn.prepare("SELECT n, run FROM strap ORDER BY n").unwrap()
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?))).unwrap()
+            .collect::<Result<_, _>>().unwrap();
+        assert_eq!(rows, [(1, "A17".to_string()), (2, "18".to_string())]);
+    }
+
+    #[test]
+    fn test_to_sqlite_no_columns() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = dir.path().join("out.sqlite");
+        let (_file, track) = track("# only a comment\n");
+        let error = track.to_sqlite(db.to_str().unwrap(), "strap", &SqliteOptions::default()).unwrap_err();
+        assert!(error.to_string().contains("no columns"), "{}", error);
+    }
+
+    #[test]
+    fn test_to_sqlite_existing_table() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = dir.path().join("out.sqlite");
+        let db = db.to_str().unwrap();
+        let (_other, other) = track("ts 2.0 y 4.0\n");
+        let (_file, track) = track("ts 1.0\n");
+        track.to_sqlite(db, "strap", &SqliteOptions::default()).unwrap();
+        assert!(track.to_sqlite(db, "strap", &SqliteOptions::default()).is_err());
+
+        let append = SqliteOptions { if_exists: IfExists::Append, ..Default::default() };
+        other.to_sqlite(db, "strap", &append).unwrap();
+        let conn = Connection::open(db).unwrap();
+        let (count, y): (i64, f64) = conn.query_row("SELECT COUNT(*), SUM(y) FROM strap", [], |row| Ok((row.get(0)?, row.get(1)?))).unwrap();
+        assert_eq!((count, y), (2, 4.0));
+        drop(conn);
+
+        let replace = SqliteOptions { if_exists: IfExists::Replace, ..Default::default() };
+        track.to_sqlite(db, "strap", &replace).unwrap();
+        let conn = Connection::open(db).unwrap();
+        let count: i64 = conn.query_row("SELECT COUNT(*) FROM strap", [], |row| row.get(0)).unwrap();
+        assert_eq!(count, 1);
+    }
+}
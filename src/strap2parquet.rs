@@ -0,0 +1,319 @@
+use std::path::PathBuf;
+
+use arrow::datatypes::TimeUnit;
+use clap::{Parser, Subcommand};
+use itertools::Itertools;
+
+use straptrack::fingerprint::is_up_to_date;
+use straptrack::{Comparison, DecimalSeparator, DuplicateKeyPolicy, InputCompression, ParquetCompression, ParquetOptions, ParseMode, ReadBackend, SpecialValuePolicy, StrapTrack, StrapTrackOptions, ROW_INDEX_COLUMN};
+
+fn parse_entry(entry: &str) -> Result<(String, String), String> {
+    let (key, value) = entry.split_once('=').ok_or_else(|| format!("expected KEY=VALUE, got {}", entry))?;
+    Ok((key.to_string(), value.to_string()))
+}
+
+fn parse_time_unit(unit: &str) -> Result<TimeUnit, String> {
+    match unit {
+        "s" => Ok(TimeUnit::Second),
+        "ms" => Ok(TimeUnit::Millisecond),
+        "us" => Ok(TimeUnit::Microsecond),
+        "ns" => Ok(TimeUnit::Nanosecond),
+        _ => Err(format!("unknown unit {}, use s, ms, us or ns", unit)),
+    }
+}
+
+/// Problems --validate describes
+const VALIDATION_EXAMPLES: usize = 10;
+
+/// Convert a STRAP file to parquet
+#[derive(Parser)]
+#[command(name = "strap2parquet", args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
+struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
+    /// STRAP file, optionally gzip, zstd, bzip2 or zip compressed, - for stdin
+    #[arg(short, long, required = true)]
+    input: Option<PathBuf>,
+    /// Compression of the input (none, gzip, zstd, bzip2, zip), guessed from the extension by default
+    #[arg(long)]
+    compression: Option<InputCompression>,
+    /// Parquet file, <input>.parquet by default, required for stdin
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+    /// Rows per record batch
+    #[arg(long, default_value_t = 1000)]
+    chunk_size: usize,
+    /// Maximum rows per row group, the chunk size by default
+    #[arg(long)]
+    row_group_size: Option<usize>,
+    /// Codec of the output (none, snappy, gzip[:level], zstd[:level])
+    #[arg(long, default_value = "none")]
+    parquet_compression: ParquetCompression,
+    /// Write without dictionary encoding
+    #[arg(long)]
+    no_dictionary: bool,
+    /// Write without min/max statistics
+    #[arg(long)]
+    no_statistics: bool,
+    /// Write only the first N rows, for a quick preview
+    #[arg(long, value_name = "N")]
+    head: Option<usize>,
+    /// Parse, build the arrays and write on separate threads
+    #[arg(long)]
+    threads: bool,
+    /// Column of epoch seconds to write as timestamps, repeatable
+    #[arg(long = "timestamp")]
+    timestamps: Vec<String>,
+    /// Also write columns that look like epoch seconds, e.g. ts or start_time, as timestamps
+    #[arg(long)]
+    detect_timestamps: bool,
+    /// Unit of the written timestamps: s, ms, us or ns
+    #[arg(long, default_value = "ms", value_parser = parse_time_unit)]
+    timestamp_unit: TimeUnit,
+    /// Footer metadata entry KEY=VALUE, repeatable
+    #[arg(long = "metadata", value_name = "KEY=VALUE", value_parser = parse_entry)]
+    metadata: Vec<(String, String)>,
+    /// Also store the `# key: value` comments of the input as footer metadata
+    #[arg(long)]
+    comment_metadata: bool,
+    /// Keep an existing output converted from the same input content
+    #[arg(long)]
+    skip_existing: bool,
+    /// Fingerprint the whole input instead of its first and last MiB
+    #[arg(long)]
+    full_hash: bool,
+    /// Write a row of nulls for each line without values, e.g. a comment, instead of leaving it out
+    #[arg(long)]
+    keep_empty: bool,
+    /// Add a column with the line number of each row in the input, __row_index unless named
+    #[arg(long, num_args = 0..=1, default_missing_value = ROW_INDEX_COLUMN)]
+    row_index: Option<String>,
+    /// Fail on the first malformed key-value pair instead of dropping it
+    #[arg(long)]
+    strict: bool,
+    /// Read only lines with a prefix, by default every line of .strap files is read
+    #[arg(long)]
+    only_prefixed: bool,
+    /// Column to write, repeatable, all columns by default
+    #[arg(long = "column")]
+    columns: Vec<String>,
+    /// Write only rows matching a comparison like "ts >= 5", repeatable, all must match
+    #[arg(long = "filter")]
+    filters: Vec<Comparison>,
+    /// Prefix marking a STRAP line, repeatable, @strap by default
+    #[arg(long = "prefix")]
+    prefixes: Vec<String>,
+    /// Token separator besides whitespace, e.g. ","
+    #[arg(long)]
+    delimiter: Option<char>,
+    /// Skip the first N rows of the input
+    #[arg(long, value_name = "N", default_value_t = 0)]
+    skip_rows: usize,
+    /// Read at most N rows of the input, counted after --skip-rows
+    #[arg(long, value_name = "N")]
+    max_rows: Option<usize>,
+    /// What to do with NaN and inf values: keep, drop-pair, drop-row or error
+    #[arg(long, default_value = "keep")]
+    non_finite: SpecialValuePolicy,
+    /// Memory-map uncompressed inputs instead of reading them line by line
+    #[arg(long)]
+    mmap: bool,
+    /// Write columns with only 0 and 1 (or true and false) as Boolean
+    #[arg(long)]
+    booleans: bool,
+    /// Drop rows with the same keys and values as an earlier row
+    #[arg(long)]
+    dedupe: bool,
+    /// Decimal separator of the numbers: dot, comma or auto
+    #[arg(long, default_value = "dot")]
+    decimal: DecimalSeparator,
+    /// What to do with a key twice in a line: last-wins, first-wins, error, max or sum
+    #[arg(long, default_value = "last-wins")]
+    dup_keys: DuplicateKeyPolicy,
+    /// Only check the input and print its problems, failing if there are more than --max-problems
+    #[arg(long)]
+    validate: bool,
+    /// Problems --validate accepts
+    #[arg(long, value_name = "N", default_value_t = 0)]
+    max_problems: usize,
+    /// Print how many rows have a value of each column
+    #[arg(long)]
+    report: bool,
+    /// Print how many row lines and pairs were lost while loading, with the first lost lines
+    #[arg(long)]
+    load_report: bool,
+    /// Report the progress of every pass over the input on stderr
+    #[arg(long)]
+    progress: bool,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Merge parquet files, columns missing in some inputs become null
+    Concat(ConcatArgs),
+}
+
+#[derive(clap::Args)]
+struct ConcatArgs {
+    /// Input parquet files or glob patterns, e.g. 'out/run_*.parquet'
+    #[arg(short, long, num_args = 1.., required = true)]
+    input: Vec<String>,
+    /// Merged parquet file
+    #[arg(short, long)]
+    output: PathBuf,
+    /// Add the input path of every row as a __source column
+    #[arg(long)]
+    source: bool,
+    /// Sort the merged rows by this column
+    #[arg(long)]
+    sort_by: Option<String>,
+}
+
+#[cfg(feature = "duckdb")]
+fn concat(args: ConcatArgs) -> Result<(), Box<dyn std::error::Error>> {
+    use straptrack::concat::{concat_parquet, expand_glob, ConcatOptions};
+
+    let mut inputs = vec![];
+    for pattern in &args.input {
+        inputs.extend(expand_glob(pattern)?);
+    }
+    let options = ConcatOptions {
+        source_column: args.source,
+        sort_by: args.sort_by,
+    };
+    concat_parquet(&inputs, &args.output, &options)?;
+    println!("{} files merged into {}", inputs.len(), args.output.to_string_lossy());
+    Ok(())
+}
+
+#[cfg(not(feature = "duckdb"))]
+fn concat(_args: ConcatArgs) -> Result<(), Box<dyn std::error::Error>> {
+    Err("concat needs the duckdb feature".into())
+}
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let mut args = Args::parse();
+    if let Some(Command::Concat(concat_args)) = args.command.take() {
+        return concat(concat_args);
+    }
+    let input = args.input.take().expect("--input is required without a subcommand");
+    let stdin = input.as_os_str() == "-";
+    if stdin && args.output.is_none() {
+        return Err("--output is required when reading stdin".into());
+    }
+    let output = args.output.unwrap_or_else(|| {
+        let mut output = input.as_os_str().to_owned();
+        output.push(".parquet");
+        PathBuf::from(output)
+    });
+    if args.skip_existing && !stdin && is_up_to_date(&input, &output) {
+        println!("{} is up to date", output.to_string_lossy());
+        return Ok(());
+    }
+    let mut options = ParquetOptions {
+        chunk_size: args.chunk_size,
+        full_fingerprint: args.full_hash,
+        skip_empty: !args.keep_empty,
+        row_index: args.row_index,
+        head: args.head,
+        compression: args.parquet_compression,
+        row_group_size: args.row_group_size,
+        dictionary: !args.no_dictionary,
+        statistics: !args.no_statistics,
+        threads: args.threads,
+        metadata: args.metadata,
+        ..Default::default()
+    };
+    let track = match args.compression {
+        // every line of stdin is read unless --only-prefixed
+        Some(compression) if stdin => StrapTrack::from_reader("stdin.strap", std::io::stdin().lock())?.with_compression(compression),
+        None if stdin => StrapTrack::from_reader("stdin.strap", std::io::stdin().lock())?,
+        Some(compression) => StrapTrack::new_with_compression(&input, compression)?,
+        None => StrapTrack::new(&input)?,
+    };
+    let track = if args.strict { track.with_parse_mode(ParseMode::Strict) } else { track };
+    let track = track.with_options(&StrapTrackOptions {
+        prefixes: args.prefixes,
+        delimiter: args.delimiter,
+        max_rows: args.max_rows,
+        skip_rows: args.skip_rows,
+        only_prefixed: args.only_prefixed.then_some(true),
+    });
+    let track = track.with_special_values(args.non_finite)
+        .with_duplicate_keys(args.dup_keys)
+        .with_decimal_separator(args.decimal)
+        .with_boolean_detection(args.booleans)
+        .with_dedupe(args.dedupe);
+    let track = if args.columns.is_empty() {
+        track
+    } else {
+        track.select_columns(&args.columns.iter().map(String::as_str).collect::<Vec<_>>())?
+    };
+    let filters = args.filters;
+    let track = if filters.is_empty() {
+        track
+    } else {
+        track.with_filter(move |row| filters.iter().all(|filter| filter.matches(row)))
+    };
+    let mut timestamps = args.timestamps;
+    if args.detect_timestamps {
+        timestamps.extend(track.detect_timestamp_columns()?);
+    }
+    let track = track.with_timestamp_columns(&timestamps.iter().map(String::as_str).collect::<Vec<_>>(), args.timestamp_unit);
+    let track = if args.mmap { track.with_backend(ReadBackend::Mmap) } else { track };
+    let track = if args.progress {
+        track.with_progress(|read, total, rows| {
+            match total {
+                Some(total) if total > 0 => eprint!("\r{:5.1}% {} rows", read as f64 / total as f64 * 100.0, rows),
+                _ => eprint!("\r{} bytes {} rows", read, rows),
+            }
+            true
+        })
+    } else {
+        track
+    };
+    if args.validate {
+        let report = track.validate(VALIDATION_EXAMPLES)?;
+        println!("{} lines, {} rows, {} columns", report.lines, report.rows, report.columns.len());
+        println!("{} lines without prefix", report.unprefixed);
+        println!("{} lines ending in a key without value", report.odd_token_lines);
+        println!("{} malformed pairs", report.malformed_pairs);
+        println!("{} lines failing to parse", report.errors);
+        for (line, problem) in &report.examples {
+            println!("line {}: {}", line, problem);
+        }
+        if report.problems() > args.max_problems {
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+    if args.comment_metadata {
+        let entries = track.metadata()?.entries.into_iter().sorted();
+        // explicit --metadata entries come last and win
+        options.metadata.splice(0..0, entries);
+    }
+    let stats = track.to_parquet_with(&output.to_string_lossy(), &options)?;
+    if args.progress {
+        eprintln!();
+    }
+    println!("{} rows written, {} lines skipped", stats.rows, stats.skipped);
+    if args.dedupe {
+        println!("{} duplicate rows dropped", stats.duplicates);
+    }
+    if stats.invalid_timestamps > 0 {
+        eprintln!("warning: {} timestamps out of range written as null", stats.invalid_timestamps);
+    }
+    if args.load_report {
+        let report = track.load_report(VALIDATION_EXAMPLES)?;
+        println!("{} row lines skipped, {} malformed pairs dropped", report.skipped, report.dropped_pairs);
+        for (line, content) in &report.examples {
+            println!("line {}: {}", line, content);
+        }
+    }
+    if args.report {
+        for (column, present, rows) in track.sparsity_report()? {
+            println!("{:>6.1}% {} ({} of {} rows)", present as f64 / rows.max(1) as f64 * 100.0, column, present, rows);
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,49 @@
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+use clap::Parser;
+
+use straptrack::StrapTrack;
+use straptrack::diff::{diff, diff_rows, Tolerance};
+
+/// Compare two STRAP files column by column
+#[derive(Parser)]
+#[command(name = "strap-diff")]
+struct Args {
+    a: PathBuf,
+    b: PathBuf,
+    /// Absolute tolerance for value comparisons
+    #[arg(long, default_value_t = 1e-12)]
+    abs: f64,
+    /// Relative tolerance for value comparisons
+    #[arg(long, default_value_t = 1e-9)]
+    rel: f64,
+    /// Align rows on this key column and report mismatching values
+    #[arg(long)]
+    by: Option<String>,
+    /// Maximum number of mismatching values reported with --by
+    #[arg(long, default_value_t = 10)]
+    limit: usize,
+    /// Print the report as JSON
+    #[arg(long)]
+    json: bool,
+}
+
+fn main() -> anyhow::Result<ExitCode> {
+    let args = Args::parse();
+    let a = StrapTrack::new(&args.a)?;
+    let b = StrapTrack::new(&args.b)?;
+    let tolerance = Tolerance { abs: args.abs, rel: args.rel };
+
+    let mut report = diff(&a, &b, tolerance)?;
+    if let Some(key) = &args.by {
+        report.rows = diff_rows(&a, &b, key, tolerance, args.limit)?;
+    }
+
+    if args.json {
+        println!("{}", serde_json::to_string_pretty(&report)?);
+    } else {
+        print!("{}", report);
+    }
+    Ok(if report.has_differences() { ExitCode::from(1) } else { ExitCode::SUCCESS })
+}
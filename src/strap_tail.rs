@@ -0,0 +1,75 @@
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+use clap::Parser;
+
+use straptrack::follow::{Follower, LiveParquet};
+
+/// Follow a growing STRAP file and append its rows to a parquet file
+#[derive(Parser)]
+#[command(name = "strap-tail")]
+struct Args {
+    /// STRAP file that is being written
+    #[arg(short, long)]
+    input: PathBuf,
+    /// Live parquet file, a <output>.meta.json sidecar is written next to it
+    #[arg(short, long)]
+    output: PathBuf,
+    /// Flush new rows after this time, e.g. 500ms, 10s or 1m. Every flush rewrites
+    /// the whole output, so longer runs want fewer flushes.
+    #[arg(long, default_value = "10s", value_parser = parse_duration)]
+    flush_every: Duration,
+    /// Flush as soon as this many new rows are pending
+    #[arg(long, default_value_t = 100_000)]
+    flush_rows: usize,
+    /// How often the input is checked for new lines
+    #[arg(long, default_value = "200ms", value_parser = parse_duration)]
+    poll: Duration,
+}
+
+fn parse_duration(s: &str) -> Result<Duration, String> {
+    let (number, unit) = s.find(|c: char| c.is_alphabetic())
+        .map_or((s, "s"), |pos| s.split_at(pos));
+    let number: f64 = number.trim().parse().map_err(|e| format!("invalid duration {:?}: {}", s, e))?;
+    let seconds = match unit {
+        "ms" => number / 1000.0,
+        "s" => number,
+        "m" => number * 60.0,
+        "h" => number * 3600.0,
+        _ => return Err(format!("unknown duration unit {:?}, use ms, s, m or h", unit)),
+    };
+    Duration::try_from_secs_f64(seconds).map_err(|e| e.to_string())
+}
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let args = Args::parse();
+    let stop = Arc::new(AtomicBool::new(false));
+    let handler_stop = stop.clone();
+    ctrlc::set_handler(move || handler_stop.store(true, Ordering::SeqCst))?;
+
+    let mut follower = Follower::new(&args.input)?;
+    let mut live = LiveParquet::new(&args.output);
+    let mut pending = vec![];
+    let mut last_flush = Instant::now();
+    while !stop.load(Ordering::SeqCst) {
+        pending.extend(follower.poll_values()?);
+        if pending.len() >= args.flush_rows
+            || (!pending.is_empty() && last_flush.elapsed() >= args.flush_every)
+        {
+            live.append(&pending, follower.offset())?;
+            pending.clear();
+            last_flush = Instant::now();
+        }
+        std::thread::sleep(args.poll);
+    }
+
+    // the output is complete after every append, only pending rows are left to write
+    pending.extend(follower.poll_values()?);
+    if !pending.is_empty() {
+        live.append(&pending, follower.offset())?;
+    }
+    println!("{} rows written to {}", live.rows(), args.output.to_string_lossy());
+    Ok(())
+}
@@ -0,0 +1,178 @@
+//! Pure-Rust viewer for STRAP files without DuckDB, built for the browser with trunk
+use std::sync::{Arc, Mutex};
+
+use eframe::egui;
+use egui_plot::{Bar, BarChart, Legend, Plot};
+
+use straptrack::{Histogram, StrapTrack};
+
+/// A file picked in the browser arrives asynchronously as name and content
+type PickedFile = Arc<Mutex<Option<(String, Vec<u8>)>>>;
+
+struct WebApp {
+    track: Option<StrapTrack>,
+    column_names: Vec<String>,
+    column: Option<String>,
+    bins: usize,
+    histogram: Option<Histogram>,
+    update: bool,
+    error: Option<String>,
+    picked: PickedFile,
+}
+
+impl Default for WebApp {
+    fn default() -> Self {
+        Self {
+            track: None,
+            column_names: vec![],
+            column: None,
+            bins: 50,
+            histogram: None,
+            update: false,
+            error: None,
+            picked: Arc::new(Mutex::new(None)),
+        }
+    }
+}
+
+impl WebApp {
+    fn load(&mut self, track: StrapTrack) {
+        // text and list columns have no histogram
+        match track.schema() {
+            Ok(schema) => {
+                let column_names: Vec<String> = schema.fields().iter()
+                    .filter(|field| field.data_type().is_numeric())
+                    .map(|field| field.name().clone())
+                    .collect();
+                self.column = column_names.first().cloned();
+                self.column_names = column_names;
+                self.track = Some(track);
+                self.error = None;
+                self.update = true;
+            }
+            Err(e) => self.error = Some(format!("Error reading {}: {}", track.name(), e)),
+        }
+    }
+
+    fn receive_files(&mut self, ctx: &egui::Context) {
+        let picked = self.picked.lock().unwrap().take();
+        if let Some((name, bytes)) = picked {
+            self.load(StrapTrack::from_bytes(name, bytes));
+        }
+        for file in ctx.input(|i| i.raw.dropped_files.clone()) {
+            // the browser hands over bytes, native drops only have a path
+            let bytes = match (&file.bytes, &file.path) {
+                (Some(bytes), _) => Ok(bytes.to_vec()),
+                (None, Some(path)) => std::fs::read(path),
+                (None, None) => continue,
+            };
+            let name = file.path.as_ref()
+                .map(|p| p.to_string_lossy().to_string())
+                .unwrap_or(file.name.clone());
+            match bytes {
+                Ok(bytes) => self.load(StrapTrack::from_bytes(name, bytes)),
+                Err(e) => self.error = Some(format!("Error reading {}: {}", name, e)),
+            }
+        }
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    fn pick_file(&self, ctx: &egui::Context) {
+        let picked = self.picked.clone();
+        let ctx = ctx.clone();
+        wasm_bindgen_futures::spawn_local(async move {
+            if let Some(file) = rfd::AsyncFileDialog::new().pick_file().await {
+                let bytes = file.read().await;
+                *picked.lock().unwrap() = Some((file.file_name(), bytes));
+                ctx.request_repaint();
+            }
+        });
+    }
+}
+
+impl eframe::App for WebApp {
+    fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        self.receive_files(ctx);
+        egui::TopBottomPanel::top("top").show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                #[cfg(target_arch = "wasm32")]
+                if ui.button("Open STRAP file").clicked() {
+                    self.pick_file(ctx);
+                }
+                ui.label("or drop a file here");
+                if let Some(track) = &self.track {
+                    ui.separator();
+                    ui.label(track.name());
+                }
+            });
+        });
+        egui::CentralPanel::default().show(ctx, |ui| {
+            if let Some(error) = &self.error {
+                ui.colored_label(egui::Color32::RED, error);
+            }
+            let Some(track) = &self.track else {
+                return;
+            };
+            ui.horizontal(|ui| {
+                egui::ComboBox::from_label("Column")
+                    .selected_text(self.column.clone().unwrap_or_default())
+                    .show_ui(ui, |ui| {
+                        for name in &self.column_names {
+                            if ui.selectable_value(&mut self.column, Some(name.clone()), name).changed() {
+                                self.update = true;
+                            }
+                        }
+                    });
+                if ui.add(egui::DragValue::new(&mut self.bins).clamp_range(1..=1000).prefix("Bins: ")).changed() {
+                    self.update = true;
+                }
+            });
+            if self.update {
+                self.update = false;
+                self.histogram = self.column.as_ref().and_then(|column| match track.histogram(column, self.bins, None) {
+                    Ok(histogram) => Some(histogram),
+                    Err(e) => {
+                        self.error = Some(format!("Error computing histogram: {}", e));
+                        None
+                    }
+                });
+            }
+            if let Some(histogram) = &self.histogram {
+                let bars = histogram.counts.iter().enumerate()
+                    .map(|(i, &count)| {
+                        let (low, high) = (histogram.edges[i], histogram.edges[i + 1]);
+                        Bar::new((low + high) / 2.0, count as f64).width(high - low)
+                    })
+                    .collect();
+                Plot::new("histogram")
+                    .legend(Legend::default())
+                    .show(ui, |plot_ui| {
+                        plot_ui.bar_chart(BarChart::new(bars).name(self.column.clone().unwrap_or_default()));
+                    });
+            }
+        });
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn main() -> Result<(), eframe::Error> {
+    eframe::run_native(
+        "STRAP Web",
+        eframe::NativeOptions::default(),
+        Box::new(|_cc| Box::new(WebApp::default())),
+    )
+}
+
+#[cfg(target_arch = "wasm32")]
+fn main() {
+    wasm_bindgen_futures::spawn_local(async {
+        eframe::WebRunner::new()
+            .start(
+                "strap_canvas",
+                eframe::WebOptions::default(),
+                Box::new(|_cc| Box::new(WebApp::default())),
+            )
+            .await
+            .expect("failed to start eframe");
+    });
+}
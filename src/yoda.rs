@@ -0,0 +1,72 @@
+//! Export of histograms in the YODA text format read by Rivet
+//!
+//! Curves are written as Scatter2D since their values are already aggregated
+//! (counts, sums or averages) and the fill statistics of a Histo1D are not known.
+use crate::query::HistogramOutput;
+
+/// YODA path of a curve title, only keeping characters that are safe in a path
+pub fn yoda_path(title: &str) -> String {
+    let name: String = title.chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '-' || c == '.' { c } else { '_' })
+        .collect();
+    format!("/STRAP/{}", name)
+}
+
+/// One Scatter2D block per given (curve index, title) with the bin half-width as x error
+pub fn histogram_to_yoda(hist: &HistogramOutput, curves: &[(usize, String)]) -> String {
+    let mut yoda = String::new();
+    for (curve, title) in curves {
+        let path = yoda_path(title);
+        yoda.push_str(&format!("BEGIN YODA_SCATTER2D_V2 {}\n", path));
+        yoda.push_str(&format!("Path: {}\n", path));
+        yoda.push_str(&format!("Title: {}\n", title));
+        yoda.push_str("Type: Scatter2D\n");
+        yoda.push_str("---\n");
+        yoda.push_str("# xval\t xerr-\t xerr+\t yval\t yerr-\t yerr+\t\n");
+        for (x, w, values) in &hist.data {
+            let (y, e) = values[*curve];
+            yoda.push_str(&format!("{:e}\t{:e}\t{:e}\t{:e}\t{:e}\t{:e}\n", x, w / 2., w / 2., y, e, e));
+        }
+        yoda.push_str("END YODA_SCATTER2D_V2\n\n");
+    }
+    yoda
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::query::{HistogramAggregation, HistogramInput, HistogramSubInput, ParsedString, SQLFilter};
+
+    #[test]
+    fn test_histogram_to_yoda() {
+        let curve = |id| HistogramSubInput {
+            id,
+            table: ParsedString::parse("a.parquet").unwrap(),
+            filter: SQLFilter { conditions: vec![] },
+            x_key: ParsedString::parse("x").unwrap(),
+            value_type: HistogramAggregation::Count,
+            y_key: ParsedString::parse("y").unwrap(),
+        };
+        let hist = HistogramOutput {
+            data: vec![
+                (0.5, 1.0, vec![(4.0, 2.0), (1.0, 1.0)]),
+                (1.5, 1.0, vec![(0.0, 0.0), (2.5, 0.5)]),
+            ],
+            input: HistogramInput { range: Default::default(), bins: 2, curves: vec![curve(1), curve(2)] },
+        };
+        let yoda = histogram_to_yoda(&hist, &[(1, "Avg of y vs x (run 2)".to_string())]);
+        let expected = "\
+BEGIN YODA_SCATTER2D_V2 /STRAP/Avg_of_y_vs_x__run_2_
+Path: /STRAP/Avg_of_y_vs_x__run_2_
+Title: Avg of y vs x (run 2)
+Type: Scatter2D
+---
+# xval\t xerr-\t xerr+\t yval\t yerr-\t yerr+\t
+5e-1\t5e-1\t5e-1\t1e0\t1e0\t1e0
+1.5e0\t5e-1\t5e-1\t2.5e0\t5e-1\t5e-1
+END YODA_SCATTER2D_V2
+
+";
+        assert_eq!(yoda, expected);
+    }
+}
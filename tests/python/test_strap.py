@@ -0,0 +1,62 @@
+import strap
+
+
+def write_fixture(tmp_path):
+    path = tmp_path / "fixture.strap"
+    path.write_text("x 1.0 y 2.0\nx 3.0\ny 4.0\n")
+    return path
+
+
+def test_column_names(tmp_path):
+    track = strap.StrapTrack(str(write_fixture(tmp_path)))
+    assert track.column_names() == ["x", "y"]
+
+
+def test_to_arrow_to_pandas(tmp_path):
+    df = strap.StrapTrack(str(write_fixture(tmp_path))).to_arrow().to_pandas()
+    assert list(df.columns) == ["x", "y"]
+    assert df["x"].count() == 2
+    assert df["x"].sum() == 4.0
+    assert df["y"].isna().tolist() == [False, True, False]
+
+
+def test_rows_are_lazy_dicts(tmp_path):
+    rows = strap.StrapTrack(str(write_fixture(tmp_path))).rows()
+    assert next(rows) == {"x": 1.0, "y": 2.0}
+    assert list(rows) == [{"x": 3.0}, {"y": 4.0}]
+
+
+def test_to_parquet_round_trip(tmp_path):
+    import pyarrow.parquet as pq
+
+    out = tmp_path / "fixture.parquet"
+    strap.StrapTrack(str(write_fixture(tmp_path))).to_parquet(str(out), 2)
+    assert pq.read_table(out).num_rows == 3
+
+
+def test_missing_file_raises(tmp_path):
+    try:
+        strap.StrapTrack(str(tmp_path / "missing.strap"))
+    except OSError:
+        return
+    raise AssertionError("expected OSError")
+
+
+def test_rows_skip_lines_without_values(tmp_path):
+    path = tmp_path / "comments.strap"
+    path.write_text("# run 1\nx 1.0\n\nx 2.0\n")
+    assert list(strap.StrapTrack(str(path)).rows()) == [{"x": 1.0}, {"x": 2.0}]
+
+
+def test_parse_error_raises_value_error(tmp_path):
+    path = tmp_path / "mixed.strap"
+    path.write_text("@strap2 x:f64\n1.0\n@strap y 2.0\n")
+    rows = strap.StrapTrack(str(path)).rows()
+    assert next(rows) == {"x": 1.0}
+    try:
+        next(rows)
+    except ValueError as e:
+        assert str(e).startswith("line 3: ")
+        assert str(e).count("line 3") == 1
+        return
+    raise AssertionError("expected ValueError")
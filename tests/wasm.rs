@@ -0,0 +1,15 @@
+//! Smoke test of the file-system free core, run with wasm-bindgen-test-runner
+#![cfg(target_arch = "wasm32")]
+
+use straptrack::StrapTrack;
+use wasm_bindgen_test::wasm_bindgen_test;
+
+#[wasm_bindgen_test]
+fn test_from_str() {
+    let track: StrapTrack = "x 1.0 y 2.0\nx 3.0\n".parse().unwrap();
+    assert_eq!(track.get_column_names().unwrap(), vec!["x", "y"]);
+    let (stats, rows) = track.describe().unwrap();
+    assert_eq!(rows, 2);
+    assert_eq!(stats["x"].sum, 4.0);
+    assert_eq!(track.histogram("x", 2, None).unwrap().counts, vec![1, 1]);
+}